@@ -0,0 +1,31 @@
+//! Measures dispatching verdict responses to the requests waiting for them.
+//!
+//! Run with `cargo bench --features bench --bench dispatch`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+use vaas::bench::{dispatch_verdict_responses, verdict_response_messages};
+
+fn dispatch_responses(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("dispatch");
+
+    for waiters in [1, 100, 1000] {
+        let messages = verdict_response_messages(waiters);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(waiters),
+            &messages,
+            |b, messages| {
+                b.to_async(&runtime).iter_batched(
+                    || messages.clone(),
+                    dispatch_verdict_responses,
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, dispatch_responses);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+//! Measures requesting verdicts for buffers end-to-end, against an in-process mock of VaaS
+//! and a local upload endpoint.
+//!
+//! Run with `cargo bench --features bench --bench for_buf`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+use vaas::bench::MockVaas;
+use vaas::CancellationToken;
+
+const KIB: usize = 1024;
+
+fn known_buffer(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let (_vaas, connection) = runtime.block_on(MockVaas::answering("Clean"));
+    let ct = CancellationToken::from_seconds(10);
+
+    c.bench_function("for_buf/known", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { connection.for_buf(vec![0; KIB], &ct).await.unwrap() })
+    });
+}
+
+fn uploaded_buffer(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut upload_server = runtime.block_on(mockito::Server::new_async());
+    let upload_url = format!("{}/upload", upload_server.url());
+    let (vaas, connection) = runtime.block_on(MockVaas::requesting_uploads(upload_url));
+    let vaas = Arc::new(vaas);
+    let uploads = vaas.clone();
+    let _upload = upload_server
+        .mock("PUT", "/upload")
+        .with_body_from_request(move |request| {
+            let token = request.header("authorization")[0].to_str().unwrap();
+            uploads.upload_received(token);
+            Vec::new()
+        })
+        .create();
+    let ct = CancellationToken::from_seconds(10);
+
+    let mut group = c.benchmark_group("for_buf/upload");
+    for size in [KIB, 1024 * KIB] {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.to_async(&runtime)
+                .iter(|| async { connection.for_buf(vec![0; size], &ct).await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, known_buffer, uploaded_buffer);
+criterion_main!(benches);
@@ -0,0 +1,86 @@
+//! Measures hashing buffers and files, and compares hashing a file by reading it at once
+//! with hashing it in chunks on a blocking thread.
+//!
+//! Run with `cargo bench --bench sha256`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::convert::TryFrom;
+use std::io::Write;
+use tokio::runtime::Runtime;
+use vaas::Sha256;
+
+const KIB: usize = 1024;
+const MIB: usize = 1024 * KIB;
+const SIZES: [usize; 3] = [KIB, MIB, 100 * MIB];
+
+fn content(size: usize) -> Vec<u8> {
+    (0..=255).cycle().take(size).collect()
+}
+
+fn temp_file(size: usize) -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    let chunk = content(size.min(MIB));
+    for _ in 0..size / chunk.len() {
+        file.write_all(&chunk).unwrap();
+    }
+    file.flush().unwrap();
+    file
+}
+
+fn hash_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sha256_buffer");
+    group.sample_size(10);
+    for size in SIZES {
+        let buf = content(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &buf, |b, buf| {
+            b.iter(|| Sha256::from(buf.as_slice()))
+        });
+    }
+    group.finish();
+}
+
+fn hash_file_of_size(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("sha256_file_size");
+    group.sample_size(10);
+    for size in SIZES {
+        let file = temp_file(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &file, |b, file| {
+            b.to_async(&runtime)
+                .iter(|| async { Sha256::from_file(file.path()).await.unwrap() })
+        });
+    }
+    group.finish();
+}
+
+fn hash_file(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let size = 100 * MIB;
+    let file = temp_file(size);
+    let mut group = c.benchmark_group("sha256_file");
+    group.throughput(Throughput::Bytes(size as u64));
+    group.sample_size(10);
+
+    group.bench_function("read_at_once", |b| {
+        b.iter(|| Sha256::try_from(file.path()).unwrap())
+    });
+    for buffer_size in [64 * KIB, MIB, 8 * MIB] {
+        group.bench_with_input(
+            BenchmarkId::new("chunked_blocking", buffer_size),
+            &buffer_size,
+            |b, &buffer_size| {
+                b.to_async(&runtime).iter(|| async {
+                    Sha256::from_file_with_buffer_size(file.path(), buffer_size)
+                        .await
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hash_buffer, hash_file_of_size, hash_file);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Measures constructing and serializing the verdict requests of a batch scan.
+//!
+//! Run with `cargo bench --features bench --bench verdict_request`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::convert::TryFrom;
+use vaas::bench::verdict_requests_for_sha256;
+use vaas::Sha256;
+
+const SESSION_ID: &str = "0b8b1c2a-4ce3-4d31-9a2d-4d2c5bb6b1e7-session";
+
+fn sha256() -> Sha256 {
+    Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f").unwrap()
+}
+
+fn construct_verdict_requests(c: &mut Criterion) {
+    let sha256 = sha256();
+    let mut group = c.benchmark_group("verdict_request");
+    group.sample_size(20);
+
+    group.bench_function("construct_100k", |b| {
+        b.iter(|| verdict_requests_for_sha256(black_box(&sha256), SESSION_ID, 100_000))
+    });
+    group.finish();
+}
+
+fn serialize_verdict_requests(c: &mut Criterion) {
+    let requests = verdict_requests_for_sha256(&sha256(), SESSION_ID, 10_000);
+    let mut group = c.benchmark_group("verdict_request");
+    group.sample_size(20);
+
+    group.bench_function("serialize_10k", |b| {
+        b.iter(|| {
+            requests
+                .iter()
+                .map(|request| serde_json::to_string(request).unwrap().len())
+                .sum::<usize>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    construct_verdict_requests,
+    serialize_verdict_requests
+);
+criterion_main!(benches);
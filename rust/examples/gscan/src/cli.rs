@@ -0,0 +1,596 @@
+use crate::config::CONFIG_FILE;
+use crate::exit_code::FailOn;
+use crate::output::OutputFormat;
+use crate::report_file::ReportFormat;
+use crate::scan::DEFAULT_CONCURRENCY;
+use crate::size::parse_size;
+use crate::DEFAULT_PASSWORD_CLIENT_ID;
+use clap::{
+    crate_authors, crate_description, crate_name, crate_version, Arg, ArgAction, ArgGroup, Command,
+};
+use clap_complete::Shell;
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// The command line interface of gscan.
+///
+/// Scanning is the default, so the options of `gscan scan` can be given without the subcommand.
+/// Their defaults can be changed in a config file, see [`crate::config`].
+/// The subcommands generating shell completions and the man page are meant for packaging and hidden.
+pub fn cli() -> Command {
+    scan_args(Command::new(crate_name!()))
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about(crate_description!())
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(scan_args(
+            Command::new("scan").about("Scan files, urls and hashes. This is the default"),
+        ))
+        .subcommand(
+            Command::new("completions")
+                .about("Print the completion script for a shell")
+                .hide(true)
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(clap::value_parser!(Shell)),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Manage the config file")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("init")
+                        .about("Write a config file with all options commented out")
+                        .arg(
+                            Arg::new("path")
+                                .value_parser(clap::value_parser!(PathBuf))
+                                .default_value(CONFIG_FILE)
+                                .help("Where to write the config file"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .action(ArgAction::SetTrue)
+                                .help("Overwrite an existing file"),
+                        ),
+                ),
+        )
+        .subcommand(Command::new("man").about("Print the man page").hide(true))
+        .subcommand(serve())
+}
+
+/// The `serve` subcommand, only available if gscan was built with the `serve` feature.
+#[cfg(feature = "serve")]
+fn serve() -> Command {
+    use crate::serve::{DEFAULT_LISTEN, DEFAULT_MAX_BODY_SIZE};
+    Command::new("serve")
+        .about("Answer scan requests over a local HTTP API until Ctrl-C is pressed")
+        .arg(
+            Arg::new("listen")
+                .long("listen")
+                .value_parser(clap::value_parser!(std::net::SocketAddr))
+                .default_value(DEFAULT_LISTEN)
+                .help("The address to listen on"),
+        )
+        .arg(
+            Arg::new("max-body-size")
+                .long("max-body-size")
+                .value_parser(parse_size)
+                .default_value(DEFAULT_MAX_BODY_SIZE)
+                .help("Reject requests with a larger body, like 500M or 2G"),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help(format!(
+                    "Scan at most this many requests at the same time [default: {DEFAULT_CONCURRENCY}]"
+                )),
+        )
+        .args(connection_args())
+        .group(credentials())
+}
+
+#[cfg(not(feature = "serve"))]
+fn serve() -> Command {
+    Command::new("serve")
+        .about("Answer scan requests over a local HTTP API. Requires gscan built with the serve feature")
+        .hide(true)
+}
+
+/// Write the completion script for the shell.
+pub fn write_completions(shell: Shell, out: &mut impl Write) {
+    clap_complete::generate(shell, &mut cli(), crate_name!(), out);
+}
+
+/// Write the man page in roff format.
+pub fn write_man_page(out: &mut impl Write) -> io::Result<()> {
+    clap_mangen::Man::new(cli()).render(out)
+}
+
+/// Add the arguments of a scan to the command.
+fn scan_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("files")
+                .short('f')
+                .long("files")
+                .action(ArgAction::Append)
+                .help("List of files or glob patterns like 'target/**/*.dll' to scan separated by whitepace. Use - to scan the content piped to gscan"),
+        )
+        .arg(
+            Arg::new("files-from")
+                .long("files-from")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Scan the files listed in this file, one per line. Use - to read the list from stdin"),
+        )
+        .arg(
+            Arg::new("recursive")
+                .short('r')
+                .long("recursive")
+                .action(ArgAction::SetTrue)
+                .help("Scan the files in directories given with --files and their subdirectories"),
+        )
+        .arg(
+            Arg::new("max-depth")
+                .long("max-depth")
+                .value_parser(clap::value_parser!(usize))
+                .requires("recursive")
+                .help("Descend at most this many directory levels below a directory given with --files"),
+        )
+        .arg(
+            Arg::new("skip-hidden")
+                .long("skip-hidden")
+                .action(ArgAction::SetTrue)
+                .help("Skip hidden files and directories when scanning directories"),
+        )
+        .arg(
+            Arg::new("follow-symlinks")
+                .long("follow-symlinks")
+                .action(ArgAction::SetTrue)
+                .help("Scan the files and directories symlinks in scanned directories point to. Each directory is scanned once, even if symlinks form a loop"),
+        )
+        .arg(
+            Arg::new("one-file-system")
+                .long("one-file-system")
+                .action(ArgAction::SetTrue)
+                .help("Do not descend into directories on other file systems, like mount points, when scanning directories"),
+        )
+        .arg(
+            Arg::new("strict")
+                .long("strict")
+                .action(ArgAction::SetTrue)
+                .help("Report broken symlinks as errors instead of skipping them"),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .action(ArgAction::Append)
+                .help("Do not scan files matching this glob pattern. Patterns without a / match file and directory names anywhere"),
+        )
+        .arg(
+            Arg::new("include-ext")
+                .long("include-ext")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Scan only files with these extensions, e.g. exe,dll. Case-insensitive"),
+        )
+        .arg(
+            Arg::new("exclude-ext")
+                .long("exclude-ext")
+                .action(ArgAction::Append)
+                .value_delimiter(',')
+                .help("Do not scan files with these extensions, e.g. iso,log. Case-insensitive"),
+        )
+        .arg(
+            Arg::new("max-file-size")
+                .long("max-file-size")
+                .value_parser(parse_size)
+                .help("Skip files larger than this size in bytes or with a unit like 500M or 2G"),
+        )
+        .arg(
+            Arg::new("dedupe")
+                .long("dedupe")
+                .action(ArgAction::SetTrue)
+                .overrides_with("no-dedupe")
+                .help("Hash the files first and scan identical files only once. This is the default"),
+        )
+        .arg(
+            Arg::new("no-dedupe")
+                .long("no-dedupe")
+                .action(ArgAction::SetTrue)
+                .overrides_with("dedupe")
+                .help("Scan every file on its own, even if it is identical to another one"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::SetTrue)
+                .requires("files")
+                .conflicts_with_all(["files-from", "urls", "urls-from", "hashes", "hashes-from"])
+                .help("Keep running and scan the files created or changed in the directories given with --files, until Ctrl-C is pressed"),
+        )
+        .arg(
+            Arg::new("move-malicious")
+                .long("move-malicious")
+                .value_parser(clap::value_parser!(PathBuf))
+                .requires("watch")
+                .help("Move malicious files into this directory while watching"),
+        )
+        .arg(
+            Arg::new("urls")
+                .short('u')
+                .long("urls")
+                .action(ArgAction::Append)
+                .help("List of urls to scan separated by whitepace"),
+        )
+        .arg(
+            Arg::new("urls-from")
+                .long("urls-from")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Scan the urls listed in this file, one per line. Use - to read the list from stdin"),
+        )
+        .arg(
+            Arg::new("hashes")
+                .short('H')
+                .long("hashes")
+                .action(ArgAction::Append)
+                .help("List of SHA256 hashes to get the verdicts for, without the files"),
+        )
+        .arg(
+            Arg::new("hashes-from")
+                .long("hashes-from")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Get the verdicts for the SHA256 hashes listed in this file, one per line. The output of sha256sum works, too. Use - to read the list from stdin"),
+        )
+        .group(
+            ArgGroup::new("targets")
+                .args(["files", "files-from", "urls", "urls-from", "hashes", "hashes-from"])
+                .multiple(true)
+                .required(true),
+        )
+        .args(connection_args())
+        .group(credentials())
+        .arg(
+            Arg::new("output-format")
+                .long("output-format")
+                .value_parser(clap::value_parser!(OutputFormat))
+                .default_value("text")
+                .help("Print the results as text, as a JSON array or as one JSON object per line"),
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help(format!(
+                    "Scan at most this many files or urls at the same time [default: {DEFAULT_CONCURRENCY}]"
+                )),
+        )
+        .arg(
+            Arg::new("rate")
+                .long("rate")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help("Send at most this many verdict requests per second"),
+        )
+        .arg(
+            Arg::new("retries")
+                .long("retries")
+                .value_parser(clap::value_parser!(u32))
+                .default_value("0")
+                .help("Retry a file or url scan this many times if it failed with a transient error, like a broken connection"),
+        )
+        .arg(
+            Arg::new("no-progress")
+                .long("no-progress")
+                .action(ArgAction::SetTrue)
+                .help("Do not show a progress bar, e.g. for CI logs. It is only shown if stderr is a terminal"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Write a report of all results to this file, independent of the output"),
+        )
+        .arg(
+            Arg::new("report-format")
+                .long("report-format")
+                .value_parser(clap::value_parser!(ReportFormat))
+                .default_value("json")
+                .requires("report")
+                .help("The format of the report file"),
+        )
+        .arg(
+            Arg::new("only-findings")
+                .long("only-findings")
+                .action(ArgAction::SetTrue)
+                .help("Print only the results which are not clean"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .help("Print no results, only the summary at the end"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue)
+                .help("Print the SHA256 and the duration of each scan"),
+        )
+        .arg(
+            Arg::new("no-color")
+                .long("no-color")
+                .action(ArgAction::SetTrue)
+                .help("Do not color the verdicts. Colors are also disabled by setting NO_COLOR"),
+        )
+        .arg(
+            Arg::new("fail-on")
+                .long("fail-on")
+                .value_parser(clap::value_parser!(FailOn))
+                .default_value("pup")
+                .help("Exit with 1 if a verdict is at least this severe. Exits with 2 if a scan failed and nothing was found"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Read the default options from this file instead of gscan.toml in the current directory or in $XDG_CONFIG_HOME/gscan"),
+        )
+        .arg(
+            Arg::new("env-file")
+                .long("env-file")
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("Load environment variables from this file in addition to the .env file in the current directory"),
+        )
+}
+
+/// The arguments to connect to VaaS, shared by all subcommands scanning something.
+///
+/// There are three ways to authenticate, which cannot be mixed: a client id with a client secret, a user name with
+/// a password, for which the client id is optional, and a token obtained elsewhere. Use them with [`credentials`].
+fn connection_args() -> [Arg; 9] {
+    [
+        Arg::new("client_id")
+            .short('i')
+            .long("client_id")
+            .action(ArgAction::Set)
+            .requires("credentials")
+            .env("CLIENT_ID")
+            .help("Set your vaas client id. Defaults to the configuration from the environment"),
+        Arg::new("client_secret")
+            .short('s')
+            .long("client_secret")
+            .action(ArgAction::Set)
+            .requires("client_id")
+            .env("CLIENT_SECRET")
+            .help("Set your vaas client secret. Defaults to the configuration from the environment"),
+        Arg::new("user_name")
+            .long("user_name")
+            .action(ArgAction::Set)
+            .requires("password")
+            .conflicts_with("client_secret")
+            .env("VAAS_USER_NAME")
+            .help(format!(
+                "Log in with your personal vaas account. The client id defaults to {DEFAULT_PASSWORD_CLIENT_ID}"
+            )),
+        Arg::new("password")
+            .long("password")
+            .action(ArgAction::Set)
+            .requires("user_name")
+            .env("VAAS_PASSWORD")
+            .hide_env_values(true)
+            .help("The password of your personal vaas account"),
+        Arg::new("token")
+            .long("token")
+            .action(ArgAction::Set)
+            .conflicts_with_all(["client_id", "client_secret", "user_name", "password"])
+            .env("VAAS_TOKEN")
+            .hide_env_values(true)
+            .help("Authenticate with a token obtained elsewhere, e.g. from your SSO tooling. It is not refreshed"),
+        Arg::new("timeout")
+            .long("timeout")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .help("Cancel a scan after this many seconds [default: 60]"),
+        Arg::new("connect-retries")
+            .long("connect-retries")
+            .value_parser(clap::value_parser!(u32))
+            .default_value("0")
+            .help("Retry connecting to VaaS this many times if it failed with a transient error"),
+        Arg::new("allowlist-file")
+            .long("allowlist-file")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Report the SHA256 hashes listed in this file as clean without asking VaaS, one per line. The output of sha256sum works, too"),
+        Arg::new("blocklist-file")
+            .long("blocklist-file")
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("Report the SHA256 hashes listed in this file as malicious without asking VaaS, even if they are on the allowlist"),
+    ]
+}
+
+/// Requires a secret to go with `--client_id`, either the client secret or a user name with a password.
+fn credentials() -> ArgGroup {
+    ArgGroup::new("credentials").args(["client_secret", "user_name"])
+}
+
+/// Load the environment variables from the file given with `--env-file` and from the `.env` file in the current
+/// directory, so the environment fallbacks of the arguments can use them. Has to be called before the arguments
+/// are parsed. Variables which are already set are not overwritten, and a missing `.env` file is not an error.
+pub fn load_env_files<I: IntoIterator<Item = OsString>>(args: I) {
+    if let Some(env_file) = env_file_arg(args) {
+        if let Err(e) = dotenv::from_path(&env_file) {
+            eprintln!("Failed to load {}: {e}", env_file.display());
+            std::process::exit(2);
+        }
+    }
+    dotenv::dotenv().ok();
+}
+
+/// The value of `--env-file`, looked up before the arguments are parsed by clap.
+fn env_file_arg<I: IntoIterator<Item = OsString>>(args: I) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--env-file" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|a| a.strip_prefix("--env-file=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn env_file_arg_is_found_in_both_forms() {
+        assert_eq!(
+            Some(PathBuf::from("creds.env")),
+            env_file_arg(args(&["gscan", "-f", "a", "--env-file", "creds.env"]))
+        );
+        assert_eq!(
+            Some(PathBuf::from("creds.env")),
+            env_file_arg(args(&["gscan", "--env-file=creds.env", "-f", "a"]))
+        );
+        assert_eq!(None, env_file_arg(args(&["gscan", "-f", "a"])));
+    }
+
+    // The environment is shared by all tests, so everything depending on it is asserted in one test.
+    #[test]
+    fn credentials_from_env_file_are_used_unless_given_as_arguments() {
+        let mut env_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(env_file, "CLIENT_ID=id-from-file").unwrap();
+        writeln!(env_file, "CLIENT_SECRET=secret-from-file").unwrap();
+        let env_file = env_file.path().to_str().unwrap();
+
+        load_env_files(args(&["gscan", "--env-file", env_file]));
+        let from_file = cli()
+            .try_get_matches_from(["gscan", "-f", "a", "--env-file", env_file])
+            .unwrap();
+        let from_args = cli()
+            .try_get_matches_from(["gscan", "-f", "a", "-i", "id-from-args", "-s", "secret"])
+            .unwrap();
+
+        assert_eq!(
+            Some(&"id-from-file".to_string()),
+            from_file.get_one::<String>("client_id")
+        );
+        assert_eq!(
+            Some(&"secret-from-file".to_string()),
+            from_file.get_one::<String>("client_secret")
+        );
+        assert_eq!(
+            Some(&"id-from-args".to_string()),
+            from_args.get_one::<String>("client_id")
+        );
+    }
+
+    #[test]
+    fn authentication_methods_cannot_be_mixed() {
+        let mixed = [
+            vec!["--token", "t", "-i", "id", "-s", "secret"],
+            vec!["--token", "t", "--user_name", "u", "--password", "p"],
+            vec![
+                "-i",
+                "id",
+                "-s",
+                "secret",
+                "--user_name",
+                "u",
+                "--password",
+                "p",
+            ],
+        ];
+        for auth in mixed {
+            let error = cli()
+                .try_get_matches_from(["gscan", "-f", "a"].into_iter().chain(auth.clone()))
+                .unwrap_err();
+            assert_eq!(
+                clap::error::ErrorKind::ArgumentConflict,
+                error.kind(),
+                "{auth:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn authentication_methods_have_to_be_complete() {
+        // Only the user name and password are not read from the environment by other tests.
+        let incomplete = [vec!["--user_name", "u"], vec!["--password", "p"]];
+        for auth in incomplete {
+            let error = cli()
+                .try_get_matches_from(["gscan", "-f", "a"].into_iter().chain(auth.clone()))
+                .unwrap_err();
+            assert_eq!(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                error.kind(),
+                "{auth:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn scan_is_the_default_subcommand() {
+        let implicit = cli().try_get_matches_from(["gscan", "-f", "a"]).unwrap();
+        let explicit = cli()
+            .try_get_matches_from(["gscan", "scan", "-f", "a"])
+            .unwrap();
+
+        assert!(implicit.subcommand().is_none());
+        let (name, scan) = explicit.subcommand().unwrap();
+        assert_eq!("scan", name);
+        assert_eq!(
+            implicit
+                .get_many::<String>("files")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            scan.get_many::<String>("files")
+                .unwrap()
+                .collect::<Vec<_>>()
+        );
+        assert!(cli()
+            .try_get_matches_from(["gscan", "-f", "a", "completions", "bash"])
+            .is_err());
+    }
+
+    #[test]
+    fn completions_contain_the_long_options() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+            let mut out = Vec::new();
+            write_completions(shell, &mut out);
+            let completions = String::from_utf8(out).unwrap();
+
+            for option in ["files", "urls-from", "max-file-size", "watch", "report"] {
+                // Fish lists the long options as `-l name`.
+                let expected = match shell {
+                    Shell::Fish => format!("-l {option}"),
+                    _ => format!("--{option}"),
+                };
+                assert!(completions.contains(&expected), "{shell}: {expected}");
+            }
+        }
+    }
+
+    #[test]
+    fn man_page_documents_the_options() {
+        let mut out = Vec::new();
+        write_man_page(&mut out).unwrap();
+        let man_page = String::from_utf8(out).unwrap();
+
+        assert!(man_page.starts_with(".ie"));
+        assert!(man_page.contains("gscan"));
+        assert!(man_page.contains("files\\-from"));
+    }
+}
@@ -0,0 +1,393 @@
+use crate::cli::cli;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+use vaas::error::{Error, VResult};
+
+/// The name of the config file, looked up in the current directory and in `$XDG_CONFIG_HOME/gscan/`.
+pub const CONFIG_FILE: &str = "gscan.toml";
+
+/// The options needed to find the config file or read before it, which it cannot set itself.
+const NOT_CONFIGURABLE: [&str; 2] = ["config", "env-file"];
+
+/// The options which are secrets, so the config file should only be readable by its owner.
+const CREDENTIALS: [&str; 3] = ["client_secret", "password", "token"];
+
+/// Defaults for the options of `gscan scan`, read from a TOML file with one key per long option,
+/// like `recursive = true`, `concurrency = 16` or `exclude = ["*.log", "**/node_modules/**"]`.
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    table: Table,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> VResult<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::IoError(format!("{}: {e}", path.display())))?;
+        Self::parse(path, &text)
+    }
+
+    pub fn parse(path: &Path, text: &str) -> VResult<Self> {
+        let table = text
+            .parse::<Table>()
+            .map_err(|e| Error::InvalidConfig(format!("{}: {e}", path.display())))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            table,
+        })
+    }
+
+    /// The command line arguments for the options set in the config file, leaving out the ones
+    /// given on the command line or in the environment, as those take precedence.
+    pub fn args(&self, command: &Command, matches: &ArgMatches) -> VResult<Vec<OsString>> {
+        let mut args = Vec::new();
+        for (key, value) in &self.table {
+            let arg = command
+                .get_arguments()
+                .find(|arg| same_key(arg.get_id().as_str(), key))
+                .filter(|arg| !NOT_CONFIGURABLE.contains(&arg.get_id().as_str()))
+                .ok_or_else(|| self.error(key, "is not an option of gscan"))?;
+            let given = matches!(
+                matches.value_source(arg.get_id().as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            );
+            if !given {
+                self.push_arg(&mut args, arg, key, value)?;
+            }
+        }
+        Ok(args)
+    }
+
+    fn push_arg(
+        &self,
+        args: &mut Vec<OsString>,
+        arg: &Arg,
+        key: &str,
+        value: &Value,
+    ) -> VResult<()> {
+        let long = arg.get_long().expect("all options have a long name");
+        match (arg.get_action(), value) {
+            (ArgAction::SetTrue, Value::Boolean(set)) => {
+                if *set {
+                    args.push(format!("--{long}").into());
+                }
+            }
+            (ArgAction::SetTrue, _) => return Err(self.error(key, "must be true or false")),
+            (ArgAction::Append, Value::Array(values)) => {
+                for value in values {
+                    let value = self.scalar(key, value)?;
+                    args.push(format!("--{long}={value}").into());
+                }
+            }
+            (_, Value::Array(_)) => return Err(self.error(key, "takes a single value")),
+            (_, value) => {
+                let value = self.scalar(key, value)?;
+                args.push(format!("--{long}={value}").into());
+            }
+        }
+        Ok(())
+    }
+
+    fn scalar(&self, key: &str, value: &Value) -> VResult<String> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            Value::Integer(i) => Ok(i.to_string()),
+            Value::Float(f) => Ok(f.to_string()),
+            _ => Err(self.error(key, "must be a string or a number")),
+        }
+    }
+
+    fn error(&self, key: &str, problem: &str) -> Error {
+        Error::InvalidConfig(format!("{}: {key} {problem}", self.path.display()))
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.table.keys().any(|key| {
+            CREDENTIALS
+                .iter()
+                .any(|credential| same_key(credential, key))
+        })
+    }
+
+    /// The warning to print if the file contains credentials and its Unix permissions `mode` let every user read it.
+    fn readable_credentials_warning(&self, mode: u32) -> Option<String> {
+        (self.has_credentials() && mode & 0o004 != 0).then(|| {
+            format!(
+                "{} contains credentials and is readable by all users, restrict it with chmod 600",
+                self.path.display()
+            )
+        })
+    }
+
+    #[cfg(unix)]
+    fn warn_about_readable_credentials(&self) {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&self.path).map(|m| m.permissions().mode());
+        if let Some(warning) = mode
+            .ok()
+            .and_then(|mode| self.readable_credentials_warning(mode))
+        {
+            eprintln!("Warning: {warning}");
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn warn_about_readable_credentials(&self) {}
+}
+
+/// Keys may use `-` or `_` between words, independent of the name of the option.
+fn same_key(id: &str, key: &str) -> bool {
+    id.replace('_', "-") == key.replace('_', "-")
+}
+
+/// The config file to use if none is given with `--config`: `gscan.toml` in the current directory,
+/// or in the `gscan` directory of the user's config directory.
+fn find_config() -> Option<PathBuf> {
+    let local = PathBuf::from(CONFIG_FILE);
+    let user = config_dir().map(|dir| dir.join("gscan").join(CONFIG_FILE));
+    std::iter::once(local)
+        .chain(user)
+        .find(|candidate| candidate.is_file())
+}
+
+fn config_dir() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| var("APPDATA").map(PathBuf::from))
+}
+
+/// Add the options of the config file to the command line arguments of a scan. They are inserted before
+/// the given ones and only for options not given on the command line or in the environment, so the precedence
+/// is command line, environment, config file and then the built-in defaults.
+pub fn with_config_args(args: Vec<OsString>) -> VResult<Vec<OsString>> {
+    let command = cli();
+    // Errors, like missing targets which the config file may add, are reported when the final arguments are parsed.
+    let Ok(matches) = command
+        .clone()
+        .ignore_errors(true)
+        .try_get_matches_from(&args)
+    else {
+        return Ok(args);
+    };
+    let (command, matches, position) = match matches.subcommand() {
+        Some(("scan", scan)) => {
+            let scan_command = command.find_subcommand("scan").cloned();
+            (scan_command.expect("scan is a subcommand"), scan, 2)
+        }
+        Some(_) => return Ok(args),
+        None => (command, &matches, 1),
+    };
+    let path = match matches.get_one::<PathBuf>("config") {
+        Some(path) => path.clone(),
+        None => match find_config() {
+            Some(path) => path,
+            None => return Ok(args),
+        },
+    };
+    let config = Config::load(&path)?;
+    config.warn_about_readable_credentials();
+
+    let mut args = args;
+    let position = position.min(args.len());
+    args.splice(position..position, config.args(&command, matches)?);
+    Ok(args)
+}
+
+/// A config file with every option of a scan commented out, with its help and an example value.
+pub fn template() -> String {
+    let mut template = String::from(
+        "# gscan configuration. Every option of a scan can be set here, with the long name of the option as key.\n\
+         # Options given on the command line or in the environment take precedence.\n",
+    );
+    let command = cli();
+    let configurable = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && !NOT_CONFIGURABLE.contains(&arg.get_id().as_str()));
+    for arg in configurable {
+        let example = match arg.get_action() {
+            ArgAction::SetTrue => "true".to_string(),
+            ArgAction::Append => "[]".to_string(),
+            _ => match arg.get_default_values().first() {
+                Some(default) => format!("\"{}\"", default.to_string_lossy()),
+                None => "\"\"".to_string(),
+            },
+        };
+        let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+        let _ = write!(template, "\n# {help}\n# {} = {example}\n", arg.get_id());
+    }
+    template
+}
+
+/// Write the template to the path, unless a file exists there and `force` is not set.
+pub fn init(path: &Path, force: bool) -> VResult<()> {
+    if path.exists() && !force {
+        return Err(Error::InvalidConfig(format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        )));
+    }
+    std::fs::write(path, template()).map_err(|e| Error::IoError(format!("{}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> Config {
+        Config::parse(Path::new(CONFIG_FILE), text).unwrap()
+    }
+
+    fn args(config: &Config, command_line: &[&str]) -> VResult<Vec<String>> {
+        let command = cli();
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(command_line)
+            .unwrap();
+        let args = config.args(&command, &matches)?;
+        Ok(args
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn values_are_converted_to_arguments() {
+        let config = config(
+            r#"
+            recursive = true
+            skip-hidden = false
+            concurrency = 16
+            max_file_size = "500M"
+            exclude = ["*.log", "**/node_modules/**"]
+            "#,
+        );
+
+        assert_eq!(
+            vec![
+                "--concurrency=16",
+                "--exclude=*.log",
+                "--exclude=**/node_modules/**",
+                "--max-file-size=500M",
+                "--recursive",
+            ],
+            args(&config, &["gscan"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_keys_and_values_are_errors() {
+        let problems = [
+            ("unknown = 1", "unknown is not an option of gscan"),
+            (
+                "config = \"other.toml\"",
+                "config is not an option of gscan",
+            ),
+            ("recursive = \"yes\"", "recursive must be true or false"),
+            ("concurrency = [1, 2]", "concurrency takes a single value"),
+            ("exclude = [true]", "exclude must be a string or a number"),
+        ];
+        for (text, expected) in problems {
+            match args(&config(text), &["gscan"]) {
+                Err(Error::InvalidConfig(message)) => {
+                    assert!(message.ends_with(expected), "{message}")
+                }
+                other => panic!("{text}: {other:?}"),
+            }
+        }
+        assert!(matches!(
+            Config::parse(Path::new(CONFIG_FILE), "recursive = "),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_config() {
+        let config = config(
+            r#"
+            output-format = "json"
+            exclude = ["*.log"]
+            concurrency = 4
+            "#,
+        );
+
+        let args = args(
+            &config,
+            &["gscan", "--output-format", "ndjson", "--exclude=*.iso"],
+        )
+        .unwrap();
+
+        assert_eq!(vec!["--concurrency=4"], args);
+    }
+
+    #[test]
+    fn config_takes_precedence_over_defaults_but_not_over_environment() {
+        // A variable of its own, as the environment is shared by all tests.
+        std::env::set_var("GSCAN_TEST_PRECEDENCE", "from-env");
+        let command = Command::new("gscan")
+            .arg(
+                Arg::new("from-env")
+                    .long("from-env")
+                    .env("GSCAN_TEST_PRECEDENCE"),
+            )
+            .arg(
+                Arg::new("from-config")
+                    .long("from-config")
+                    .default_value("default"),
+            );
+        let config = config(
+            r#"
+            from-env = "from-config"
+            from-config = "from-config"
+            "#,
+        );
+
+        let matches = command.clone().try_get_matches_from(["gscan"]).unwrap();
+        let mut args = vec![OsString::from("gscan")];
+        args.extend(config.args(&command, &matches).unwrap());
+        let matches = command.try_get_matches_from(args).unwrap();
+
+        assert_eq!("from-env", matches.get_one::<String>("from-env").unwrap());
+        assert_eq!(
+            "from-config",
+            matches.get_one::<String>("from-config").unwrap()
+        );
+    }
+
+    #[test]
+    fn readable_credentials_are_warned_about() {
+        let with_secret = config("client_id = \"id\"\nclient_secret = \"secret\"");
+        let without_secret = config("recursive = true");
+
+        assert!(with_secret.readable_credentials_warning(0o644).is_some());
+        assert!(with_secret.readable_credentials_warning(0o600).is_none());
+        assert!(without_secret.readable_credentials_warning(0o644).is_none());
+    }
+
+    #[test]
+    fn template_lists_the_options_commented_out() {
+        let template = template();
+
+        assert!(template.contains("# recursive = true\n"));
+        assert!(template.contains("# exclude = []\n"));
+        assert!(template.contains("# output-format = \"text\"\n"));
+        assert!(!template.contains("env-file"));
+        assert!(config(&template).table.is_empty());
+
+        // Uncommented, every key is a valid option.
+        let uncommented: String = template
+            .lines()
+            .filter(|line| line.contains(" = "))
+            .map(|line| format!("{}\n", line.trim_start_matches("# ")))
+            .collect();
+        let config = config(&uncommented);
+        assert!(config.table.len() > 20);
+        assert!(args(&config, &["gscan"]).is_ok());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::output::{Outcome, ScanResult};
+use clap::builder::PossibleValue;
+use clap::ValueEnum;
+use vaas::message::Verdict;
+
+/// Every item was scanned and nothing was found.
+pub const CLEAN: u8 = 0;
+/// At least one item is malicious, or a PUP if those count as failures.
+pub const MALICIOUS: u8 = 1;
+/// At least one item could not be scanned, or the scan could not be started at all.
+pub const ERROR: u8 = 2;
+
+/// The least severe verdict which makes the scan fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FailOn {
+    /// PUP and malicious verdicts fail the scan.
+    #[default]
+    Pup,
+    /// Only malicious verdicts fail the scan.
+    Malicious,
+}
+
+impl ValueEnum for FailOn {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Pup, Self::Malicious]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Self::Pup => PossibleValue::new("pup"),
+            Self::Malicious => PossibleValue::new("malicious"),
+        })
+    }
+}
+
+/// The exit code for the results. A failing verdict takes precedence over errors.
+pub fn exit_code(results: &[ScanResult], fail_on: FailOn) -> u8 {
+    let mut code = CLEAN;
+    for result in results {
+        match &result.outcome {
+            Outcome::Verdict(v) if fails(&v.verdict, fail_on) => return MALICIOUS,
+            Outcome::Verdict(_) | Outcome::Skipped(_) => {}
+            Outcome::Error(_) => code = ERROR,
+        }
+    }
+    code
+}
+
+fn fails(verdict: &Verdict, fail_on: FailOn) -> bool {
+    match verdict {
+        Verdict::Malicious { .. } => true,
+        Verdict::Pup { .. } => fail_on == FailOn::Pup,
+        Verdict::Clean | Verdict::Unknown { .. } => false,
+        // A category gscan does not know yet might be a finding, so it is treated like a PUP.
+        _ => fail_on == FailOn::Pup,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TargetType;
+    use std::convert::TryFrom;
+    use vaas::error::Error;
+    use vaas::{Sha256, VaasVerdict};
+
+    fn verdict(verdict: Verdict) -> ScanResult {
+        let sha256 =
+            Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+                .unwrap();
+        let verdict = VaasVerdict::new(sha256, verdict);
+        ScanResult::new("file", TargetType::File, Ok(verdict))
+    }
+
+    fn clean() -> ScanResult {
+        verdict(Verdict::Clean)
+    }
+
+    fn malicious() -> ScanResult {
+        verdict(Verdict::Malicious {
+            detection: "EICAR-Test-File".to_string(),
+        })
+    }
+
+    fn pup() -> ScanResult {
+        verdict(Verdict::Pup {
+            detection: "Adware".to_string(),
+        })
+    }
+
+    fn error() -> ScanResult {
+        ScanResult::new("file", TargetType::File, Err(Error::Cancelled))
+    }
+
+    #[test]
+    fn all_clean_is_success() {
+        assert_eq!(CLEAN, exit_code(&[clean(), clean()], FailOn::Pup));
+        assert_eq!(CLEAN, exit_code(&[], FailOn::Pup));
+    }
+
+    #[test]
+    fn malicious_takes_precedence_over_errors() {
+        assert_eq!(ERROR, exit_code(&[clean(), error()], FailOn::Pup));
+        assert_eq!(
+            MALICIOUS,
+            exit_code(&[error(), malicious(), error()], FailOn::Pup)
+        );
+    }
+
+    #[test]
+    fn skipped_items_do_not_fail() {
+        let skipped = ScanResult::skipped("file", TargetType::File, "too large");
+        assert_eq!(CLEAN, exit_code(&[clean(), skipped], FailOn::Pup));
+    }
+
+    #[test]
+    fn pup_fails_unless_failing_on_malicious_only() {
+        assert_eq!(MALICIOUS, exit_code(&[clean(), pup()], FailOn::Pup));
+        assert_eq!(CLEAN, exit_code(&[clean(), pup()], FailOn::Malicious));
+        assert_eq!(ERROR, exit_code(&[pup(), error()], FailOn::Malicious));
+        assert_eq!(MALICIOUS, exit_code(&[malicious()], FailOn::Malicious));
+    }
+
+    #[test]
+    fn unexpected_verdict_category_fails_like_a_pup() {
+        let other = || verdict(Verdict::Other("Suspicious".to_string()));
+        assert_eq!(MALICIOUS, exit_code(&[clean(), other()], FailOn::Pup));
+        assert_eq!(CLEAN, exit_code(&[clean(), other()], FailOn::Malicious));
+    }
+}
@@ -0,0 +1,566 @@
+use crate::patterns::{is_glob, Excludes, Extensions, FilePattern};
+use crate::size::format_size;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use vaas::error::Error;
+use walkdir::{DirEntry, WalkDir};
+
+/// How directories given as scan targets are walked.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    pub recursive: bool,
+    pub max_depth: Option<usize>,
+    pub skip_hidden: bool,
+    pub exclude: Excludes,
+    pub extensions: Extensions,
+    /// Larger files are skipped instead of scanned.
+    pub max_file_size: Option<u64>,
+    /// Scan the files and walk the directories symlinks point to.
+    pub follow_symlinks: bool,
+    /// Do not descend into directories on other file systems than the walked directory.
+    pub one_file_system: bool,
+    /// Report broken symlinks as errors instead of skipping them.
+    pub strict: bool,
+}
+
+impl WalkOptions {
+    /// Why a file of this size is not scanned, if it is not.
+    pub fn skip_reason(&self, size: u64) -> Option<String> {
+        self.max_file_size
+            .filter(|&max| size > max)
+            .map(|max| format!("{} is larger than {}", format_size(size), format_size(max)))
+    }
+}
+
+/// The regular files to scan, the ones skipped with the reason why, the paths which could not be read
+/// and the glob patterns which matched nothing.
+#[derive(Debug, Default)]
+pub struct FileTargets {
+    pub files: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, String)>,
+    pub errors: Vec<(PathBuf, Error)>,
+    pub warnings: Vec<String>,
+}
+
+/// Collect the files to scan. Directories are walked if `recursive` is set, otherwise they are reported as errors.
+/// Paths which do not exist but contain glob characters are expanded. Excluded files and files with other extensions
+/// than the selected ones are left out in any case, files larger than the maximum size are skipped.
+pub fn collect_files(paths: &[PathBuf], options: &WalkOptions) -> FileTargets {
+    let mut targets = FileTargets::default();
+    let mut walker = Walker::new(options);
+    for path in paths {
+        if options.exclude.is_excluded(path) {
+            continue;
+        }
+        match std::fs::metadata(path) {
+            Err(_) if is_glob(&path.to_string_lossy()) => {
+                expand_glob(path, &mut walker, &mut targets)
+            }
+            Ok(metadata) if metadata.is_dir() && options.recursive => {
+                walker.walk(path, options.max_depth, None, &mut targets)
+            }
+            Ok(metadata) if metadata.is_dir() => targets.errors.push((
+                path.clone(),
+                Error::IoError("is a directory, use --recursive to scan it".to_string()),
+            )),
+            Ok(metadata) => add_file(path.clone(), || Some(metadata.len()), options, &mut targets),
+            Err(_) if is_symlink(path) => add_broken_symlink(path.clone(), options, &mut targets),
+            Err(e) => targets.errors.push((path.clone(), e.into())),
+        }
+    }
+    targets
+}
+
+fn expand_glob(pattern: &Path, walker: &mut Walker, targets: &mut FileTargets) {
+    let pattern = match FilePattern::new(&pattern.to_string_lossy()) {
+        Ok(pattern) => pattern,
+        Err(e) => return targets.errors.push((pattern.to_path_buf(), e)),
+    };
+    let found = targets.files.len() + targets.skipped.len();
+    walker.walk(pattern.base(), pattern.max_depth(), Some(&pattern), targets);
+    if targets.files.len() + targets.skipped.len() == found {
+        targets.warnings.push(format!(
+            "pattern {} did not match any files",
+            pattern.pattern()
+        ));
+    }
+}
+
+/// Identifies a directory independent of the path it was reached by.
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryId = PathBuf;
+
+/// The device and inode of the directory.
+#[cfg(unix)]
+fn directory_id(path: &Path) -> Option<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// The canonical path of the directory, as there are no inodes to compare.
+#[cfg(not(unix))]
+fn directory_id(path: &Path) -> Option<DirectoryId> {
+    path.canonicalize().ok()
+}
+
+/// The device the path is on, to stop at mount points.
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Without a portable device number, followed symlinks are not checked. Walking a directory
+/// still stops at its mount points.
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Walks directories, following symlinks to files and directories if configured.
+///
+/// Every walked directory is remembered by its [`DirectoryId`] while following symlinks, so a directory reached
+/// again through a symlink is not walked twice. This also ends symlink loops.
+struct Walker<'a> {
+    options: &'a WalkOptions,
+    visited: HashSet<DirectoryId>,
+    /// The device of the directory given on the command line which is walked at the moment.
+    device: Option<u64>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(options: &'a WalkOptions) -> Self {
+        Self {
+            options,
+            visited: HashSet::new(),
+            device: None,
+        }
+    }
+
+    /// Walk the directory and add its files, or only those matching the pattern when expanding a glob.
+    /// A missing base directory of a glob just means that nothing matches, so walk errors are only reported
+    /// without a pattern.
+    fn walk(
+        &mut self,
+        directory: &Path,
+        max_depth: Option<usize>,
+        pattern: Option<&FilePattern>,
+        targets: &mut FileTargets,
+    ) {
+        self.device = device(directory);
+        self.walk_tree(directory, max_depth, pattern, targets);
+    }
+
+    fn walk_tree(
+        &mut self,
+        directory: &Path,
+        max_depth: Option<usize>,
+        pattern: Option<&FilePattern>,
+        targets: &mut FileTargets,
+    ) {
+        let options = self.options;
+        let mut walker = WalkDir::new(directory)
+            .sort_by_file_name()
+            .same_file_system(options.one_file_system);
+        if let Some(max_depth) = max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        let visited = &mut self.visited;
+        let entries = walker.into_iter().filter_entry(|entry| {
+            let walked = is_walked(entry, options);
+            // Symlinks can only lead back into directories which were walked before.
+            if walked && options.follow_symlinks && entry.file_type().is_dir() {
+                if let Some(id) = directory_id(entry.path()) {
+                    return visited.insert(id) || entry.depth() == 0;
+                }
+            }
+            walked
+        });
+        let is_match = |path: &Path| pattern.is_none_or(|pattern| pattern.is_match(path));
+        let mut links = Vec::new();
+        for entry in entries {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() && is_match(entry.path()) => {
+                    add_walked_file(entry, options, targets)
+                }
+                Ok(entry) if entry.file_type().is_symlink() => links.push(entry),
+                Ok(_) => {}
+                Err(_) if pattern.is_some() => {}
+                Err(e) => {
+                    let path = e.path().unwrap_or(directory).to_path_buf();
+                    targets.errors.push((path, Error::IoError(e.to_string())));
+                }
+            }
+        }
+        for link in links {
+            let remaining_depth = max_depth.map(|max_depth| max_depth - link.depth());
+            self.add_symlink(link.path(), remaining_depth, pattern, targets);
+        }
+    }
+
+    /// Add a symlink found while walking. Broken ones are skipped, the targets of the others are only
+    /// added if symlinks are followed.
+    fn add_symlink(
+        &mut self,
+        link: &Path,
+        remaining_depth: Option<usize>,
+        pattern: Option<&FilePattern>,
+        targets: &mut FileTargets,
+    ) {
+        let options = self.options;
+        let is_match = pattern.is_none_or(|pattern| pattern.is_match(link));
+        match std::fs::metadata(link) {
+            Err(_) if is_match => add_broken_symlink(link.to_path_buf(), options, targets),
+            Err(_) => {}
+            Ok(_) if !options.follow_symlinks => {}
+            Ok(metadata) if metadata.is_file() && is_match => add_file(
+                link.to_path_buf(),
+                || Some(metadata.len()),
+                options,
+                targets,
+            ),
+            Ok(metadata) if metadata.is_dir() && remaining_depth != Some(0) => {
+                if options.one_file_system && self.device.is_some() && device(link) != self.device {
+                    return;
+                }
+                match directory_id(link) {
+                    Some(id) if !self.visited.insert(id) => targets.skipped.push((
+                        link.to_path_buf(),
+                        "links to a directory which is already scanned".to_string(),
+                    )),
+                    _ => self.walk_tree(link, remaining_depth, pattern, targets),
+                }
+            }
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Broken symlinks are skipped, or errors with `--strict`.
+fn add_broken_symlink(link: PathBuf, options: &WalkOptions, targets: &mut FileTargets) {
+    let reason = "broken symlink";
+    if options.strict {
+        targets
+            .errors
+            .push((link, Error::IoError(reason.to_string())));
+    } else {
+        targets.skipped.push((link, reason.to_string()));
+    }
+}
+
+fn is_symlink(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink())
+}
+
+fn add_walked_file(entry: DirEntry, options: &WalkOptions, targets: &mut FileTargets) {
+    let size = || entry.metadata().ok().map(|metadata| metadata.len());
+    add_file(entry.path().to_path_buf(), size, options, targets);
+}
+
+/// Add a regular file to the files to scan, unless its extension is not selected or it is too large.
+/// The size is only read if there is a maximum.
+fn add_file(
+    path: PathBuf,
+    size: impl FnOnce() -> Option<u64>,
+    options: &WalkOptions,
+    targets: &mut FileTargets,
+) {
+    if !options.extensions.is_selected(&path) {
+        return;
+    }
+    let skip_reason = options
+        .max_file_size
+        .and_then(|_| size())
+        .and_then(|size| options.skip_reason(size));
+    match skip_reason {
+        Some(reason) => targets.skipped.push((path, reason)),
+        None => targets.files.push(path),
+    }
+}
+
+/// Whether the entry is scanned, or descended into if it is a directory.
+fn is_walked(entry: &DirEntry, options: &WalkOptions) -> bool {
+    // The directory itself was given explicitly, so it is walked even if it is hidden.
+    if entry.depth() == 0 {
+        return true;
+    }
+    let hidden = options.skip_hidden && is_hidden(entry);
+    !hidden && !options.exclude.is_excluded(entry.path())
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Creates `a.txt`, `.hidden.txt`, `sub/b.txt`, `sub/deeper/c.txt` and `.git/config`.
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sub/deeper")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        for file in [
+            "a.txt",
+            ".hidden.txt",
+            "sub/b.txt",
+            "sub/deeper/c.txt",
+            ".git/config",
+        ] {
+            fs::write(dir.path().join(file), file).unwrap();
+        }
+        dir
+    }
+
+    fn relative(dir: &tempfile::TempDir, targets: &FileTargets) -> Vec<String> {
+        targets
+            .files
+            .iter()
+            .map(|f| {
+                let relative = f.strip_prefix(dir.path()).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recursive_walk_includes_hidden_files() {
+        let dir = tree();
+        let options = WalkOptions {
+            recursive: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+
+        assert_eq!(
+            vec![
+                ".git/config",
+                ".hidden.txt",
+                "a.txt",
+                "sub/b.txt",
+                "sub/deeper/c.txt"
+            ],
+            relative(&dir, &targets)
+        );
+        assert!(targets.errors.is_empty());
+    }
+
+    #[test]
+    fn recursive_walk_skips_hidden_files_and_directories() {
+        let dir = tree();
+        let options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+
+        assert_eq!(
+            vec!["a.txt", "sub/b.txt", "sub/deeper/c.txt"],
+            relative(&dir, &targets)
+        );
+    }
+
+    #[test]
+    fn recursive_walk_respects_max_depth() {
+        let dir = tree();
+        let options = WalkOptions {
+            recursive: true,
+            max_depth: Some(2),
+            skip_hidden: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+
+        assert_eq!(vec!["a.txt", "sub/b.txt"], relative(&dir, &targets));
+    }
+
+    #[test]
+    fn glob_is_expanded_without_excluded_files() {
+        let dir = tree();
+        fs::create_dir_all(dir.path().join("sub/node_modules/pkg")).unwrap();
+        fs::write(dir.path().join("sub/node_modules/pkg/d.txt"), "d").unwrap();
+        fs::write(dir.path().join("sub/b.log"), "log").unwrap();
+        let options = WalkOptions {
+            exclude: Excludes::new(["**/node_modules/**", "*.log", "**/deeper"]).unwrap(),
+            ..WalkOptions::default()
+        };
+
+        let pattern = dir.path().join("**/*");
+        let targets = collect_files(&[pattern], &options);
+
+        assert_eq!(
+            vec![".git/config", ".hidden.txt", "a.txt", "sub/b.txt"],
+            relative(&dir, &targets)
+        );
+        assert!(targets.warnings.is_empty());
+    }
+
+    #[test]
+    fn excludes_apply_to_recursion_and_explicit_files() {
+        let dir = tree();
+        let options = WalkOptions {
+            recursive: true,
+            exclude: Excludes::new(["*.txt"]).unwrap(),
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(
+            &[dir.path().to_path_buf(), dir.path().join("a.txt")],
+            &options,
+        );
+
+        assert_eq!(vec![".git/config"], relative(&dir, &targets));
+    }
+
+    #[test]
+    fn files_are_filtered_by_extension_and_skipped_by_size() {
+        let dir = tree();
+        fs::write(dir.path().join("sub/image.ISO"), "iso").unwrap();
+        fs::write(dir.path().join("sub/large.txt"), "x".repeat(2048)).unwrap();
+        let options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            extensions: Extensions::new(["txt", "iso"], ["iso"]),
+            max_file_size: Some(1024),
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(
+            &[dir.path().to_path_buf(), dir.path().join("sub/large.txt")],
+            &options,
+        );
+
+        assert_eq!(
+            vec!["a.txt", "sub/b.txt", "sub/deeper/c.txt"],
+            relative(&dir, &targets)
+        );
+        let large = dir.path().join("sub/large.txt");
+        let skipped = (large, "2.0 KiB is larger than 1.0 KiB".to_string());
+        assert_eq!(vec![skipped.clone(), skipped], targets.skipped);
+    }
+
+    #[test]
+    fn glob_without_match_is_a_warning() {
+        let dir = tree();
+        let pattern = dir.path().join("*.exe");
+
+        let targets = collect_files(std::slice::from_ref(&pattern), &WalkOptions::default());
+
+        assert!(targets.files.is_empty());
+        assert!(targets.errors.is_empty());
+        assert_eq!(
+            vec![format!(
+                "pattern {} did not match any files",
+                pattern.display()
+            )],
+            targets.warnings
+        );
+    }
+
+    #[test]
+    fn directory_without_recursive_and_missing_file_are_errors() {
+        let dir = tree();
+        let file = dir.path().join("a.txt");
+        let missing = dir.path().join("missing.txt");
+
+        let targets = collect_files(
+            &[dir.path().to_path_buf(), file.clone(), missing.clone()],
+            &WalkOptions::default(),
+        );
+
+        assert_eq!(vec![file], targets.files);
+        let errors: Vec<&PathBuf> = targets.errors.iter().map(|(path, _)| path).collect();
+        assert_eq!(vec![&dir.path().to_path_buf(), &missing], errors);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop_is_walked_once() {
+        let dir = tree();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("sub/loop")).unwrap();
+        let options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            follow_symlinks: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+
+        assert_eq!(
+            vec!["a.txt", "sub/b.txt", "sub/deeper/c.txt"],
+            relative(&dir, &targets)
+        );
+        let reason = "links to a directory which is already scanned".to_string();
+        assert_eq!(vec![(dir.path().join("sub/loop"), reason)], targets.skipped);
+        assert!(targets.errors.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_only_followed_if_enabled() {
+        let dir = tree();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("d.txt"), "d").unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("linked")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("a.txt"), dir.path().join("sub/a.lnk")).unwrap();
+        let mut options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+        assert_eq!(
+            vec!["a.txt", "sub/b.txt", "sub/deeper/c.txt"],
+            relative(&dir, &targets)
+        );
+
+        // Symlinks are followed after the rest of the tree was walked, so real directories come first.
+        options.follow_symlinks = true;
+        let targets = collect_files(&[dir.path().to_path_buf()], &options);
+        assert_eq!(
+            vec![
+                "a.txt",
+                "sub/b.txt",
+                "sub/deeper/c.txt",
+                "linked/d.txt",
+                "sub/a.lnk"
+            ],
+            relative(&dir, &targets)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_skipped_unless_strict() {
+        let dir = tree();
+        let broken = dir.path().join("sub/broken");
+        std::os::unix::fs::symlink(dir.path().join("gone"), &broken).unwrap();
+        let mut options = WalkOptions {
+            recursive: true,
+            skip_hidden: true,
+            ..WalkOptions::default()
+        };
+
+        let targets = collect_files(&[dir.path().to_path_buf(), broken.clone()], &options);
+        let skipped = (broken.clone(), "broken symlink".to_string());
+        assert_eq!(vec![skipped.clone(), skipped], targets.skipped);
+        assert!(targets.errors.is_empty());
+
+        options.strict = true;
+        let targets = collect_files(&[dir.path().to_path_buf(), broken.clone()], &options);
+        assert!(targets.skipped.is_empty());
+        let errors: Vec<&PathBuf> = targets.errors.iter().map(|(path, _)| path).collect();
+        assert_eq!(vec![&broken, &broken], errors);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+/// Read the entries of a list file, or of stdin if the path is `-`.
+pub fn read_list_file(path: &Path) -> io::Result<Vec<String>> {
+    read_list(open_list(path)?)
+}
+
+/// Open a list file, or stdin if the path is `-`.
+pub fn open_list(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+/// Read one entry per line. Blank lines and lines starting with `#` are skipped.
+/// Entries are taken verbatim otherwise, so paths may contain spaces.
+pub fn read_list(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        entries.push(line.to_string());
+    }
+    Ok(entries)
+}
+
+/// Remove repeated items, keeping the first occurrence of each.
+pub fn unique<T: Eq + Hash + Clone>(items: impl IntoIterator<Item = T>) -> Vec<T> {
+    let mut seen = HashSet::new();
+    items
+        .into_iter()
+        .filter(|item| seen.insert(item.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_lines_and_comments_are_skipped() {
+        let list = "# scanned nightly\n\
+                    /srv/a file with spaces.txt\n\
+                    \n   \n\
+                    \t# indented comment\r\n\
+                    /srv/b.txt\r\n";
+
+        let entries = read_list(list.as_bytes()).unwrap();
+
+        assert_eq!(vec!["/srv/a file with spaces.txt", "/srv/b.txt"], entries);
+    }
+
+    #[test]
+    fn unique_keeps_first_occurrence() {
+        assert_eq!(vec!["b", "a", "c"], unique(["b", "a", "b", "c", "a"]));
+    }
+}
@@ -1,118 +1,588 @@
-use clap::{crate_authors, crate_description, crate_name, crate_version, Arg, ArgAction, Command};
-use reqwest::Url;
-use std::{collections::HashMap, path::PathBuf, str::FromStr};
-use vaas::{
-    auth::authenticators::ClientCredentials, error::VResult, CancellationToken, Connection, Vaas,
-    VaasVerdict,
-};
-
-#[tokio::main]
-async fn main() -> VResult<()> {
-    let matches = Command::new(crate_name!())
-        .version(crate_version!())
-        .author(crate_authors!())
-        .about(crate_description!())
-        .arg(
-            Arg::new("files")
-                .short('f')
-                .long("files")
-                .required_unless_present("urls")
-                .action(ArgAction::Append)
-                .help("List of files to scan separated by whitepace"),
-        )
-        .arg(
-            Arg::new("urls")
-                .short('u')
-                .long("urls")
-                .action(ArgAction::Append)
-                .required_unless_present("files")
-                .help("List of urls to scan separated by whitepace"),
-        )
-        .arg(
-            Arg::new("client_id")
-                .short('i')
-                .long("client_id")
-                .env("CLIENT_ID")
-                .action(ArgAction::Set)
-                .help("Set your vaas username"),
-        )
-        .arg(
-            Arg::new("client_secret")
-                .short('s')
-                .long("client_secret")
-                .env("CLIENT_SECRET")
-                .action(ArgAction::Set)
-                .help("Set your vaas password"),
-        )
-        .get_matches();
-
-    let files = matches
-        .get_many::<String>("files")
-        .unwrap_or_default()
-        .map(|f| PathBuf::from_str(f).unwrap_or_else(|_| panic!("Not a valid file path: {}", f)))
-        .collect::<Vec<PathBuf>>();
-
-    let urls = matches
-        .get_many::<String>("urls")
-        .unwrap_or_default()
-        .map(|f| Url::parse(f).unwrap_or_else(|_| panic!("Not a valid url: {}", f)))
-        .collect::<Vec<Url>>();
-
-    let client_id = matches
-        .get_one::<String>("client_id")
-        .expect("--client_id or the enviroment variable CLIENT_ID must be set");
-    let client_secret = matches
-        .get_one::<String>("client_secret")
-        .expect("--client_secret or the enviroment variable CLIENT_SECRET must be set");
-
-    let authenticator = ClientCredentials::new(client_id.to_owned(), client_secret.to_owned());
-    let vaas_connection = Vaas::builder(authenticator).build()?.connect().await?;
-
-    let file_verdicts = scan_files(&files, &vaas_connection).await?;
-    let url_verdicts = scan_urls(&urls, &vaas_connection).await?;
-
-    file_verdicts
-        .iter()
-        .for_each(|(f, v)| print_verdicts(f.display().to_string(), v));
-
-    url_verdicts.iter().for_each(|(u, v)| print_verdicts(u, v));
-
-    Ok(())
-}
-
-fn print_verdicts<I: AsRef<str>>(i: I, v: &VResult<VaasVerdict>) {
-    print!("{} -> ", i.as_ref());
-    match v {
-        Ok(v) => {
-            println!("{}", v.verdict);
-        }
-        Err(e) => {
-            println!("{}", e.to_string());
-        }
-    };
-}
-
-async fn scan_files<'a>(
-    files: &'a [PathBuf],
-    vaas_connection: &Connection,
-) -> VResult<Vec<(&'a PathBuf, VResult<VaasVerdict>)>> {
-    let ct = CancellationToken::from_minutes(1);
-    let verdicts = vaas_connection.for_file_list(files, &ct).await;
-    let results = files.iter().zip(verdicts).collect();
-
-    Ok(results)
-}
-
-async fn scan_urls(
-    urls: &[Url],
-    vaas_connection: &Connection,
-) -> VResult<HashMap<Url, Result<VaasVerdict, vaas::error::Error>>> {
-    let ct = CancellationToken::from_minutes(1);
-    let mut verdicts = HashMap::new();
-    for url in urls {
-        let verdict = vaas_connection.for_url(url, &ct).await;
-        verdicts.insert(url.to_owned(), verdict);
-    }
-
-    Ok(verdicts)
-}
+mod cli;
+mod config;
+mod exit_code;
+mod files;
+mod lists;
+mod output;
+mod patterns;
+mod report;
+mod report_file;
+mod scan;
+#[cfg(feature = "serve")]
+mod serve;
+mod size;
+mod watch;
+
+use clap::ArgMatches;
+use clap_complete::Shell;
+use cli::{cli, load_env_files};
+use exit_code::{exit_code, FailOn};
+use files::{collect_files, WalkOptions};
+use lists::{open_list, read_list_file, unique};
+use output::{OutputFormat, ScanResult, TargetType, TextStyle};
+use patterns::{Excludes, Extensions};
+use report::{summary, ReportOptions, Reporter};
+use report_file::{write_report, ReportFormat, Run};
+use reqwest::Url;
+use scan::{retry, Dedupe, Limits, RateLimiter, DEFAULT_CONCURRENCY};
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{IsTerminal, Read};
+use std::time::{Instant, SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+};
+use vaas::{
+    auth::{
+        authenticators::{ClientCredentials, Password, StaticToken},
+        Authenticator,
+    },
+    cancellation::CancellationToken,
+    error::{Error, VResult},
+    LazyConnection, Sha256, Vaas,
+};
+use watch::WatchOptions;
+
+/// The client id for logging in with `--user_name` and `--password` if none is given.
+pub const DEFAULT_PASSWORD_CLIENT_ID: &str = "vaas-customer";
+
+/// The name of the content piped to gscan with `-f -` in the results.
+const STDIN: &str = "<stdin>";
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    load_env_files(args.iter().cloned());
+    let args = match config::with_config_args(args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::from(exit_code::ERROR);
+        }
+    };
+    let matches = cli().get_matches_from(args);
+    let scan_matches = match matches.subcommand() {
+        Some(("completions", completions)) => {
+            let shell = completions.get_one::<Shell>("shell").copied();
+            cli::write_completions(
+                shell.expect("the shell is required"),
+                &mut std::io::stdout(),
+            );
+            return ExitCode::SUCCESS;
+        }
+        Some(("man", _)) => {
+            return match cli::write_man_page(&mut std::io::stdout()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::from(exit_code::ERROR)
+                }
+            };
+        }
+        Some(("config", config)) => {
+            let init = config.subcommand_matches("init").expect("init is required");
+            let path = init
+                .get_one::<PathBuf>("path")
+                .expect("the path has a default");
+            return match config::init(path, init.get_flag("force")) {
+                Ok(()) => {
+                    eprintln!("Wrote {}", path.display());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::from(exit_code::ERROR)
+                }
+            };
+        }
+        #[cfg(feature = "serve")]
+        Some(("serve", serve)) => {
+            return match run_server(serve).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("{e}");
+                    ExitCode::from(exit_code::ERROR)
+                }
+            };
+        }
+        #[cfg(not(feature = "serve"))]
+        Some(("serve", _)) => {
+            eprintln!(
+                "gscan was built without the serve feature, rebuild it with --features serve"
+            );
+            return ExitCode::from(exit_code::ERROR);
+        }
+        Some(("scan", scan)) => scan,
+        _ => &matches,
+    };
+
+    match run(scan_matches).await {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(exit_code::ERROR)
+        }
+    }
+}
+
+async fn run(matches: &ArgMatches) -> VResult<u8> {
+    let files_from = matches.get_one::<PathBuf>("files-from");
+    let urls_from = matches.get_one::<PathBuf>("urls-from");
+    let hashes_from = matches.get_one::<PathBuf>("hashes-from");
+    let from_stdin = [files_from, urls_from, hashes_from]
+        .into_iter()
+        .flatten()
+        .filter(|list| *list == Path::new("-"))
+        .count();
+    if from_stdin > 1 {
+        return Err(Error::InvalidConfig(
+            "only one of --files-from, --urls-from and --hashes-from can read from stdin"
+                .to_string(),
+        ));
+    }
+
+    // `-f -` scans the content piped to gscan.
+    let stdin_files = matches
+        .get_many::<String>("files")
+        .unwrap_or_default()
+        .filter(|file| *file == "-")
+        .count();
+    if stdin_files > 1 {
+        return Err(Error::InvalidConfig(
+            "-f - can only be given once".to_string(),
+        ));
+    }
+    let scan_stdin = stdin_files == 1;
+    if scan_stdin && from_stdin > 0 {
+        return Err(Error::InvalidConfig(
+            "-f - cannot be combined with a list read from stdin".to_string(),
+        ));
+    }
+
+    let listed_files = match files_from {
+        Some(list) => read_list(list)?,
+        None => Vec::new(),
+    };
+    let files = unique(
+        matches
+            .get_many::<String>("files")
+            .unwrap_or_default()
+            .filter(|file| *file != "-")
+            .cloned()
+            .chain(listed_files)
+            .map(|f| {
+                PathBuf::from_str(&f).unwrap_or_else(|_| panic!("Not a valid file path: {}", f))
+            }),
+    );
+    let walk_options = WalkOptions {
+        recursive: matches.get_flag("recursive"),
+        max_depth: matches.get_one::<usize>("max-depth").copied(),
+        skip_hidden: matches.get_flag("skip-hidden"),
+        follow_symlinks: matches.get_flag("follow-symlinks"),
+        one_file_system: matches.get_flag("one-file-system"),
+        strict: matches.get_flag("strict"),
+        exclude: Excludes::new(
+            matches
+                .get_many::<String>("exclude")
+                .unwrap_or_default()
+                .map(String::as_str),
+        )?,
+        extensions: Extensions::new(
+            matches
+                .get_many::<String>("include-ext")
+                .unwrap_or_default()
+                .map(String::as_str),
+            matches
+                .get_many::<String>("exclude-ext")
+                .unwrap_or_default()
+                .map(String::as_str),
+        ),
+        max_file_size: matches.get_one::<u64>("max-file-size").copied(),
+    };
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+    if matches.get_flag("watch") {
+        if scan_stdin {
+            return Err(Error::InvalidConfig("stdin cannot be watched".to_string()));
+        }
+        let options = WatchOptions {
+            walk: walk_options,
+            quarantine: quarantine_dir(matches)?,
+            retries: retries(matches, "retries"),
+            timeout: timeout(matches),
+        };
+        let paths = files
+            .iter()
+            .map(|path| {
+                path.canonicalize()
+                    .map_err(|e| Error::IoError(format!("{}: {e}", path.display())))
+            })
+            .collect::<VResult<Vec<_>>>()?;
+        // There is no known total to show the progress of.
+        let report_options = ReportOptions {
+            show_progress: false,
+            ..report_options(matches)
+        };
+        let mut reporter = Reporter::new(std::io::stdout(), report_options, 0);
+        let connection = connect(matches).await?;
+        let results = watch::watch(&paths, &options, &connection, &mut reporter).await?;
+        return finish(matches, reporter, results, None, started, started_at);
+    }
+
+    let mut stdin_content = if scan_stdin {
+        Some(read_stdin()?)
+    } else {
+        None
+    };
+    let mut stdin_skipped = None;
+    if let Some(reason) = stdin_content
+        .as_ref()
+        .and_then(|content| walk_options.skip_reason(content.len() as u64))
+    {
+        stdin_content = None;
+        stdin_skipped = Some(ScanResult::skipped(STDIN, TargetType::File, reason));
+    }
+
+    let mut file_targets = collect_files(&files, &walk_options);
+    for warning in &file_targets.warnings {
+        eprintln!("Warning: {warning}");
+    }
+    // A file may also be part of a directory given on the command line.
+    file_targets.files = unique(file_targets.files);
+    file_targets.skipped = unique(file_targets.skipped);
+
+    let listed_urls = match urls_from {
+        Some(list) => read_list(list)?,
+        None => Vec::new(),
+    };
+    let mut urls = Vec::new();
+    let mut invalid_urls = Vec::new();
+    let all_urls = matches
+        .get_many::<String>("urls")
+        .unwrap_or_default()
+        .cloned()
+        .chain(listed_urls.iter().map(|u| u.trim().to_string()));
+    for url in unique(all_urls) {
+        match Url::parse(&url) {
+            Ok(parsed) => urls.push(parsed),
+            Err(e) => invalid_urls.push(ScanResult::new(
+                url,
+                TargetType::Url,
+                Err(Error::InvalidConfig(format!("not a valid url: {e}"))),
+            )),
+        }
+    }
+    // Different spellings may parse to the same url.
+    let urls = unique(urls);
+
+    let mut hashes = Vec::new();
+    let mut invalid_hashes = Vec::new();
+    for hash in unique(matches.get_many::<String>("hashes").unwrap_or_default()) {
+        match Sha256::try_from(hash.as_str()) {
+            Ok(sha256) => hashes.push(sha256),
+            Err(e) => invalid_hashes.push(ScanResult::new(hash, TargetType::Sha256, Err(e))),
+        }
+    }
+    if let Some(list) = hashes_from {
+        // Invalid lines are labeled with their position in the list.
+        let (hashes_listed, invalid_listed) = read_hash_list(list)?;
+        hashes.extend(hashes_listed);
+        invalid_hashes.extend(invalid_listed);
+    }
+    let hashes = unique(hashes);
+
+    let total = usize::from(scan_stdin)
+        + file_targets.errors.len()
+        + file_targets.skipped.len()
+        + file_targets.files.len()
+        + urls.len()
+        + invalid_urls.len()
+        + hashes.len()
+        + invalid_hashes.len();
+    let mut reporter = Reporter::new(std::io::stdout(), report_options(matches), total);
+
+    let mut results = stdin_skipped
+        .into_iter()
+        .chain(
+            file_targets
+                .errors
+                .into_iter()
+                .map(|(f, e)| ScanResult::new(f.display().to_string(), TargetType::File, Err(e))),
+        )
+        .chain(file_targets.skipped.into_iter().map(|(f, reason)| {
+            ScanResult::skipped(f.display().to_string(), TargetType::File, reason)
+        }))
+        .chain(invalid_urls)
+        .chain(invalid_hashes)
+        .collect::<Vec<_>>();
+    results.iter().for_each(|result| reporter.report(result));
+
+    let mut dedupe = None;
+    // Without anything to scan, e.g. because all files are missing, there is no need to connect.
+    if stdin_content.is_some()
+        || !file_targets.files.is_empty()
+        || !urls.is_empty()
+        || !hashes.is_empty()
+    {
+        let vaas_connection = connect(matches).await?;
+        let timeout = timeout(matches);
+        let limits = Limits {
+            concurrency: matches
+                .get_one::<u32>("concurrency")
+                .map_or(DEFAULT_CONCURRENCY, |&c| c as usize),
+            rate: matches.get_one::<u32>("rate").map(|&r| RateLimiter::new(r)),
+            retries: retries(matches, "retries"),
+        };
+        if let Some(content) = &stdin_content {
+            let result = scan::scan_content(STDIN, content, &limits, |content| {
+                vaas_connection.for_buf(content, timeout.as_ref())
+            })
+            .await;
+            reporter.report(&result);
+            results.push(result);
+        }
+        let file_results = if matches.get_flag("no-dedupe") {
+            scan::scan_files(
+                &file_targets.files,
+                &limits,
+                |file| vaas_connection.for_file(file, timeout.as_ref()),
+                |result| reporter.report(result),
+            )
+            .await
+        } else {
+            let (file_results, files) = scan::scan_unique_files(
+                &file_targets.files,
+                &limits,
+                Sha256::from_file,
+                |file| vaas_connection.for_file(file, timeout.as_ref()),
+                |result| reporter.report(result),
+            )
+            .await;
+            dedupe = Some(files);
+            file_results
+        };
+        let url_results = scan::scan_urls(
+            &urls,
+            &limits,
+            |url| vaas_connection.for_url(url, timeout.as_ref()),
+            |result| reporter.report(result),
+        )
+        .await;
+        let hash_results = scan::scan_hashes(
+            &hashes,
+            |hashes| vaas_connection.for_sha256_list(hashes, timeout.as_ref()),
+            |result| reporter.report(result),
+        )
+        .await;
+        results.extend(file_results);
+        results.extend(url_results);
+        results.extend(hash_results);
+    }
+    finish(matches, reporter, results, dedupe, started, started_at)
+}
+
+/// Print the summary, write the report file and return the exit code for the results.
+fn finish(
+    matches: &ArgMatches,
+    reporter: Reporter<std::io::Stdout>,
+    results: Vec<ScanResult>,
+    dedupe: Option<Dedupe>,
+    started: Instant,
+    started_at: SystemTime,
+) -> VResult<u8> {
+    let counts = reporter.finish(&results)?;
+    eprintln!("{}", summary(&counts, dedupe, started.elapsed()));
+
+    if let Some(path) = matches.get_one::<PathBuf>("report") {
+        let format = matches
+            .get_one::<ReportFormat>("report-format")
+            .copied()
+            .unwrap_or_default();
+        let run = Run {
+            results: &results,
+            started: started_at,
+            finished: SystemTime::now(),
+            dedupe,
+        };
+        let file = std::fs::File::create(path)
+            .map_err(|e| Error::IoError(format!("{}: {e}", path.display())))?;
+        write_report(std::io::BufWriter::new(file), format, &run)?;
+    }
+
+    let fail_on = matches
+        .get_one::<FailOn>("fail-on")
+        .copied()
+        .unwrap_or_default();
+    Ok(exit_code(&results, fail_on))
+}
+
+fn report_options(matches: &ArgMatches) -> ReportOptions {
+    let quiet = matches.get_flag("quiet");
+    ReportOptions {
+        format: matches
+            .get_one::<OutputFormat>("output-format")
+            .copied()
+            .unwrap_or_default(),
+        style: TextStyle {
+            verbose: matches.get_flag("verbose"),
+            color: use_color(matches),
+        },
+        show_progress: !quiet
+            && !matches.get_flag("no-progress")
+            && std::io::stderr().is_terminal(),
+        only_findings: matches.get_flag("only-findings"),
+        quiet,
+    }
+}
+
+/// Verdicts are colored on a terminal, unless disabled with `--no-color` or the `NO_COLOR` convention.
+fn use_color(matches: &ArgMatches) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && !matches.get_flag("no-color") && std::io::stdout().is_terminal()
+}
+
+type DynVaas = Vaas<Box<dyn Authenticator + Send + Sync>>;
+
+fn vaas(matches: &ArgMatches) -> VResult<DynVaas> {
+    let client_id = matches.get_one::<String>("client_id");
+    let authenticator: Box<dyn Authenticator + Send + Sync> = match (
+        matches.get_one::<String>("token"),
+        matches.get_one::<String>("user_name"),
+        matches.get_one::<String>("password"),
+        matches.get_one::<String>("client_secret"),
+    ) {
+        (Some(token), _, _, _) => Box::new(StaticToken::new(token.to_owned())),
+        (None, Some(user_name), Some(password), _) => Box::new(Password::new(
+            client_id
+                .map_or(DEFAULT_PASSWORD_CLIENT_ID, String::as_str)
+                .to_owned(),
+            user_name.to_owned(),
+            password.to_owned(),
+        )),
+        (None, _, _, Some(client_secret)) => Box::new(ClientCredentials::new(
+            client_id.cloned().unwrap_or_default(),
+            client_secret.to_owned(),
+        )),
+        _ => return Vaas::from_env(),
+    };
+    Vaas::builder(authenticator).build()
+}
+
+/// Connect now rather than on the first scan, so an unreachable VaaS or invalid credentials are reported once
+/// instead of for every item. Scans establish a new connection if VaaS closes this one.
+async fn connect(matches: &ArgMatches) -> VResult<LazyConnection> {
+    let allowlist = read_hash_file(matches, "allowlist-file")?;
+    let blocklist = read_hash_file(matches, "blocklist-file")?;
+    let connection = vaas(matches)?.lazy();
+    let (connected, _) = retry(retries(matches, "connect-retries"), || {
+        connection.connection()
+    })
+    .await;
+    // The lists are shared by all connections of the instance, including the ones after a reconnect.
+    let connected = connected?;
+    allowlist
+        .into_iter()
+        .for_each(|sha256| connected.add_to_allowlist(sha256));
+    blocklist
+        .into_iter()
+        .for_each(|sha256| connected.add_to_blocklist(sha256));
+    Ok(connection)
+}
+
+/// The hashes of `--allowlist-file` or `--blocklist-file`. An invalid line fails the scan, so a typo does not
+/// go unnoticed.
+fn read_hash_file(matches: &ArgMatches, arg: &str) -> VResult<Vec<Sha256>> {
+    let Some(path) = matches.get_one::<PathBuf>(arg) else {
+        return Ok(Vec::new());
+    };
+    let with_path = |e: &dyn std::fmt::Display| format!("--{arg} {}: {e}", path.display());
+    let list = open_list(path).map_err(|e| Error::IoError(with_path(&e)))?;
+    Sha256::parse_list_strict(list).map_err(|e| match e {
+        Error::IoError(e) => Error::IoError(with_path(&e)),
+        e => Error::InvalidConfig(with_path(&e)),
+    })
+}
+
+#[cfg(feature = "serve")]
+async fn run_server(matches: &ArgMatches) -> VResult<()> {
+    let listen = *matches
+        .get_one::<std::net::SocketAddr>("listen")
+        .expect("the address has a default");
+    let max_body_size = *matches
+        .get_one::<u64>("max-body-size")
+        .expect("the size has a default");
+    let options = serve::ServeOptions {
+        concurrency: matches
+            .get_one::<u32>("concurrency")
+            .map_or(DEFAULT_CONCURRENCY, |&c| c as usize),
+        max_body_size: usize::try_from(max_body_size).unwrap_or(usize::MAX),
+    };
+    let scanner = serve::VaasScanner {
+        connection: connect(matches).await?,
+        timeout: timeout(matches),
+    };
+    serve::serve(listen, scanner, options).await
+}
+
+fn retries(matches: &ArgMatches, arg: &str) -> u32 {
+    matches.get_one::<u32>(arg).copied().unwrap_or_default()
+}
+
+fn timeout(matches: &ArgMatches) -> Option<CancellationToken> {
+    matches
+        .get_one::<u64>("timeout")
+        .map(|&seconds| CancellationToken::from_seconds(seconds))
+}
+
+/// The quarantine directory for `--move-malicious`, created if it does not exist yet.
+fn quarantine_dir(matches: &ArgMatches) -> VResult<Option<PathBuf>> {
+    let Some(dir) = matches.get_one::<PathBuf>("move-malicious") else {
+        return Ok(None);
+    };
+    // Events report absolute paths, so files inside the quarantine can be recognized.
+    std::fs::create_dir_all(dir)
+        .and_then(|_| dir.canonicalize())
+        .map(Some)
+        .map_err(|e| Error::IoError(format!("{}: {e}", dir.display())))
+}
+
+/// Read everything piped to gscan, which may be binary.
+fn read_stdin() -> VResult<Vec<u8>> {
+    let mut content = Vec::new();
+    std::io::stdin()
+        .lock()
+        .read_to_end(&mut content)
+        .map_err(|e| Error::IoError(format!("{STDIN}: {e}")))?;
+    Ok(content)
+}
+
+/// Parse a list of hashes, which may be the output of `sha256sum`. Invalid lines become
+/// results, errors reading the list are returned.
+fn read_hash_list(list: &Path) -> VResult<(Vec<Sha256>, Vec<ScanResult>)> {
+    let io_error = |e: &dyn std::fmt::Display| Error::IoError(format!("{}: {e}", list.display()));
+    let label = if list == Path::new("-") {
+        STDIN.to_string()
+    } else {
+        list.display().to_string()
+    };
+    let mut hashes = Vec::new();
+    let mut invalid = Vec::new();
+    for (line, result) in Sha256::parse_list(open_list(list).map_err(|e| io_error(&e))?) {
+        match result {
+            Ok(sha256) => hashes.push(sha256),
+            Err(Error::IoError(e)) => return Err(io_error(&e)),
+            Err(e) => invalid.push(ScanResult::new(
+                format!("{label}:{line}"),
+                TargetType::Sha256,
+                Err(e),
+            )),
+        }
+    }
+    Ok((hashes, invalid))
+}
+
+fn read_list(list: &Path) -> VResult<Vec<String>> {
+    read_list_file(list).map_err(|e| Error::IoError(format!("{}: {e}", list.display())))
+}
@@ -0,0 +1,587 @@
+use crate::size::format_size;
+use anstyle::{AnsiColor, Style};
+use clap::builder::PossibleValue;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
+use vaas::error::{Error, VResult};
+use vaas::message::Verdict;
+use vaas::VaasVerdict;
+
+/// How the scan results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// One `target -> verdict` line per result.
+    #[default]
+    Text,
+    /// A JSON array with one object per result.
+    Json,
+    /// One JSON object per line and result.
+    Ndjson,
+}
+
+impl ValueEnum for OutputFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Text, Self::Json, Self::Ndjson]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Self::Text => PossibleValue::new("text"),
+            Self::Json => PossibleValue::new("json"),
+            Self::Ndjson => PossibleValue::new("ndjson"),
+        })
+    }
+}
+
+/// The kind of a scanned item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetType {
+    File,
+    Url,
+    Sha256,
+}
+
+/// What came out of scanning an item.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Verdict(VaasVerdict),
+    Error(Error),
+    /// The item was left out on purpose, e.g. because it is too large. The reason says why.
+    Skipped(String),
+}
+
+impl From<VResult<VaasVerdict>> for Outcome {
+    fn from(result: VResult<VaasVerdict>) -> Self {
+        match result {
+            Ok(verdict) => Self::Verdict(verdict),
+            Err(e) => Self::Error(e),
+        }
+    }
+}
+
+/// The verdict or error for one scanned item.
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub target: String,
+    pub target_type: TargetType,
+    pub outcome: Outcome,
+    /// How long the request to VaaS took, if the item was scanned on its own.
+    pub duration: Option<Duration>,
+    /// When the result was available.
+    pub scanned_at: SystemTime,
+    /// How often the scan was attempted, more than once if transient errors were retried.
+    pub attempts: u32,
+    /// The size of the scanned file or content in bytes.
+    pub size: Option<u64>,
+}
+
+impl ScanResult {
+    pub fn new(
+        target: impl Into<String>,
+        target_type: TargetType,
+        result: VResult<VaasVerdict>,
+    ) -> Self {
+        Self::with_outcome(target, target_type, result.into())
+    }
+
+    /// A result for an item which was not scanned on purpose.
+    pub fn skipped(
+        target: impl Into<String>,
+        target_type: TargetType,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::with_outcome(target, target_type, Outcome::Skipped(reason.into()))
+    }
+
+    fn with_outcome(target: impl Into<String>, target_type: TargetType, outcome: Outcome) -> Self {
+        Self {
+            target: target.into(),
+            target_type,
+            outcome,
+            duration: None,
+            scanned_at: SystemTime::now(),
+            attempts: 1,
+            size: None,
+        }
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Whether the result needs attention, i.e. it is neither clean nor skipped.
+    pub fn is_finding(&self) -> bool {
+        match &self.outcome {
+            Outcome::Verdict(v) => v.verdict != Verdict::Clean,
+            Outcome::Error(_) => true,
+            Outcome::Skipped(_) => false,
+        }
+    }
+
+    /// The verdict, if the item was scanned successfully.
+    pub fn verdict(&self) -> Option<&VaasVerdict> {
+        match &self.outcome {
+            Outcome::Verdict(v) => Some(v),
+            Outcome::Error(_) | Outcome::Skipped(_) => None,
+        }
+    }
+}
+
+/// How results are printed in the text format.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextStyle {
+    /// Include the SHA256 and the duration of the scan.
+    pub verbose: bool,
+    /// Color the verdicts with ANSI escape codes.
+    pub color: bool,
+}
+
+impl TextStyle {
+    fn paint(&self, color: AnsiColor, text: &str) -> String {
+        if self.color {
+            let style = Style::new().fg_color(Some(color.into()));
+            format!("{style}{text}{style:#}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// The JSON representation of a [`ScanResult`]. All fields are always present so consumers can rely on the schema.
+#[derive(Serialize)]
+pub struct ResultRecord<'a> {
+    pub target: &'a str,
+    pub target_type: TargetType,
+    pub sha256: Option<String>,
+    pub verdict: Option<&'a str>,
+    pub detection: Option<&'a str>,
+    pub error: Option<ErrorRecord>,
+    /// Why the item was not scanned.
+    pub skipped: Option<&'a str>,
+    pub attempts: u32,
+    pub duration_ms: Option<u128>,
+}
+
+#[derive(Serialize)]
+pub struct ErrorRecord {
+    pub kind: String,
+    pub message: String,
+}
+
+impl<'a> From<&'a ScanResult> for ResultRecord<'a> {
+    fn from(result: &'a ScanResult) -> Self {
+        let mut record = Self {
+            target: &result.target,
+            target_type: result.target_type,
+            sha256: None,
+            verdict: None,
+            detection: None,
+            error: None,
+            skipped: None,
+            attempts: result.attempts,
+            duration_ms: result.duration.map(|d| d.as_millis()),
+        };
+        match &result.outcome {
+            Outcome::Verdict(verdict) => {
+                record.sha256 = Some(verdict.sha256.to_string());
+                record.verdict = Some(verdict_name(&verdict.verdict));
+                record.detection = match &verdict.verdict {
+                    Verdict::Malicious { detection } | Verdict::Pup { detection } => {
+                        Some(detection)
+                    }
+                    _ => None,
+                };
+            }
+            Outcome::Error(e) => record.error = Some(ErrorRecord::from(e)),
+            Outcome::Skipped(reason) => record.skipped = Some(reason),
+        }
+        record
+    }
+}
+
+impl From<&Error> for ErrorRecord {
+    fn from(error: &Error) -> Self {
+        Self {
+            kind: error_kind(error),
+            message: error.to_string(),
+        }
+    }
+}
+
+fn verdict_name(verdict: &Verdict) -> &str {
+    match verdict {
+        Verdict::Clean => "Clean",
+        Verdict::Malicious { .. } => "Malicious",
+        Verdict::Pup { .. } => "Pup",
+        Verdict::Unknown { .. } => "Unknown",
+        Verdict::Other(name) => name,
+        // A category the SDK knows, but this version of gscan does not.
+        _ => "Other",
+    }
+}
+
+/// The name of the error variant, e.g. `IoError`. The error enum is non-exhaustive,
+/// so the name is taken from the `Debug` representation instead of matching all variants.
+fn error_kind(error: &Error) -> String {
+    let debug = format!("{error:?}");
+    debug
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Write the results in the given format.
+pub fn write_results(
+    out: &mut impl Write,
+    format: OutputFormat,
+    style: &TextStyle,
+    results: &[&ScanResult],
+) -> io::Result<()> {
+    if format == OutputFormat::Json {
+        let records: Vec<ResultRecord> = results.iter().map(|r| ResultRecord::from(*r)).collect();
+        serde_json::to_writer_pretty(&mut *out, &records)?;
+        return writeln!(out);
+    }
+    for result in results {
+        write_result(out, format, style, result)?;
+    }
+    Ok(())
+}
+
+/// Write a single result as a line of text or NDJSON. A single result is written
+/// as a JSON array with one element, so use [`write_results`] for JSON instead.
+pub fn write_result(
+    out: &mut impl Write,
+    format: OutputFormat,
+    style: &TextStyle,
+    result: &ScanResult,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => write_text(out, style, result),
+        OutputFormat::Json => write_results(out, format, style, &[result]),
+        OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut *out, &ResultRecord::from(result))?;
+            writeln!(out)
+        }
+    }
+}
+
+fn write_text(out: &mut impl Write, style: &TextStyle, result: &ScanResult) -> io::Result<()> {
+    let (outcome, sha256) = match &result.outcome {
+        Outcome::Verdict(v) => {
+            let color = match v.verdict {
+                Verdict::Clean => AnsiColor::Green,
+                Verdict::Malicious { .. } => AnsiColor::Red,
+                _ => AnsiColor::Yellow,
+            };
+            (style.paint(color, &v.verdict.to_string()), Some(&v.sha256))
+        }
+        Outcome::Error(e) => (style.paint(AnsiColor::Red, &e.to_string()), None),
+        Outcome::Skipped(reason) => (
+            style.paint(AnsiColor::BrightBlack, &format!("Skipped ({reason})")),
+            None,
+        ),
+    };
+    write!(out, "{} -> {outcome}", result.target)?;
+    if matches!(result.outcome, Outcome::Error(_)) && result.attempts > 1 {
+        write!(out, " (after {} attempts)", result.attempts)?;
+    }
+    if style.verbose {
+        let timing = result.verdict().and_then(|verdict| verdict.timing);
+        let upload = timing.and_then(|t| Some((t.upload_duration?, t.uploaded_bytes?)));
+        let details: Vec<String> = sha256
+            .map(|sha256| format!("sha256 {sha256}"))
+            .into_iter()
+            .chain(result.duration.map(|d| format!("{} ms", d.as_millis())))
+            .chain(timing.map(|t| format!("lookup {} ms", t.lookup_duration().as_millis())))
+            .chain(upload.map(|(duration, bytes)| {
+                format!(
+                    "upload {} in {} ms",
+                    format_size(bytes),
+                    duration.as_millis()
+                )
+            }))
+            .collect();
+        if !details.is_empty() {
+            write!(out, " ({})", details.join(", "))?;
+        }
+    }
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::convert::TryFrom;
+    use vaas::sha256::Sha256;
+    use vaas::ScanTiming;
+
+    const SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+    fn verdict(verdict: Verdict) -> VResult<VaasVerdict> {
+        Ok(VaasVerdict::new(Sha256::try_from(SHA256).unwrap(), verdict))
+    }
+
+    fn results() -> Vec<ScanResult> {
+        vec![
+            ScanResult::new("clean.txt", TargetType::File, verdict(Verdict::Clean))
+                .with_duration(Duration::from_millis(12)),
+            ScanResult::new(
+                "https://example.com/eicar.com",
+                TargetType::Url,
+                verdict(Verdict::Malicious {
+                    detection: "EICAR-Test-File".to_string(),
+                }),
+            ),
+            ScanResult::new(
+                SHA256,
+                TargetType::Sha256,
+                verdict(Verdict::Pup {
+                    detection: "Adware".to_string(),
+                }),
+            ),
+            ScanResult::new(
+                "missing.txt",
+                TargetType::File,
+                Err(Error::IoError("No such file or directory".to_string())),
+            ),
+            ScanResult::skipped("image.iso", TargetType::File, "larger than 500.0 MiB"),
+        ]
+    }
+
+    fn expected() -> Vec<Value> {
+        vec![
+            json!({
+                "target": "clean.txt",
+                "target_type": "file",
+                "sha256": SHA256,
+                "verdict": "Clean",
+                "detection": null,
+                "error": null,
+                "skipped": null,
+                "attempts": 1,
+                "duration_ms": 12,
+            }),
+            json!({
+                "target": "https://example.com/eicar.com",
+                "target_type": "url",
+                "sha256": SHA256,
+                "verdict": "Malicious",
+                "detection": "EICAR-Test-File",
+                "error": null,
+                "skipped": null,
+                "attempts": 1,
+                "duration_ms": null,
+            }),
+            json!({
+                "target": SHA256,
+                "target_type": "sha256",
+                "sha256": SHA256,
+                "verdict": "Pup",
+                "detection": "Adware",
+                "error": null,
+                "skipped": null,
+                "attempts": 1,
+                "duration_ms": null,
+            }),
+            json!({
+                "target": "missing.txt",
+                "target_type": "file",
+                "sha256": null,
+                "verdict": null,
+                "detection": null,
+                "error": {
+                    "kind": "IoError",
+                    "message": "IO Error: `No such file or directory`",
+                },
+                "skipped": null,
+                "attempts": 1,
+                "duration_ms": null,
+            }),
+            json!({
+                "target": "image.iso",
+                "target_type": "file",
+                "sha256": null,
+                "verdict": null,
+                "detection": null,
+                "error": null,
+                "skipped": "larger than 500.0 MiB",
+                "attempts": 1,
+                "duration_ms": null,
+            }),
+        ]
+    }
+
+    fn write(format: OutputFormat) -> String {
+        write_styled(format, &TextStyle::default())
+    }
+
+    fn write_styled(format: OutputFormat, style: &TextStyle) -> String {
+        let results = results();
+        let results: Vec<&ScanResult> = results.iter().collect();
+        let mut out = Vec::new();
+        write_results(&mut out, format, style, &results).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json_is_an_array_of_results() {
+        let output: Value = serde_json::from_str(&write(OutputFormat::Json)).unwrap();
+
+        assert_eq!(Value::Array(expected()), output);
+    }
+
+    #[test]
+    fn ndjson_is_one_result_per_line() {
+        let output = write(OutputFormat::Ndjson);
+        let lines: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(expected(), lines);
+    }
+
+    #[test]
+    fn text_is_one_line_per_result() {
+        assert_eq!(
+            "clean.txt -> Clean\n\
+             https://example.com/eicar.com -> Malicious { detection: \"EICAR-Test-File\" }\n\
+             275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f -> Pup { detection: \"Adware\" }\n\
+             missing.txt -> IO Error: `No such file or directory`\n\
+             image.iso -> Skipped (larger than 500.0 MiB)\n",
+            write(OutputFormat::Text)
+        );
+    }
+
+    #[test]
+    fn unexpected_verdict_category_is_written_as_sent() {
+        let result = ScanResult::new(
+            "new.exe",
+            TargetType::File,
+            verdict(Verdict::Other("Suspicious".to_string())),
+        );
+        let (mut text, mut ndjson) = (Vec::new(), Vec::new());
+
+        write_result(
+            &mut text,
+            OutputFormat::Text,
+            &TextStyle::default(),
+            &result,
+        )
+        .unwrap();
+        write_result(
+            &mut ndjson,
+            OutputFormat::Ndjson,
+            &TextStyle::default(),
+            &result,
+        )
+        .unwrap();
+
+        assert_eq!("new.exe -> Suspicious\n", String::from_utf8(text).unwrap());
+        let record: Value = serde_json::from_slice(&ndjson).unwrap();
+        assert_eq!("Suspicious", record["verdict"]);
+        assert_eq!(Value::Null, record["detection"]);
+    }
+
+    #[test]
+    fn error_kind_is_the_variant_name() {
+        assert_eq!("Cancelled", error_kind(&Error::Cancelled));
+        assert_eq!(
+            "InvalidSha256",
+            error_kind(&Error::InvalidSha256("abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn verbose_text_includes_sha256_and_duration() {
+        let style = TextStyle {
+            verbose: true,
+            color: false,
+        };
+        let result = ScanResult::new("clean.txt", TargetType::File, verdict(Verdict::Clean))
+            .with_duration(Duration::from_millis(42));
+        let mut out = Vec::new();
+
+        write_result(&mut out, OutputFormat::Text, &style, &result).unwrap();
+
+        assert_eq!(
+            format!("clean.txt -> Clean (sha256 {SHA256}, 42 ms)\n"),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn verbose_text_includes_timing_of_vaas() {
+        let style = TextStyle {
+            verbose: true,
+            color: false,
+        };
+        let requested_at = SystemTime::UNIX_EPOCH;
+        let mut verdict = verdict(Verdict::Clean).unwrap();
+        verdict.timing = Some(ScanTiming {
+            requested_at,
+            responded_at: requested_at + Duration::from_millis(12),
+            upload_duration: Some(Duration::from_millis(250)),
+            total: Duration::from_millis(300),
+            uploaded_bytes: Some(2048),
+        });
+        let result = ScanResult::new("new.exe", TargetType::File, Ok(verdict))
+            .with_duration(Duration::from_millis(310));
+        let mut out = Vec::new();
+
+        write_result(&mut out, OutputFormat::Text, &style, &result).unwrap();
+
+        assert_eq!(
+            format!(
+                "new.exe -> Clean (sha256 {SHA256}, 310 ms, lookup 12 ms, upload 2.0 KiB in 250 ms)\n"
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn text_shows_attempts_of_retried_errors() {
+        let failed =
+            ScanResult::new("a", TargetType::File, Err(Error::ConnectionClosed)).with_attempts(3);
+        let mut out = Vec::new();
+
+        write_result(&mut out, OutputFormat::Text, &TextStyle::default(), &failed).unwrap();
+
+        assert_eq!(
+            "a -> Connection was closed (after 3 attempts)\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn colored_text_wraps_verdicts_in_escape_codes() {
+        let style = TextStyle {
+            verbose: false,
+            color: true,
+        };
+
+        let output = write_styled(OutputFormat::Text, &style);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!("clean.txt -> \u{1b}[32mClean\u{1b}[0m", lines[0]);
+        assert!(lines[1].contains("\u{1b}[31mMalicious"));
+        assert!(lines[2].contains("\u{1b}[33mPup"));
+    }
+}
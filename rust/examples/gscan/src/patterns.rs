@@ -0,0 +1,232 @@
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path, PathBuf};
+use vaas::error::{Error, VResult};
+
+/// Whether a file argument is a glob pattern rather than a path.
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// A glob pattern given as a file argument, e.g. `target/**/*.dll`.
+#[derive(Debug, Clone)]
+pub struct FilePattern {
+    pattern: String,
+    matcher: GlobMatcher,
+    /// The directory before the first component containing a glob character, where the walk starts.
+    base: Option<PathBuf>,
+    /// How deep below the base the pattern can match, `None` if it contains `**`.
+    max_depth: Option<usize>,
+}
+
+impl FilePattern {
+    pub fn new(pattern: &str) -> VResult<Self> {
+        let normalized = normalize_pattern(pattern);
+        let matcher = glob(&normalized)?.compile_matcher();
+        let components: Vec<&str> = normalized.split('/').collect();
+        let literal = components.iter().take_while(|c| !is_glob(c)).count();
+        let base = (literal > 0).then(|| PathBuf::from(components[..literal].join("/")));
+        let max_depth = (!normalized.contains("**")).then(|| components.len() - literal);
+        Ok(Self {
+            pattern: pattern.to_string(),
+            matcher,
+            base,
+            max_depth,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The directory to walk to find the matching files.
+    pub fn base(&self) -> &Path {
+        self.base.as_deref().unwrap_or(Path::new("."))
+    }
+
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth
+    }
+
+    /// Whether a path found below [`FilePattern::base`] matches the pattern.
+    pub fn is_match(&self, path: &Path) -> bool {
+        self.matcher.is_match(slash_path(path))
+    }
+}
+
+/// Glob patterns of files and directories to leave out.
+///
+/// Patterns without a `/` match the name of a file or directory anywhere in the tree, like `*.log`.
+/// Patterns with a `/` match the whole path, like `**/node_modules/**`.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    names: GlobSet,
+    paths: GlobSet,
+}
+
+impl Excludes {
+    pub fn new<'a>(patterns: impl IntoIterator<Item = &'a str>) -> VResult<Self> {
+        let mut names = GlobSetBuilder::new();
+        let mut paths = GlobSetBuilder::new();
+        for pattern in patterns {
+            let pattern = normalize_pattern(pattern);
+            if pattern.contains('/') {
+                paths.add(glob(&pattern)?);
+            } else {
+                names.add(glob(&pattern)?);
+            }
+        }
+        Ok(Self {
+            names: names.build().map_err(invalid_pattern)?,
+            paths: paths.build().map_err(invalid_pattern)?,
+        })
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let name_excluded = path
+            .file_name()
+            .is_some_and(|name| self.names.is_match(Path::new(name)));
+        name_excluded || self.paths.is_match(slash_path(path))
+    }
+}
+
+/// File extensions to scan or to leave out, compared case-insensitively and with or without the leading dot.
+#[derive(Debug, Clone, Default)]
+pub struct Extensions {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl Extensions {
+    pub fn new<'a>(
+        include: impl IntoIterator<Item = &'a str>,
+        exclude: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        Self {
+            include: include.into_iter().map(normalize_extension).collect(),
+            exclude: exclude.into_iter().map(normalize_extension).collect(),
+        }
+    }
+
+    /// Whether the file is scanned. If extensions to include are given, files without an extension are left out.
+    pub fn is_selected(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let included = self.include.is_empty()
+            || extension
+                .as_ref()
+                .is_some_and(|extension| self.include.contains(extension));
+        let excluded = extension.is_some_and(|extension| self.exclude.contains(&extension));
+        included && !excluded
+    }
+}
+
+fn normalize_extension(extension: &str) -> String {
+    extension.trim().trim_start_matches('.').to_lowercase()
+}
+
+fn glob(pattern: &str) -> VResult<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(invalid_pattern)
+}
+
+fn invalid_pattern(e: globset::Error) -> Error {
+    Error::InvalidConfig(format!("Invalid pattern: {e}"))
+}
+
+/// Windows users write patterns with backslashes, which globset would treat as escapes.
+/// A leading `./` is removed, as it is from the paths the patterns are matched against.
+fn normalize_pattern(pattern: &str) -> String {
+    let pattern = if cfg!(windows) {
+        pattern.replace('\\', "/")
+    } else {
+        pattern.to_string()
+    };
+    match pattern.strip_prefix("./") {
+        Some(pattern) => pattern.to_string(),
+        None => pattern,
+    }
+}
+
+/// The path with `/` as separator, independent of the platform, as the patterns use it.
+fn slash_path(path: &Path) -> String {
+    let components: Vec<String> = path
+        .components()
+        .filter(|c| !matches!(c, Component::RootDir | Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let joined = components.join("/");
+    if path.has_root() && cfg!(not(windows)) {
+        format!("/{joined}")
+    } else {
+        joined
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_pattern_walks_from_literal_prefix() {
+        let pattern = FilePattern::new("target/**/*.dll").unwrap();
+
+        assert_eq!(Path::new("target"), pattern.base());
+        assert_eq!(None, pattern.max_depth());
+        assert!(pattern.is_match(Path::new("target/debug/deps/vaas.dll")));
+        assert!(pattern.is_match(Path::new("target/vaas.dll")));
+        assert!(!pattern.is_match(Path::new("target/debug/vaas.so")));
+    }
+
+    #[test]
+    fn file_pattern_without_directory_matches_in_current_directory_only() {
+        let pattern = FilePattern::new("*.txt").unwrap();
+
+        assert_eq!(Path::new("."), pattern.base());
+        assert_eq!(Some(1), pattern.max_depth());
+        assert!(pattern.is_match(Path::new("./a.txt")));
+        assert!(!pattern.is_match(Path::new("./sub/a.txt")));
+        assert!(FilePattern::new("./*.txt")
+            .unwrap()
+            .is_match(Path::new("./a.txt")));
+    }
+
+    #[test]
+    fn nested_excludes_match_names_and_paths() {
+        let excludes = Excludes::new(["**/node_modules/**", "*.log", "build/*/cache"]).unwrap();
+
+        assert!(excludes.is_excluded(Path::new("web/node_modules/left-pad/index.js")));
+        assert!(excludes.is_excluded(Path::new("web/app/node_modules/a/b/c.js")));
+        assert!(excludes.is_excluded(Path::new("logs/2024/app.log")));
+        assert!(excludes.is_excluded(Path::new("build/debug/cache")));
+        assert!(!excludes.is_excluded(Path::new("build/debug/deep/cache")));
+        assert!(!excludes.is_excluded(Path::new("web/src/index.js")));
+    }
+
+    #[test]
+    fn extensions_are_compared_case_insensitively() {
+        let extensions = Extensions::new(["exe", ".DLL"], []);
+
+        assert!(extensions.is_selected(Path::new("setup.EXE")));
+        assert!(extensions.is_selected(Path::new("lib/vaas.dll")));
+        assert!(!extensions.is_selected(Path::new("readme.txt")));
+        assert!(!extensions.is_selected(Path::new("Makefile")));
+
+        let extensions = Extensions::new([], ["iso", "Log"]);
+
+        assert!(extensions.is_selected(Path::new("Makefile")));
+        assert!(extensions.is_selected(Path::new("setup.exe")));
+        assert!(!extensions.is_selected(Path::new("ubuntu.ISO")));
+        assert!(!extensions.is_selected(Path::new("app.log")));
+    }
+
+    #[test]
+    fn invalid_pattern_is_an_error() {
+        assert!(matches!(
+            Excludes::new(["a[b"]),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,359 @@
+use crate::output::{
+    write_result, write_results, Outcome, OutputFormat, ScanResult, TargetType, TextStyle,
+};
+use crate::scan::Dedupe;
+use crate::size::format_size;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io::{self, Write};
+use std::time::Duration;
+use vaas::message::Verdict;
+
+/// The number of results per outcome, and how many of them were files with how many bytes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub clean: usize,
+    pub malicious: usize,
+    pub pup: usize,
+    pub unknown: usize,
+    pub errors: usize,
+    pub skipped: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Counts {
+    pub fn of(results: &[ScanResult]) -> Self {
+        let mut counts = Self::default();
+        results.iter().for_each(|result| counts.add(result));
+        counts
+    }
+
+    fn add(&mut self, result: &ScanResult) {
+        if result.target_type == TargetType::File {
+            self.files += 1;
+        }
+        self.bytes += result.size.unwrap_or_default();
+        match &result.outcome {
+            Outcome::Verdict(v) => match v.verdict {
+                Verdict::Clean => self.clean += 1,
+                Verdict::Malicious { .. } => self.malicious += 1,
+                Verdict::Pup { .. } => self.pup += 1,
+                _ => self.unknown += 1,
+            },
+            Outcome::Error(_) => self.errors += 1,
+            Outcome::Skipped(_) => self.skipped += 1,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.clean + self.malicious + self.pup + self.unknown + self.errors + self.skipped
+    }
+}
+
+/// What the reporter prints.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReportOptions {
+    pub format: OutputFormat,
+    pub style: TextStyle,
+    /// Draw a progress bar on stderr.
+    pub show_progress: bool,
+    /// Leave out clean results.
+    pub only_findings: bool,
+    /// Print no results at all, only the summary.
+    pub quiet: bool,
+}
+
+/// Prints the results as soon as they are available and keeps the progress bar up to date.
+///
+/// Text and NDJSON are printed per result, in the order the scans finish.
+/// A JSON array can only be printed as a whole, so it is printed by [`Reporter::finish`].
+pub struct Reporter<W: Write> {
+    out: W,
+    options: ReportOptions,
+    bar: Option<ProgressBar>,
+    counts: Counts,
+    write_error: Option<io::Error>,
+}
+
+impl<W: Write> Reporter<W> {
+    /// A reporter for `total` results.
+    pub fn new(out: W, options: ReportOptions, total: usize) -> Self {
+        let bar = options.show_progress.then(|| {
+            let bar = ProgressBar::new(total as u64);
+            bar.set_style(
+                ProgressStyle::with_template("{bar:40} {pos}/{len} {msg}")
+                    .expect("the progress bar template is valid"),
+            );
+            bar
+        });
+        Self {
+            out,
+            options,
+            bar,
+            counts: Counts::default(),
+            write_error: None,
+        }
+    }
+
+    fn is_printed(&self, result: &ScanResult) -> bool {
+        !self.options.quiet && (!self.options.only_findings || result.is_finding())
+    }
+
+    /// Count and print a result.
+    pub fn report(&mut self, result: &ScanResult) {
+        self.counts.add(result);
+        let format = self.options.format;
+        if format != OutputFormat::Json && self.write_error.is_none() && self.is_printed(result) {
+            let (out, style) = (&mut self.out, &self.options.style);
+            let written = match &self.bar {
+                // Printing while the bar is visible would garble the terminal.
+                Some(bar) => bar.suspend(|| write_result(out, format, style, result)),
+                None => write_result(out, format, style, result),
+            };
+            self.write_error = written.err();
+        }
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+            bar.set_message(format!(
+                "{} clean, {} malicious, {} pup, {} errors",
+                self.counts.clean, self.counts.malicious, self.counts.pup, self.counts.errors
+            ));
+        }
+    }
+
+    /// Remove the progress bar and print the results which were not printed yet.
+    /// Returns the counts of all results, or the first error which occurred while printing.
+    pub fn finish(mut self, results: &[ScanResult]) -> io::Result<Counts> {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+        if let Some(e) = self.write_error {
+            return Err(e);
+        }
+        if self.options.format == OutputFormat::Json && !self.options.quiet {
+            let printed: Vec<&ScanResult> = results.iter().filter(|r| self.is_printed(r)).collect();
+            write_results(
+                &mut self.out,
+                self.options.format,
+                &self.options.style,
+                &printed,
+            )?;
+        }
+        self.out.flush()?;
+        Ok(self.counts)
+    }
+}
+
+/// The totals per verdict and how long the scan took, to tune the concurrency and rate limits.
+/// If identical files were scanned once, the summary also says how many of the files were unique.
+///
+/// Numbers are grouped with commas independent of the locale, e.g. `Scanned 1,234 files (3.2 GiB) in 94.0 s`.
+pub fn summary(counts: &Counts, dedupe: Option<Dedupe>, elapsed: Duration) -> String {
+    let items = counts.total();
+    let noun = if counts.files == items {
+        "files"
+    } else {
+        "items"
+    };
+    let seconds = elapsed.as_secs_f64();
+    let per_second = if seconds > 0.0 {
+        items as f64 / seconds
+    } else {
+        0.0
+    };
+    let mut summary = format!("Scanned {} {noun}", group_digits(items as u64));
+    if counts.bytes > 0 {
+        summary.push_str(&format!(" ({})", format_size(counts.bytes)));
+    }
+    let errors = if counts.errors == 1 {
+        "error"
+    } else {
+        "errors"
+    };
+    summary.push_str(&format!(
+        " in {seconds:.1} s ({per_second:.1} {noun}/s) \u{2014} \
+         {} clean, {} malicious, {} pup, {} unknown, {} {errors}, {} skipped",
+        group_digits(counts.clean as u64),
+        group_digits(counts.malicious as u64),
+        group_digits(counts.pup as u64),
+        group_digits(counts.unknown as u64),
+        group_digits(counts.errors as u64),
+        group_digits(counts.skipped as u64),
+    ));
+    if let Some(Dedupe { files, unique }) = dedupe {
+        summary.push_str(&format!(
+            "; {} files, {} unique",
+            group_digits(files as u64),
+            group_digits(unique as u64)
+        ));
+    }
+    summary
+}
+
+/// Separate the thousands with commas, e.g. `1,234,567`.
+fn group_digits(number: u64) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TargetType;
+    use std::convert::TryFrom;
+    use vaas::error::Error;
+    use vaas::{Sha256, VaasVerdict};
+
+    fn error(target: &str) -> ScanResult {
+        ScanResult::new(target, TargetType::File, Err(Error::Cancelled))
+    }
+
+    fn clean(target: &str) -> ScanResult {
+        let sha256 =
+            Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+                .unwrap();
+        let verdict = VaasVerdict::new(sha256, Verdict::Clean);
+        ScanResult::new(target, TargetType::File, Ok(verdict))
+    }
+
+    fn text() -> ReportOptions {
+        ReportOptions::default()
+    }
+
+    /// Report the results one by one, and return the output.
+    fn report(options: ReportOptions, results: &[ScanResult]) -> String {
+        let mut out = Vec::new();
+        let mut reporter = Reporter::new(&mut out, options, results.len());
+        results.iter().for_each(|r| reporter.report(r));
+        let counts = reporter.finish(results).unwrap();
+        assert_eq!(results.len(), counts.total());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn text_is_printed_per_result() {
+        assert_eq!(
+            "a -> Request was cancelled\nb -> Clean\n",
+            report(text(), &[error("a"), clean("b")])
+        );
+    }
+
+    #[test]
+    fn json_is_printed_on_finish_in_the_given_order() {
+        let mut out = Vec::new();
+        let options = ReportOptions {
+            format: OutputFormat::Json,
+            ..text()
+        };
+        let mut reporter = Reporter::new(&mut out, options, 2);
+
+        reporter.report(&error("b"));
+        reporter.report(&error("a"));
+        reporter.finish(&[error("a"), error("b")]).unwrap();
+
+        let output: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!("a", output[0]["target"]);
+        assert_eq!("b", output[1]["target"]);
+    }
+
+    #[test]
+    fn only_findings_leaves_out_clean_results() {
+        let results = [clean("a"), error("b"), clean("c")];
+        let options = ReportOptions {
+            only_findings: true,
+            ..text()
+        };
+        let json = ReportOptions {
+            format: OutputFormat::Json,
+            ..options
+        };
+
+        assert_eq!("b -> Request was cancelled\n", report(options, &results));
+        let output: serde_json::Value = serde_json::from_str(&report(json, &results)).unwrap();
+        assert_eq!(1, output.as_array().unwrap().len());
+        assert_eq!("b", output[0]["target"]);
+    }
+
+    #[test]
+    fn quiet_prints_nothing() {
+        let results = [clean("a"), error("b")];
+        for format in [OutputFormat::Text, OutputFormat::Json, OutputFormat::Ndjson] {
+            let options = ReportOptions {
+                format,
+                quiet: true,
+                ..text()
+            };
+            assert_eq!("", report(options, &results));
+        }
+    }
+
+    #[test]
+    fn summary_lists_totals_and_throughput() {
+        let counts = Counts {
+            clean: 20,
+            malicious: 1,
+            pup: 1,
+            unknown: 1,
+            errors: 2,
+            skipped: 3,
+            files: 20,
+            bytes: 0,
+        };
+
+        assert_eq!(
+            "Scanned 28 items in 2.0 s (14.0 items/s) \u{2014} 20 clean, 1 malicious, 1 pup, 1 unknown, 2 errors, 3 skipped",
+            summary(&counts, None, Duration::from_secs(2))
+        );
+        assert!(summary(&Counts::default(), None, Duration::ZERO)
+            .starts_with("Scanned 0 files in 0.0 s (0.0 files/s)"));
+    }
+
+    #[test]
+    fn summary_of_files_has_grouped_numbers_and_size() {
+        let counts = Counts {
+            clean: 1230,
+            malicious: 2,
+            pup: 1,
+            errors: 1,
+            files: 1234,
+            bytes: 3 * 1024 * 1024 * 1024 + 200 * 1024 * 1024,
+            ..Counts::default()
+        };
+
+        assert_eq!(
+            "Scanned 1,234 files (3.2 GiB) in 94.0 s (13.1 files/s) \u{2014} 1,230 clean, 2 malicious, 1 pup, 0 unknown, 1 error, 0 skipped",
+            summary(&counts, None, Duration::from_secs(94))
+        );
+    }
+
+    #[test]
+    fn summary_counts_unique_files() {
+        let counts = Counts {
+            clean: 10,
+            files: 10,
+            ..Counts::default()
+        };
+        let dedupe = Dedupe {
+            files: 10,
+            unique: 4,
+        };
+
+        assert!(summary(&counts, Some(dedupe), Duration::from_secs(1))
+            .ends_with("0 skipped; 10 files, 4 unique"));
+    }
+
+    #[test]
+    fn digits_are_grouped_by_thousands() {
+        assert_eq!("0", group_digits(0));
+        assert_eq!("999", group_digits(999));
+        assert_eq!("1,000", group_digits(1000));
+        assert_eq!("12,345,678", group_digits(12_345_678));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::output::{ResultRecord, ScanResult, TargetType};
+use crate::report::Counts;
+use crate::scan::Dedupe;
+use clap::builder::PossibleValue;
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+/// The format of the report written with `--report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReportFormat {
+    /// A JSON object with the tool versions and one record per result.
+    #[default]
+    Json,
+    /// One row per result with a header row.
+    Csv,
+    /// SARIF 2.1.0, with one result per finding or error.
+    Sarif,
+}
+
+impl ValueEnum for ReportFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Json, Self::Csv, Self::Sarif]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self {
+            Self::Json => PossibleValue::new("json"),
+            Self::Csv => PossibleValue::new("csv"),
+            Self::Sarif => PossibleValue::new("sarif"),
+        })
+    }
+}
+
+/// A complete scan to write a report for.
+pub struct Run<'a> {
+    pub results: &'a [ScanResult],
+    pub started: SystemTime,
+    pub finished: SystemTime,
+    /// Set if identical files were scanned once.
+    pub dedupe: Option<Dedupe>,
+}
+
+const TOOL_NAME: &str = "gscan";
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+const INFORMATION_URI: &str = "https://github.com/GDATASoftwareAG/vaas";
+
+/// Write the report of the run in the given format.
+pub fn write_report(out: impl Write, format: ReportFormat, run: &Run) -> io::Result<()> {
+    match format {
+        ReportFormat::Json => write_json(out, run),
+        ReportFormat::Csv => write_csv(out, run),
+        ReportFormat::Sarif => write_sarif(out, run),
+    }
+}
+
+fn timestamp(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// The totals of the run, the same as in the summary gscan prints.
+fn summary(run: &Run) -> Value {
+    let counts = Counts::of(run.results);
+    let duration = run.finished.duration_since(run.started).unwrap_or_default();
+    json!({
+        "items": counts.total(),
+        "files": counts.files,
+        "unique_files": run.dedupe.map(|dedupe| dedupe.unique),
+        "bytes": counts.bytes,
+        "clean": counts.clean,
+        "malicious": counts.malicious,
+        "pup": counts.pup,
+        "unknown": counts.unknown,
+        "errors": counts.errors,
+        "skipped": counts.skipped,
+        "duration_ms": duration.as_millis(),
+    })
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    #[serde(flatten)]
+    result: ResultRecord<'a>,
+    scanned_at: String,
+}
+
+fn write_json(mut out: impl Write, run: &Run) -> io::Result<()> {
+    let results: Vec<JsonRecord> = run
+        .results
+        .iter()
+        .map(|result| JsonRecord {
+            result: ResultRecord::from(result),
+            scanned_at: timestamp(result.scanned_at),
+        })
+        .collect();
+    let report = json!({
+        "tool": {
+            "name": TOOL_NAME,
+            "version": TOOL_VERSION,
+            "sdk_version": vaas::VERSION,
+        },
+        "started_at": timestamp(run.started),
+        "finished_at": timestamp(run.finished),
+        "summary": summary(run),
+        "results": results,
+    });
+    serde_json::to_writer_pretty(&mut out, &report)?;
+    writeln!(out)
+}
+
+#[derive(Serialize)]
+struct CsvRecord<'a> {
+    target: &'a str,
+    target_type: TargetType,
+    sha256: Option<String>,
+    verdict: Option<&'a str>,
+    detection: Option<&'a str>,
+    error_kind: Option<String>,
+    error: Option<String>,
+    skipped: Option<&'a str>,
+    scanned_at: String,
+    duration_ms: Option<u128>,
+    attempts: u32,
+}
+
+fn write_csv(out: impl Write, run: &Run) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    for result in run.results {
+        let record = ResultRecord::from(result);
+        let (error_kind, error) = match record.error {
+            Some(e) => (Some(e.kind), Some(e.message)),
+            None => (None, None),
+        };
+        writer.serialize(CsvRecord {
+            target: record.target,
+            target_type: record.target_type,
+            sha256: record.sha256,
+            verdict: record.verdict,
+            detection: record.detection,
+            error_kind,
+            error,
+            skipped: record.skipped,
+            scanned_at: timestamp(result.scanned_at),
+            duration_ms: record.duration_ms,
+            attempts: record.attempts,
+        })?;
+    }
+    writer.flush()
+}
+
+/// The SARIF rules, one per kind of finding.
+fn sarif_rules() -> Value {
+    json!([
+        {
+            "id": "malicious",
+            "shortDescription": { "text": "Malicious content" },
+            "defaultConfiguration": { "level": "error" },
+        },
+        {
+            "id": "pup",
+            "shortDescription": { "text": "Potentially unwanted program" },
+            "defaultConfiguration": { "level": "warning" },
+        },
+        {
+            "id": "unknown",
+            "shortDescription": { "text": "VaaS could not determine a verdict" },
+            "defaultConfiguration": { "level": "note" },
+        },
+        {
+            "id": "scan-error",
+            "shortDescription": { "text": "The item could not be scanned" },
+            "defaultConfiguration": { "level": "warning" },
+        },
+    ])
+}
+
+fn sarif_result(result: &ScanResult) -> Option<Value> {
+    let record = ResultRecord::from(result);
+    let (rule, level, message) = match (&record.verdict, &record.error) {
+        (Some("Clean"), _) => return None,
+        (Some("Malicious"), _) => ("malicious", "error", record.detection.unwrap_or_default()),
+        (Some("Pup"), _) => ("pup", "warning", record.detection.unwrap_or_default()),
+        (Some(_), _) => ("unknown", "note", "Unknown"),
+        (None, Some(error)) => ("scan-error", "warning", error.message.as_str()),
+        (None, None) => return None,
+    };
+    let mut sarif = json!({
+        "ruleId": rule,
+        "level": level,
+        "message": { "text": format!("{}: {message}", record.target) },
+        "properties": {
+            "targetType": record.target_type,
+            "sha256": record.sha256,
+            "scannedAt": timestamp(result.scanned_at),
+        },
+    });
+    // A hash is no location, it is only part of the properties.
+    if record.target_type != TargetType::Sha256 {
+        sarif["locations"] = json!([{
+            "physicalLocation": {
+                "artifactLocation": { "uri": artifact_uri(record.target, record.target_type) },
+            },
+        }]);
+    }
+    Some(sarif)
+}
+
+/// Relative paths are kept relative to the directory gscan ran in, with `/` as separator as SARIF requires.
+fn artifact_uri(target: &str, target_type: TargetType) -> String {
+    if target_type == TargetType::Url {
+        return target.to_string();
+    }
+    let path = percent_encode(&target.replace('\\', "/"));
+    if path.starts_with('/') {
+        format!("file://{path}")
+    } else if path.chars().nth(1) == Some(':') {
+        format!("file:///{path}")
+    } else {
+        path
+    }
+}
+
+/// Encode everything but the unreserved characters and the separators of a path, e.g. spaces.
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+fn write_sarif(mut out: impl Write, run: &Run) -> io::Result<()> {
+    let results: Vec<Value> = run.results.iter().filter_map(sarif_result).collect();
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": TOOL_NAME,
+                    "version": TOOL_VERSION,
+                    "informationUri": INFORMATION_URI,
+                    "rules": sarif_rules(),
+                    "properties": { "sdkVersion": vaas::VERSION },
+                },
+            },
+            "invocations": [{
+                "executionSuccessful": true,
+                "startTimeUtc": timestamp(run.started),
+                "endTimeUtc": timestamp(run.finished),
+                "properties": { "summary": summary(run) },
+            }],
+            "results": results,
+        }],
+    });
+    serde_json::to_writer_pretty(&mut out, &sarif)?;
+    writeln!(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::time::{Duration, UNIX_EPOCH};
+    use vaas::error::Error;
+    use vaas::message::Verdict;
+    use vaas::{Sha256, VaasVerdict};
+
+    const SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + seconds)
+    }
+
+    fn result(target: &str, result: Result<Verdict, Error>) -> ScanResult {
+        let result =
+            result.map(|verdict| VaasVerdict::new(Sha256::try_from(SHA256).unwrap(), verdict));
+        let mut result = ScanResult::new(target, TargetType::File, result)
+            .with_duration(Duration::from_millis(12));
+        result.scanned_at = at(1);
+        result
+    }
+
+    fn fixture() -> Vec<ScanResult> {
+        vec![
+            result("clean, with comma.txt", Ok(Verdict::Clean)),
+            result(
+                "samples/eicar.com",
+                Ok(Verdict::Malicious {
+                    detection: "EICAR-Test-File".to_string(),
+                }),
+            ),
+            result(
+                "missing \"quoted\".txt",
+                Err(Error::IoError("not found".to_string())),
+            ),
+        ]
+    }
+
+    fn write(format: ReportFormat) -> String {
+        let results = fixture();
+        let run = Run {
+            results: &results,
+            started: at(0),
+            finished: at(2),
+            dedupe: None,
+        };
+        let mut out = Vec::new();
+        write_report(&mut out, format, &run).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_quotes_paths_with_commas_and_quotes() {
+        let expected = format!(
+            "target,target_type,sha256,verdict,detection,error_kind,error,skipped,scanned_at,duration_ms,attempts\n\
+             \"clean, with comma.txt\",file,{SHA256},Clean,,,,,2023-11-14T22:13:21.000Z,12,1\n\
+             samples/eicar.com,file,{SHA256},Malicious,EICAR-Test-File,,,,2023-11-14T22:13:21.000Z,12,1\n\
+             \"missing \"\"quoted\"\".txt\",file,,,,IoError,IO Error: `not found`,,2023-11-14T22:13:21.000Z,12,1\n"
+        );
+
+        assert_eq!(expected, write(ReportFormat::Csv));
+    }
+
+    #[test]
+    fn json_report_contains_versions_and_timestamps() {
+        let report: Value = serde_json::from_str(&write(ReportFormat::Json)).unwrap();
+
+        assert_eq!("gscan", report["tool"]["name"]);
+        assert_eq!(vaas::VERSION, report["tool"]["sdk_version"]);
+        assert_eq!("2023-11-14T22:13:20.000Z", report["started_at"]);
+        assert_eq!(
+            json!({
+                "items": 3,
+                "files": 3,
+                "unique_files": null,
+                "bytes": 0,
+                "clean": 1,
+                "malicious": 1,
+                "pup": 0,
+                "unknown": 0,
+                "errors": 1,
+                "skipped": 0,
+                "duration_ms": 2000,
+            }),
+            report["summary"]
+        );
+        assert_eq!(3, report["results"].as_array().unwrap().len());
+        assert_eq!(
+            json!({
+                "target": "samples/eicar.com",
+                "target_type": "file",
+                "sha256": SHA256,
+                "verdict": "Malicious",
+                "detection": "EICAR-Test-File",
+                "error": null,
+                "skipped": null,
+                "attempts": 1,
+                "scanned_at": "2023-11-14T22:13:21.000Z",
+                "duration_ms": 12,
+            }),
+            report["results"][1]
+        );
+    }
+
+    #[test]
+    fn sarif_report_matches_snapshot() {
+        let report: Value = serde_json::from_str(&write(ReportFormat::Sarif)).unwrap();
+
+        let expected = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "gscan",
+                        "version": TOOL_VERSION,
+                        "informationUri": INFORMATION_URI,
+                        "rules": sarif_rules(),
+                        "properties": { "sdkVersion": vaas::VERSION },
+                    },
+                },
+                "invocations": [{
+                    "executionSuccessful": true,
+                    "startTimeUtc": "2023-11-14T22:13:20.000Z",
+                    "endTimeUtc": "2023-11-14T22:13:22.000Z",
+                    "properties": { "summary": summary(&Run {
+                        results: &fixture(),
+                        started: at(0),
+                        finished: at(2),
+                        dedupe: None,
+                    }) },
+                }],
+                "results": [
+                    {
+                        "ruleId": "malicious",
+                        "level": "error",
+                        "message": { "text": "samples/eicar.com: EICAR-Test-File" },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "samples/eicar.com" },
+                            },
+                        }],
+                        "properties": {
+                            "targetType": "file",
+                            "sha256": SHA256,
+                            "scannedAt": "2023-11-14T22:13:21.000Z",
+                        },
+                    },
+                    {
+                        "ruleId": "scan-error",
+                        "level": "warning",
+                        "message": { "text": "missing \"quoted\".txt: IO Error: `not found`" },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "missing%20%22quoted%22.txt" },
+                            },
+                        }],
+                        "properties": {
+                            "targetType": "file",
+                            "sha256": null,
+                            "scannedAt": "2023-11-14T22:13:21.000Z",
+                        },
+                    },
+                ],
+            }],
+        });
+        assert_eq!(expected, report);
+    }
+
+    #[test]
+    fn sarif_rule_ids_are_defined() {
+        let report: Value = serde_json::from_str(&write(ReportFormat::Sarif)).unwrap();
+        let run = &report["runs"][0];
+        let rules: Vec<&Value> = run["tool"]["driver"]["rules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rule| &rule["id"])
+            .collect();
+
+        for result in run["results"].as_array().unwrap() {
+            assert!(rules.contains(&&result["ruleId"]));
+            assert!(result["message"]["text"].is_string());
+        }
+    }
+
+    #[test]
+    fn absolute_paths_are_file_uris() {
+        assert_eq!(
+            "file:///srv/a.txt",
+            artifact_uri("/srv/a.txt", TargetType::File)
+        );
+        assert_eq!(
+            "file:///C:/Users/a.txt",
+            artifact_uri("C:\\Users\\a.txt", TargetType::File)
+        );
+        assert_eq!("sub/a.txt", artifact_uri("sub/a.txt", TargetType::File));
+        assert_eq!(
+            "sub/a%20b%C3%A4.txt",
+            artifact_uri("sub/a bä.txt", TargetType::File)
+        );
+    }
+}
@@ -0,0 +1,555 @@
+use crate::output::{ScanResult, TargetType};
+use futures::stream::{self, StreamExt};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use vaas::error::VResult;
+use vaas::{Sha256, VaasVerdict};
+
+/// The number of files scanned at the same time if not configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// The delay before the first retry, doubled for each further one up to [`MAX_RETRY_DELAY`].
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Bounds how many scans run at the same time and how many are started per second,
+/// and how often a scan which failed with a transient error is repeated.
+#[derive(Debug)]
+pub struct Limits {
+    pub concurrency: usize,
+    pub rate: Option<RateLimiter>,
+    pub retries: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            rate: None,
+            retries: 0,
+        }
+    }
+}
+
+impl Limits {
+    async fn wait_for_rate(&self) {
+        if let Some(rate) = &self.rate {
+            rate.acquire().await;
+        }
+    }
+}
+
+/// Spreads requests evenly so at most the given number are started per second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// A limiter for `per_second` requests per second, which has to be greater than zero.
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / per_second,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Wait until the next request may be started.
+    pub async fn acquire(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = next.map_or_else(Instant::now, |next| next.max(Instant::now()));
+            *next = Some(slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Scan the files concurrently with `scan` within the limits and pass each result to `on_result` as soon as it
+/// is available. The returned results are in the order of the files, independent of the order in which the scans finished.
+pub async fn scan_files<'a, S, F>(
+    files: &'a [PathBuf],
+    limits: &Limits,
+    scan: S,
+    on_result: impl FnMut(&ScanResult),
+) -> Vec<ScanResult>
+where
+    S: Fn(&'a Path) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let files: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+    scan_in_order(&files, TargetType::File, limits, scan, on_result).await
+}
+
+/// How many files were given to [`scan_unique_files`] and how many distinct contents were scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dedupe {
+    pub files: usize,
+    pub unique: usize,
+}
+
+/// Like [`scan_files`], but hash the files with `hash` first and scan each content only once.
+/// Every file sharing the content gets a copy of its result. Files which cannot be hashed are reported with the error.
+pub async fn scan_unique_files<'a, H, HF, S, F>(
+    files: &'a [PathBuf],
+    limits: &Limits,
+    hash: H,
+    scan: S,
+    mut on_result: impl FnMut(&ScanResult),
+) -> (Vec<ScanResult>, Dedupe)
+where
+    H: Fn(&'a Path) -> HF,
+    HF: Future<Output = VResult<Sha256>>,
+    S: Fn(&'a Path) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let hashes: Vec<VResult<Sha256>> = stream::iter(files)
+        .map(|file| hash(file))
+        .buffered(limits.concurrency)
+        .collect()
+        .await;
+
+    let mut results: Vec<Option<ScanResult>> = files.iter().map(|_| None).collect();
+    // The first file of each content is scanned, `sharing` holds the indices of all files with that content.
+    let mut unique: Vec<&Path> = Vec::new();
+    let mut sharing: Vec<Vec<usize>> = Vec::new();
+    let mut seen: HashMap<Sha256, usize> = HashMap::new();
+    for (index, (file, sha256)) in files.iter().zip(hashes).enumerate() {
+        match sha256 {
+            Ok(sha256) => {
+                let content = *seen.entry(sha256).or_insert_with(|| {
+                    unique.push(file);
+                    sharing.push(Vec::new());
+                    unique.len() - 1
+                });
+                sharing[content].push(index);
+            }
+            Err(e) => {
+                let result = ScanResult::new(file.display().to_string(), TargetType::File, Err(e));
+                on_result(&result);
+                results[index] = Some(result);
+            }
+        }
+    }
+
+    let dedupe = Dedupe {
+        files: files.len(),
+        unique: unique.len(),
+    };
+    scan_indexed(
+        &unique,
+        TargetType::File,
+        limits,
+        scan,
+        |content, result| {
+            for &index in &sharing[content] {
+                let mut shared = result.clone();
+                shared.target = files[index].display().to_string();
+                on_result(&shared);
+                results[index] = Some(shared);
+            }
+        },
+    )
+    .await;
+    (results.into_iter().flatten().collect(), dedupe)
+}
+
+/// A file or URL to scan, which also names the result.
+trait Target {
+    fn target(&self) -> String;
+
+    /// The number of bytes scanned, if known before the scan.
+    fn size(&self) -> Option<u64>;
+}
+
+impl Target for Path {
+    fn target(&self) -> String {
+        self.display().to_string()
+    }
+
+    fn size(&self) -> Option<u64> {
+        std::fs::metadata(self).ok().map(|metadata| metadata.len())
+    }
+}
+
+impl Target for Url {
+    fn target(&self) -> String {
+        self.to_string()
+    }
+
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Scan the items concurrently within the limits and pass each result to `on_result` as soon as it is available.
+/// The returned results are in the order of the items.
+async fn scan_in_order<'a, T, S, F>(
+    items: &[&'a T],
+    target_type: TargetType,
+    limits: &Limits,
+    scan: S,
+    mut on_result: impl FnMut(&ScanResult),
+) -> Vec<ScanResult>
+where
+    T: Target + ?Sized,
+    S: Fn(&'a T) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let mut results: Vec<Option<ScanResult>> = items.iter().map(|_| None).collect();
+    scan_indexed(items, target_type, limits, scan, |index, result| {
+        on_result(&result);
+        results[index] = Some(result);
+    })
+    .await;
+    results.into_iter().flatten().collect()
+}
+
+/// Scan the items concurrently within the limits and pass each result with the index of its item to `on_result`.
+async fn scan_indexed<'a, T, S, F>(
+    items: &[&'a T],
+    target_type: TargetType,
+    limits: &Limits,
+    scan: S,
+    mut on_result: impl FnMut(usize, ScanResult),
+) where
+    T: Target + ?Sized,
+    S: Fn(&'a T) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let scan = &scan;
+    let mut pending = stream::iter(items.iter().copied().enumerate())
+        .map(|(index, item)| async move {
+            let started = Instant::now();
+            let (verdict, attempts) = retry(limits.retries, || async {
+                limits.wait_for_rate().await;
+                scan(item).await
+            })
+            .await;
+            (index, verdict, attempts, started.elapsed())
+        })
+        .buffer_unordered(limits.concurrency);
+
+    while let Some((index, verdict, attempts, duration)) = pending.next().await {
+        let mut result = ScanResult::new(items[index].target(), target_type, verdict)
+            .with_duration(duration)
+            .with_attempts(attempts);
+        result.size = items[index].size();
+        on_result(index, result);
+    }
+}
+
+/// Scan the URLs concurrently within the limits like [`scan_files`].
+pub async fn scan_urls<'a, S, F>(
+    urls: &'a [Url],
+    limits: &Limits,
+    scan: S,
+    on_result: impl FnMut(&ScanResult),
+) -> Vec<ScanResult>
+where
+    S: Fn(&'a Url) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let urls: Vec<&Url> = urls.iter().collect();
+    scan_in_order(&urls, TargetType::Url, limits, scan, on_result).await
+}
+
+/// Scan content which is not a file on disk, like the data piped to gscan, within the limits.
+pub async fn scan_content<S, F>(
+    target: &str,
+    content: &[u8],
+    limits: &Limits,
+    scan: S,
+) -> ScanResult
+where
+    S: Fn(Vec<u8>) -> F,
+    F: Future<Output = VResult<VaasVerdict>>,
+{
+    let started = Instant::now();
+    let (verdict, attempts) = retry(limits.retries, || async {
+        limits.wait_for_rate().await;
+        scan(content.to_vec()).await
+    })
+    .await;
+    ScanResult::new(target, TargetType::File, verdict)
+        .with_duration(started.elapsed())
+        .with_attempts(attempts)
+        .with_size(content.len() as u64)
+}
+
+/// Run `attempt` until it succeeds, fails with an error which is not retryable or `retries` retries were made.
+/// Returns the result of the last attempt and the number of attempts.
+pub async fn retry<T, F, Fut>(retries: u32, mut attempt: F) -> (VResult<T>, u32)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = VResult<T>>,
+{
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Err(e) if e.is_retryable() && attempts <= retries => {
+                tokio::time::sleep(retry_delay(attempts)).await;
+                attempts += 1;
+            }
+            result => return (result, attempts),
+        }
+    }
+}
+
+/// The delay before the given retry, starting with `1` for the first one.
+fn retry_delay(retry: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(retry - 1))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Request the verdicts for all hashes at once with `scan` and pass each result to `on_result`.
+pub async fn scan_hashes<'a, S, F>(
+    hashes: &'a [Sha256],
+    scan: S,
+    mut on_result: impl FnMut(&ScanResult),
+) -> Vec<ScanResult>
+where
+    S: FnOnce(&'a [Sha256]) -> F,
+    F: Future<Output = Vec<VResult<VaasVerdict>>>,
+{
+    let verdicts = if hashes.is_empty() {
+        Vec::new()
+    } else {
+        scan(hashes).await
+    };
+    hashes
+        .iter()
+        .zip(verdicts)
+        .map(|(sha256, verdict)| {
+            let result = ScanResult::new(sha256.to_string(), TargetType::Sha256, verdict);
+            on_result(&result);
+            result
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Outcome;
+    use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use vaas::error::Error;
+    use vaas::message::Verdict;
+
+    fn clean() -> VResult<VaasVerdict> {
+        let sha256 =
+            Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+                .unwrap();
+        Ok(VaasVerdict::new(sha256, Verdict::Clean))
+    }
+
+    #[tokio::test]
+    async fn files_are_reported_on_completion_and_returned_in_order() {
+        let files: Vec<PathBuf> = ["30", "10", "20"].iter().map(PathBuf::from).collect();
+        let mut reported = Vec::new();
+
+        // Each scan takes as many milliseconds as the name of the file says.
+        let results = scan_files(
+            &files,
+            &Limits::default(),
+            |file| async move {
+                let millis = file.to_str().unwrap().parse().unwrap();
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                clean()
+            },
+            |result| reported.push(result.target.clone()),
+        )
+        .await;
+
+        assert_eq!(vec!["10", "20", "30"], reported);
+        let targets: Vec<&str> = results.iter().map(|r| r.target.as_str()).collect();
+        assert_eq!(vec!["30", "10", "20"], targets);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn urls_are_scanned_concurrently_and_returned_in_order() {
+        let urls: Vec<Url> = [30, 10, 20]
+            .iter()
+            .map(|millis| Url::parse(&format!("https://example.com/{millis}")).unwrap())
+            .collect();
+        let mut reported = Vec::new();
+        let start = Instant::now();
+
+        let results = scan_urls(
+            &urls,
+            &Limits::default(),
+            |url| async move {
+                let millis = url.path()[1..].parse().unwrap();
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                clean()
+            },
+            |result| reported.push(result.target.clone()),
+        )
+        .await;
+
+        assert_eq!(Duration::from_millis(30), start.elapsed());
+        let paths = |targets: Vec<String>| -> Vec<String> {
+            targets
+                .iter()
+                .map(|t| t.trim_start_matches("https://example.com/").to_string())
+                .collect()
+        };
+        assert_eq!(vec!["10", "20", "30"], paths(reported));
+        let targets = results.iter().map(|r| r.target.clone()).collect();
+        assert_eq!(vec!["30", "10", "20"], paths(targets));
+        assert!(results.iter().all(|r| r.target_type == TargetType::Url));
+    }
+
+    #[tokio::test]
+    async fn concurrent_scans_are_bounded() {
+        let files: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(i.to_string())).collect();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let limits = Limits {
+            concurrency: 3,
+            ..Limits::default()
+        };
+
+        let results = scan_files(
+            &files,
+            &limits,
+            |_| async {
+                let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                clean()
+            },
+            |_| {},
+        )
+        .await;
+
+        assert_eq!(20, results.len());
+        assert_eq!(3, max_running.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter_spreads_requests_over_time() {
+        let limiter = RateLimiter::new(10);
+        let start = Instant::now();
+
+        futures::future::join_all((0..5).map(|_| limiter.acquire())).await;
+
+        assert_eq!(Duration::from_millis(400), start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retryable_errors_are_retried_with_backoff() {
+        let calls = AtomicUsize::new(0);
+        let start = Instant::now();
+
+        let (result, attempts) = retry(3, || async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(Error::ConnectionClosed),
+                _ => clean(),
+            }
+        })
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(3, attempts);
+        assert_eq!(RETRY_DELAY * 3, start.elapsed());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_stop_at_permanent_errors_and_the_limit() {
+        let (result, attempts) = retry(3, || async {
+            Err::<(), _>(Error::IoError("No such file".to_string()))
+        })
+        .await;
+        assert!(matches!(result, Err(Error::IoError(_))));
+        assert_eq!(1, attempts);
+
+        let (result, attempts) = retry(2, || async { Err::<(), _>(Error::Cancelled) }).await;
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(3, attempts);
+    }
+
+    #[tokio::test]
+    async fn identical_files_are_scanned_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<PathBuf> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        for (file, content) in files.iter().zip(["same", "other", "same", "same"]) {
+            std::fs::write(file, content).unwrap();
+        }
+        let missing = dir.path().join("missing");
+        let files: Vec<PathBuf> = files.into_iter().chain([missing.clone()]).collect();
+        let requests = AtomicUsize::new(0);
+        let mut reported = Vec::new();
+
+        let (results, dedupe) = scan_unique_files(
+            &files,
+            &Limits::default(),
+            Sha256::from_file,
+            |file| {
+                requests.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let sha256 = Sha256::from_file(file).await?;
+                    Ok(VaasVerdict::new(sha256, Verdict::Clean))
+                }
+            },
+            |result| reported.push(result.target.clone()),
+        )
+        .await;
+
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+        assert_eq!(
+            Dedupe {
+                files: 5,
+                unique: 2
+            },
+            dedupe
+        );
+        assert_eq!(5, reported.len());
+        let targets: Vec<String> = results.iter().map(|r| r.target.clone()).collect();
+        let expected: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+        assert_eq!(expected, targets);
+        let same = Sha256::from(&b"same"[..]);
+        for index in [0, 2, 3] {
+            assert_eq!(Some(&same), results[index].verdict().map(|v| &v.sha256));
+        }
+        assert_ne!(Some(&same), results[1].verdict().map(|v| &v.sha256));
+        assert!(matches!(results[4].outcome, Outcome::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn hashes_are_requested_at_once_and_reported_in_order() {
+        let hashes = vec![
+            Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+                .unwrap(),
+            Sha256::try_from("ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2")
+                .unwrap(),
+        ];
+        let mut reported = 0;
+
+        let results = scan_hashes(
+            &hashes,
+            |hashes| async move { hashes.iter().map(|_| clean()).collect() },
+            |_| reported += 1,
+        )
+        .await;
+
+        assert_eq!(2, reported);
+        let targets: Vec<String> = results.iter().map(|r| r.target.clone()).collect();
+        let expected: Vec<String> = hashes.iter().map(ToString::to_string).collect();
+        assert_eq!(expected, targets);
+        assert!(results.iter().all(|r| r.target_type == TargetType::Sha256));
+    }
+}
@@ -0,0 +1,554 @@
+//! `gscan serve`, a local HTTP API for scans sharing one connection to VaaS.
+//!
+//! * `POST /scan/file` scans the body, or the first file of a `multipart/form-data` body.
+//! * `POST /scan/sha256` looks up `{"sha256": "..."}`.
+//! * `POST /scan/url` scans `{"url": "https://..."}`.
+//! * `GET /healthz` checks that VaaS can be reached.
+//!
+//! The scans answer with the JSON object `--output-format json` prints for a result.
+
+use crate::output::{Outcome, ResultRecord, ScanResult, TargetType};
+use futures::stream::{FuturesUnordered, StreamExt};
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Bytes};
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
+use vaas::cancellation::CancellationToken;
+use vaas::error::{Error, VResult};
+use vaas::{LazyConnection, Sha256, VaasVerdict};
+
+/// The default address to listen on. Only local clients can connect to it.
+pub const DEFAULT_LISTEN: &str = "127.0.0.1:3993";
+/// The default limit for the size of a request body.
+pub const DEFAULT_MAX_BODY_SIZE: &str = "100M";
+
+/// What the server needs from VaaS. Implemented for [`LazyConnection`], the tests use a fake.
+pub trait Scanner {
+    async fn for_buf(&self, buf: Vec<u8>) -> VResult<VaasVerdict>;
+    async fn for_sha256(&self, sha256: &Sha256) -> VResult<VaasVerdict>;
+    async fn for_url(&self, url: &Url) -> VResult<VaasVerdict>;
+    /// Check that VaaS can be reached, connecting again if the connection was closed.
+    async fn health(&self) -> VResult<()>;
+}
+
+/// A [`LazyConnection`] cancelling every request after the timeout.
+pub struct VaasScanner {
+    pub connection: LazyConnection,
+    pub timeout: Option<CancellationToken>,
+}
+
+impl Scanner for VaasScanner {
+    async fn for_buf(&self, buf: Vec<u8>) -> VResult<VaasVerdict> {
+        self.connection.for_buf(buf, self.timeout.as_ref()).await
+    }
+
+    async fn for_sha256(&self, sha256: &Sha256) -> VResult<VaasVerdict> {
+        self.connection
+            .for_sha256(sha256, self.timeout.as_ref())
+            .await
+    }
+
+    async fn for_url(&self, url: &Url) -> VResult<VaasVerdict> {
+        self.connection.for_url(url, self.timeout.as_ref()).await
+    }
+
+    async fn health(&self) -> VResult<()> {
+        self.connection.connection().await.map(|_| ())
+    }
+}
+
+/// The limits of the server.
+#[derive(Debug, Clone, Copy)]
+pub struct ServeOptions {
+    /// How many scans run at the same time. Further requests wait for a free slot.
+    pub concurrency: usize,
+    /// Larger request bodies are rejected with `413 Payload Too Large`.
+    pub max_body_size: usize,
+}
+
+/// Answer requests on `listen` until Ctrl-C is pressed.
+///
+/// All connections are served on the current task, so the scanner does not have to be `Send`.
+pub async fn serve<S: Scanner>(
+    listen: SocketAddr,
+    scanner: S,
+    options: ServeOptions,
+) -> VResult<()> {
+    let listener = TcpListener::bind(listen)
+        .await
+        .map_err(|e| Error::IoError(format!("Cannot listen on {listen}: {e}")))?;
+    eprintln!(
+        "Listening on http://{}, press Ctrl-C to stop",
+        listener.local_addr().map_err(io_error)?
+    );
+    let server = Server::new(scanner, options);
+    let server = &server;
+    let mut connections = FuturesUnordered::new();
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            accepted = listener.accept() => {
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        eprintln!("Warning: cannot accept a connection: {e}");
+                        continue;
+                    }
+                };
+                let service = service_fn(move |request| async move {
+                    Ok::<_, Infallible>(server.handle(request).await)
+                });
+                connections.push(async move {
+                    if let Err(e) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await
+                    {
+                        eprintln!("Warning: connection from {peer} failed: {e}");
+                    }
+                });
+            },
+            Some(()) = connections.next() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Routes the requests to the scanner, independent of the connections they came in on.
+pub struct Server<S> {
+    scanner: S,
+    scans: Semaphore,
+    max_body_size: usize,
+}
+
+#[derive(Deserialize)]
+struct Sha256Request {
+    sha256: String,
+}
+
+#[derive(Deserialize)]
+struct UrlRequest {
+    url: String,
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A request which cannot be scanned, answered with the status and `{"error": message}`.
+struct Rejection(StatusCode, String);
+
+impl<S: Scanner> Server<S> {
+    pub fn new(scanner: S, options: ServeOptions) -> Self {
+        Self {
+            scanner,
+            scans: Semaphore::new(options.concurrency),
+            max_body_size: options.max_body_size,
+        }
+    }
+
+    pub async fn handle<B>(&self, request: Request<B>) -> Response<Full<Bytes>>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let response = match (request.method(), request.uri().path()) {
+            (&Method::POST, "/scan/file") => self.scan_file(request).await,
+            (&Method::POST, "/scan/sha256") => self.scan_sha256(request).await,
+            (&Method::POST, "/scan/url") => self.scan_url(request).await,
+            (&Method::GET, "/healthz") => Ok(self.health().await),
+            (_, "/scan/file" | "/scan/sha256" | "/scan/url" | "/healthz") => Err(Rejection(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("{} is not allowed here", request.method()),
+            )),
+            (_, path) => Err(Rejection(
+                StatusCode::NOT_FOUND,
+                format!("{path} not found"),
+            )),
+        };
+        response.unwrap_or_else(|Rejection(status, message)| {
+            json_response(status, &serde_json::json!({ "error": message }))
+        })
+    }
+
+    async fn scan_file<B>(&self, request: Request<B>) -> Result<Response<Full<Bytes>>, Rejection>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let _permit = self
+            .scans
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        let boundary = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| multer::parse_boundary(content_type).ok());
+        let body = self.read_body(request).await?;
+        let (name, file) = match boundary {
+            Some(boundary) => first_file(body, boundary).await?,
+            None => ("upload".to_string(), body),
+        };
+        let started = Instant::now();
+        let verdict = self.scanner.for_buf(file.to_vec()).await;
+        Ok(verdict_response(
+            ScanResult::new(name, TargetType::File, verdict)
+                .with_duration(started.elapsed())
+                .with_size(file.len() as u64),
+        ))
+    }
+
+    async fn scan_sha256<B>(&self, request: Request<B>) -> Result<Response<Full<Bytes>>, Rejection>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let _permit = self
+            .scans
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        let Sha256Request { sha256 } = self.read_json(request).await?;
+        let started = Instant::now();
+        let verdict = match Sha256::try_from(sha256.as_str()) {
+            Ok(hash) => self.scanner.for_sha256(&hash).await,
+            Err(e) => Err(e),
+        };
+        Ok(verdict_response(
+            ScanResult::new(sha256, TargetType::Sha256, verdict).with_duration(started.elapsed()),
+        ))
+    }
+
+    async fn scan_url<B>(&self, request: Request<B>) -> Result<Response<Full<Bytes>>, Rejection>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let _permit = self
+            .scans
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        let UrlRequest { url } = self.read_json(request).await?;
+        let parsed = Url::parse(&url).map_err(|e| {
+            Rejection(
+                StatusCode::BAD_REQUEST,
+                format!("{url} is not a valid url: {e}"),
+            )
+        })?;
+        let started = Instant::now();
+        let verdict = self.scanner.for_url(&parsed).await;
+        Ok(verdict_response(
+            ScanResult::new(url, TargetType::Url, verdict).with_duration(started.elapsed()),
+        ))
+    }
+
+    async fn health(&self) -> Response<Full<Bytes>> {
+        match self.scanner.health().await {
+            Ok(()) => json_response(
+                StatusCode::OK,
+                &Status {
+                    status: "ok",
+                    error: None,
+                },
+            ),
+            Err(e) => json_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                &Status {
+                    status: "unavailable",
+                    error: Some(e.to_string()),
+                },
+            ),
+        }
+    }
+
+    async fn read_body<B>(&self, request: Request<B>) -> Result<Bytes, Rejection>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        match Limited::new(request.into_body(), self.max_body_size)
+            .collect()
+            .await
+        {
+            Ok(body) => Ok(body.to_bytes()),
+            Err(e) if e.is::<LengthLimitError>() => Err(Rejection(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("the body is larger than {} bytes", self.max_body_size),
+            )),
+            Err(e) => Err(Rejection(
+                StatusCode::BAD_REQUEST,
+                format!("cannot read the body: {e}"),
+            )),
+        }
+    }
+
+    async fn read_json<B, T>(&self, request: Request<B>) -> Result<T, Rejection>
+    where
+        B: Body<Data = Bytes>,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        T: for<'de> Deserialize<'de>,
+    {
+        let body = self.read_body(request).await?;
+        serde_json::from_slice(&body)
+            .map_err(|e| Rejection(StatusCode::BAD_REQUEST, format!("invalid JSON body: {e}")))
+    }
+}
+
+/// The name and content of the first file in a multipart form.
+async fn first_file(body: Bytes, boundary: String) -> Result<(String, Bytes), Rejection> {
+    let invalid =
+        |e: multer::Error| Rejection(StatusCode::BAD_REQUEST, format!("invalid form: {e}"));
+    let stream = futures::stream::once(async move { Ok::<_, Infallible>(body) });
+    let mut form = multer::Multipart::new(stream, boundary);
+    while let Some(field) = form.next_field().await.map_err(invalid)? {
+        if let Some(name) = field.file_name().map(str::to_string) {
+            return Ok((name, field.bytes().await.map_err(invalid)?));
+        }
+    }
+    Err(Rejection(
+        StatusCode::BAD_REQUEST,
+        "the form contains no file".to_string(),
+    ))
+}
+
+fn verdict_response(result: ScanResult) -> Response<Full<Bytes>> {
+    let status = match &result.outcome {
+        Outcome::Verdict(_) | Outcome::Skipped(_) => StatusCode::OK,
+        Outcome::Error(Error::InvalidSha256(_)) => StatusCode::BAD_REQUEST,
+        Outcome::Error(Error::Cancelled) => StatusCode::GATEWAY_TIMEOUT,
+        Outcome::Error(e) if e.is_retryable() => StatusCode::SERVICE_UNAVAILABLE,
+        Outcome::Error(_) => StatusCode::BAD_GATEWAY,
+    };
+    json_response(status, &ResultRecord::from(&result))
+}
+
+fn json_response(status: StatusCode, body: &impl Serialize) -> Response<Full<Bytes>> {
+    let body = serde_json::to_vec(body).expect("the response serializes to JSON");
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn io_error(e: std::io::Error) -> Error {
+    Error::IoError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::time::Duration;
+    use vaas::message::Verdict;
+
+    const EICAR_SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+    /// Finds everything clean and remembers the largest number of scans running at the same time.
+    #[derive(Default)]
+    struct FakeScanner {
+        unavailable: bool,
+        running: Cell<usize>,
+        most_running: Cell<usize>,
+    }
+
+    impl FakeScanner {
+        async fn verdict(&self, sha256: Sha256) -> VResult<VaasVerdict> {
+            self.running.set(self.running.get() + 1);
+            self.most_running
+                .set(self.most_running.get().max(self.running.get()));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.running.set(self.running.get() - 1);
+            if self.unavailable {
+                return Err(Error::ConnectionClosed);
+            }
+            Ok(VaasVerdict::new(sha256, Verdict::Clean))
+        }
+    }
+
+    impl Scanner for FakeScanner {
+        async fn for_buf(&self, buf: Vec<u8>) -> VResult<VaasVerdict> {
+            self.verdict(Sha256::from(buf.as_slice())).await
+        }
+
+        async fn for_sha256(&self, sha256: &Sha256) -> VResult<VaasVerdict> {
+            self.verdict(sha256.clone()).await
+        }
+
+        async fn for_url(&self, _url: &Url) -> VResult<VaasVerdict> {
+            self.verdict(Sha256::from(&b""[..])).await
+        }
+
+        async fn health(&self) -> VResult<()> {
+            match self.unavailable {
+                true => Err(Error::ConnectionClosed),
+                false => Ok(()),
+            }
+        }
+    }
+
+    fn server(scanner: FakeScanner) -> Server<FakeScanner> {
+        Server::new(
+            scanner,
+            ServeOptions {
+                concurrency: 2,
+                max_body_size: 1024,
+            },
+        )
+    }
+
+    fn post(path: &str, body: impl Into<Bytes>) -> Request<Full<Bytes>> {
+        Request::post(path).body(Full::new(body.into())).unwrap()
+    }
+
+    async fn send(
+        server: &Server<FakeScanner>,
+        request: Request<Full<Bytes>>,
+    ) -> (StatusCode, serde_json::Value) {
+        let response = server.handle(request).await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn raw_file_is_scanned() {
+        let server = server(FakeScanner::default());
+
+        let (status, body) = send(&server, post("/scan/file", "hello")).await;
+
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("upload", body["target"]);
+        assert_eq!("Clean", body["verdict"]);
+        assert_eq!(Sha256::from(&b"hello"[..]).to_string(), body["sha256"]);
+    }
+
+    #[tokio::test]
+    async fn file_from_a_form_is_scanned_with_its_name() {
+        let server = server(FakeScanner::default());
+        let form = "--XYZ\r\n\
+            Content-Disposition: form-data; name=\"comment\"\r\n\r\n\
+            not a file\r\n\
+            --XYZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"sample.exe\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n\
+            hello\r\n\
+            --XYZ--\r\n";
+        let request = Request::post("/scan/file")
+            .header(CONTENT_TYPE, "multipart/form-data; boundary=XYZ")
+            .body(Full::new(Bytes::from(form)))
+            .unwrap();
+
+        let (status, body) = send(&server, request).await;
+
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("sample.exe", body["target"]);
+        assert_eq!(Sha256::from(&b"hello"[..]).to_string(), body["sha256"]);
+    }
+
+    #[tokio::test]
+    async fn too_large_body_is_rejected() {
+        let server = server(FakeScanner::default());
+
+        let (status, body) = send(&server, post("/scan/file", vec![0; 1025])).await;
+
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+        assert_eq!("the body is larger than 1024 bytes", body["error"]);
+    }
+
+    #[tokio::test]
+    async fn sha256_and_url_are_looked_up() {
+        let server = server(FakeScanner::default());
+
+        let sha256 = format!(r#"{{"sha256": "{EICAR_SHA256}"}}"#);
+        let (status, body) = send(&server, post("/scan/sha256", sha256)).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("sha256", body["target_type"]);
+        assert_eq!(EICAR_SHA256, body["sha256"]);
+
+        let url = r#"{"url": "https://www.gdata.de"}"#;
+        let (status, body) = send(&server, post("/scan/url", url)).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("https://www.gdata.de", body["target"]);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests_are_bad_requests() {
+        let server = server(FakeScanner::default());
+
+        let (status, body) = send(&server, post("/scan/sha256", r#"{"sha256": "abc"}"#)).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+        assert_eq!("InvalidSha256", body["error"]["kind"]);
+
+        let (status, _) = send(&server, post("/scan/url", r#"{"url": "not a url"}"#)).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+
+        let (status, _) = send(&server, post("/scan/sha256", "abc")).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_and_methods_are_rejected() {
+        let server = server(FakeScanner::default());
+
+        let (status, _) = send(&server, post("/scan/files", "")).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let request = Request::get("/scan/file").body(Full::default()).unwrap();
+        let (status, _) = send(&server, request).await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, status);
+    }
+
+    #[tokio::test]
+    async fn health_reflects_the_connection() {
+        let healthz = || Request::get("/healthz").body(Full::default()).unwrap();
+
+        let (status, body) = send(&server(FakeScanner::default()), healthz()).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!("ok", body["status"]);
+
+        let unavailable = server(FakeScanner {
+            unavailable: true,
+            ..FakeScanner::default()
+        });
+        let (status, body) = send(&unavailable, healthz()).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!("unavailable", body["status"]);
+
+        let (status, body) = send(&unavailable, post("/scan/file", "hello")).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!("ConnectionClosed", body["error"]["kind"]);
+    }
+
+    #[tokio::test]
+    async fn scans_are_limited_to_the_concurrency() {
+        let server = server(FakeScanner::default());
+
+        let responses =
+            futures::future::join_all((0..5).map(|_| send(&server, post("/scan/file", "hello"))))
+                .await;
+
+        assert!(responses
+            .iter()
+            .all(|(status, _)| *status == StatusCode::OK));
+        assert_eq!(2, server.scanner.most_running.get());
+    }
+}
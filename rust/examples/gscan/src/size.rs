@@ -0,0 +1,81 @@
+/// Parse a file size like `1048576`, `500M`, `2G` or `1.5GiB`.
+///
+/// The units are binary, `1K` is 1024 bytes, and are case-insensitive. `B` and `iB` after the unit are optional.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let number_end = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(number_end);
+    let unit = unit.trim().to_ascii_lowercase();
+    let unit = unit
+        .strip_suffix("ib")
+        .or_else(|| unit.strip_suffix('b'))
+        .unwrap_or(&unit);
+    let exponent = match unit {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return Err(format!("unknown unit in {size}, use K, M, G or T")),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("{size} is not a size like 500M or 2G"))?;
+    let bytes = number * 1024_f64.powi(exponent);
+    if bytes > u64::MAX as f64 {
+        return Err(format!("{size} is too large"));
+    }
+    Ok(bytes as u64)
+}
+
+/// Format a number of bytes with the largest binary unit which keeps the number at least 1, e.g. `1.5 GiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_with_units_are_binary_multiples() {
+        assert_eq!(Ok(1000), parse_size("1000"));
+        assert_eq!(Ok(2048), parse_size("2K"));
+        assert_eq!(Ok(500 * 1024 * 1024), parse_size("500M"));
+        assert_eq!(Ok(500 * 1024 * 1024), parse_size("500mb"));
+        assert_eq!(Ok(2 * 1024 * 1024 * 1024), parse_size("2G"));
+        assert_eq!(Ok(3 * 512 * 1024 * 1024), parse_size("1.5GiB"));
+        assert_eq!(Ok(1024_u64.pow(4)), parse_size("1 T"));
+        assert_eq!(Ok(10), parse_size("10B"));
+    }
+
+    #[test]
+    fn invalid_sizes_are_rejected() {
+        for size in ["", "M", "1.2.3G", "5X", "-1K", "100000000000T"] {
+            assert!(parse_size(size).is_err(), "{size}");
+        }
+    }
+
+    #[test]
+    fn sizes_are_formatted_with_the_largest_unit() {
+        assert_eq!("512 B", format_size(512));
+        assert_eq!("1.5 KiB", format_size(1536));
+        assert_eq!("500.0 MiB", format_size(500 * 1024 * 1024));
+        assert_eq!("2.0 GiB", format_size(2 * 1024 * 1024 * 1024));
+    }
+}
@@ -0,0 +1,360 @@
+use crate::files::WalkOptions;
+use crate::output::{ScanResult, TargetType};
+use crate::report::Reporter;
+use crate::scan::retry;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use vaas::cancellation::CancellationToken;
+use vaas::error::{Error, VResult};
+use vaas::message::Verdict;
+use vaas::VaasScanner;
+
+/// How long a file has to stay unchanged before it is scanned.
+pub const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Collects the files which were created or changed and hands them out once they stopped changing.
+///
+/// Downloads and copies write a file in many steps, each of them raising an event. A file is ready
+/// when there was no event for the quiet period and its size did not change since the last check.
+#[derive(Debug)]
+pub struct Debouncer {
+    quiet_period: Duration,
+    pending: HashMap<PathBuf, Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    changed: Instant,
+    size: Option<u64>,
+}
+
+impl Debouncer {
+    pub fn new(quiet_period: Duration) -> Self {
+        Self {
+            quiet_period,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Note that the file was created or changed at `now`.
+    pub fn changed(&mut self, path: PathBuf, now: Instant) {
+        self.pending
+            .entry(path)
+            .and_modify(|pending| pending.changed = now)
+            .or_insert(Pending {
+                changed: now,
+                size: None,
+            });
+    }
+
+    /// The files which are ready to be scanned at `now` with their sizes, sorted by path.
+    /// `size_of` returns the size of a regular file, or `None` if the path is gone or not a regular file,
+    /// which removes it from the pending files.
+    pub fn ready(
+        &mut self,
+        now: Instant,
+        size_of: impl Fn(&Path) -> Option<u64>,
+    ) -> Vec<(PathBuf, u64)> {
+        let quiet_period = self.quiet_period;
+        let mut ready = Vec::new();
+        self.pending.retain(|path, pending| {
+            if now.saturating_duration_since(pending.changed) < quiet_period {
+                return true;
+            }
+            match size_of(path) {
+                None => false,
+                Some(size) if pending.size == Some(size) => {
+                    ready.push((path.clone(), size));
+                    false
+                }
+                Some(size) => {
+                    pending.size = Some(size);
+                    pending.changed = now;
+                    true
+                }
+            }
+        });
+        ready.sort();
+        ready
+    }
+}
+
+/// Where the watched files are found and what happens to malicious ones.
+#[derive(Debug, Default)]
+pub struct WatchOptions {
+    pub walk: WalkOptions,
+    /// Malicious files are moved into this directory.
+    pub quarantine: Option<PathBuf>,
+    /// How often a scan which failed with a transient error is repeated.
+    pub retries: u32,
+    pub timeout: Option<CancellationToken>,
+}
+
+impl WatchOptions {
+    fn is_watched(&self, path: &Path) -> bool {
+        let hidden = self.walk.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        let quarantined = self
+            .quarantine
+            .as_deref()
+            .is_some_and(|dir| path.starts_with(dir));
+        !hidden
+            && !quarantined
+            && !self.walk.exclude.is_excluded(path)
+            && self.walk.extensions.is_selected(path)
+    }
+}
+
+/// Scan the files created or changed in `paths` until Ctrl-C is pressed and return all results.
+///
+/// Directories are watched including their subdirectories if `recursive` is set. With a
+/// [`LazyConnection`](vaas::LazyConnection) as scanner, the connection is established again after VaaS closed it,
+/// so the watch survives disconnects.
+pub async fn watch<W: Write>(
+    paths: &[PathBuf],
+    options: &WatchOptions,
+    scanner: &dyn VaasScanner,
+    reporter: &mut Reporter<W>,
+) -> VResult<Vec<ScanResult>> {
+    let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // The receiver is only gone after the watch ended.
+        let _ = sender.send(event);
+    })
+    .map_err(watch_error)?;
+    let mode = if options.walk.recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    for path in paths {
+        watcher
+            .watch(path, mode)
+            .map_err(|e| Error::IoError(format!("{}: {e}", path.display())))?;
+    }
+    eprintln!("Watching for new files, press Ctrl-C to stop");
+
+    let mut debouncer = Debouncer::new(QUIET_PERIOD);
+    let mut tick = tokio::time::interval(QUIET_PERIOD / 2);
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    let mut results = Vec::new();
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => break,
+            Some(event) = events.recv() => match event {
+                Ok(event) if is_write(&event.kind) => event
+                    .paths
+                    .into_iter()
+                    .filter(|path| options.is_watched(path))
+                    .for_each(|path| debouncer.changed(path, Instant::now())),
+                Ok(_) => {}
+                Err(e) => eprintln!("Warning: {}", watch_error(e)),
+            },
+            _ = tick.tick() => {
+                for (file, size) in debouncer.ready(Instant::now(), file_size) {
+                    let result = match options.walk.skip_reason(size) {
+                        Some(reason) => ScanResult::skipped(file.display().to_string(), TargetType::File, reason),
+                        None => scan(&file, options, scanner).await.with_size(size),
+                    };
+                    reporter.report(&result);
+                    if let Some(dir) = &options.quarantine {
+                        move_if_malicious(&file, &result, dir);
+                    }
+                    results.push(result);
+                }
+            }
+        }
+    }
+    Ok(results)
+}
+
+fn is_write(kind: &EventKind) -> bool {
+    match kind {
+        EventKind::Create(_) => true,
+        // A file renamed into the directory is new there, one renamed away is gone.
+        EventKind::Modify(ModifyKind::Name(_)) => true,
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Modify(_) => true,
+        _ => false,
+    }
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+async fn scan(file: &Path, options: &WatchOptions, scanner: &dyn VaasScanner) -> ScanResult {
+    let started = Instant::now();
+    let (verdict, attempts) = retry(options.retries, || {
+        scanner.for_file(file, options.timeout.as_ref())
+    })
+    .await;
+    ScanResult::new(file.display().to_string(), TargetType::File, verdict)
+        .with_duration(started.elapsed())
+        .with_attempts(attempts)
+}
+
+fn move_if_malicious(file: &Path, result: &ScanResult, dir: &Path) {
+    if !result
+        .verdict()
+        .is_some_and(|v| matches!(v.verdict, Verdict::Malicious { .. }))
+    {
+        return;
+    }
+    match quarantine(file, dir) {
+        Ok(target) => eprintln!("Moved {} to {}", file.display(), target.display()),
+        Err(e) => eprintln!("Warning: cannot move {}: {e}", file.display()),
+    }
+}
+
+/// Move the file into the quarantine directory without replacing a file of the same name moved there before.
+fn quarantine(file: &Path, dir: &Path) -> io::Result<PathBuf> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+    std::fs::create_dir_all(dir)?;
+    let mut target = dir.join(name);
+    let mut n = 1;
+    while target.try_exists()? {
+        target = dir.join(format!("{}.{n}", name.to_string_lossy()));
+        n += 1;
+    }
+    // Renaming fails if the quarantine is on another file system.
+    if std::fs::rename(file, &target).is_err() {
+        std::fs::copy(file, &target)?;
+        std::fs::remove_file(file)?;
+    }
+    Ok(target)
+}
+
+fn watch_error(e: notify::Error) -> Error {
+    Error::IoError(format!("Cannot watch files: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const QUIET: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn file_is_ready_when_events_and_size_settled() {
+        let mut debouncer = Debouncer::new(QUIET);
+        let start = Instant::now();
+        let file = PathBuf::from("download.zip");
+
+        debouncer.changed(file.clone(), start);
+        assert!(debouncer.ready(start + QUIET / 2, |_| Some(10)).is_empty());
+        // The first check after the quiet period records the size.
+        assert!(debouncer.ready(start + QUIET, |_| Some(10)).is_empty());
+        // Still growing.
+        assert!(debouncer.ready(start + QUIET * 2, |_| Some(20)).is_empty());
+        assert!(debouncer
+            .ready(start + QUIET * 5 / 2, |_| Some(20))
+            .is_empty());
+        assert_eq!(
+            vec![(file.clone(), 20)],
+            debouncer.ready(start + QUIET * 3, |_| Some(20))
+        );
+        assert!(debouncer.ready(start + QUIET * 10, |_| Some(20)).is_empty());
+    }
+
+    #[test]
+    fn new_event_restarts_quiet_period() {
+        let mut debouncer = Debouncer::new(QUIET);
+        let start = Instant::now();
+        let file = PathBuf::from("a");
+
+        debouncer.changed(file.clone(), start);
+        assert!(debouncer.ready(start + QUIET, |_| Some(1)).is_empty());
+        debouncer.changed(file.clone(), start + QUIET * 3 / 2);
+        assert!(debouncer.ready(start + QUIET * 2, |_| Some(1)).is_empty());
+        assert_eq!(
+            vec![(file, 1)],
+            debouncer.ready(start + QUIET * 3, |_| Some(1))
+        );
+    }
+
+    #[test]
+    fn removed_file_is_dropped() {
+        let mut debouncer = Debouncer::new(QUIET);
+        let start = Instant::now();
+        debouncer.changed(PathBuf::from("a"), start);
+
+        assert!(debouncer.ready(start + QUIET, |_| None).is_empty());
+        assert!(debouncer.ready(start + QUIET * 3, |_| Some(1)).is_empty());
+    }
+
+    #[test]
+    fn quarantine_keeps_earlier_files_of_the_same_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine_dir = dir.path().join("quarantine");
+        let file = dir.path().join("eicar.com");
+
+        fs::write(&file, "first").unwrap();
+        let first = quarantine(&file, &quarantine_dir).unwrap();
+        fs::write(&file, "second").unwrap();
+        let second = quarantine(&file, &quarantine_dir).unwrap();
+
+        assert!(!file.exists());
+        assert_eq!(quarantine_dir.join("eicar.com"), first);
+        assert_eq!(quarantine_dir.join("eicar.com.1"), second);
+        assert_eq!("first", fs::read_to_string(first).unwrap());
+        assert_eq!("second", fs::read_to_string(second).unwrap());
+    }
+
+    #[test]
+    fn quarantine_and_excluded_files_are_not_watched() {
+        let options = WatchOptions {
+            walk: WalkOptions {
+                skip_hidden: true,
+                exclude: crate::patterns::Excludes::new(["*.part"]).unwrap(),
+                ..WalkOptions::default()
+            },
+            quarantine: Some(PathBuf::from("/downloads/quarantine")),
+            ..WatchOptions::default()
+        };
+
+        assert!(options.is_watched(Path::new("/downloads/setup.exe")));
+        assert!(!options.is_watched(Path::new("/downloads/quarantine/setup.exe")));
+        assert!(!options.is_watched(Path::new("/downloads/setup.exe.part")));
+        assert!(!options.is_watched(Path::new("/downloads/.setup.exe")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scan_retries_transient_errors_of_the_scanner() {
+        let options = WatchOptions {
+            retries: 2,
+            ..WatchOptions::default()
+        };
+        let scanner = vaas::MockScanner::new()
+            .with_file("/downloads/closed.exe", Err(Error::ConnectionClosed))
+            .with_file(
+                "/downloads/eicar.com",
+                Ok(Verdict::Malicious {
+                    detection: "EICAR".to_string(),
+                }),
+            );
+
+        let failed = scan(Path::new("/downloads/closed.exe"), &options, &scanner).await;
+        let malicious = scan(Path::new("/downloads/eicar.com"), &options, &scanner).await;
+
+        assert_eq!(3, failed.attempts);
+        assert!(failed.verdict().is_none());
+        assert_eq!(1, malicious.attempts);
+        assert!(malicious
+            .verdict()
+            .is_some_and(|v| matches!(v.verdict, Verdict::Malicious { .. })));
+    }
+}
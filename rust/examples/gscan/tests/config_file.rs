@@ -0,0 +1,89 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+use std::fs;
+
+/// gscan without credentials in the environment, run in an empty directory so no config or `.env` file is found.
+fn gscan(dir: &tempfile::TempDir) -> Command {
+    let mut command = Command::cargo_bin("gscan").unwrap();
+    command.env_clear().current_dir(dir.path());
+    command
+}
+
+#[test]
+fn config_init_writes_a_template_once() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir).args(["config", "init"]).assert().success();
+    let template = fs::read_to_string(dir.path().join("gscan.toml")).unwrap();
+    assert!(template.contains("# recursive = true"));
+
+    gscan(&dir)
+        .args(["config", "init"])
+        .assert()
+        .code(2)
+        .stderr(contains("already exists"));
+    gscan(&dir)
+        .args(["config", "init", "--force"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn config_in_current_directory_provides_targets_and_options() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("gscan.toml"),
+        "files = [\"missing.txt\"]\noutput-format = \"ndjson\"\n",
+    )
+    .unwrap();
+
+    gscan(&dir)
+        .assert()
+        .code(2)
+        .stdout(contains(r#""target":"missing.txt""#));
+    gscan(&dir)
+        .args(["--output-format", "text"])
+        .assert()
+        .code(2)
+        .stdout(contains("missing.txt -> IO Error"));
+}
+
+#[test]
+fn config_is_found_in_xdg_config_home_or_given_explicitly() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("xdg/gscan")).unwrap();
+    fs::write(
+        dir.path().join("xdg/gscan/gscan.toml"),
+        "files = [\"from-xdg.txt\"]\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("other.toml"),
+        "files = [\"from-other.txt\"]\n",
+    )
+    .unwrap();
+
+    gscan(&dir)
+        .env("XDG_CONFIG_HOME", dir.path().join("xdg"))
+        .assert()
+        .code(2)
+        .stdout(contains("from-xdg.txt -> IO Error"));
+    gscan(&dir)
+        .env("XDG_CONFIG_HOME", dir.path().join("xdg"))
+        .args(["scan", "--config", "other.toml"])
+        .assert()
+        .code(2)
+        .stdout(contains("from-other.txt -> IO Error"));
+}
+
+#[test]
+fn invalid_config_exits_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("gscan.toml"), "recursve = true\n").unwrap();
+
+    gscan(&dir)
+        .args(["-f", "a.txt"])
+        .assert()
+        .code(2)
+        .stderr(contains("gscan.toml: recursve is not an option of gscan"));
+}
@@ -0,0 +1,87 @@
+use assert_cmd::Command;
+use predicates::str::contains;
+
+/// gscan without credentials in the environment, run in an empty directory so no `.env` file is loaded.
+fn gscan(dir: &tempfile::TempDir) -> Command {
+    let mut command = Command::cargo_bin("gscan").unwrap();
+    command.env_clear().current_dir(dir.path());
+    command
+}
+
+#[test]
+fn missing_file_exits_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir)
+        .args(["-f", "missing.txt"])
+        .assert()
+        .code(2)
+        .stdout(contains("missing.txt -> IO Error"));
+}
+
+#[test]
+fn missing_credentials_exit_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "content").unwrap();
+
+    gscan(&dir)
+        .args(["-f", "file.txt"])
+        .assert()
+        .code(2)
+        .stderr(contains("CLIENT_ID is not set"));
+}
+
+#[test]
+fn unknown_fail_on_value_exits_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir)
+        .args(["-f", "missing.txt", "--fail-on", "unknown"])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn skipped_files_are_not_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("image.iso"), "x".repeat(2048)).unwrap();
+
+    gscan(&dir)
+        .args(["-f", "image.iso", "--max-file-size", "1K"])
+        .args(["--output-format", "ndjson"])
+        .assert()
+        .code(0)
+        .stdout(contains(r#""skipped":"2.0 KiB is larger than 1.0 KiB""#))
+        .stdout(contains(r#""error":null"#))
+        .stderr(contains("0 errors, 1 skipped"));
+}
+
+#[test]
+fn invalid_max_file_size_exits_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir)
+        .args(["-f", "file.txt", "--max-file-size", "5X"])
+        .assert()
+        .code(2)
+        .stderr(contains("unknown unit in 5X"));
+}
+
+#[test]
+fn invalid_allowlist_file_exits_with_error() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("file.txt"), "content").unwrap();
+    let sha256 = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+    std::fs::write(
+        dir.path().join("allow.txt"),
+        format!("{sha256}\nnot a hash\n"),
+    )
+    .unwrap();
+
+    gscan(&dir)
+        .args(["-f", "file.txt", "--allowlist-file", "allow.txt"])
+        .assert()
+        .code(2)
+        .stderr(contains("--allowlist-file allow.txt: "))
+        .stderr(contains("line 2"));
+}
@@ -0,0 +1,40 @@
+use assert_cmd::Command;
+use predicates::str::{contains, starts_with};
+
+fn gscan() -> Command {
+    let mut command = Command::cargo_bin("gscan").unwrap();
+    command.env_clear();
+    command
+}
+
+#[test]
+fn completions_are_printed_for_the_shell() {
+    gscan()
+        .args(["completions", "zsh"])
+        .assert()
+        .success()
+        .stdout(starts_with("#compdef gscan"))
+        .stdout(contains("--files-from"))
+        .stdout(contains("--output-format"));
+}
+
+#[test]
+fn man_page_is_printed() {
+    gscan()
+        .arg("man")
+        .assert()
+        .success()
+        .stdout(contains(".TH gscan"));
+}
+
+#[test]
+fn scan_subcommand_takes_the_scan_options() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan()
+        .current_dir(dir.path())
+        .args(["scan", "-f", "missing.txt"])
+        .assert()
+        .code(2)
+        .stdout(contains("missing.txt -> IO Error"));
+}
@@ -0,0 +1,118 @@
+use assert_cmd::Command;
+
+fn gscan(dir: &tempfile::TempDir) -> Command {
+    let mut command = Command::cargo_bin("gscan").unwrap();
+    command.env_clear().current_dir(dir.path());
+    command
+}
+
+#[test]
+fn files_from_stdin_are_merged_with_arguments_and_deduplicated() {
+    let dir = tempfile::tempdir().unwrap();
+    let list = "# missing files\nmissing file.txt\n\nother.txt\nmissing file.txt\n";
+
+    let output = gscan(&dir)
+        .args(["-f", "other.txt", "--files-from", "-"])
+        .write_stdin(list)
+        .assert()
+        .code(2)
+        .get_output()
+        .stdout
+        .clone();
+
+    let targets: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(" -> ").next().unwrap().to_string())
+        .collect();
+    assert_eq!(vec!["other.txt", "missing file.txt"], targets);
+}
+
+#[test]
+fn invalid_urls_are_reported_per_item() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("urls.txt"),
+        "not a url
+",
+    )
+    .unwrap();
+
+    gscan(&dir)
+        .args(["--urls-from", "urls.txt", "-u", "also not a url"])
+        .args(["--output-format", "ndjson"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(
+            r#""target":"also not a url","target_type":"url""#,
+        ))
+        .stdout(predicates::str::contains(
+            r#""target":"not a url","target_type":"url""#,
+        ))
+        .stdout(predicates::str::contains("not a valid url"));
+}
+
+#[test]
+fn invalid_hashes_are_reported_per_item() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir)
+        .args(["-H", "not-a-hash", "--output-format", "ndjson"])
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(
+            r#""target":"not-a-hash","target_type":"sha256""#,
+        ))
+        .stdout(predicates::str::contains(r#""kind":"InvalidSha256""#));
+}
+
+#[test]
+fn invalid_lines_of_a_hash_list_are_reported_with_their_position() {
+    let dir = tempfile::tempdir().unwrap();
+    let list = "# sha256sum *\r\nnot-a-hash  a.txt\r\n\r\n0123  b.txt \r\n";
+
+    gscan(&dir)
+        .args(["--hashes-from", "-", "--output-format", "ndjson"])
+        .write_stdin(list)
+        .assert()
+        .code(2)
+        .stdout(predicates::str::contains(
+            r#""target":"<stdin>:2","target_type":"sha256""#,
+        ))
+        .stdout(predicates::str::contains(
+            r#""target":"<stdin>:4","target_type":"sha256""#,
+        ))
+        .stdout(predicates::str::contains("expected 64 hex digits, found 4"));
+}
+
+#[test]
+fn content_piped_to_stdin_is_read_completely() {
+    let dir = tempfile::tempdir().unwrap();
+    // Three MiB of binary data which is not valid UTF-8.
+    let content: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 256) as u8).collect();
+
+    gscan(&dir)
+        .args(["-f", "-", "--max-file-size", "1M"])
+        .write_stdin(content)
+        .assert()
+        .success()
+        .stdout("<stdin> -> Skipped (3.0 MiB is larger than 1.0 MiB)\n");
+}
+
+#[test]
+fn stdin_can_only_be_read_once() {
+    let dir = tempfile::tempdir().unwrap();
+
+    gscan(&dir)
+        .args(["-f", "-", "-f", "-"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("-f - can only be given once"));
+    gscan(&dir)
+        .args(["-f", "-", "--urls-from", "-"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains(
+            "-f - cannot be combined with a list read from stdin",
+        ));
+}
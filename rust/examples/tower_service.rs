@@ -0,0 +1,68 @@
+//! Requests verdicts through tower middleware: transient failures are retried, and at most 4 requests run at once.
+//!
+//! Run with `CLIENT_ID=... CLIENT_SECRET=... cargo run --example tower_service --features tower -- <files>`.
+
+use std::future::{ready, Ready};
+use std::path::PathBuf;
+use tower::limit::ConcurrencyLimitLayer;
+use tower::retry::{Policy, RetryLayer};
+use tower::{ServiceBuilder, ServiceExt};
+use vaas::auth::authenticators::ClientCredentials;
+use vaas::error::{Error, VResult};
+use vaas::{ScanRequest, Vaas, VaasService, VaasVerdict};
+
+/// Retries requests which failed with a transient error, see [`Error::is_retryable`].
+#[derive(Clone)]
+struct RetryTransient {
+    attempts_left: usize,
+}
+
+impl Policy<ScanRequest, VaasVerdict, Error> for RetryTransient {
+    type Future = Ready<()>;
+
+    fn retry(
+        &mut self,
+        _request: &mut ScanRequest,
+        result: &mut VResult<VaasVerdict>,
+    ) -> Option<Self::Future> {
+        match result {
+            Err(e) if e.is_retryable() && self.attempts_left > 0 => {
+                self.attempts_left -= 1;
+                Some(ready(()))
+            }
+            _ => None,
+        }
+    }
+
+    fn clone_request(&mut self, request: &ScanRequest) -> Option<ScanRequest> {
+        Some(request.clone())
+    }
+}
+
+#[tokio::main]
+async fn main() -> VResult<()> {
+    let client_id = std::env::var("CLIENT_ID").expect("CLIENT_ID is not set");
+    let client_secret = std::env::var("CLIENT_SECRET").expect("CLIENT_SECRET is not set");
+    let authenticator = ClientCredentials::new(client_id, client_secret);
+    let connection = Vaas::builder(authenticator).build()?.connect().await?;
+
+    let service = ServiceBuilder::new()
+        .layer(RetryLayer::new(RetryTransient { attempts_left: 2 }))
+        .layer(ConcurrencyLimitLayer::new(4))
+        .service(VaasService::new(connection));
+
+    let requests = std::env::args().skip(1).map(PathBuf::from).map(|file| {
+        let service = service.clone();
+        async move {
+            let verdict = service.oneshot(ScanRequest::File(file.clone())).await;
+            (file, verdict)
+        }
+    });
+    for (file, verdict) in futures::future::join_all(requests).await {
+        match verdict {
+            Ok(verdict) => println!("{}: {}", file.display(), verdict.verdict),
+            Err(e) => println!("{}: {e}", file.display()),
+        }
+    }
+    Ok(())
+}
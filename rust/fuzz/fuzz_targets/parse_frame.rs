@@ -0,0 +1,9 @@
+//! Arbitrary payloads of text frames, as the reader loop of a connection receives them.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    vaas::fuzzing::parse_frame(data);
+});
@@ -0,0 +1,9 @@
+//! Arbitrary JSON for verdict responses, including their conversion to verdicts and the checks of upload URLs.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|json: &str| {
+    vaas::fuzzing::verdict_response(json);
+});
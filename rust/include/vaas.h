@@ -0,0 +1,115 @@
+#ifndef VAAS_H
+#define VAAS_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdint.h>
+
+/**
+ * The function succeeded.
+ */
+#define VAAS_OK 0
+
+/**
+ * An argument is `NULL` or invalid, e.g. not UTF-8 or not a SHA256.
+ */
+#define VAAS_ERROR_INVALID_ARGUMENT 1
+
+/**
+ * The credentials were rejected.
+ */
+#define VAAS_ERROR_UNAUTHORIZED 2
+
+/**
+ * The request or the connect timed out.
+ */
+#define VAAS_ERROR_TIMEOUT 3
+
+/**
+ * The connection to VaaS failed or was closed.
+ */
+#define VAAS_ERROR_CONNECTION 4
+
+/**
+ * A file could not be read.
+ */
+#define VAAS_ERROR_IO 5
+
+/**
+ * VaaS answered with an error or an invalid response.
+ */
+#define VAAS_ERROR_SERVER 6
+
+/**
+ * Any other error, e.g. a bug in the SDK.
+ */
+#define VAAS_ERROR_INTERNAL 7
+
+/**
+ * A connection to VaaS, created with [`vaas_connect`] and freed with [`vaas_close`].
+ */
+typedef struct VaasHandle VaasHandle;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Connect to VaaS with client credentials and store the handle of the connection in `out_handle`.
+ *
+ * # Safety
+ *
+ * `client_id` and `client_secret` must be `NULL` or valid C strings, `out_handle` must be `NULL` or valid
+ * for writes.
+ */
+int vaas_connect(const char *client_id, const char *client_secret, VaasHandle **out_handle);
+
+/**
+ * Request a verdict for the file at `path` and store it as JSON string in `out_verdict_json`.
+ *
+ * # Safety
+ *
+ * `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed. `path` must be
+ * `NULL` or a valid C string, `out_verdict_json` must be `NULL` or valid for writes.
+ */
+int vaas_for_file(const VaasHandle *handle, const char *path, char **out_verdict_json);
+
+/**
+ * Request a verdict for the SHA256 given as 64 hex digits and store it as JSON string in `out_verdict_json`.
+ *
+ * # Safety
+ *
+ * `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed. `sha256` must be
+ * `NULL` or a valid C string, `out_verdict_json` must be `NULL` or valid for writes.
+ */
+int vaas_for_sha256(const VaasHandle *handle, const char *sha256, char **out_verdict_json);
+
+/**
+ * The message of the last error on the calling thread, or `NULL` if no function failed on it yet.
+ * The string is owned by the SDK and valid until the next call of a `vaas_*` function on the thread.
+ */
+const char *vaas_last_error(void);
+
+/**
+ * Free a string returned by the SDK. Does nothing for `NULL`.
+ *
+ * # Safety
+ *
+ * `string` must be `NULL` or a string returned in an `out_verdict_json` argument, which has not been freed yet.
+ */
+void vaas_free_string(char *string);
+
+/**
+ * Close the connection and free the handle. Does nothing for `NULL`.
+ *
+ * # Safety
+ *
+ * `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed yet.
+ */
+void vaas_close(VaasHandle *handle);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* VAAS_H */
@@ -1,12 +1,192 @@
-use crate::error::VResult;
+use crate::error::{Error, VResult};
+use crate::message::{OAuthErrorResponse, OpenIdConnectTokenResponse};
 use async_trait::async_trait;
+use reqwest::{StatusCode, Url};
 
 pub static DEFAULT_TOKEN_URL: &str =
     "https://account.gdata.de/realms/vaas-production/protocol/openid-connect/token";
 
 /// This trait has to be implemented by any authentication methods for VaaS.
+///
+/// Implement it to obtain tokens from your own source, e.g. an internal token broker.
+/// The trait is object safe, so authenticators can be selected at runtime as `Box<dyn Authenticator + Send + Sync>`.
+/// ```rust
+/// # fn main() -> vaas::error::VResult<()> {
+/// use async_trait::async_trait;
+/// use vaas::auth::Authenticator;
+/// use vaas::error::VResult;
+/// use vaas::Vaas;
+///
+/// struct TokenBroker;
+///
+/// #[async_trait]
+/// impl Authenticator for TokenBroker {
+///     async fn get_token(&self) -> VResult<String> {
+///         // Request the token from the broker.
+///         Ok("token".to_string())
+///     }
+/// }
+///
+/// let vaas = Vaas::builder(TokenBroker).build()?;
+/// # Ok(()) }
+/// ```
 #[async_trait]
 pub trait Authenticator {
     /// Return a valid token that can be used to authenticate against the VaaS service.
     async fn get_token(&self) -> VResult<String>;
+
+    /// Return a new token, even if a previously returned token is still valid,
+    /// e.g. because VaaS rejected the previous token.
+    /// The default implementation calls [`Authenticator::get_token`].
+    async fn refresh_token(&self) -> VResult<String> {
+        self.get_token().await
+    }
+
+    /// Receive the HTTP client configured on the [`Builder`](crate::Builder), e.g. with a proxy.
+    /// Authenticators which request their token over HTTP should use it for the request.
+    /// Authenticators with a client of their own, e.g. set with [`ClientCredentials::with_http_client`](crate::auth::authenticators::ClientCredentials::with_http_client),
+    /// should keep it. The default implementation ignores the client.
+    fn set_http_client(&mut self, _http_client: reqwest::Client) {}
+
+    /// Validate the configuration of the authenticator before connecting.
+    /// Called by [`Builder::build`](crate::Builder::build). The default implementation accepts any configuration.
+    fn validate(&self) -> VResult<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Authenticator for Box<dyn Authenticator + Send + Sync> {
+    async fn get_token(&self) -> VResult<String> {
+        (**self).get_token().await
+    }
+
+    async fn refresh_token(&self) -> VResult<String> {
+        (**self).refresh_token().await
+    }
+
+    fn set_http_client(&mut self, http_client: reqwest::Client) {
+        (**self).set_http_client(http_client)
+    }
+
+    fn validate(&self) -> VResult<()> {
+        (**self).validate()
+    }
+}
+
+/// Request a token from the OpenID Connect token endpoint with the given form parameters.
+pub(crate) async fn request_token(
+    http_client: &reqwest::Client,
+    token_url: &Url,
+    params: &[(&str, &str)],
+) -> VResult<OpenIdConnectTokenResponse> {
+    ensure_token_url_scheme(token_url)?;
+    let token_response = http_client
+        .post(token_url.clone())
+        .form(params)
+        .send()
+        .await?;
+
+    match token_response.status() {
+        StatusCode::OK => {
+            let json_string = token_response.text().await?;
+            OpenIdConnectTokenResponse::try_from(&json_string)
+        }
+        status => Err(token_error(
+            status,
+            token_response.text().await.unwrap_or_default(),
+        )),
+    }
+}
+
+/// Map the response of a failed token request to an error. Invalid credentials are reported as
+/// [`Error::Unauthorized`], other OAuth errors as [`Error::AuthServer`].
+fn token_error(status: StatusCode, body: String) -> Error {
+    let response = match OAuthErrorResponse::try_from(&body) {
+        Ok(response) => response,
+        Err(_) => return Error::FailedAuthTokenRequest(status, body),
+    };
+    match response.error.as_str() {
+        "invalid_client" | "invalid_grant" => {
+            Error::Unauthorized(response.error_description.unwrap_or(response.error))
+        }
+        _ => Error::AuthServer {
+            error: response.error,
+            description: response.error_description,
+            status,
+        },
+    }
+}
+
+pub(crate) fn ensure_not_empty(field: &str, value: &str) -> VResult<()> {
+    if value.is_empty() {
+        return Err(Error::InvalidConfig(format!("{field} must not be empty")));
+    }
+    Ok(())
+}
+
+pub(crate) fn ensure_token_url_scheme(token_url: &Url) -> VResult<()> {
+    match token_url.scheme() {
+        "https" | "http" => Ok(()),
+        _ => Err(Error::InvalidConfig(format!(
+            "token_url must use the https or http scheme, got `{token_url}`"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID_CLIENT: &str = include_str!("../../tests/fixtures/oauth/invalid_client.json");
+    const INVALID_SCOPE: &str = include_str!("../../tests/fixtures/oauth/invalid_scope.json");
+    const SERVICE_UNAVAILABLE: &str =
+        include_str!("../../tests/fixtures/oauth/service_unavailable.html");
+
+    #[test]
+    fn token_error_with_invalid_client_is_unauthorized() {
+        let error = token_error(StatusCode::UNAUTHORIZED, INVALID_CLIENT.to_string());
+        assert!(matches!(
+            error,
+            Error::Unauthorized(description) if description == "Invalid client or Invalid client credentials"
+        ));
+    }
+
+    #[test]
+    fn token_error_with_invalid_scope_is_auth_server_error() {
+        let error = token_error(StatusCode::BAD_REQUEST, INVALID_SCOPE.to_string());
+        assert!(matches!(
+            error,
+            Error::AuthServer { error, description: Some(description), status: StatusCode::BAD_REQUEST }
+                if error == "invalid_scope" && description == "Invalid scopes: vaas-audience"
+        ));
+    }
+
+    #[test]
+    fn token_error_with_html_page_keeps_body() {
+        let error = token_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            SERVICE_UNAVAILABLE.to_string(),
+        );
+        assert!(matches!(
+            error,
+            Error::FailedAuthTokenRequest(StatusCode::SERVICE_UNAVAILABLE, body) if body.contains("503")
+        ));
+    }
+
+    #[tokio::test]
+    async fn request_token_with_invalid_client_is_unauthorized() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/token")
+            .with_status(401)
+            .with_body(INVALID_CLIENT)
+            .create_async()
+            .await;
+        let token_url = Url::parse(&format!("{}/token", server.url())).unwrap();
+
+        let result = request_token(&reqwest::Client::new(), &token_url, &[]).await;
+
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+    }
 }
@@ -1,16 +1,31 @@
-use crate::auth::authenticator::{Authenticator, DEFAULT_TOKEN_URL};
-use crate::error::{Error, VResult};
+use crate::auth::authenticator::{
+    ensure_not_empty, ensure_token_url_scheme, request_token, Authenticator, DEFAULT_TOKEN_URL,
+};
+use crate::auth::token_cache::TokenCache;
+use crate::auth::Token;
+use crate::error::VResult;
+use crate::http_client::default_http_client;
 use crate::message::OpenIdConnectTokenResponse;
 use async_trait::async_trait;
-use reqwest::StatusCode;
 use reqwest::Url;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Authenticator for the VaaS service using the client credentials flow.
 /// Expects a client id and a client secret.
+///
+/// The token is cached and reused until one minute before it expires, see [`ClientCredentials::with_refresh_margin`].
+/// Clones of the authenticator share the cached token.
+#[derive(Clone)]
 pub struct ClientCredentials {
     client_id: String,
     client_secret: String,
     token_url: Url,
+    scope: Option<String>,
+    http_client: reqwest::Client,
+    custom_http_client: bool,
+    token_cache: Arc<TokenCache>,
 }
 
 impl ClientCredentials {
@@ -20,47 +35,122 @@ impl ClientCredentials {
             client_id,
             client_secret,
             token_url: Url::parse(DEFAULT_TOKEN_URL).unwrap(), // Safe to unwrap, as this is a constant URL and will always be valid.
+            scope: None,
+            http_client: default_http_client(),
+            custom_http_client: false,
+            token_cache: Arc::new(TokenCache::default()),
         }
     }
+
+    /// Create a new authenticator for the VaaS service using the client credentials flow
+    /// with the token endpoint of a self-hosted identity provider.
+    /// Fails with [`Error::InvalidConfig`](crate::error::Error::InvalidConfig) if the URL does not use the `https` or `http` scheme.
+    pub fn new_with_token_url(
+        client_id: String,
+        client_secret: String,
+        token_url: Url,
+    ) -> VResult<Self> {
+        ensure_token_url_scheme(&token_url)?;
+        Ok(Self::new(client_id, client_secret).with_token_url(token_url))
+    }
+
     /// Set the token URL to be used for authentication.
+    /// The URL must use the `https` or `http` scheme.
     pub fn with_token_url(mut self, token_url: Url) -> Self {
         self.token_url = token_url;
         self
     }
+
+    /// Request the given scope in addition to the default scopes of the client.
+    /// Call it repeatedly to request several scopes. The granted scope is returned in [`Token::scope`].
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(match self.scope {
+            Some(scopes) => format!("{scopes} {scope}"),
+            None => scope.to_string(),
+        });
+        self
+    }
+
+    /// Set the HTTP client for the token request, e.g. with a proxy or root certificates for the identity provider.
+    /// The client takes precedence over the one configured on the [`Builder`](crate::Builder).
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self.custom_http_client = true;
+        self
+    }
+
+    /// Set how long before its expiry a cached token is replaced by a new one. Defaults to one minute.
+    pub fn with_refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.token_cache = Arc::new(TokenCache::new(refresh_margin));
+        self
+    }
+
+    /// Return the cached token, or request a new one if there is no token or it is about to expire.
+    ///
+    /// Use it to call other APIs which accept the same tokens as VaaS.
+    /// The access token is a bearer token, see [`Token`] on how to handle it.
+    pub async fn token(&self) -> VResult<Token> {
+        self.token_cache
+            .get_or_request(|| self.request_token())
+            .await
+    }
+
+    async fn request_token(&self) -> VResult<OpenIdConnectTokenResponse> {
+        request_token(&self.http_client, &self.token_url, &self.params()).await
+    }
+
+    fn params(&self) -> Vec<(&str, &str)> {
+        let mut params = vec![
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+            ("grant_type", "client_credentials"),
+        ];
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+        params
+    }
+}
+
+impl fmt::Debug for ClientCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCredentials")
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"***")
+            .field("token_url", &self.token_url)
+            .field("scope", &self.scope)
+            .finish()
+    }
 }
 
 #[async_trait]
 impl Authenticator for ClientCredentials {
     async fn get_token(&self) -> VResult<String> {
-        let params = [
-            ("client_id", self.client_id.clone()),
-            ("client_secret", self.client_secret.clone()),
-            ("grant_type", "client_credentials".to_string()),
-        ];
-        let client = reqwest::Client::new();
-        let token_response = client
-            .post(self.token_url.clone())
-            .form(&params)
-            .send()
-            .await?;
-
-        match token_response.status() {
-            StatusCode::OK => {
-                let json_string = token_response.text().await?;
-                Ok(OpenIdConnectTokenResponse::try_from(&json_string)?.access_token)
-            }
-            status => Err(Error::FailedAuthTokenRequest(
-                status,
-                token_response.text().await.unwrap_or_default(),
-            )),
+        Ok(self.token().await?.access_token)
+    }
+
+    async fn refresh_token(&self) -> VResult<String> {
+        let token = self.token_cache.refresh(|| self.request_token()).await?;
+        Ok(token.access_token)
+    }
+
+    fn set_http_client(&mut self, http_client: reqwest::Client) {
+        if !self.custom_http_client {
+            self.http_client = http_client;
         }
     }
+
+    fn validate(&self) -> VResult<()> {
+        ensure_not_empty("client_id", &self.client_id)?;
+        ensure_not_empty("client_secret", &self.client_secret)?;
+        ensure_token_url_scheme(&self.token_url)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::Error::FailedAuthTokenRequest;
+    use crate::error::Error::{InvalidConfig, Unauthorized};
 
     #[tokio::test]
     async fn authenticator_returns_token() {
@@ -98,8 +188,174 @@ mod tests {
         assert!(token.is_err());
         assert!(match token {
             Ok(_) => false,
-            Err(FailedAuthTokenRequest(_, _)) => true,
+            Err(Unauthorized(_)) => true,
             _ => false,
         })
     }
+
+    #[test]
+    fn debug_redacts_client_secret() {
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "top-secret".to_string());
+
+        let debug = format!("{:?}", authenticator);
+
+        assert!(debug.contains("client_id"));
+        assert!(!debug.contains("top-secret"));
+    }
+
+    async fn mock_token_endpoint(
+        server: &mut mockito::Server,
+        expected_requests: usize,
+    ) -> mockito::Mock {
+        server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::UrlEncoded(
+                "grant_type".to_string(),
+                "client_credentials".to_string(),
+            ))
+            .with_body(r#"{"access_token":"token","expires_in":300}"#)
+            .expect(expected_requests)
+            .create_async()
+            .await
+    }
+
+    fn authenticator(server: &mockito::Server) -> ClientCredentials {
+        ClientCredentials::new("id".to_string(), "secret".to_string())
+            .with_token_url(Url::parse(&format!("{}/token", server.url())).unwrap())
+    }
+
+    #[tokio::test]
+    async fn get_token_within_validity_requests_token_once() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = mock_token_endpoint(&mut server, 1).await;
+        let authenticator = authenticator(&server);
+
+        assert_eq!("token", authenticator.get_token().await.unwrap());
+        assert_eq!("token", authenticator.clone().get_token().await.unwrap());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_token_bypasses_cached_token() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = mock_token_endpoint(&mut server, 2).await;
+        let authenticator = authenticator(&server);
+
+        authenticator.get_token().await.unwrap();
+        authenticator.refresh_token().await.unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn token_returns_expiry_and_scope() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token":"token","expires_in":300,"scope":"profile email"}"#)
+            .create_async()
+            .await;
+        let requested_at = std::time::Instant::now();
+
+        let token = authenticator(&server).token().await.unwrap();
+
+        let expires_at = token.expires_at.unwrap();
+        assert_eq!("token", token.access_token);
+        assert!(expires_at >= requested_at + Duration::from_secs(300));
+        assert!(expires_at <= std::time::Instant::now() + Duration::from_secs(300));
+        assert_eq!(Some("profile email"), token.scope.as_deref());
+    }
+
+    #[test]
+    fn params_with_scopes_serialize_to_form_body() {
+        let authenticator = ClientCredentials::new("id".to_string(), "secret".to_string())
+            .with_scope("openid")
+            .with_scope("vaas-audience");
+
+        let request = reqwest::Client::new()
+            .post("https://account.gdata.de/token")
+            .form(&authenticator.params())
+            .build()
+            .unwrap();
+        let body = request.body().and_then(|body| body.as_bytes()).unwrap();
+
+        assert_eq!(
+            "client_id=id&client_secret=secret&grant_type=client_credentials&scope=openid+vaas-audience",
+            std::str::from_utf8(body).unwrap()
+        );
+    }
+
+    #[test]
+    fn params_without_scope_omit_scope() {
+        let authenticator = ClientCredentials::new("id".to_string(), "secret".to_string());
+
+        assert!(authenticator
+            .params()
+            .iter()
+            .all(|(name, _)| *name != "scope"));
+    }
+
+    #[tokio::test]
+    async fn with_scope_returns_granted_scope() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::UrlEncoded(
+                "scope".to_string(),
+                "openid vaas-audience".to_string(),
+            ))
+            .with_body(
+                r#"{"access_token":"token","expires_in":300,"scope":"openid vaas-audience"}"#,
+            )
+            .create_async()
+            .await;
+
+        let token = authenticator(&server)
+            .with_scope("openid")
+            .with_scope("vaas-audience")
+            .token()
+            .await
+            .unwrap();
+
+        assert_eq!(Some("openid vaas-audience"), token.scope.as_deref());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn new_with_token_url_requests_token_from_token_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = mock_token_endpoint(&mut server, 1).await;
+        let token_url = Url::parse(&format!("{}/token", server.url())).unwrap();
+        let authenticator = ClientCredentials::new_with_token_url(
+            "id".to_string(),
+            "secret".to_string(),
+            token_url,
+        )
+        .unwrap();
+
+        assert_eq!("token", authenticator.get_token().await.unwrap());
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn new_with_token_url_with_invalid_scheme_fails() {
+        let result = ClientCredentials::new_with_token_url(
+            "id".to_string(),
+            "secret".to_string(),
+            Url::parse("ftp://account.gdata.de/token").unwrap(),
+        );
+
+        assert!(matches!(result, Err(InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn authenticator_with_invalid_token_url_scheme() {
+        let authenticator = ClientCredentials::new("id".to_string(), "secret".to_string())
+            .with_token_url(Url::parse("ftp://account.gdata.de/token").unwrap());
+
+        let token = authenticator.get_token().await;
+
+        assert!(matches!(token, Err(InvalidConfig(_))));
+    }
 }
@@ -1,9 +1,12 @@
 //! # Authenticators
 //!
-//! This module contains the different **OAuth2 Grant Types** that can be used to authenticate against the VaaS service.
+//! This module contains the different **OAuth2 Grant Types** that can be used to authenticate against the VaaS service,
+//! and [`StaticToken`] for tokens which are obtained elsewhere.
 
 mod client_credentials;
 mod password;
+mod static_token;
 
 pub use client_credentials::ClientCredentials;
 pub use password::Password;
+pub use static_token::StaticToken;
@@ -1,16 +1,31 @@
+use crate::auth::authenticator::{ensure_not_empty, ensure_token_url_scheme, request_token};
+use crate::auth::token_cache::TokenCache;
 use crate::auth::Authenticator;
-use crate::error::{Error, VResult};
+use crate::auth::Token;
+use crate::error::VResult;
+use crate::http_client::default_http_client;
 use crate::message::OpenIdConnectTokenResponse;
 use async_trait::async_trait;
-use reqwest::{StatusCode, Url};
+use reqwest::Url;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Authenticator for the VaaS service using the password flow.
 /// Expects a client id, a user name and a password.
+///
+/// The token is cached and reused until one minute before it expires, see [`Password::with_refresh_margin`].
+/// Clones of the authenticator share the cached token.
+#[derive(Clone)]
 pub struct Password {
     client_id: String,
     user_name: String,
     password: String,
     token_url: Url,
+    scope: Option<String>,
+    http_client: reqwest::Client,
+    custom_http_client: bool,
+    token_cache: Arc<TokenCache>,
 }
 
 impl Password {
@@ -21,48 +36,188 @@ impl Password {
             user_name,
             password,
             token_url: Url::parse(crate::auth::authenticator::DEFAULT_TOKEN_URL).unwrap(), // Safe to unwrap, as this is a constant URL and will always be valid.
+            scope: None,
+            http_client: default_http_client(),
+            custom_http_client: false,
+            token_cache: Arc::new(TokenCache::default()),
         }
     }
+
+    /// Create a new authenticator for the VaaS service using the password flow
+    /// with the token endpoint of a self-hosted identity provider.
+    /// Fails with [`Error::InvalidConfig`](crate::error::Error::InvalidConfig) if the URL does not use the `https` or `http` scheme.
+    pub fn new_with_token_url(
+        client_id: String,
+        user_name: String,
+        password: String,
+        token_url: Url,
+    ) -> VResult<Self> {
+        ensure_token_url_scheme(&token_url)?;
+        Ok(Self::new(client_id, user_name, password).with_token_url(token_url))
+    }
+
     /// Set the token URL to be used for authentication.
+    /// The URL must use the `https` or `http` scheme.
     pub fn with_token_url(mut self, token_url: Url) -> Self {
         self.token_url = token_url;
         self
     }
+
+    /// Request the given scope in addition to the default scopes of the client.
+    /// Call it repeatedly to request several scopes. The granted scope is returned in [`Token::scope`].
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scope = Some(match self.scope {
+            Some(scopes) => format!("{scopes} {scope}"),
+            None => scope.to_string(),
+        });
+        self
+    }
+
+    /// Set the HTTP client for the token request, e.g. with a proxy or root certificates for the identity provider.
+    /// The client takes precedence over the one configured on the [`Builder`](crate::Builder).
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self.custom_http_client = true;
+        self
+    }
+
+    /// Set how long before its expiry a cached token is replaced by a new one. Defaults to one minute.
+    pub fn with_refresh_margin(mut self, refresh_margin: Duration) -> Self {
+        self.token_cache = Arc::new(TokenCache::new(refresh_margin));
+        self
+    }
+
+    /// Return the cached token, or request a new one if there is no token or it is about to expire.
+    ///
+    /// Use it to call other APIs which accept the same tokens as VaaS.
+    /// The access token is a bearer token, see [`Token`] on how to handle it.
+    pub async fn token(&self) -> VResult<Token> {
+        self.token_cache
+            .get_or_request(|| self.request_token())
+            .await
+    }
+
+    async fn request_token(&self) -> VResult<OpenIdConnectTokenResponse> {
+        request_token(&self.http_client, &self.token_url, &self.params()).await
+    }
+
+    fn params(&self) -> Vec<(&str, &str)> {
+        let mut params = vec![
+            ("client_id", self.client_id.as_str()),
+            ("username", self.user_name.as_str()),
+            ("password", self.password.as_str()),
+            ("grant_type", "password"),
+        ];
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+        params
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Password")
+            .field("client_id", &self.client_id)
+            .field("user_name", &self.user_name)
+            .field("password", &"***")
+            .field("token_url", &self.token_url)
+            .field("scope", &self.scope)
+            .finish()
+    }
 }
 
 #[async_trait]
 impl Authenticator for Password {
     async fn get_token(&self) -> VResult<String> {
-        let params = [
-            ("client_id", self.client_id.clone()),
-            ("username", self.user_name.clone()),
-            ("password", self.password.clone()),
-            ("grant_type", "password".to_string()),
-        ];
-        let client = reqwest::Client::new();
-        let token_response = client
-            .post(self.token_url.clone())
-            .form(&params)
-            .send()
-            .await?;
-
-        match token_response.status() {
-            StatusCode::OK => {
-                let json_string = token_response.text().await?;
-                Ok(OpenIdConnectTokenResponse::try_from(&json_string)?.access_token)
-            }
-            status => Err(Error::FailedAuthTokenRequest(
-                status,
-                token_response.text().await.unwrap_or_default(),
-            )),
+        Ok(self.token().await?.access_token)
+    }
+
+    async fn refresh_token(&self) -> VResult<String> {
+        let token = self.token_cache.refresh(|| self.request_token()).await?;
+        Ok(token.access_token)
+    }
+
+    fn set_http_client(&mut self, http_client: reqwest::Client) {
+        if !self.custom_http_client {
+            self.http_client = http_client;
         }
     }
+
+    fn validate(&self) -> VResult<()> {
+        ensure_not_empty("client_id", &self.client_id)?;
+        ensure_not_empty("user_name", &self.user_name)?;
+        ensure_not_empty("password", &self.password)?;
+        ensure_token_url_scheme(&self.token_url)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::error::Error::FailedAuthTokenRequest;
+    use crate::error::Error::{InvalidConfig, Unauthorized};
+
+    #[tokio::test]
+    async fn new_with_token_url_requests_token_from_token_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/realms/vaas/token")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("grant_type".to_string(), "password".to_string()),
+                mockito::Matcher::UrlEncoded("username".to_string(), "user".to_string()),
+            ]))
+            .with_body(r#"{"access_token":"token","expires_in":300}"#)
+            .create_async()
+            .await;
+        let token_url = Url::parse(&format!("{}/realms/vaas/token", server.url())).unwrap();
+        let authenticator = Password::new_with_token_url(
+            "id".to_string(),
+            "user".to_string(),
+            "password".to_string(),
+            token_url,
+        )
+        .unwrap();
+
+        assert_eq!("token", authenticator.get_token().await.unwrap());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn with_scope_sends_scope_and_returns_granted_scope() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/token")
+            .match_body(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("grant_type".to_string(), "password".to_string()),
+                mockito::Matcher::UrlEncoded("scope".to_string(), "vaas-audience".to_string()),
+            ]))
+            .with_body(r#"{"access_token":"token","expires_in":300,"scope":"vaas-audience"}"#)
+            .create_async()
+            .await;
+        let token_url = Url::parse(&format!("{}/token", server.url())).unwrap();
+
+        let token = Password::new("id".to_string(), "user".to_string(), "pw".to_string())
+            .with_token_url(token_url)
+            .with_scope("vaas-audience")
+            .token()
+            .await
+            .unwrap();
+
+        assert_eq!(Some("vaas-audience"), token.scope.as_deref());
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn new_with_token_url_with_invalid_scheme_fails() {
+        let result = Password::new_with_token_url(
+            "id".to_string(),
+            "user".to_string(),
+            "password".to_string(),
+            Url::parse("wss://account.gdata.de/token").unwrap(),
+        );
+
+        assert!(matches!(result, Err(InvalidConfig(_))));
+    }
 
     #[tokio::test]
     async fn authenticator_returns_token() {
@@ -86,6 +241,20 @@ mod tests {
         assert!(token.is_ok())
     }
 
+    #[test]
+    fn debug_redacts_password() {
+        let authenticator = Password::new(
+            "client_id".to_string(),
+            "user_name".to_string(),
+            "top-secret".to_string(),
+        );
+
+        let debug = format!("{:?}", authenticator);
+
+        assert!(debug.contains("user_name"));
+        assert!(!debug.contains("top-secret"));
+    }
+
     #[tokio::test]
     async fn authenticator_wrong_credentials() {
         let token_url: Url = dotenv::var("TOKEN_URL")
@@ -102,7 +271,7 @@ mod tests {
         assert!(token.is_err());
         assert!(match token {
             Ok(_) => false,
-            Err(FailedAuthTokenRequest(_, _)) => true,
+            Err(Unauthorized(_)) => true,
             _ => false,
         })
     }
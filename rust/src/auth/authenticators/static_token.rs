@@ -0,0 +1,72 @@
+use crate::auth::authenticator::{ensure_not_empty, Authenticator};
+use crate::error::VResult;
+use async_trait::async_trait;
+use std::fmt;
+
+/// Authenticator for the VaaS service using a token which was obtained elsewhere.
+/// The token is not refreshed, so it has to be valid whenever a connection is established.
+/// ```rust
+/// # fn main() -> vaas::error::VResult<()> {
+/// use vaas::auth::authenticators::StaticToken;
+/// use vaas::Vaas;
+///
+/// let vaas = Vaas::builder(StaticToken::new("token".to_string())).build()?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct StaticToken(String);
+
+impl StaticToken {
+    /// Create a new authenticator which always returns the given token.
+    pub fn new(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl fmt::Debug for StaticToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("StaticToken").field(&"***").finish()
+    }
+}
+
+#[async_trait]
+impl Authenticator for StaticToken {
+    async fn get_token(&self) -> VResult<String> {
+        Ok(self.0.clone())
+    }
+
+    fn validate(&self) -> VResult<()> {
+        ensure_not_empty("token", &self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[tokio::test]
+    async fn get_token_returns_token() {
+        let authenticator = StaticToken::new("token".to_string());
+        assert_eq!("token", authenticator.get_token().await.unwrap());
+    }
+
+    #[test]
+    fn validate_with_empty_token_fails() {
+        let result = StaticToken::new(String::new()).validate();
+        assert!(matches!(result, Err(Error::InvalidConfig(message)) if message.contains("token")));
+    }
+
+    #[test]
+    fn debug_redacts_token() {
+        let debug = format!("{:?}", StaticToken::new("secret-token".to_string()));
+        assert!(!debug.contains("secret-token"));
+    }
+
+    #[tokio::test]
+    async fn can_be_used_as_trait_object() {
+        let authenticator: Box<dyn Authenticator + Send + Sync> =
+            Box::new(StaticToken::new("token".to_string()));
+        assert_eq!("token", authenticator.get_token().await.unwrap());
+    }
+}
@@ -4,5 +4,8 @@
 
 mod authenticator;
 pub mod authenticators;
+mod token;
+mod token_cache;
 
 pub use authenticator::Authenticator;
+pub use token::Token;
@@ -0,0 +1,83 @@
+use crate::message::OpenIdConnectTokenResponse;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// An access token issued by the identity provider, see [`ClientCredentials::token`](crate::auth::authenticators::ClientCredentials::token).
+///
+/// The access token is a bearer token: whoever holds it can use it until it expires.
+/// Do not log it or pass it to untrusted services. The access token is redacted from the `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token {
+    /// The bearer token, sent as `Authorization: Bearer <access_token>`.
+    pub access_token: String,
+    /// The point in time at which the token expires.
+    /// `None` if the identity provider did not state a lifetime.
+    pub expires_at: Option<Instant>,
+    /// The scope granted by the identity provider, if it stated one.
+    pub scope: Option<String>,
+}
+
+impl Token {
+    /// Create the token from the response of the token endpoint to a request sent at the given point in time.
+    /// Measuring the lifetime from the request errs on the side of an earlier expiry.
+    pub(crate) fn from_response(
+        response: OpenIdConnectTokenResponse,
+        requested_at: Instant,
+    ) -> Self {
+        Self {
+            access_token: response.access_token,
+            expires_at: response
+                .expires_in
+                .map(|expires_in| requested_at + Duration::from_secs(expires_in)),
+            scope: response.scope,
+        }
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Token")
+            .field("access_token", &"***")
+            .field("expires_at", &self.expires_at)
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_response_sets_expiry_and_scope() {
+        let requested_at = Instant::now();
+        let response = OpenIdConnectTokenResponse {
+            access_token: "token".to_string(),
+            expires_in: Some(300),
+            scope: Some("vaas".to_string()),
+        };
+
+        let token = Token::from_response(response, requested_at);
+
+        assert_eq!("token", token.access_token);
+        assert_eq!(
+            Some(requested_at + Duration::from_secs(300)),
+            token.expires_at
+        );
+        assert_eq!(Some("vaas"), token.scope.as_deref());
+    }
+
+    #[test]
+    fn debug_redacts_access_token() {
+        let token = Token {
+            access_token: "top-secret".to_string(),
+            expires_at: None,
+            scope: Some("vaas".to_string()),
+        };
+
+        let debug = format!("{:?}", token);
+
+        assert!(debug.contains("vaas"));
+        assert!(!debug.contains("top-secret"));
+    }
+}
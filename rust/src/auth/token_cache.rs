@@ -0,0 +1,188 @@
+use crate::auth::Token;
+use crate::error::VResult;
+use crate::message::OpenIdConnectTokenResponse;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// The default time before the expiry of a token in which a new token is requested.
+pub(crate) const DEFAULT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Caches the token of an authenticator until shortly before it expires.
+///
+/// The lock is held while a new token is requested, so concurrent callers wait for the
+/// pending request instead of requesting a token each.
+#[derive(Debug)]
+pub(crate) struct TokenCache {
+    token: Mutex<Option<CachedToken>>,
+    refresh_margin: Duration,
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    token: Token,
+    refresh_at: Instant,
+}
+
+impl TokenCache {
+    pub fn new(refresh_margin: Duration) -> Self {
+        Self {
+            token: Mutex::new(None),
+            refresh_margin,
+        }
+    }
+
+    /// Return the cached token, or request a new one if there is no token or it is about to expire.
+    pub async fn get_or_request<F, Fut>(&self, request: F) -> VResult<Token>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = VResult<OpenIdConnectTokenResponse>>,
+    {
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if Instant::now() < token.refresh_at {
+                return Ok(token.token.clone());
+            }
+        }
+        let requested_at = Instant::now();
+        let token = Token::from_response(request().await?, requested_at.into_std());
+        *cached = self.to_cached_token(&token, requested_at);
+        Ok(token)
+    }
+
+    /// Request a new token, regardless of the cached one.
+    pub async fn refresh<F, Fut>(&self, request: F) -> VResult<Token>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = VResult<OpenIdConnectTokenResponse>>,
+    {
+        let mut cached = self.token.lock().await;
+        *cached = None;
+        let requested_at = Instant::now();
+        let token = Token::from_response(request().await?, requested_at.into_std());
+        *cached = self.to_cached_token(&token, requested_at);
+        Ok(token)
+    }
+
+    /// Tokens without an expiry or which expire within the refresh margin are not cached.
+    fn to_cached_token(&self, token: &Token, requested_at: Instant) -> Option<CachedToken> {
+        let expires_in = token
+            .expires_at?
+            .checked_duration_since(requested_at.into_std())?;
+        let valid_for = expires_in.checked_sub(self.refresh_margin)?;
+        Some(CachedToken {
+            token: token.clone(),
+            refresh_at: requested_at + valid_for,
+        })
+    }
+}
+
+impl Default for TokenCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_REFRESH_MARGIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn token_response(access_token: &str, expires_in: Option<u64>) -> OpenIdConnectTokenResponse {
+        OpenIdConnectTokenResponse {
+            access_token: access_token.to_string(),
+            expires_in,
+            scope: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn get_or_request_returns_cached_token_within_validity() {
+        let cache = TokenCache::default();
+        let requests = AtomicUsize::new(0);
+        let request = || async {
+            let request = requests.fetch_add(1, Ordering::SeqCst);
+            Ok(token_response(&format!("token-{request}"), Some(300)))
+        };
+
+        assert_eq!(
+            "token-0",
+            cache.get_or_request(request).await.unwrap().access_token
+        );
+        assert_eq!(
+            "token-0",
+            cache.get_or_request(request).await.unwrap().access_token
+        );
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn get_or_request_without_expiry_requests_new_token() {
+        let cache = TokenCache::default();
+        let requests = AtomicUsize::new(0);
+        let request = || async {
+            requests.fetch_add(1, Ordering::SeqCst);
+            Ok(token_response("token", None))
+        };
+
+        cache.get_or_request(request).await.unwrap();
+        cache.get_or_request(request).await.unwrap();
+
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn get_or_request_within_refresh_margin_requests_new_token() {
+        let cache = TokenCache::new(Duration::from_secs(60));
+        let requests = AtomicUsize::new(0);
+        let request = || async {
+            requests.fetch_add(1, Ordering::SeqCst);
+            Ok(token_response("token", Some(30)))
+        };
+
+        cache.get_or_request(request).await.unwrap();
+        cache.get_or_request(request).await.unwrap();
+
+        assert_eq!(2, requests.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn refresh_bypasses_cached_token() {
+        let cache = TokenCache::default();
+
+        cache
+            .get_or_request(|| async { Ok(token_response("old", Some(300))) })
+            .await
+            .unwrap();
+        let refreshed = cache
+            .refresh(|| async { Ok(token_response("new", Some(300))) })
+            .await
+            .unwrap();
+        let cached = cache
+            .get_or_request(|| async { Ok(token_response("unused", Some(300))) })
+            .await
+            .unwrap();
+
+        assert_eq!("new", refreshed.access_token);
+        assert_eq!("new", cached.access_token);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_token_request() {
+        let cache = TokenCache::default();
+        let requests = AtomicUsize::new(0);
+        let request = || async {
+            requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(token_response("token", Some(300)))
+        };
+
+        let tokens = futures::future::join_all((0..5).map(|_| cache.get_or_request(request))).await;
+
+        assert!(tokens
+            .iter()
+            .all(|token| matches!(token, Ok(token) if token.access_token == "token")));
+        assert_eq!(1, requests.load(Ordering::SeqCst));
+    }
+}
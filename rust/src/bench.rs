@@ -0,0 +1,129 @@
+//! Internals exposed to the benchmarks. Only available with the `bench` feature and not part of the public API.
+
+use crate::connection::Connection;
+use crate::error::Error;
+use crate::message::{AuthResponse, MessageType, RequestId, VerdictRequestFile, VerdictResponse};
+use crate::mock_websocket::{verdict_response, MockServer, MockSink, MockSource};
+use crate::options::Options;
+use crate::response_broker::ResponseBroker;
+use crate::sha256::Sha256;
+use futures::future::join_all;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Construct `count` verdict requests for the same hash and session, as a batch scan does.
+pub fn verdict_requests_for_sha256(
+    sha256: &Sha256,
+    session_id: &str,
+    count: usize,
+) -> Vec<impl Debug + Serialize> {
+    let session_id: Arc<str> = session_id.into();
+    (0..count)
+        .map(|_| VerdictRequestFile::new(sha256, session_id.clone(), true, true))
+        .collect()
+}
+
+/// `count` verdict responses as received from VaaS, each for a different request.
+pub fn verdict_response_messages(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|i| {
+            let request = serde_json::json!({
+                "guid": guid(i),
+                "sha256": "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f",
+            });
+            verdict_response(&request, "Clean")
+        })
+        .collect()
+}
+
+/// The GUID of the `i`th request of [`verdict_response_messages`].
+fn guid(i: usize) -> String {
+    RequestId::from(uuid::Uuid::from_u128(i as u128)).to_string()
+}
+
+/// Wait for the responses to one request each, while the messages are parsed and dispatched
+/// to the waiting requests as the reader loop of a connection does.
+pub async fn dispatch_verdict_responses(messages: Vec<String>) {
+    let responses = Arc::new(ResponseBroker::<VerdictResponse, Error>::new());
+    let auth_responses = ResponseBroker::<AuthResponse, Error>::new();
+    let waiters: Vec<_> = (0..messages.len())
+        .map(|i| responses.get_response(guid(i)))
+        .collect();
+
+    let reader = responses.clone();
+    let reader = tokio::spawn(async move {
+        for message in &messages {
+            Connection::dispatch(MessageType::try_from(message), &reader, &auth_responses);
+        }
+    });
+    for response in join_all(waiters).await {
+        response.unwrap();
+    }
+    reader.await.unwrap();
+}
+
+/// An in-process mock of VaaS which answers verdict requests without a delay.
+pub struct MockVaas {
+    server: MockServer,
+    /// The SHA256 of each request waiting for an upload, by the id of the request.
+    uploads: Arc<Mutex<HashMap<String, Value>>>,
+}
+
+impl MockVaas {
+    /// A connection whose verdict requests are all answered with the given verdict.
+    pub async fn answering(verdict: &'static str) -> (Self, Connection) {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, verdict);
+        let vaas = Self {
+            server,
+            uploads: Arc::default(),
+        };
+        (vaas, Self::connect(sink, source).await)
+    }
+
+    /// A connection whose verdict requests are all answered with `Unknown`, so the content is uploaded
+    /// to the given URL. The upload token is the id of the request, and the upload endpoint has to
+    /// call [`MockVaas::upload_received`] with it to send the final verdict.
+    pub async fn requesting_uploads(upload_url: String) -> (Self, Connection) {
+        let uploads: Arc<Mutex<HashMap<String, Value>>> = Arc::default();
+        let requested_uploads = uploads.clone();
+        let (server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            requested_uploads.lock().unwrap().insert(
+                request["guid"].as_str().unwrap_or_default().to_string(),
+                request["sha256"].clone(),
+            );
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": request["sha256"],
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": request["guid"],
+            });
+            Some(response.to_string())
+        });
+        let vaas = Self { server, uploads };
+        (vaas, Self::connect(sink, source).await)
+    }
+
+    /// Send the `Clean` verdict for the request the upload with the given token belongs to.
+    pub fn upload_received(&self, upload_token: &str) {
+        let sha256 = self.uploads.lock().unwrap().remove(upload_token);
+        let request = serde_json::json!({ "guid": upload_token, "sha256": sha256 });
+        self.server.send(verdict_response(&request, "Clean"));
+    }
+
+    async fn connect(sink: MockSink, source: MockSource) -> Connection {
+        let options = Options {
+            keep_alive: false,
+            // The mock upload endpoints are served over plain http on localhost.
+            allowed_upload_hosts: None,
+            ..Options::default()
+        };
+        MockServer::connect(sink, source, options).await
+    }
+}
@@ -0,0 +1,346 @@
+//! # Blocking API
+//!
+//! Synchronous versions of [`Vaas`](crate::Vaas) and [`Connection`](crate::Connection) for code which does not use
+//! async Rust. Requires the `blocking` feature.
+//!
+//! Each blocking [`Vaas`] owns a Tokio runtime, which runs its connections in the background, e.g. to keep them
+//! alive between requests. The runtime is shut down when the `Vaas` and all of its connections are dropped.
+//! Requests take an optional timeout instead of a [`CancellationToken`]. Without one, the default timeout
+//! configured with [`Builder::default_timeout`](crate::Builder::default_timeout) is used.
+//!
+//! The blocking API must not be used from within an async runtime, as it would block a thread of the runtime.
+//! It fails with [`Error::BlockingInAsyncContext`] there.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use vaas::auth::authenticators::ClientCredentials;
+//! use vaas::blocking::Vaas;
+//! use vaas::error::VResult;
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! fn main() -> VResult<()> {
+//!     let authenticator = ClientCredentials::new("client_id".to_string(), "client_secret".to_string());
+//!     let vaas = Vaas::new(authenticator)?;
+//!
+//!     let verdict = vaas.for_file(Path::new("myfile"), Duration::from_secs(10))?;
+//!
+//!     // Prints "Clean", "Pup" or "Malicious"
+//!     println!("{}", verdict.verdict);
+//!     Ok(())
+//! }
+//! ```
+
+use crate::auth::Authenticator;
+use crate::cancellation::CancellationToken;
+use crate::error::{Error, VResult};
+use crate::lazy_connection::LazyConnection;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use reqwest::Url;
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+
+/// How long the runtime waits for running tasks, e.g. hashing a file, when it is shut down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Blocking version of [`Vaas`](crate::Vaas).
+///
+/// Requests are sent on a connection which is established on the first request and shared by all requests,
+/// like with [`LazyConnection`]. A dedicated connection can be established with [`Vaas::connect`].
+pub struct Vaas {
+    connection: LazyConnection,
+    runtime: Arc<BackgroundRuntime>,
+}
+
+impl Vaas {
+    /// Create an instance with the default configuration, see [`Builder`](crate::Builder).
+    pub fn new<A: Authenticator + Send + Sync + 'static>(authenticator: A) -> VResult<Self> {
+        Self::from_vaas(crate::Vaas::builder(authenticator).build()?)
+    }
+
+    /// Create an instance with the configuration of the given [`Vaas`](crate::Vaas) instance, e.g. to use the
+    /// [`Builder`](crate::Builder) to configure it:
+    ///
+    /// ```rust,no_run
+    /// # use vaas::auth::authenticators::ClientCredentials;
+    /// # use std::time::Duration;
+    /// # fn main() -> vaas::error::VResult<()> {
+    /// # let authenticator = ClientCredentials::new("client_id".to_string(), "client_secret".to_string());
+    /// let vaas = vaas::Vaas::builder(authenticator)
+    ///     .default_timeout(Duration::from_secs(30))
+    ///     .build()?;
+    /// let vaas = vaas::blocking::Vaas::from_vaas(vaas)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_vaas<A: Authenticator + Send + Sync + 'static>(
+        vaas: crate::Vaas<A>,
+    ) -> VResult<Self> {
+        ensure_blocking_allowed()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("vaas-blocking")
+            .enable_all()
+            .build()?;
+        Ok(Self {
+            connection: vaas.lazy(),
+            runtime: Arc::new(BackgroundRuntime(Some(runtime))),
+        })
+    }
+
+    /// Connect to VaaS, see [`Vaas::connect`](crate::Vaas::connect). The connection shares the runtime of this
+    /// instance, which is kept running until the connection is dropped, too.
+    pub fn connect(&self) -> VResult<Connection> {
+        let connection = self.runtime.block_on(self.connection.connect_new())??;
+        Ok(Connection {
+            connection,
+            runtime: self.runtime.clone(),
+        })
+    }
+
+    /// Request a verdict for a SHA256 file hash, see [`Connection::for_sha256`](crate::Connection::for_sha256).
+    pub fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_sha256(sha256, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a file behind a URL, see [`Connection::for_url`](crate::Connection::for_url).
+    pub fn for_url(&self, url: &Url, timeout: impl Into<Option<Duration>>) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_url(url, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a file, see [`Connection::for_file`](crate::Connection::for_file).
+    pub fn for_file(
+        &self,
+        file: &Path,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_file(file, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a buffer, see [`Connection::for_buf`](crate::Connection::for_buf).
+    pub fn for_buf(
+        &self,
+        buf: Vec<u8>,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_buf(buf, ct.as_ref()))?
+    }
+}
+
+/// Blocking version of [`Connection`](crate::Connection).
+pub struct Connection {
+    // Dropped before the runtime, so the tasks of the connection are aborted while the runtime still runs.
+    connection: crate::Connection,
+    runtime: Arc<BackgroundRuntime>,
+}
+
+impl Connection {
+    /// Request a verdict for a SHA256 file hash, see [`Connection::for_sha256`](crate::Connection::for_sha256).
+    pub fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_sha256(sha256, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a file behind a URL, see [`Connection::for_url`](crate::Connection::for_url).
+    pub fn for_url(&self, url: &Url, timeout: impl Into<Option<Duration>>) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_url(url, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a file, see [`Connection::for_file`](crate::Connection::for_file).
+    pub fn for_file(
+        &self,
+        file: &Path,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_file(file, ct.as_ref()))?
+    }
+
+    /// Request a verdict for a buffer, see [`Connection::for_buf`](crate::Connection::for_buf).
+    pub fn for_buf(
+        &self,
+        buf: Vec<u8>,
+        timeout: impl Into<Option<Duration>>,
+    ) -> VResult<VaasVerdict> {
+        let ct = cancellation_token(timeout);
+        self.runtime
+            .block_on(self.connection.for_buf(buf, ct.as_ref()))?
+    }
+
+    /// Whether VaaS closed the connection, see [`Connection::is_closed`](crate::Connection::is_closed).
+    pub fn is_closed(&self) -> bool {
+        self.connection.is_closed()
+    }
+}
+
+/// The runtime of the blocking API, shut down when the last [`Vaas`] or [`Connection`] using it is dropped.
+struct BackgroundRuntime(Option<Runtime>);
+
+impl BackgroundRuntime {
+    fn block_on<F: Future>(&self, future: F) -> VResult<F::Output> {
+        ensure_blocking_allowed()?;
+        // The runtime is only taken when it is dropped.
+        Ok(self.0.as_ref().unwrap().block_on(future))
+    }
+}
+
+impl Drop for BackgroundRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            // Waiting for the tasks would block the thread of the other runtime, and panic.
+            if Handle::try_current().is_ok() {
+                runtime.shutdown_background();
+            } else {
+                runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+            }
+        }
+    }
+}
+
+fn ensure_blocking_allowed() -> VResult<()> {
+    match Handle::try_current() {
+        Ok(_) => Err(Error::BlockingInAsyncContext),
+        Err(_) => Ok(()),
+    }
+}
+
+fn cancellation_token(timeout: impl Into<Option<Duration>>) -> Option<CancellationToken> {
+    timeout
+        .into()
+        .map(|duration| CancellationToken { duration })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::authenticators::ClientCredentials;
+    use crate::message::Verdict;
+    use crate::mock_websocket::MockServer;
+    use crate::options::Options;
+
+    fn connect(
+        server: (
+            MockServer,
+            crate::mock_websocket::MockSink,
+            crate::mock_websocket::MockSource,
+        ),
+    ) -> (MockServer, Connection) {
+        let (server, sink, source) = server;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .unwrap();
+        let options = Options {
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = runtime.block_on(MockServer::connect(sink, source, options));
+        let runtime = Arc::new(BackgroundRuntime(Some(runtime)));
+        (
+            server,
+            Connection {
+                connection,
+                runtime,
+            },
+        )
+    }
+
+    #[test]
+    fn requests_block_until_the_verdict_is_received() {
+        let (_server, connection) = connect(MockServer::answering(Duration::ZERO, "Clean"));
+        let sha256 = Sha256::from(&b"content"[..]);
+
+        let verdict = connection.for_sha256(&sha256, None).unwrap();
+        assert_eq!(Verdict::Clean, verdict.verdict);
+        assert_eq!(sha256, verdict.sha256);
+
+        let verdict = connection
+            .for_buf(b"content".to_vec(), Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(Verdict::Clean, verdict.verdict);
+        assert_eq!(sha256, verdict.sha256);
+    }
+
+    #[test]
+    fn request_is_cancelled_after_the_timeout() {
+        let server = MockServer::answering(Duration::from_secs(5), "Clean");
+        let (_server, connection) = connect(server);
+        let sha256 = Sha256::from(&b"content"[..]);
+
+        let started = std::time::Instant::now();
+        let result = connection.for_sha256(&sha256, Duration::from_millis(50));
+
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn use_within_async_runtime_fails_and_drop_does_not_panic() {
+        let (_server, connection) = connect(MockServer::answering(Duration::ZERO, "Clean"));
+        let sha256 = Sha256::from(&b"content"[..]);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async move {
+            let result = connection.for_sha256(&sha256, None);
+            assert!(
+                matches!(result, Err(Error::BlockingInAsyncContext)),
+                "{result:?}"
+            );
+            drop(connection);
+        });
+    }
+
+    #[test]
+    fn vaas_cannot_be_created_within_async_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let result = runtime.block_on(async {
+            Vaas::new(ClientCredentials::new(
+                "id".to_string(),
+                "secret".to_string(),
+            ))
+            .map(|_| ())
+        });
+
+        assert!(
+            matches!(result, Err(Error::BlockingInAsyncContext)),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn vaas_can_be_created_and_dropped_without_connecting() {
+        let vaas = Vaas::new(ClientCredentials::new(
+            "id".to_string(),
+            "secret".to_string(),
+        ))
+        .unwrap();
+        drop(vaas);
+    }
+}
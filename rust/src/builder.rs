@@ -1,10 +1,27 @@
 //! The `Builder` struct create a new [Vaas] instance with the expected default values and allows the custom configuration.
 
 use crate::auth::Authenticator;
-use crate::error::VResult;
+use crate::error::{Error, VResult};
+use crate::http_client::http_client;
+use crate::message::HostPattern;
 use crate::options::Options;
+use crate::proxy::ProxyConfig;
+use crate::rate_limiter::RateLimiter;
+use crate::retry::RetryPolicy;
+use crate::sha256::Sha256;
+use crate::tls::{Certificate, Identity};
+use crate::transport::Transport;
 use crate::vaas::Vaas;
-use reqwest::Url;
+use crate::vaas_verdict::VaasVerdict;
+use crate::verdict_cache::VerdictCache;
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::{Url, Version};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+const MIN_KEEP_ALIVE_DELAY: Duration = Duration::from_secs(1);
 
 /// Builder struct to create a new Vaas instance with the expected default values.
 /// ```rust
@@ -18,10 +35,12 @@ use reqwest::Url;
 /// let vaas = Builder::new(authenticator).build()?;
 /// # Ok(()) }
 /// ```
+#[derive(Debug)]
 pub struct Builder<A: Authenticator> {
     authenticator: A,
     url: Url,
     options: Options,
+    http_client: Option<reqwest::Client>,
 }
 
 impl<A: Authenticator> Builder<A> {
@@ -29,23 +48,39 @@ impl<A: Authenticator> Builder<A> {
     pub fn new(authenticator: A) -> Self {
         use std::str::FromStr;
         Self {
-            options: Options {
-                keep_alive_delay_ms: 10_000,
-                keep_alive: true,
-                use_cache: true,
-                use_hash_lookup: true,
-            },
+            options: Options::default(),
             authenticator,
             url: Url::from_str("wss://gateway.production.vaas.gdatasecurity.de").unwrap(),
+            http_client: None,
         }
     }
 
-    /// Set the delay in which a Ping is sent to the server to keep the connection alive.
-    /// Defaults to 10s.
+    /// Set the delay in milliseconds in which a Ping is sent to the server to keep the connection alive.
+    /// Defaults to 10s. See [`Builder::keep_alive_delay`].
     pub fn keep_alive_delay_ms(self, delay: u64) -> Self {
+        self.keep_alive_delay(Duration::from_millis(delay))
+    }
+
+    /// Set the delay in which a Ping is sent to the server to keep the connection alive.
+    /// Shorter delays detect broken connections earlier and keep idle connections open behind aggressive proxies,
+    /// but cause more traffic. Must be at least 1s. Defaults to 10s.
+    pub fn keep_alive_delay(self, delay: Duration) -> Self {
         Self {
             options: Options {
-                keep_alive_delay_ms: delay,
+                keep_alive_delay: delay,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Vary the delay between two pings randomly by up to the given jitter in both directions,
+    /// so that many clients which connect at the same time do not ping the server in bursts.
+    /// Must be less than the keep alive delay. Defaults to no jitter.
+    pub fn keep_alive_jitter(self, jitter: Duration) -> Self {
+        Self {
+            options: Options {
+                keep_alive_jitter: jitter,
                 ..self.options
             },
             ..self
@@ -86,24 +121,1070 @@ impl<A: Authenticator> Builder<A> {
         }
     }
 
+    /// Enable or disable the upload of files to VaaS. Defaults to enabled (true).
+    ///
+    /// If disabled, only the SHA256 of files and buffers is sent. If VaaS does not know the SHA256,
+    /// the [`Verdict::Unknown`](crate::message::Verdict::Unknown) verdict is returned instead of uploading the content.
+    /// Requests for streams and URLs fail with [`Error::UploadDisabled`], as they require to send the content.
+    pub fn upload(self, upload: bool) -> Self {
+        Self {
+            options: Options {
+                upload,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the timeout for requests which are sent without a [`CancellationToken`](crate::CancellationToken).
+    /// Defaults to one minute.
+    pub fn default_timeout(self, default_timeout: Duration) -> Self {
+        Self {
+            options: Options {
+                default_timeout,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the timeout for the TCP, TLS and websocket handshake with the VaaS gateway.
+    /// Defaults to 30s.
+    pub fn connect_timeout(self, connect_timeout: Duration) -> Self {
+        Self {
+            options: Options {
+                connect_timeout,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the timeout for the token request and, separately, for the authentication of the session
+    /// on the websocket connection. Defaults to 30s.
+    pub fn auth_timeout(self, auth_timeout: Duration) -> Self {
+        Self {
+            options: Options {
+                auth_timeout,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the timeout for uploading a file which is unknown to VaaS.
+    /// By default, an upload is only aborted when the [`CancellationToken`](crate::CancellationToken) of the request expires.
+    pub fn upload_timeout(self, upload_timeout: Duration) -> Self {
+        Self {
+            options: Options {
+                upload_timeout: Some(upload_timeout),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the HTTP version used to upload files which are unknown to VaaS.
+    /// Defaults to [`Version::HTTP_11`]. Only HTTP/1.1 and HTTP/2 are supported.
+    ///
+    /// With [`Version::HTTP_2`], concurrent uploads are multiplexed over a single connection. Over `https`,
+    /// the upload endpoint has to support HTTP/2 via ALPN. Over plain `http`, HTTP/2 requires a client
+    /// configured with `http2_prior_knowledge`, see [`Builder::http_client`].
+    pub fn upload_http_version(self, upload_http_version: Version) -> Self {
+        Self {
+            options: Options {
+                upload_http_version,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the `Content-Type` header of uploads. Defaults to `application/octet-stream`.
+    pub fn upload_content_type(self, content_type: &str) -> Self {
+        Self {
+            options: Options {
+                upload_content_type: content_type.to_string(),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Send the SHA256 of files and buffers in this header when they are uploaded, so the upload endpoint can verify
+    /// the content, e.g. `x-amz-checksum-sha256`. The value is the base64 encoded digest. Streams are uploaded without
+    /// the header, as their SHA256 is not known beforehand. By default, no checksum is sent.
+    pub fn upload_checksum_header(self, header_name: &str) -> Self {
+        Self {
+            options: Options {
+                upload_checksum_header: Some(header_name.to_string()),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Limit the number of files which are uploaded at the same time per connection, e.g. to not saturate
+    /// the network with large batches of unknown files. Verdict requests are still sent without a limit,
+    /// only the uploads of the content wait for a free slot. By default, the uploads are not limited.
+    pub fn max_concurrent_uploads(self, max_concurrent_uploads: usize) -> Self {
+        Self {
+            options: Options {
+                max_concurrent_uploads: Some(max_concurrent_uploads),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Limit the upload speed of each file, buffer or stream to the given number of bytes per second, e.g. to
+    /// not saturate a thin WAN link. `0` means unlimited, which is the default. Concurrent uploads are limited
+    /// separately, so combine this with [`Builder::max_concurrent_uploads`] to cap the total bandwidth.
+    pub fn upload_rate_limit(self, bytes_per_sec: u64) -> Self {
+        Self {
+            options: Options {
+                upload_rate_limit: Some(bytes_per_sec).filter(|rate| *rate > 0),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Compress the content of files and buffers with gzip while they are uploaded, which speeds up the upload of
+    /// compressible files like scripts or documents on slow links. The content is sent with `Content-Encoding: gzip`
+    /// and chunked, as its compressed size is not known beforehand. Streams are always uploaded uncompressed with
+    /// their given length, for upload endpoints which require one. Disabled by default.
+    pub fn compress_uploads(self, compress_uploads: bool) -> Self {
+        Self {
+            options: Options {
+                compress_uploads,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Only upload files to these hosts. VaaS tells the SDK where to upload unknown files, and the URL is checked
+    /// before any content is sent: it has to use `https` and its host has to match one of the given hosts.
+    /// `*.example.com` allows all subdomains of `example.com`. Internationalized domain names may be given in
+    /// Unicode or punycode. Otherwise, the request fails with [`Error::UntrustedUploadHost`].
+    /// By default, only the VaaS upload hosts below `vaas.gdatasecurity.de` are allowed.
+    pub fn allowed_upload_hosts(self, hosts: Vec<String>) -> Self {
+        Self {
+            options: Options {
+                allowed_upload_hosts: Some(hosts),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Upload to any URL VaaS sends, e.g. to the plain HTTP endpoint of [`MockVaas`](crate::test_utils::MockVaas).
+    #[cfg(feature = "test-utils")]
+    pub(crate) fn allow_any_upload_host(self) -> Self {
+        Self {
+            options: Options {
+                allowed_upload_hosts: None,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Limit the number of verdict requests which are processed at the same time per connection.
+    /// A request counts from the moment it is started until its verdict is returned, including the upload.
+    /// Further requests wait for a free slot instead of failing, at most until their [`CancellationToken`](crate::CancellationToken)
+    /// is cancelled. By default, the requests are not limited.
+    pub fn max_in_flight(self, max_in_flight: usize) -> Self {
+        Self {
+            options: Options {
+                max_in_flight: Some(max_in_flight),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Close the connection with [`Error::MessageTooLarge`] if VaaS sends a message larger than `max_message_size`
+    /// bytes, instead of parsing it. The message has been received completely at that point, so the limit bounds
+    /// the memory used to parse it, not to receive it. VaaS messages are a few hundred bytes, the default is 4 MiB.
+    pub fn max_message_size(self, max_message_size: usize) -> Self {
+        Self {
+            options: Options {
+                max_message_size,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Limit the verdict requests to `requests_per_second`, with bursts of up to `burst` requests, to stay within
+    /// the request rate of the contract instead of being throttled by VaaS. Each request, including each element
+    /// of the list variants, waits for a slot at most until its [`CancellationToken`](crate::CancellationToken) is
+    /// cancelled. Requests answered locally, e.g. from the [`local_cache`](Builder::local_cache), are not limited.
+    /// The limit is shared by all connections of the [`Vaas`] instance, the time spent waiting is counted in
+    /// [`StatsSnapshot::throttled_millis`](crate::stats::StatsSnapshot::throttled_millis). By default, the
+    /// requests are not limited.
+    pub fn rate_limit(self, requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            options: Options {
+                rate_limiter: Some(Arc::new(RateLimiter::new(requests_per_second, burst))),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Retry token requests and uploads of files which failed with a transient error.
+    /// Uploads of streams are not retried, as a stream can only be read once. By default, nothing is retried.
+    pub fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            options: Options {
+                retry_policy,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the size of the chunks in which [`Connection::for_file`](crate::Connection::for_file) reads a file to hash it.
+    /// Files are hashed on a blocking thread, so the async runtime is not stalled. Defaults to 1 MiB.
+    pub fn hash_buffer_size(self, hash_buffer_size: usize) -> Self {
+        Self {
+            options: Options {
+                hash_buffer_size,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Let the [`CancellationToken`](crate::CancellationToken) of [`Connection::for_file`](crate::Connection::for_file)
+    /// also cancel hashing the file, which is checked between chunks. Otherwise, huge files are always hashed
+    /// completely before the token limits the rest of the request. Disabled by default.
+    pub fn cancellable_hashing(self, cancellable_hashing: bool) -> Self {
+        Self {
+            options: Options {
+                cancellable_hashing,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Set the time to wait for the verdict after a file has been uploaded.
+    /// By default, the rest of the time of the [`CancellationToken`](crate::CancellationToken) is used, and the
+    /// verdict timeout never extends it. Together with [`Builder::upload_timeout`], this allows to give large
+    /// uploads more time than the analysis.
+    pub fn verdict_timeout(self, verdict_timeout: Duration) -> Self {
+        Self {
+            options: Options {
+                verdict_timeout: Some(verdict_timeout),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Cache the final verdicts of up to `capacity` files in the process for `ttl`, so repeated requests for the same
+    /// file are answered without a round trip to VaaS. The least recently used verdicts are evicted first.
+    /// `Unknown` verdicts are never cached. The cache is shared by all connections of the [`Vaas`] instance, see
+    /// [`Connection::invalidate`](crate::Connection::invalidate). Disabled by default.
+    pub fn local_cache(self, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            options: Options {
+                local_cache: Some(Arc::new(VerdictCache::new(capacity, ttl))),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Answer requests for these hashes with [`Verdict::Clean`](crate::message::Verdict::Clean) without a request
+    /// to VaaS, e.g. for signed internal tools. The verdicts are marked with
+    /// [`VerdictSource::LocalList`](crate::VerdictSource::LocalList). Applies to files, buffers and SHA256 hashes.
+    /// The [`blocklist`](Builder::blocklist) takes precedence. More hashes can be added at runtime with
+    /// [`Connection::add_to_allowlist`](crate::Connection::add_to_allowlist).
+    pub fn allowlist(self, hashes: impl IntoIterator<Item = Sha256>) -> Self {
+        Self {
+            options: Options {
+                hash_lists: Arc::new(self.options.hash_lists.with_allowed(hashes)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Answer requests for these hashes with [`Verdict::Malicious`](crate::message::Verdict::Malicious) and the
+    /// detection `Blocklist` without a request to VaaS, e.g. for known indicators of compromise. Takes precedence
+    /// over the [`allowlist`](Builder::allowlist).
+    pub fn blocklist(self, hashes: impl IntoIterator<Item = Sha256>) -> Self {
+        Self {
+            options: Options {
+                hash_lists: Arc::new(self.options.hash_lists.with_blocked(hashes)),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Identify your application towards VaaS. The name and version are appended to the user agent
+    /// `vaas-rust/<sdk version>`, which is sent with the websocket connection, the file uploads and the token requests.
+    pub fn app_info(self, name: &str, version: &str) -> Self {
+        Self {
+            options: Options {
+                app_info: Some((name.to_string(), version.to_string())),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Call the hook with every verdict a request returns, e.g. to count verdicts without `tracing`.
+    /// Can be called multiple times to register several hooks.
+    ///
+    /// Hooks are called on the task of the request, so a slow hook delays its request, but not the others.
+    /// A panic in a hook is caught and logged as a warning, and the request returns its result as usual.
+    ///
+    /// ```rust
+    /// # fn main() -> vaas::error::VResult<()> {
+    /// use vaas::Builder;
+    /// use vaas::auth::authenticators::ClientCredentials;
+    ///
+    /// let authenticator = ClientCredentials::new("client_id".to_string(), "client_secret".to_string());
+    /// let vaas = Builder::new(authenticator)
+    ///     .on_verdict(|verdict| println!("{}: {}", verdict.sha256, verdict.verdict))
+    ///     .on_error(|e| eprintln!("Request failed: {e}"))
+    ///     .on_upload(|sha256, bytes| println!("Uploaded {bytes} bytes of {sha256}"))
+    ///     .build()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn on_verdict(mut self, hook: impl Fn(&VaasVerdict) + Send + Sync + 'static) -> Self {
+        self.options.hooks.on_verdict.push(Arc::new(hook));
+        self
+    }
+
+    /// Call the hook with every error a request returns, see [`Builder::on_verdict`].
+    pub fn on_error(mut self, hook: impl Fn(&Error) + Send + Sync + 'static) -> Self {
+        self.options.hooks.on_error.push(Arc::new(hook));
+        self
+    }
+
+    /// Call the hook with the SHA256 and size of every finished upload, see [`Builder::on_verdict`].
+    /// Compressed uploads report the size before compression.
+    pub fn on_upload(mut self, hook: impl Fn(&Sha256, u64) + Send + Sync + 'static) -> Self {
+        self.options.hooks.on_upload.push(Arc::new(hook));
+        self
+    }
+
+    /// Set the protocol the verdict requests are sent with. Defaults to [`Transport::WebSocket`].
+    /// [`Transport::Rest`] requires the `rest` feature.
+    ///
+    /// The requests of the [`Connection`](crate::Connection) are the same with every transport.
+    /// The REST transport uses the HTTP client of the SDK, see [`Builder::http_client`].
+    pub fn transport(self, transport: Transport) -> Self {
+        Self {
+            options: Options {
+                transport,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Record every frame of the websocket sessions to the NDJSON file at the path, with secrets redacted, e.g. to
+    /// reproduce a protocol issue with [`ReplayTransport`](crate::recording::ReplayTransport). An existing file is
+    /// replaced, and each connection of the instance starts the recording anew. Requires the `recording` feature.
+    ///
+    /// See the [`recording`](crate::recording) module for the format.
+    #[cfg(feature = "recording")]
+    pub fn record_session(self, path: impl Into<std::path::PathBuf>) -> Self {
+        Self {
+            options: Options {
+                record_session: Some(path.into()),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Use the given HTTP client for the file uploads and the token requests of the SDK authenticators,
+    /// e.g. to share a connection pool or custom settings with the rest of your application.
+    ///
+    /// The HTTP related settings of the builder ([`Builder::proxy`], [`Builder::add_root_certificate`],
+    /// [`Builder::identity`], [`Builder::danger_accept_invalid_certs`] and [`Builder::app_info`]) are not applied
+    /// to the given client, but still to the websocket connection.
+    pub fn http_client(self, http_client: reqwest::Client) -> Self {
+        Self {
+            http_client: Some(http_client),
+            ..self
+        }
+    }
+
+    /// Route the file uploads and the token requests of the SDK authenticators through a proxy.
+    /// Without an explicit proxy, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are honored.
+    ///
+    /// The websocket connection to the VaaS gateway does not support proxies and is always established directly.
+    pub fn proxy(self, proxy: ProxyConfig) -> Self {
+        Self {
+            options: Options {
+                proxy: Some(proxy),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Trust the given root certificate in addition to the default ones of the TLS backend, e.g. the certificate
+    /// authority of a TLS-inspecting gateway. Applies to the websocket connection, the file uploads and the token
+    /// requests. Can be called multiple times to add several certificates.
+    pub fn add_root_certificate(mut self, certificate: Certificate) -> Self {
+        self.options.root_certificates.push(certificate);
+        self
+    }
+
+    /// Authenticate with the client certificate of the identity, if the server requests it.
+    /// Applies to the websocket connection, the file uploads and the token requests.
+    pub fn identity(self, identity: Identity) -> Self {
+        Self {
+            options: Options {
+                identity: Some(identity),
+                ..self.options
+            },
+            ..self
+        }
+    }
+
+    /// Disable the validation of TLS certificates for the websocket connection, the file uploads and the token requests.
+    ///
+    /// **Warning:** Only use this in tests. Any certificate is accepted, which makes the connection vulnerable to
+    /// man-in-the-middle attacks. Defaults to `false`.
+    pub fn danger_accept_invalid_certs(self, accept_invalid_certs: bool) -> Self {
+        Self {
+            options: Options {
+                danger_accept_invalid_certs: accept_invalid_certs,
+                ..self.options
+            },
+            ..self
+        }
+    }
+
     /// Previously used to set the channel capacity of the internal results channel.
-    /// No longer used and currently a no-op.
+    /// No longer used and currently a no-op, as each response is dispatched directly to the waiting request,
+    /// so the number of concurrent requests is not limited by a channel capacity anymore.
     #[deprecated(since = "6.1.1", note = "Not used anymore")]
     pub fn channel_capacity(self, _capacity: usize) -> Self {
         self
     }
 
-    /// Change the URL of the VaaS API.
+    /// Change the URL of the VaaS API, e.g. to use a self-hosted or staging deployment.
+    /// The URL must use the `wss` or `ws` scheme.
+    ///
+    /// The URL of the token endpoint is configured on the authenticator, see
+    /// [`ClientCredentials::with_token_url`](crate::auth::authenticators::ClientCredentials::with_token_url).
     pub fn url(self, url: Url) -> Self {
         Self { url, ..self }
     }
 
     /// Create a [Vaas] struct from the `VaasBuilder`.
+    ///
+    /// Validates the configuration and the authenticator and returns [`Error::InvalidConfig`]
+    /// naming the offending setting, if it is invalid.
     pub fn build(self) -> VResult<Vaas<A>> {
+        self.validate()?;
+        self.authenticator.validate()?;
+
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => http_client(&self.options)?,
+        };
+        let mut authenticator = self.authenticator;
+        authenticator.set_http_client(http_client.clone());
+
         Ok(Vaas {
             options: self.options,
-            authenticator: self.authenticator,
+            authenticator: Arc::new(authenticator),
             url: self.url,
+            http_client,
         })
     }
+
+    fn validate(&self) -> VResult<()> {
+        if !matches!(self.url.scheme(), "wss" | "ws") {
+            return Err(Error::InvalidConfig(format!(
+                "url must use the wss or ws scheme, got `{}`",
+                self.url
+            )));
+        }
+        if self.options.keep_alive && self.options.keep_alive_delay < MIN_KEEP_ALIVE_DELAY {
+            return Err(Error::InvalidConfig(format!(
+                "keep_alive_delay must be at least {}ms, got {}ms",
+                MIN_KEEP_ALIVE_DELAY.as_millis(),
+                self.options.keep_alive_delay.as_millis()
+            )));
+        }
+        if self.options.keep_alive
+            && self.options.keep_alive_jitter >= self.options.keep_alive_delay
+        {
+            return Err(Error::InvalidConfig(format!(
+                "keep_alive_jitter must be less than keep_alive_delay ({}ms), got {}ms",
+                self.options.keep_alive_delay.as_millis(),
+                self.options.keep_alive_jitter.as_millis()
+            )));
+        }
+        ensure_not_zero("default_timeout", self.options.default_timeout)?;
+        ensure_not_zero("connect_timeout", self.options.connect_timeout)?;
+        ensure_not_zero("auth_timeout", self.options.auth_timeout)?;
+        if let Some(upload_timeout) = self.options.upload_timeout {
+            ensure_not_zero("upload_timeout", upload_timeout)?;
+        }
+        if !matches!(
+            self.options.upload_http_version,
+            Version::HTTP_11 | Version::HTTP_2
+        ) {
+            return Err(Error::InvalidConfig(format!(
+                "upload_http_version must be HTTP/1.1 or HTTP/2, got {:?}",
+                self.options.upload_http_version
+            )));
+        }
+        if let Some(max_concurrent_uploads) = self.options.max_concurrent_uploads {
+            if !(1..=Semaphore::MAX_PERMITS).contains(&max_concurrent_uploads) {
+                return Err(Error::InvalidConfig(format!(
+                    "max_concurrent_uploads must be between 1 and {}, got {max_concurrent_uploads}",
+                    Semaphore::MAX_PERMITS
+                )));
+            }
+        }
+        if HeaderValue::from_str(&self.options.upload_content_type).is_err() {
+            return Err(Error::InvalidConfig(format!(
+                "upload_content_type is not a valid header value: {:?}",
+                self.options.upload_content_type
+            )));
+        }
+        if let Some(header_name) = &self.options.upload_checksum_header {
+            if HeaderName::from_str(header_name).is_err() {
+                return Err(Error::InvalidConfig(format!(
+                    "upload_checksum_header is not a valid header name: {header_name:?}"
+                )));
+            }
+        }
+        let allowed_upload_hosts = self.options.allowed_upload_hosts.iter().flatten();
+        for host in allowed_upload_hosts {
+            if HostPattern::parse(host).is_none() {
+                return Err(Error::InvalidConfig(format!(
+                    "allowed_upload_hosts contains an invalid host: {host:?}"
+                )));
+            }
+        }
+        if let Some(max_in_flight) = self.options.max_in_flight {
+            if !(1..=Semaphore::MAX_PERMITS).contains(&max_in_flight) {
+                return Err(Error::InvalidConfig(format!(
+                    "max_in_flight must be between 1 and {}, got {max_in_flight}",
+                    Semaphore::MAX_PERMITS
+                )));
+            }
+        }
+        if self.options.max_message_size == 0 {
+            return Err(Error::InvalidConfig(
+                "max_message_size must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            if rate_limiter.requests_per_second() == 0 || rate_limiter.burst() == 0 {
+                return Err(Error::InvalidConfig(
+                    "rate_limit requests_per_second and burst must be greater than 0".to_string(),
+                ));
+            }
+        }
+        let retry_policy = &self.options.retry_policy;
+        if retry_policy.max_attempts == 0 {
+            return Err(Error::InvalidConfig(
+                "retry_policy.max_attempts must be at least 1".to_string(),
+            ));
+        }
+        if retry_policy.base_delay > retry_policy.max_delay {
+            return Err(Error::InvalidConfig(
+                "retry_policy.base_delay must not be greater than retry_policy.max_delay"
+                    .to_string(),
+            ));
+        }
+        if self.options.hash_buffer_size == 0 {
+            return Err(Error::InvalidConfig(
+                "hash_buffer_size must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(verdict_timeout) = self.options.verdict_timeout {
+            ensure_not_zero("verdict_timeout", verdict_timeout)?;
+        }
+        if let Some(cache) = &self.options.local_cache {
+            if cache.capacity() == 0 {
+                return Err(Error::InvalidConfig(
+                    "local_cache capacity must be greater than 0".to_string(),
+                ));
+            }
+            ensure_not_zero("local_cache ttl", cache.ttl())?;
+        }
+        if let Some((name, version)) = &self.options.app_info {
+            if name.is_empty() || version.is_empty() {
+                return Err(Error::InvalidConfig(
+                    "app_info name and version must not be empty".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ensure_not_zero(field: &str, duration: Duration) -> VResult<()> {
+    if duration.is_zero() {
+        return Err(Error::InvalidConfig(format!(
+            "{field} must be greater than 0"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::authenticators::ClientCredentials;
+    use crate::cancellation::CancellationToken;
+    use crate::message::Verdict;
+    use std::str::FromStr;
+
+    fn builder() -> Builder<ClientCredentials> {
+        Builder::new(ClientCredentials::new(
+            "client_id".to_string(),
+            "client_secret".to_string(),
+        ))
+    }
+
+    #[test]
+    fn build_with_custom_url_uses_url() {
+        let url = Url::from_str("ws://localhost:8080").unwrap();
+        let vaas = builder().url(url.clone()).build().unwrap();
+        assert_eq!(url, vaas.url);
+    }
+
+    #[test]
+    fn build_with_https_url_fails() {
+        let url = Url::from_str("https://gateway.production.vaas.gdatasecurity.de").unwrap();
+        let result = builder().url(url).build();
+        assert_invalid_config(result, "url");
+    }
+
+    #[test]
+    fn build_with_keep_alive_delay_uses_delay() {
+        let vaas = builder()
+            .keep_alive_delay(Duration::from_secs(30))
+            .build()
+            .unwrap();
+        assert!(vaas.options.keep_alive);
+        assert_eq!(Duration::from_secs(30), vaas.options.keep_alive_delay);
+    }
+
+    #[test]
+    fn build_with_keep_alive_jitter_uses_jitter() {
+        let vaas = builder()
+            .keep_alive_jitter(Duration::from_secs(2))
+            .build()
+            .unwrap();
+        assert_eq!(Duration::from_secs(2), vaas.options.keep_alive_jitter);
+    }
+
+    #[test]
+    fn build_with_keep_alive_jitter_above_delay_fails() {
+        let result = builder()
+            .keep_alive_delay(Duration::from_secs(5))
+            .keep_alive_jitter(Duration::from_secs(5))
+            .build();
+        assert_invalid_config(result, "keep_alive_jitter");
+    }
+
+    #[test]
+    fn build_with_default_timeout_uses_timeout() {
+        let vaas = builder()
+            .default_timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        assert_eq!(Duration::from_secs(5), vaas.options.default_timeout);
+    }
+
+    #[test]
+    fn build_without_default_timeout_uses_cancellation_token_default() {
+        let vaas = builder().build().unwrap();
+        assert_eq!(
+            CancellationToken::default().duration,
+            vaas.options.default_timeout
+        );
+    }
+
+    #[test]
+    fn build_with_app_info_appends_app_info_to_user_agent() {
+        let vaas = builder().app_info("my-app", "1.2.3").build().unwrap();
+        assert_eq!(
+            format!("vaas-rust/{} my-app/1.2.3", env!("CARGO_PKG_VERSION")),
+            vaas.options.user_agent()
+        );
+    }
+
+    #[tokio::test]
+    async fn token_request_sends_user_agent() {
+        let mut server = mockito::Server::new_async().await;
+        let token_endpoint = server
+            .mock("POST", "/token")
+            .match_header(
+                "user-agent",
+                format!("vaas-rust/{} my-app/1.2.3", env!("CARGO_PKG_VERSION")).as_str(),
+            )
+            .with_status(200)
+            .with_body(r#"{"access_token":"token"}"#)
+            .create_async()
+            .await;
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "client_secret".to_string())
+                .with_token_url(Url::parse(&format!("{}/token", server.url())).unwrap());
+
+        let vaas = Builder::new(authenticator)
+            .app_info("my-app", "1.2.3")
+            .build()
+            .unwrap();
+        let token = vaas.authenticator.get_token().await.unwrap();
+
+        assert_eq!("token", token);
+        token_endpoint.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn token_request_uses_injected_http_client() {
+        let mut server = mockito::Server::new_async().await;
+        let token_endpoint = server
+            .mock("POST", "/token")
+            .match_header("x-injected-client", "true")
+            .with_status(200)
+            .with_body(r#"{"access_token":"token"}"#)
+            .create_async()
+            .await;
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "client_secret".to_string())
+                .with_token_url(Url::parse(&format!("{}/token", server.url())).unwrap());
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-injected-client", "true".parse().unwrap());
+        let http_client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+
+        let vaas = Builder::new(authenticator)
+            .http_client(http_client)
+            .build()
+            .unwrap();
+        let token = vaas.authenticator.get_token().await.unwrap();
+
+        assert_eq!("token", token);
+        token_endpoint.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn token_request_prefers_http_client_of_authenticator() {
+        let mut proxy = mockito::Server::new_async().await;
+        let token_endpoint = proxy
+            .mock("POST", "/token")
+            .match_header("host", "idp.invalid")
+            .with_status(200)
+            .with_body(r#"{"access_token":"token"}"#)
+            .create_async()
+            .await;
+        let proxied_client = reqwest::Client::builder()
+            .proxy(reqwest::Proxy::http(proxy.url()).unwrap())
+            .build()
+            .unwrap();
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "client_secret".to_string())
+                .with_token_url(Url::parse("http://idp.invalid/token").unwrap())
+                .with_http_client(proxied_client);
+
+        let vaas = Builder::new(authenticator)
+            .http_client(reqwest::Client::new())
+            .build()
+            .unwrap();
+        let token = vaas.authenticator.get_token().await.unwrap();
+
+        assert_eq!("token", token);
+        token_endpoint.assert_async().await;
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "client-secret".to_string());
+        let proxy = ProxyConfig::new(Url::from_str("http://proxy.local:3128").unwrap())
+            .with_basic_auth("user".to_string(), "proxy-password".to_string());
+        let builder = Builder::new(authenticator).proxy(proxy);
+
+        let debug = format!("{:?}", builder);
+        let vaas_debug = format!("{:?}", builder.build().unwrap());
+
+        for debug in [debug, vaas_debug] {
+            assert!(!debug.contains("client-secret"));
+            assert!(!debug.contains("proxy-password"));
+        }
+    }
+
+    #[test]
+    fn build_with_upload_disabled() {
+        let vaas = builder().upload(false).build().unwrap();
+        assert!(!vaas.options.upload);
+    }
+
+    #[test]
+    fn build_without_upload_option_enables_upload() {
+        let vaas = builder().build().unwrap();
+        assert!(vaas.options.upload);
+    }
+
+    fn assert_invalid_config(result: VResult<Vaas<ClientCredentials>>, field: &str) {
+        match result {
+            Err(Error::InvalidConfig(message)) => assert!(
+                message.contains(field),
+                "`{message}` does not name `{field}`"
+            ),
+            other => panic!("expected invalid config for {field}, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn build_with_too_short_keep_alive_delay_fails() {
+        let result = builder().keep_alive_delay_ms(500).build();
+        assert_invalid_config(result, "keep_alive_delay");
+    }
+
+    #[test]
+    fn build_with_zero_default_timeout_fails() {
+        let result = builder().default_timeout(Duration::ZERO).build();
+        assert_invalid_config(result, "default_timeout");
+    }
+
+    #[test]
+    fn build_with_zero_connect_timeout_fails() {
+        let result = builder().connect_timeout(Duration::ZERO).build();
+        assert_invalid_config(result, "connect_timeout");
+    }
+
+    #[test]
+    fn build_with_zero_auth_timeout_fails() {
+        let result = builder().auth_timeout(Duration::ZERO).build();
+        assert_invalid_config(result, "auth_timeout");
+    }
+
+    #[test]
+    fn build_with_http2_uploads_uses_http2() {
+        let vaas = builder()
+            .upload_http_version(Version::HTTP_2)
+            .build()
+            .unwrap();
+        assert_eq!(Version::HTTP_2, vaas.options.upload_http_version);
+    }
+
+    #[test]
+    fn build_with_http3_uploads_fails() {
+        let result = builder().upload_http_version(Version::HTTP_3).build();
+        assert_invalid_config(result, "upload_http_version");
+    }
+
+    #[test]
+    fn build_with_zero_max_concurrent_uploads_fails() {
+        let result = builder().max_concurrent_uploads(0).build();
+        assert_invalid_config(result, "max_concurrent_uploads");
+    }
+
+    #[test]
+    fn build_with_zero_max_in_flight_fails() {
+        let result = builder().max_in_flight(0).build();
+        assert_invalid_config(result, "max_in_flight");
+    }
+
+    #[test]
+    fn build_with_zero_max_message_size_fails() {
+        let result = builder().max_message_size(0).build();
+        assert_invalid_config(result, "max_message_size");
+    }
+
+    #[test]
+    fn build_with_zero_rate_limit_fails() {
+        let result = builder().rate_limit(10, 0).build();
+        assert_invalid_config(result, "rate_limit");
+    }
+
+    #[test]
+    fn build_with_allowlist_and_blocklist_answers_listed_hashes() {
+        let allowed = Sha256::from(&b"allowed"[..]);
+        let blocked = Sha256::from(&b"blocked"[..]);
+        let vaas = builder()
+            .allowlist([allowed.clone(), blocked.clone()])
+            .blocklist([blocked.clone()])
+            .build()
+            .unwrap();
+
+        let lists = &vaas.options.hash_lists;
+        assert_eq!(Verdict::Clean, lists.verdict(&allowed).unwrap().verdict);
+        assert!(matches!(
+            lists.verdict(&blocked).unwrap().verdict,
+            Verdict::Malicious { .. }
+        ));
+    }
+
+    #[test]
+    fn build_with_empty_local_cache_fails() {
+        let result = builder().local_cache(0, Duration::from_secs(60)).build();
+        assert_invalid_config(result, "local_cache");
+    }
+
+    #[test]
+    fn build_with_invalid_upload_content_type_fails() {
+        let result = builder()
+            .upload_content_type("application/octet-stream\r\nx-injected: 1")
+            .build();
+        assert_invalid_config(result, "upload_content_type");
+    }
+
+    #[test]
+    fn build_with_invalid_upload_checksum_header_fails() {
+        let result = builder().upload_checksum_header("checksum sha256").build();
+        assert_invalid_config(result, "upload_checksum_header");
+    }
+
+    #[test]
+    fn build_with_upload_headers() {
+        let vaas = builder()
+            .upload_content_type("application/x-binary")
+            .upload_checksum_header("x-amz-checksum-sha256")
+            .build()
+            .unwrap();
+        assert_eq!("application/x-binary", vaas.options.upload_content_type);
+        assert_eq!(
+            Some("x-amz-checksum-sha256"),
+            vaas.options.upload_checksum_header.as_deref()
+        );
+    }
+
+    #[test]
+    fn build_with_zero_upload_rate_limit_is_unlimited() {
+        let vaas = builder().upload_rate_limit(0).build().unwrap();
+        assert_eq!(None, vaas.options.upload_rate_limit);
+
+        let vaas = builder().upload_rate_limit(1024).build().unwrap();
+        assert_eq!(Some(1024), vaas.options.upload_rate_limit);
+    }
+
+    #[test]
+    fn build_with_compressed_uploads() {
+        let vaas = builder().build().unwrap();
+        assert!(!vaas.options.compress_uploads);
+
+        let vaas = builder().compress_uploads(true).build().unwrap();
+        assert!(vaas.options.compress_uploads);
+    }
+
+    #[test]
+    fn build_with_invalid_allowed_upload_host_fails() {
+        let result = builder()
+            .allowed_upload_hosts(vec![
+                "*.example.com".to_string(),
+                "example.com/upload".to_string(),
+            ])
+            .build();
+        assert_invalid_config(result, "allowed_upload_hosts");
+    }
+
+    #[test]
+    fn build_with_allowed_upload_hosts() {
+        let vaas = builder().build().unwrap();
+        assert_eq!(
+            Some(vec!["*.vaas.gdatasecurity.de".to_string()]),
+            vaas.options.allowed_upload_hosts
+        );
+
+        let hosts = vec![
+            "upload.example.com".to_string(),
+            "*.bücher.example".to_string(),
+        ];
+        let vaas = builder()
+            .allowed_upload_hosts(hosts.clone())
+            .build()
+            .unwrap();
+        assert_eq!(Some(hosts), vaas.options.allowed_upload_hosts);
+    }
+
+    #[test]
+    fn build_with_zero_hash_buffer_size_fails() {
+        let result = builder().hash_buffer_size(0).build();
+        assert_invalid_config(result, "hash_buffer_size");
+    }
+
+    #[test]
+    fn build_with_zero_retry_attempts_fails() {
+        let result = builder()
+            .retry_policy(RetryPolicy {
+                max_attempts: 0,
+                ..RetryPolicy::default()
+            })
+            .build();
+        assert_invalid_config(result, "retry_policy.max_attempts");
+    }
+
+    #[test]
+    fn build_with_base_delay_above_max_delay_fails() {
+        let result = builder()
+            .retry_policy(RetryPolicy {
+                base_delay: Duration::from_secs(2),
+                max_delay: Duration::from_secs(1),
+                ..RetryPolicy::default()
+            })
+            .build();
+        assert_invalid_config(result, "retry_policy.base_delay");
+    }
+
+    #[test]
+    fn build_with_zero_upload_timeout_fails() {
+        let result = builder().upload_timeout(Duration::ZERO).build();
+        assert_invalid_config(result, "upload_timeout");
+    }
+
+    #[test]
+    fn build_with_zero_verdict_timeout_fails() {
+        let result = builder().verdict_timeout(Duration::ZERO).build();
+        assert_invalid_config(result, "verdict_timeout");
+    }
+
+    #[test]
+    fn build_with_empty_app_info_fails() {
+        let result = builder().app_info("", "1.0.0").build();
+        assert_invalid_config(result, "app_info");
+    }
+
+    #[test]
+    fn build_with_empty_client_id_fails() {
+        let authenticator = ClientCredentials::new(String::new(), "client_secret".to_string());
+        let result = Builder::new(authenticator).build();
+        assert_invalid_config(result, "client_id");
+    }
+
+    #[test]
+    fn build_with_invalid_token_url_fails() {
+        let authenticator = ClientCredentials::new("id".to_string(), "secret".to_string())
+            .with_token_url(Url::from_str("ftp://account.gdata.de/token").unwrap());
+        let result = Builder::new(authenticator).build();
+        assert_invalid_config(result, "token_url");
+    }
+
+    #[test]
+    fn build_with_disabled_keep_alive_ignores_delay() {
+        let vaas = builder()
+            .keep_alive(false)
+            .keep_alive_delay_ms(0)
+            .build()
+            .unwrap();
+        assert!(!vaas.options.keep_alive);
+    }
 }
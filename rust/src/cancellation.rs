@@ -3,11 +3,15 @@
 //! As a request for a verdict can take some time if, for example the file is huge or the network connection is slow, it is possible to cancel
 //! each verdict request after some time. This is done by using a `CancellationToken` which can be created from a `Duration`.
 //! If the duration is up, the request is aborted and an error is returned.
+//!
+//! If no `CancellationToken` is passed to a request, the default timeout of the connection is used,
+//! which can be configured with [`Builder::default_timeout`](crate::Builder::default_timeout).
 
 use std::time::Duration;
 
 /// The `CancellationToken` allows to cancel a request after a specific time
 /// if no response was received from the server.
+#[derive(Debug, Clone)]
 pub struct CancellationToken {
     /// Duration after which the request is cancelled.
     pub duration: Duration,
@@ -28,3 +32,16 @@ impl CancellationToken {
         }
     }
 }
+
+impl Default for CancellationToken {
+    /// Create a new `CancellationToken` which cancels the request after one minute.
+    fn default() -> Self {
+        Self::from_minutes(1)
+    }
+}
+
+impl From<Duration> for CancellationToken {
+    fn from(duration: Duration) -> Self {
+        Self { duration }
+    }
+}
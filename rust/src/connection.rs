@@ -1,102 +1,296 @@
 //! The `Connection` module provides all functionality to create an active connection to the verdict backend.
 
-use crate::error::{Error, VResult};
+use crate::auth::Authenticator;
+use crate::error::{ConnectPhase, Error, VResult};
+use crate::hashing_stream::{BoxedByteStream, HashingStream};
+use crate::instrumentation::{debug_event, metric, span};
 use crate::message::{
-    MessageType, UploadUrl, Verdict, VerdictRequest, VerdictRequestFile, VerdictRequestForStream,
-    VerdictRequestForUrl, VerdictResponse,
+    AuthRequest, AuthResponse, MessageType, RequestId, UploadUrl, Verdict, VerdictRequest,
+    VerdictRequestFile, VerdictRequestForStream, VerdictRequestForUrl, VerdictResponse,
 };
 use crate::options::Options;
+use crate::pending_upload::{PendingUpload, UploadUrlResponse};
+use crate::propagation;
 use crate::sha256::Sha256;
-use crate::vaas_verdict::VaasVerdict;
+use crate::stats::{InFlight, Stats, StatsSnapshot};
+use crate::throttled_stream::ThrottledStream;
+use crate::vaas::with_timeout;
+use crate::vaas_verdict::{Stopwatch, VaasVerdict, VerdictSource};
 use crate::response_broker::ResponseBroker;
+use crate::retry::{retry, TokioClock};
+use crate::websocket::{Frame, WebSocketError, WebSocketReadHalf};
+use crate::ws_writer::{FrameSink, OutgoingFrame, WsWriter};
 use crate::CancellationToken;
+use async_compression::tokio::bufread::GzipEncoder;
+use async_trait::async_trait;
+use base64::Engine;
 use bytes::Bytes;
 use futures::future::join_all;
-use futures_util::FutureExt;
-use reqwest::{Body, Response, Url, Version};
+use futures_util::{FutureExt, StreamExt, TryStreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Body, Response, StatusCode, Url, Version};
 use serde::Serialize;
 use std::convert::TryFrom;
+use std::fmt;
 use std::future::Future;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
 use tokio::task::JoinHandle;
-use tokio::time::timeout;
-use websockets::{Frame, WebSocketError, WebSocketReadHalf, WebSocketWriteHalf};
+use tokio::time::{timeout, timeout_at, Instant, MissedTickBehavior};
+use tokio_util::io::{ReaderStream, StreamReader};
+use tracing::{debug, warn, Instrument, Span};
 
 type ThreadHandle = JoinHandle<Result<(), Error>>;
-type WebSocketWriter = Arc<Mutex<WebSocketWriteHalf>>;
 type VaasResponseBroker = ResponseBroker<VerdictResponse, Error>;
+type AuthResponseBroker = ResponseBroker<AuthResponse, Error>;
+
+/// The server answers authentication requests without a request id, so only one can be pending at a time.
+const AUTH_RESPONSE_ID: &str = "auth";
+
+/// The default of [`Builder::max_message_size`](crate::Builder::max_message_size). VaaS messages are a few hundred
+/// bytes.
+pub(crate) const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// The authenticator of the connection, used to re-authenticate the session.
+#[derive(Clone)]
+struct SharedAuthenticator(Arc<dyn Authenticator + Send + Sync>);
+
+impl fmt::Debug for SharedAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Authenticator")
+    }
+}
 
 /// Active connection to the verdict server.
+///
+/// Each request takes an optional [`CancellationToken`]. If `None` is passed, the request is cancelled after the
+/// default timeout configured with [`Builder::default_timeout`](crate::Builder::default_timeout).
+///
+/// All uploads of a connection use the HTTP client of the [`Vaas`](crate::Vaas) instance,
+/// so connections to the upload endpoint are kept alive and reused.
 #[derive(Debug)]
 pub struct Connection {
-    ws_writer: WebSocketWriter,
-    session_id: String,
+    ws_writer: WsWriter,
+    /// Shared with every request instead of being copied into it.
+    session_id: Arc<str>,
     reader_thread: ThreadHandle,
+    writer_thread: ThreadHandle,
     keep_alive_thread: Option<ThreadHandle>,
     responses: Arc<VaasResponseBroker>,
     options: Options,
+    http_client: reqwest::Client,
+    upload_permits: Arc<Semaphore>,
+    in_flight_permits: Semaphore,
+    stats: Arc<Stats>,
+    authenticator: SharedAuthenticator,
+    auth_responses: Arc<AuthResponseBroker>,
+    auth_lock: Mutex<()>,
+    closed: Arc<AtomicBool>,
+    /// The span of the connection, which the spans of its requests and uploads belong to.
+    span: Span,
 }
 
 impl Connection {
     pub(crate) async fn start(
-        ws_writer: WebSocketWriteHalf,
-        ws_reader: WebSocketReadHalf,
+        ws_writer: impl FrameSink,
+        ws_reader: impl FrameSource,
         session_id: String,
         options: Options,
+        http_client: reqwest::Client,
+        authenticator: Arc<dyn Authenticator + Send + Sync>,
     ) -> Self {
-        let ws_writer = Arc::new(Mutex::new(ws_writer));
-        let responses = Arc::new(ResponseBroker::new());
+        let span = span!("vaas_connection", session_id = %session_id);
+        metric!(counter!(CONNECTIONS).increment(1));
+        let responses: Arc<VaasResponseBroker> = Arc::new(ResponseBroker::new());
+        let auth_responses: Arc<AuthResponseBroker> = Arc::new(ResponseBroker::new());
+        let (ws_writer, writer_loop) = {
+            let responses = responses.clone();
+            let auth_responses = auth_responses.clone();
+            span.in_scope(|| {
+                WsWriter::spawn(ws_writer, move |e| {
+                    responses.set_all_responses(Err(e.clone()));
+                    auth_responses.set_all_responses(Err(e));
+                })
+            })
+        };
 
-        let reader_loop = Connection::start_reader_loop(ws_reader, responses.clone()).await;
-        let keep_alive_loop = Self::start_keep_alive(&options, &ws_writer, responses.clone()).await;
+        let closed = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(Stats::default());
+        let reader_loop = Connection::start_reader_loop(
+            ws_reader,
+            responses.clone(),
+            auth_responses.clone(),
+            closed.clone(),
+            stats.clone(),
+            options.max_message_size,
+        )
+        .instrument(span.clone())
+        .await;
+        let keep_alive_loop = Self::start_keep_alive(&options, &ws_writer)
+            .instrument(span.clone())
+            .await;
+        let upload_permits = Arc::new(Semaphore::new(
+            options
+                .max_concurrent_uploads
+                .unwrap_or(Semaphore::MAX_PERMITS),
+        ));
+        let in_flight_permits =
+            Semaphore::new(options.max_in_flight.unwrap_or(Semaphore::MAX_PERMITS));
 
         Connection {
             ws_writer,
-            session_id,
+            session_id: session_id.into(),
             reader_thread: reader_loop,
+            writer_thread: writer_loop,
             keep_alive_thread: keep_alive_loop,
             responses,
             options,
+            http_client,
+            upload_permits,
+            in_flight_permits,
+            stats,
+            authenticator: SharedAuthenticator(authenticator),
+            auth_responses,
+            auth_lock: Mutex::new(()),
+            closed,
+            span,
         }
     }
 
-    async fn start_keep_alive(
-        options: &Options,
-        ws_writer: &Arc<Mutex<WebSocketWriteHalf>>,
-        responses: Arc<VaasResponseBroker>,
-    ) -> Option<ThreadHandle> {
+    /// Authenticate the session again with a new token from the authenticator, before the token used so far expires.
+    ///
+    /// The session and pending requests are kept. The token request and the authentication are each bounded by
+    /// the timeout configured with [`Builder::auth_timeout`](crate::Builder::auth_timeout).
+    /// Fails with [`Error::Unauthorized`] if VaaS rejects the new token.
+    pub async fn refresh_auth(&self) -> VResult<()> {
+        let _auth = self.auth_lock.lock().await;
+        let token = with_timeout(
+            self.options.auth_timeout,
+            ConnectPhase::TokenRequest,
+            self.authenticator.0.refresh_token(),
+        )
+        .await?;
+        let response = self
+            .auth_responses
+            .get_response(AUTH_RESPONSE_ID.to_string());
+        let request = AuthRequest::new(token, Some(self.session_id.to_string())).to_json()?;
+        self.ws_writer.send(OutgoingFrame::Text(request))?;
+        let response = with_timeout(
+            self.options.auth_timeout,
+            ConnectPhase::Authentication,
+            response,
+        )
+        .await?;
+        if response.success {
+            Ok(())
+        } else {
+            Err(Error::Unauthorized(response.text))
+        }
+    }
+
+    /// Whether VaaS closed the connection or reading from it failed. Requests on a closed connection fail,
+    /// a new connection has to be established with [`Vaas::connect`](crate::Vaas::connect).
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Answer requests for the hash with `Clean` from now on, on all connections of the [`Vaas`](crate::Vaas)
+    /// instance. See [`Builder::allowlist`](crate::Builder::allowlist).
+    pub fn add_to_allowlist(&self, sha256: Sha256) {
+        self.options.hash_lists.allow(sha256);
+    }
+
+    /// Answer requests for the hash with `Malicious` from now on, on all connections of the [`Vaas`](crate::Vaas)
+    /// instance. See [`Builder::blocklist`](crate::Builder::blocklist).
+    pub fn add_to_blocklist(&self, sha256: Sha256) {
+        self.options.hash_lists.block(sha256);
+    }
+
+    /// Remove the verdict of the file from the local cache, e.g. because it was classified again.
+    /// The cache is shared by all connections of the [`Vaas`](crate::Vaas) instance,
+    /// see [`Builder::local_cache`](crate::Builder::local_cache).
+    pub fn invalidate(&self, sha256: &Sha256) {
+        if let Some(cache) = &self.options.local_cache {
+            cache.remove(sha256);
+        }
+    }
+
+    /// Remove all verdicts from the local cache, see [`Connection::invalidate`].
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.options.local_cache {
+            cache.clear();
+        }
+    }
+
+    /// The limit of concurrent requests, see [`Builder::max_in_flight`](crate::Builder::max_in_flight).
+    #[cfg(feature = "tower")]
+    pub(crate) fn max_in_flight(&self) -> Option<usize> {
+        self.options.max_in_flight
+    }
+
+    /// A snapshot of the statistics of this connection, e.g. the number of requests, verdicts and uploads.
+    pub fn stats(&self) -> StatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    async fn start_keep_alive(options: &Options, ws_writer: &WsWriter) -> Option<ThreadHandle> {
         if !options.keep_alive {
             return None;
         }
         Some(
-            Connection::keep_alive_loop(ws_writer.clone(), options.keep_alive_delay_ms, responses)
-                .await,
+            Connection::keep_alive_loop(
+                ws_writer.clone(),
+                options.keep_alive_delay,
+                options.keep_alive_jitter,
+            )
+            .await,
         )
     }
 
     /// Request a verdict for a file behind a URL.
-    pub async fn for_url(&self, url: &Url, ct: &CancellationToken) -> VResult<VaasVerdict> {
+    /// Fails with [`Error::UploadDisabled`] if uploads are disabled.
+    pub async fn for_url(
+        &self,
+        url: &Url,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        let verdict = self.verdict_for_url(url, ct.into()).await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_for_url(
+        &self,
+        url: &Url,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        self.ensure_upload_enabled()?;
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let _request = self.start_request(&ct).await?;
         let request = VerdictRequestForUrl::new(
             url,
             self.session_id.clone(),
             self.options.use_cache,
             self.options.use_hash_lookup,
         );
-        let response =
-            self.for_request(request, ct).await?;
-        VaasVerdict::try_from(response)
+        let response = self.for_request(request, &ct, &mut stopwatch).await?;
+        stopwatch.stamp(VaasVerdict::try_from(response))
     }
 
     /// Request a verdict for files behind a list of URLs.
     pub async fn for_url_list(
         &self,
         url_list: &[Url],
-        ct: &CancellationToken,
+        ct: impl Into<Option<&CancellationToken>>,
     ) -> Vec<VResult<VaasVerdict>> {
+        let ct = ct.into();
         let req = url_list
             .iter()
             .map(|url| self.for_url(url, ct))
@@ -109,57 +303,88 @@ impl Connection {
     pub async fn for_sha256(
         &self,
         sha256: &Sha256,
-        ct: &CancellationToken,
+        ct: impl Into<Option<&CancellationToken>>,
     ) -> VResult<VaasVerdict> {
+        let verdict = self.verdict_for_sha256(sha256, ct.into()).await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_for_sha256(
+        &self,
+        sha256: &Sha256,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        if let Some(verdict) = self.local_verdict(sha256) {
+            return Ok(verdict);
+        }
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let _request = self.start_request(&ct).await?;
         let request = VerdictRequestFile::new(
             sha256,
             self.session_id.clone(),
             self.options.use_cache,
             self.options.use_hash_lookup,
         );
-        let response =
-            self.for_request(request, ct).await?;
-        VaasVerdict::try_from(response)
+        let response = self.for_request(request, &ct, &mut stopwatch).await?;
+        self.cache(stopwatch.stamp(VaasVerdict::try_from(response)))
     }
 
-    /// Request a verdict for a SHA256 file hash.
+    /// Request a verdict for a stream.
+    /// Fails with [`Error::UploadDisabled`] if uploads are disabled.
     pub async fn for_stream<S>(
         &self,
         stream: S,
         content_length: usize,
-        ct: &CancellationToken,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict>
+    where
+        S: futures_util::stream::TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        let verdict = self
+            .verdict_for_stream(stream, content_length, ct.into())
+            .await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_for_stream<S>(
+        &self,
+        stream: S,
+        content_length: usize,
+        ct: Option<&CancellationToken>,
     ) -> VResult<VaasVerdict>
     where
         S: futures_util::stream::TryStream + Send + Sync + 'static,
         S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
         Bytes: From<S::Ok>,
     {
+        self.ensure_upload_enabled()?;
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let _request = self.start_request(&ct).await?;
         let request = VerdictRequestForStream::new(
             self.session_id.clone(),
             self.options.use_cache,
             self.options.use_hash_lookup,
         );
-        let guid = request.guid.to_string();
+        let guid = request.guid;
 
-        let response =
-            self.for_request(request, ct).await?;
+        let response = self.for_request(request, &ct, &mut stopwatch).await?;
 
         let verdict = Verdict::try_from(&response)?;
 
-        match verdict {
+        let verdict = match verdict {
             Verdict::Unknown { upload_url } => {
-                self.handle_unknown_stream(
-                    stream,
-                    content_length,
-                    guid,
-                    response,
-                    upload_url,
-                    ct,
-                )
-                .await
+                let upload = PendingUpload::new(guid, upload_url, response)?;
+                self.handle_unknown_stream(stream, content_length, upload, &ct, &mut stopwatch)
+                    .await
             }
             _ => Err(Error::Cancelled),
-        }
+        };
+        // The SHA256 of a stream is only known afterwards, so it is not looked up but cached for other requests.
+        self.cache(stopwatch.stamp(verdict))
     }
 
     /// Request verdicts for a list of SHA256 file hashes.
@@ -167,8 +392,9 @@ impl Connection {
     pub async fn for_sha256_list(
         &self,
         sha256_list: &[Sha256],
-        ct: &CancellationToken,
+        ct: impl Into<Option<&CancellationToken>>,
     ) -> Vec<VResult<VaasVerdict>> {
+        let ct = ct.into();
         let req = sha256_list
             .iter()
             .map(|sha256| self.for_sha256(sha256, ct))
@@ -177,76 +403,424 @@ impl Connection {
     }
 
     /// Request a verdict for a file.
-    pub async fn for_file(&self, file: &Path, ct: &CancellationToken) -> VResult<VaasVerdict> {
-        let buf = tokio::fs::read(file).await?;
-        self.for_buf(buf, ct).await
+    /// If VaaS does not know the file, it is uploaded for analysis unless uploads are disabled.
+    ///
+    /// The file is hashed on a blocking thread in chunks of [`Builder::hash_buffer_size`](crate::Builder::hash_buffer_size).
+    /// With [`Builder::cancellable_hashing`](crate::Builder::cancellable_hashing), the cancellation token also limits hashing.
+    /// If it has to be uploaded, it is streamed from disk and hashed again while uploading. If the file was
+    /// modified in the meantime, [`Error::Sha256Mismatch`] is returned. If the upload token expired before the
+    /// upload finished, a new one is requested and the file is uploaded again.
+    pub async fn for_file(
+        &self,
+        file: &Path,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        let verdict = self.verdict_for_file(file, ct.into()).await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_for_file(
+        &self,
+        file: &Path,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let sha256 = if self.options.cancellable_hashing {
+            let deadline = std::time::Instant::now() + ct.duration;
+            Sha256::from_file_until(file, self.options.hash_buffer_size, deadline, |_, _| {}).await
+        } else {
+            Sha256::from_file_with_buffer_size(file, self.options.hash_buffer_size).await
+        };
+        let sha256 = sha256.inspect_err(|e| self.stats.request_failed(e))?;
+        if let Some(verdict) = self.local_verdict(&sha256) {
+            return Ok(verdict);
+        }
+        let _request = self.start_request(&ct).await?;
+        let (guid, response) = self
+            .request_file_verdict(&sha256, &ct, &mut stopwatch)
+            .await?;
+
+        let verdict = Verdict::try_from(&response)?;
+        let verdict = match verdict {
+            Verdict::Unknown { upload_url } if self.options.upload => {
+                let upload = PendingUpload::new(guid, upload_url, response)?;
+                self.handle_unknown_file(file, &sha256, upload, &ct, &mut stopwatch)
+                    .await
+            }
+            _ => VaasVerdict::try_from(response),
+        };
+        self.cache(stopwatch.stamp(verdict))
     }
 
     /// Request a verdict for a buffer.
-    pub async fn for_buf(&self, buf: Vec<u8>, ct: &CancellationToken) -> VResult<VaasVerdict> {
-        let sha256 = Sha256::from(buf.as_slice());
+    /// If VaaS does not know the buffer, it is uploaded for analysis unless uploads are disabled.
+    pub async fn for_buf(
+        &self,
+        buf: Vec<u8>,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        let verdict = self.verdict_for_buf(buf, ct.into()).await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_for_buf(
+        &self,
+        buf: Vec<u8>,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let (sha256, buf) =
+            tokio::task::spawn_blocking(move || (Sha256::from(buf.as_slice()), buf))
+                .await
+                .map_err(std::io::Error::other)?;
+        if let Some(verdict) = self.local_verdict(&sha256) {
+            return Ok(verdict);
+        }
+        let _request = self.start_request(&ct).await?;
+        let (guid, response) = self
+            .request_file_verdict(&sha256, &ct, &mut stopwatch)
+            .await?;
+
+        let verdict = Verdict::try_from(&response)?;
+        let verdict = match verdict {
+            Verdict::Unknown { upload_url } if self.options.upload => {
+                let upload = PendingUpload::new(guid, upload_url, response)?;
+                self.handle_unknown(buf, &sha256, upload, &ct, &mut stopwatch)
+                    .await
+            }
+            _ => VaasVerdict::try_from(response),
+        };
+        self.cache(stopwatch.stamp(verdict))
+    }
+
+    /// Request a new upload URL and token for a file which VaaS does not know, e.g. because the upload was
+    /// deferred and the URL of the [`Verdict::Unknown`] expired in the meantime, see [`UploadUrl::is_expired`].
+    /// The upload is completed with [`Connection::upload_and_await`]. If VaaS knows the file by now, its verdict
+    /// is returned instead. Fails with [`Error::UploadDisabled`] if uploads are disabled.
+    pub async fn request_upload_url(
+        &self,
+        sha256: &Sha256,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<UploadUrlResponse> {
+        let response = self.upload_url_response(sha256, ct.into()).await;
+        match &response {
+            Ok(UploadUrlResponse::Verdict(verdict)) => self.options.hooks.verdict(verdict),
+            Ok(UploadUrlResponse::Upload(_)) => {}
+            Err(e) => self.options.hooks.error(e),
+        }
+        response
+    }
+
+    async fn upload_url_response(
+        &self,
+        sha256: &Sha256,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<UploadUrlResponse> {
+        self.ensure_upload_enabled()?;
+        let ct = self.cancellation_token(ct);
+        let _request = self.start_request(&ct).await?;
+        self.request_upload(sha256, &ct, &mut Stopwatch::start())
+            .await
+    }
+
+    /// Upload a buffer which VaaS asked for and wait for its verdict, to complete an upload which was deferred
+    /// with [`Connection::request_upload_url`]. Like with [`Connection::for_buf`], the upload is retried and a
+    /// new upload URL is requested once if the given one expired or the upload is rejected.
+    /// Fails with [`Error::UploadDisabled`] if uploads are disabled.
+    pub async fn upload_and_await(
+        &self,
+        buf: Vec<u8>,
+        upload: PendingUpload,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        let verdict = self
+            .verdict_after_deferred_upload(buf, upload, ct.into())
+            .await;
+        self.reported(verdict)
+    }
+
+    async fn verdict_after_deferred_upload(
+        &self,
+        buf: Vec<u8>,
+        upload: PendingUpload,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        self.ensure_upload_enabled()?;
+        let mut stopwatch = Stopwatch::start();
+        let ct = self.cancellation_token(ct);
+        let _request = self.start_request(&ct).await?;
+        let (sha256, buf) =
+            tokio::task::spawn_blocking(move || (Sha256::from(buf.as_slice()), buf))
+                .await
+                .map_err(std::io::Error::other)?;
+        let verdict = self
+            .handle_unknown(buf, &sha256, upload, &ct, &mut stopwatch)
+            .await;
+        stopwatch.stamp(verdict)
+    }
+
+    async fn request_upload(
+        &self,
+        sha256: &Sha256,
+        ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
+    ) -> VResult<UploadUrlResponse> {
+        let (guid, response) = self.request_file_verdict(sha256, ct, stopwatch).await?;
+        UploadUrlResponse::new(guid, response)
+    }
+
+    /// Request the verdict for the hash of a file which is uploaded if VaaS does not know it.
+    async fn request_file_verdict(
+        &self,
+        sha256: &Sha256,
+        ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
+    ) -> VResult<(RequestId, VerdictResponse)> {
         let request = VerdictRequestFile::new(
-            &sha256,
+            sha256,
             self.session_id.clone(),
             self.options.use_cache,
             self.options.use_hash_lookup,
         );
-        let guid = request.guid.to_string();
-
-        let response =
-            self.for_request(request, ct).await?;
+        let guid = request.guid;
+        let response = self.for_request(request, ct, stopwatch).await?;
+        Ok((guid, response))
+    }
 
-        let verdict = Verdict::try_from(&response)?;
-        match verdict {
-            Verdict::Unknown { upload_url } => {
-                self.handle_unknown(buf, guid, response, upload_url, ct).await
-            }
-            _ => VaasVerdict::try_from(response),
+    fn ensure_upload_enabled(&self) -> VResult<()> {
+        if self.options.upload {
+            Ok(())
+        } else {
+            Err(Error::UploadDisabled)
         }
     }
 
     async fn handle_unknown(
         &self,
         buf: Vec<u8>,
-        guid: String,
-        response: VerdictResponse,
-        upload_url: UploadUrl,
+        sha256: &Sha256,
+        upload: PendingUpload,
+        ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
+    ) -> Result<VaasVerdict, Error> {
+        let buf = Bytes::from(buf);
+        let upload_content = |upload_url, auth_token: String| {
+            let buf = buf.clone();
+            async move {
+                let response = upload_buf(
+                    buf,
+                    UploadTarget::new(upload_url, &auth_token)
+                        .with_sha256(sha256)
+                        .compressed(self.options.compress_uploads),
+                    &self.http_client,
+                    &self.options,
+                    &self.upload_permits,
+                )
+                .await?;
+                Self::ensure_http_success(response).await
+            }
+        };
+        self.upload_and_wait(sha256, upload, buf.len(), ct, stopwatch, upload_content)
+            .await
+    }
+
+    async fn handle_unknown_file(
+        &self,
+        file: &Path,
+        sha256: &Sha256,
+        upload: PendingUpload,
         ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
     ) -> Result<VaasVerdict, Error> {
-        let auth_token = response
-            .upload_token
-            .as_ref()
-            .ok_or(Error::MissingAuthToken)?;
-        let resp = self.wait_for_response(guid, ct);
-        let response = upload_buf(buf, upload_url, auth_token).await?;
+        let content_length = tokio::fs::metadata(file).await?.len() as usize;
+        let upload_content = |upload_url, auth_token: String| async move {
+            let reader = tokio::fs::File::open(file).await?;
+            let reader = ReaderStream::with_capacity(reader, self.options.hash_buffer_size);
+            let (stream, digest) = HashingStream::new(reader, content_length);
+            let response = upload_stream(
+                stream.boxed(),
+                content_length,
+                UploadTarget::new(upload_url, &auth_token)
+                    .with_sha256(sha256)
+                    .compressed(self.options.compress_uploads),
+                &self.http_client,
+                &self.options,
+                &self.upload_permits,
+            )
+            .await?;
+            Self::ensure_http_success(response).await?;
+            ensure_sha256(sha256, digest.digest())
+        };
+        self.upload_and_wait(
+            sha256,
+            upload,
+            content_length,
+            ct,
+            stopwatch,
+            upload_content,
+        )
+        .await
+    }
+
+    /// Upload content which can be read again and wait for its verdict. Failed uploads are retried according to
+    /// the retry policy.
+    ///
+    /// The upload token may expire while a large file is uploaded. If the upload is rejected with 401 or 403,
+    /// the verdict is requested again for a fresh upload URL and token, and the content is uploaded once more.
+    /// The same happens before the upload if the upload URL is already expired, e.g. because it was deferred.
+    async fn upload_and_wait<F, Fut>(
+        &self,
+        sha256: &Sha256,
+        mut upload: PendingUpload,
+        content_length: usize,
+        ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
+        upload_content: F,
+    ) -> VResult<VaasVerdict>
+    where
+        F: Fn(UploadUrl, String) -> Fut,
+        Fut: Future<Output = VResult<()>>,
+    {
+        let mut refreshed = false;
+        loop {
+            if !refreshed && upload.upload_url.is_expired() {
+                refreshed = true;
+                debug!("The upload URL expired, requesting a new one");
+                match self.request_upload(sha256, ct, stopwatch).await? {
+                    UploadUrlResponse::Upload(fresh) => upload = fresh,
+                    UploadUrlResponse::Verdict(verdict) => return Ok(verdict),
+                }
+            }
+            self.ensure_trusted_upload_url(&upload.upload_url)?;
+            let span =
+                span!(parent: &self.span, "vaas_upload", guid = %upload.guid, sha256 = %sha256);
+            let deadline = Instant::now() + ct.duration;
+            // VaaS may send the verdict before the upload is answered, so the request is registered first.
+            let resp = self.responses.get_response(upload.guid.to_string());
+            let attempt = || upload_content(upload.upload_url.clone(), upload.upload_token.clone());
+            let upload_result = retry(&self.options.retry_policy, &TokioClock, "upload", attempt);
+            let upload_result = until_deadline(deadline, upload_result);
+            let upload_started = Instant::now();
+            let upload_result = traced_upload(&span, content_length, upload_result).await;
+            match upload_result {
+                Err(e) if !refreshed && is_rejected_upload(&e) => {
+                    drop(resp);
+                    refreshed = true;
+                    warn!(
+                        reason = %e,
+                        "The upload was rejected, requesting a new upload URL and token"
+                    );
+                    match self.request_upload(sha256, ct, stopwatch).await? {
+                        UploadUrlResponse::Upload(fresh) => upload = fresh,
+                        // Someone else uploaded the file in the meantime.
+                        UploadUrlResponse::Verdict(verdict) => return Ok(verdict),
+                    }
+                }
+                upload_result => {
+                    self.upload_finished(&upload_result, Some(sha256), content_length);
+                    upload_result?;
+                    stopwatch.uploaded(upload_started.elapsed(), content_length as u64);
+                    let response = self.verdict_after_upload(deadline, resp);
+                    let response = response.instrument(span).await?;
+                    return VaasVerdict::try_from(response);
+                }
+            }
+        }
+    }
 
-        Self::ensure_http_success(response).await?;
-        VaasVerdict::try_from(resp.await?)
+    fn ensure_trusted_upload_url(&self, upload_url: &UploadUrl) -> VResult<()> {
+        match &self.options.allowed_upload_hosts {
+            Some(allowed_hosts) => upload_url.ensure_trusted(allowed_hosts),
+            None => Ok(()),
+        }
     }
 
     async fn handle_unknown_stream<S>(
         &self,
         stream: S,
         content_length: usize,
-        guid: String,
-        response: VerdictResponse,
-        upload_url: UploadUrl,
+        upload: PendingUpload,
         ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
     ) -> Result<VaasVerdict, Error>
     where
         S: futures_util::stream::TryStream + Send + Sync + 'static,
         S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
         Bytes: From<S::Ok>,
     {
-        let auth_token = response
-            .upload_token
-            .as_ref()
-            .ok_or(Error::MissingAuthToken)?;
-        let resp = self.wait_for_response(guid, ct);
-        let response = upload_stream(stream, content_length, upload_url, auth_token).await?;
+        self.ensure_trusted_upload_url(&upload.upload_url)?;
+        let span = span!(parent: &self.span, "vaas_upload", guid = %upload.guid);
+        let deadline = Instant::now() + ct.duration;
+        let resp = self.responses.get_response(upload.guid.to_string());
+        let (stream, digest) = HashingStream::new(stream, content_length);
+        let upload = async {
+            let response = upload_stream(
+                stream.boxed(),
+                content_length,
+                UploadTarget::new(upload.upload_url, &upload.upload_token),
+                &self.http_client,
+                &self.options,
+                &self.upload_permits,
+            )
+            .await?;
+            Self::ensure_http_success(response).await
+        };
+        let upload_started = Instant::now();
+        let upload = traced_upload(&span, content_length, until_deadline(deadline, upload)).await;
+        let uploaded_sha256 = digest.digest();
+        self.upload_finished(&upload, uploaded_sha256.as_ref(), content_length);
+        upload?;
+        stopwatch.uploaded(upload_started.elapsed(), content_length as u64);
+
+        let response = self.verdict_after_upload(deadline, resp);
+        let response = response.instrument(span).await?;
+        // The stream can't be hashed before the request, so VaaS reports the SHA256 of what it received.
+        if let Ok(expected) = Sha256::try_from(response.sha256.as_str()) {
+            ensure_sha256(&expected, uploaded_sha256)?;
+        }
+        VaasVerdict::try_from(response)
+    }
+
+    fn upload_finished(
+        &self,
+        upload: &VResult<()>,
+        sha256: Option<&Sha256>,
+        content_length: usize,
+    ) {
+        match upload {
+            Ok(()) => {
+                self.stats.upload_completed(content_length);
+                if let Some(sha256) = sha256 {
+                    self.options.hooks.upload(sha256, content_length as u64);
+                }
+            }
+            Err(e) => self.stats.request_failed(e),
+        }
+    }
+
+    async fn verdict_after_upload(
+        &self,
+        deadline: Instant,
+        response: impl Future<Output = VResult<VerdictResponse>>,
+    ) -> VResult<VerdictResponse> {
+        let response = timeout(self.verdict_timeout(deadline), response)
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+        self.stats.response_received(&response);
+        response_event(&response);
+        response
+    }
 
-        Self::ensure_http_success(response).await?;
-        VaasVerdict::try_from(resp.await?)
+    /// The time to wait for the verdict after an upload: the rest of the time until the deadline of the
+    /// `CancellationToken`, limited by the verdict timeout if there is one.
+    fn verdict_timeout(&self, deadline: Instant) -> Duration {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match self.options.verdict_timeout {
+            Some(verdict_timeout) => verdict_timeout.min(remaining),
+            None => remaining,
+        }
     }
 
     async fn ensure_http_success(response: Response) -> Result<(), Error> {
@@ -267,21 +841,106 @@ impl Connection {
     pub async fn for_file_list(
         &self,
         files: &[PathBuf],
-        ct: &CancellationToken,
+        ct: impl Into<Option<&CancellationToken>>,
     ) -> Vec<VResult<VaasVerdict>> {
+        let ct = ct.into();
         let req = files.iter().map(|f| self.for_file(f, ct));
         join_all(req).await
     }
 
+    /// The verdict of the file from the allowlist or blocklist, or else from the local cache, if any.
+    fn local_verdict(&self, sha256: &Sha256) -> Option<VaasVerdict> {
+        if let Some(verdict) = self.options.hash_lists.verdict(sha256) {
+            debug_event!(sha256 = %sha256, "Verdict answered from the allowlist or blocklist");
+            return Some(verdict);
+        }
+        let mut verdict = self.options.local_cache.as_ref()?.get(sha256)?;
+        verdict.source = VerdictSource::LocalCache;
+        verdict.timing = None;
+        self.stats.cache_hit();
+        metric!(counter!(CACHE_HITS).increment(1));
+        debug_event!(sha256 = %sha256, "Verdict answered from the local cache");
+        Some(verdict)
+    }
+
+    /// Store a final verdict of VaaS in the local cache, if it is enabled.
+    fn cache(&self, verdict: VResult<VaasVerdict>) -> VResult<VaasVerdict> {
+        if let (Some(cache), Ok(verdict)) = (&self.options.local_cache, &verdict) {
+            if verdict.source == VerdictSource::Vaas {
+                cache.insert(verdict);
+            }
+        }
+        verdict
+    }
+
+    /// Call the verdict or error hooks with the result of a request, on the task of the request.
+    fn reported(&self, verdict: VResult<VaasVerdict>) -> VResult<VaasVerdict> {
+        self.options.hooks.result(&verdict);
+        verdict
+    }
+
+    fn cancellation_token<'a>(
+        &self,
+        ct: impl Into<Option<&'a CancellationToken>>,
+    ) -> CancellationToken {
+        ct.into()
+            .cloned()
+            .unwrap_or_else(|| CancellationToken::from(self.options.default_timeout))
+    }
+
+    /// Wait for the [`Builder::rate_limit`](crate::Builder::rate_limit) and a free slot of
+    /// [`Builder::max_in_flight`](crate::Builder::max_in_flight), at most until the cancellation token is cancelled.
+    /// The slot is freed when the returned request is dropped.
+    async fn start_request(&self, ct: &CancellationToken) -> VResult<InFlightRequest<'_>> {
+        let deadline = Instant::now() + ct.duration;
+        if let Some(rate_limiter) = &self.options.rate_limiter {
+            let started = Instant::now();
+            let throttled = timeout_at(deadline, rate_limiter.acquire()).await;
+            self.stats.throttled(started.elapsed());
+            if let Err(e) = throttled {
+                let e = e.into();
+                self.stats.request_failed(&e);
+                return Err(e);
+            }
+        }
+        let permit = match timeout_at(deadline, self.in_flight_permits.acquire()).await {
+            // The semaphore is never closed.
+            Ok(permit) => permit.unwrap(),
+            Err(e) => {
+                let e = e.into();
+                self.stats.request_failed(&e);
+                return Err(e);
+            }
+        };
+        Ok(InFlightRequest {
+            _permit: permit,
+            _in_flight: self.stats.request_started(),
+        })
+    }
+
     async fn for_request<T: VerdictRequest + Serialize>(
         &self,
         request: T,
         ct: &CancellationToken,
+        stopwatch: &mut Stopwatch,
     ) -> VResult<VerdictResponse> {
         let guid = request.guid().to_string();
-        let response = self.wait_for_response(guid, ct);
-        self.ws_writer.lock().await.send_text(request.to_json()?).await?;
-        response.await
+        let span = request.span(&self.span);
+        if let Some(trace_id) = propagation::trace_id() {
+            span.record("trace_id", tracing::field::display(&trace_id));
+        }
+        let response = self.wait_for_response(guid, ct).instrument(span.clone());
+        self.ws_writer.send(OutgoingFrame::Text(request.to_json()?))?;
+        stopwatch.requested();
+        self.stats.request_sent();
+        span.in_scope(|| debug_event!("Verdict request sent"));
+        metric!(counter!(REQUESTS).increment(1));
+        #[cfg(feature = "metrics")]
+        let sent = Instant::now();
+        let response = response.await;
+        stopwatch.responded();
+        metric!(histogram!(REQUEST_DURATION).record(sent.elapsed()));
+        response
     }
 
     fn wait_for_response(
@@ -290,113 +949,2382 @@ impl Connection {
         ct: &CancellationToken,
     ) -> impl Future<Output = VResult<VerdictResponse>> {
         let response = self.responses.get_response(guid);
-        timeout(ct.duration, response).map(|outer| outer?)
+        let stats = self.stats.clone();
+        timeout(ct.duration, response).map(move |outer| {
+            let response = outer.unwrap_or_else(|e| Err(e.into()));
+            stats.response_received(&response);
+            response_event(&response);
+            response
+        })
     }
 
     // TODO: Move this functionality into the underlying websocket library.
     async fn keep_alive_loop(
-        ws_writer: WebSocketWriter,
-        keep_alive_delay_ms: u64,
-        responses: Arc<VaasResponseBroker>,
+        ws_writer: WsWriter,
+        keep_alive_delay: Duration,
+        keep_alive_jitter: Duration,
     ) -> ThreadHandle {
-        tokio::spawn(async move {
+        let keep_alive = async move {
+            let mut rng = StdRng::from_entropy();
+            let mut interval = tokio::time::interval_at(
+                Instant::now() + jittered_delay(keep_alive_delay, keep_alive_jitter, &mut rng),
+                keep_alive_delay,
+            );
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
             loop {
-                tokio::time::sleep(Duration::from_millis(keep_alive_delay_ms)).await;
-                if let Err(e) = ws_writer.lock().await.send_ping(None).await {
-                    responses.set_all_responses(Err(e.into()));
-                }
-                if let Err(e) = ws_writer.lock().await.flush().await {
-                    responses.set_all_responses(Err(e.into()));
+                interval.tick().await;
+                // Write errors of the ping are reported to the pending requests by the writer task.
+                if ws_writer.send(OutgoingFrame::Ping).is_err() {
+                    debug_event!("Keep-alive stopped, the connection is closed");
+                    return Ok(());
                 }
+                let next_delay = jittered_delay(keep_alive_delay, keep_alive_jitter, &mut rng);
+                interval.reset_after(next_delay);
             }
-        })
+        };
+        tokio::spawn(keep_alive.in_current_span())
     }
 
     async fn start_reader_loop(
-        mut ws_reader: WebSocketReadHalf,
+        mut ws_reader: impl FrameSource,
         responses: Arc<VaasResponseBroker>,
+        auth_responses: Arc<AuthResponseBroker>,
+        closed: Arc<AtomicBool>,
+        stats: Arc<Stats>,
+        max_message_size: usize,
     ) -> ThreadHandle {
-        tokio::spawn(async move {
+        let reader = async move {
+            let mut fragments = Fragments::new(max_message_size);
             loop {
                 let frame = ws_reader.receive().await;
-                match Self::parse_frame(frame) {
-                    Ok(MessageType::VerdictResponse(vr)) => {
-                        responses.set_response(&vr.guid.clone(), Ok(vr));
-                    }
-                    Ok(MessageType::Close) => {
-                        responses.set_all_responses(Err(Error::ConnectionClosed));
-                    }
-                    Err(e) => {
-                        responses.set_all_responses(Err(e));
-                    }
-                    _ => {}
+                let Some(message) = fragments.message(frame) else {
+                    continue;
+                };
+                if let Ok(MessageType::Malformed(_, e)) = &message {
+                    warn!("Received a malformed message: {e}");
+                    stats.malformed_message();
+                    metric!(counter!(MALFORMED_MESSAGES).increment(1));
+                }
+                let closed_with = match &message {
+                    Ok(MessageType::Close) => Some(Error::ConnectionClosed),
+                    Err(e @ (Error::WebSocket(_) | Error::MessageTooLarge(_))) => Some(e.clone()),
+                    _ => None,
+                };
+                Self::dispatch(message, &responses, &auth_responses);
+                if let Some(e) = closed_with {
+                    // Reading a broken connection fails immediately, so the loop stops instead of spinning,
+                    // and later requests fail with the same error. An endpoint which sends oversized messages
+                    // is not read from any further.
+                    closed.store(true, Ordering::Relaxed);
+                    responses.close(Err(e.clone()));
+                    auth_responses.close(Err(e));
+                    return Ok(());
                 }
             }
-        })
+        };
+        tokio::spawn(reader.in_current_span())
+    }
+
+    pub(crate) fn dispatch(
+        message: VResult<MessageType>,
+        responses: &VaasResponseBroker,
+        auth_responses: &AuthResponseBroker,
+    ) {
+        match message {
+            Ok(MessageType::VerdictResponse(vr)) => {
+                if let Some(guid) = Self::request_id(&vr.guid) {
+                    responses.set_response(&guid, Ok(vr));
+                }
+            }
+            Ok(MessageType::RequestError(guid, e)) => {
+                if let Some(guid) = Self::request_id(&guid) {
+                    responses.set_response(&guid, Err(Error::ErrorResponse(e)));
+                }
+            }
+            Ok(MessageType::AuthResponse(ar)) => {
+                auth_responses.set_response(AUTH_RESPONSE_ID, Ok(ar));
+            }
+            Ok(MessageType::Malformed(guids, e)) => {
+                for guid in guids {
+                    responses.set_pending_response(&guid, Err(e.clone()));
+                }
+            }
+            Ok(MessageType::Close) => {
+                responses.set_all_responses(Err(Error::ConnectionClosed));
+                auth_responses.set_all_responses(Err(Error::ConnectionClosed));
+            }
+            Err(e) => {
+                responses.set_all_responses(Err(e.clone()));
+                auth_responses.set_all_responses(Err(e));
+            }
+            _ => {}
+        }
+    }
+
+    /// The GUID of a response in the format of the requests, or `None` if it is no valid GUID and the response has
+    /// to be dropped.
+    fn request_id(guid: &str) -> Option<String> {
+        match RequestId::try_from(guid) {
+            Ok(guid) => Some(guid.to_string()),
+            Err(e) => {
+                warn!("Dropping a response: {e}");
+                None
+            }
+        }
     }
 
-    fn parse_frame(frame: Result<Frame, WebSocketError>) -> VResult<MessageType> {
+    /// Parse a frame of the reader loop. Text frames larger than `max_message_size` are rejected before parsing.
+    pub(crate) fn parse_frame(
+        frame: Result<Frame, WebSocketError>,
+        max_message_size: usize,
+    ) -> VResult<MessageType> {
         match frame {
-            Ok(Frame::Text { payload: json, .. }) => MessageType::try_from(&json),
-            Ok(Frame::Ping { .. }) => Ok(MessageType::Ping),
-            Ok(Frame::Pong { .. }) => Ok(MessageType::Pong),
-            Ok(Frame::Close { .. }) => Ok(MessageType::Close),
+            Ok(Frame::Text { payload, .. }) if payload.len() > max_message_size => {
+                Err(Error::MessageTooLarge(payload.len()))
+            }
+            Ok(Frame::Text { payload: json, .. }) => MessageType::from_text(&json),
+            Ok(Frame::Ping) => Ok(MessageType::Ping),
+            Ok(Frame::Pong) => Ok(MessageType::Pong),
+            Ok(Frame::Close) => Ok(MessageType::Close),
             Ok(_) => Err(Error::InvalidFrame),
             Err(e) => Err(e.into()),
         }
     }
 }
 
-async fn upload_buf(buf: Vec<u8>, upload_url: UploadUrl, auth_token: &str) -> VResult<Response> {
-    let content_length = buf.len();
-    upload_internal(buf, content_length, upload_url, auth_token).await
+/// A text message which was split into continuation frames, e.g. by a reverse proxy, until its last frame arrives.
+///
+/// Control frames may arrive between the fragments of a message (RFC 6455, section 5.4) and are passed on at once.
+#[derive(Debug)]
+struct Fragments {
+    text: Option<String>,
+    max_message_size: usize,
 }
 
-async fn upload_stream<S>(
-    stream: S,
-    content_length: usize,
-    upload_url: UploadUrl,
-    auth_token: &str,
-) -> VResult<Response>
-where
-    S: futures_util::stream::TryStream + Send + Sync + 'static,
-    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
-    Bytes: From<S::Ok>,
-{
-    let body = Body::wrap_stream(stream);
-    upload_internal(body, content_length, upload_url, auth_token).await
+impl Fragments {
+    fn new(max_message_size: usize) -> Self {
+        Self {
+            text: None,
+            max_message_size,
+        }
+    }
+
+    /// The message of the frame, or `None` if it is a fragment of a message which is not complete yet.
+    /// The message is limited to `max_message_size` while its fragments are collected.
+    fn message(&mut self, frame: Result<Frame, WebSocketError>) -> Option<VResult<MessageType>> {
+        let (payload, continuation, fin) = match frame {
+            Ok(Frame::Text {
+                payload,
+                continuation,
+                fin,
+            }) => (payload, continuation, fin),
+            frame => return Some(Connection::parse_frame(frame, self.max_message_size)),
+        };
+        let text = match (self.text.take(), continuation) {
+            (None, false) => payload,
+            (Some(mut text), true) => {
+                text.push_str(&payload);
+                text
+            }
+            // A continuation frame without a message, or a new message before the last one was complete.
+            _ => return Some(Err(Error::InvalidFrame)),
+        };
+        if !fin {
+            if text.len() > self.max_message_size {
+                return Some(Err(Error::MessageTooLarge(text.len())));
+            }
+            self.text = Some(text);
+            return None;
+        }
+        Some(Connection::parse_frame(
+            Ok(Frame::text(text)),
+            self.max_message_size,
+        ))
+    }
 }
 
-async fn upload_internal<T: Into<Body>>(
-    body: T,
-    content_length: usize,
-    upload_url: UploadUrl,
-    auth_token: &str,
-) -> VResult<Response> {
-    let client = reqwest::Client::new();
-    let response = client
-        .put(upload_url.deref())
-        .version(Version::HTTP_11)
-        .body(body)
-        .header("Authorization", auth_token)
-        .header("Content-Length", content_length)
-        .send()
-        .await?;
+/// A verdict request holding a slot of [`Builder::max_in_flight`](crate::Builder::max_in_flight).
+struct InFlightRequest<'a> {
+    _permit: SemaphorePermit<'a>,
+    _in_flight: InFlight<'a>,
+}
 
-    Ok(response)
+/// The read half of the websocket, abstracted so the connection can be tested without a server.
+#[async_trait]
+pub(crate) trait FrameSource: Send + 'static {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError>;
 }
 
-impl Drop for Connection {
-    fn drop(&mut self) {
-        // Abort the spawned threads in the case that the connection
-        // is dropped.
-        // If the threads are not aborted, they will live past the connection
-        // lifetime which is not what the user expects.
-        // Abort is only safe if we never block or wait for mutex in the thread.
-        // If we had a mutex in the thread blocked and aborted the thread, we would deadlock.
-        self.reader_thread.abort();
-        if self.keep_alive_thread.is_some() {
-            self.keep_alive_thread.as_ref().unwrap().abort();
+#[async_trait]
+impl FrameSource for WebSocketReadHalf {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError> {
+        match self.next().await {
+            Some(message) => message.map(Frame::from),
+            None => Err(WebSocketError::ConnectionClosed),
         }
     }
 }
+
+/// The delay varied randomly by up to the jitter in both directions.
+fn jittered_delay<R: Rng>(delay: Duration, jitter: Duration, rng: &mut R) -> Duration {
+    if jitter.is_zero() {
+        return delay;
+    }
+    let offset = rng.gen_range(Duration::ZERO..=jitter * 2);
+    (delay + offset).saturating_sub(jitter)
+}
+
+/// Whether the upload endpoint rejected the upload token, e.g. because it expired.
+fn is_rejected_upload(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::FailedUploadFile(status, _)
+            if *status == StatusCode::UNAUTHORIZED || *status == StatusCode::FORBIDDEN
+    )
+}
+
+/// Run the upload in its span, with events for its start and end.
+async fn traced_upload(
+    span: &Span,
+    content_length: usize,
+    upload: impl Future<Output = VResult<()>>,
+) -> VResult<()> {
+    let upload = async {
+        debug_event!(bytes = content_length, "Upload started");
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let result = upload.await;
+        match &result {
+            Ok(()) => {
+                debug_event!(bytes = content_length, "Upload finished");
+                metric!(counter!(UPLOAD_BYTES).increment(content_length as u64));
+                metric!(histogram!(UPLOAD_DURATION).record(started.elapsed()));
+            }
+            Err(e) => {
+                debug_event!(error = %e, "Upload failed");
+                metric!(counter!(ERRORS, "error_kind" => e.kind()).increment(1));
+            }
+        }
+        result
+    };
+    upload.instrument(span.clone()).await
+}
+
+/// An event and metric for the verdict VaaS sent, or why there is none.
+fn response_event(response: &VResult<VerdictResponse>) {
+    match response {
+        Ok(response) => {
+            debug_event!(verdict = %response.verdict, "Verdict received");
+            metric!(counter!(VERDICTS, "verdict" => response.verdict.clone()).increment(1));
+        }
+        Err(e) => {
+            debug_event!(error = %e, "No verdict received");
+            metric!(counter!(ERRORS, "error_kind" => e.kind()).increment(1));
+        }
+    }
+}
+
+/// Run the upload until the deadline of the `CancellationToken` passes. Then the request is dropped,
+/// which closes its connection and abandons the rest of the body.
+async fn until_deadline(
+    deadline: Instant,
+    upload: impl Future<Output = VResult<()>>,
+) -> VResult<()> {
+    timeout_at(deadline, upload)
+        .await
+        .unwrap_or_else(|e| Err(e.into()))
+}
+
+/// Compare the expected SHA256 with the one of the uploaded content.
+/// Passes if the content was not read to the end, as its SHA256 is unknown then.
+fn ensure_sha256(expected: &Sha256, actual: Option<Sha256>) -> VResult<()> {
+    match actual {
+        Some(actual) if actual != *expected => Err(Error::Sha256Mismatch {
+            expected: expected.clone(),
+            actual,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The SHA256 as the base64 encoded digest, like the `x-amz-checksum-sha256` header of S3 expects it.
+fn checksum_header_value(sha256: &Sha256) -> HeaderValue {
+    let value = base64::engine::general_purpose::STANDARD.encode(sha256.as_bytes());
+    // Base64 only consists of visible ASCII characters.
+    HeaderValue::from_str(&value).expect("base64 is a valid header value")
+}
+
+/// Where content is uploaded to, how it is authorized and how it is encoded.
+struct UploadTarget<'a> {
+    url: UploadUrl,
+    auth_token: &'a str,
+    /// The SHA256 of the content, if it is known before the upload.
+    sha256: Option<&'a Sha256>,
+    /// Whether the content is sent gzip compressed, without a `Content-Length`.
+    gzip: bool,
+}
+
+impl<'a> UploadTarget<'a> {
+    fn new(url: UploadUrl, auth_token: &'a str) -> Self {
+        Self {
+            url,
+            auth_token,
+            sha256: None,
+            gzip: false,
+        }
+    }
+
+    fn with_sha256(self, sha256: &'a Sha256) -> Self {
+        Self {
+            sha256: Some(sha256),
+            ..self
+        }
+    }
+
+    fn compressed(self, gzip: bool) -> Self {
+        Self { gzip, ..self }
+    }
+}
+
+/// Compress the content with gzip while it is uploaded. The compressed size is only known at the end.
+fn gzip(stream: BoxedByteStream) -> BoxedByteStream {
+    let reader = StreamReader::new(stream.map_err(std::io::Error::other));
+    Box::pin(ReaderStream::new(GzipEncoder::new(reader)).map_err(Into::into))
+}
+
+/// The body of an upload, compressed first, so the rate limit applies to the bytes which are actually sent.
+fn upload_body(stream: BoxedByteStream, gzip_content: bool, options: &Options) -> Body {
+    let stream = if gzip_content { gzip(stream) } else { stream };
+    match options.upload_rate_limit {
+        Some(bytes_per_sec) => Body::wrap_stream(ThrottledStream::new(stream, bytes_per_sec)),
+        None => Body::wrap_stream(stream),
+    }
+}
+
+async fn upload_buf(
+    buf: Bytes,
+    target: UploadTarget<'_>,
+    http_client: &reqwest::Client,
+    options: &Options,
+    upload_permits: &Semaphore,
+) -> VResult<Response> {
+    let content_length = buf.len();
+    let body = if target.gzip || options.upload_rate_limit.is_some() {
+        let stream: BoxedByteStream = Box::pin(futures::stream::once(async { Ok(buf) }));
+        upload_body(stream, target.gzip, options)
+    } else {
+        Body::from(buf)
+    };
+    upload_internal(
+        body,
+        content_length,
+        target,
+        http_client,
+        options,
+        upload_permits,
+    )
+    .await
+}
+
+async fn upload_stream(
+    stream: BoxedByteStream,
+    content_length: usize,
+    target: UploadTarget<'_>,
+    http_client: &reqwest::Client,
+    options: &Options,
+    upload_permits: &Semaphore,
+) -> VResult<Response> {
+    let body = upload_body(stream, target.gzip, options);
+    upload_internal(
+        body,
+        content_length,
+        target,
+        http_client,
+        options,
+        upload_permits,
+    )
+    .await
+}
+
+async fn upload_internal<T: Into<Body>>(
+    body: T,
+    content_length: usize,
+    target: UploadTarget<'_>,
+    http_client: &reqwest::Client,
+    options: &Options,
+    upload_permits: &Semaphore,
+) -> VResult<Response> {
+    // The header values are validated by the builder, so this only fails for options created elsewhere.
+    let content_type = HeaderValue::from_str(&options.upload_content_type)
+        .map_err(|e| Error::InvalidConfig(format!("upload_content_type: {e}")))?;
+    let checksum = match (&options.upload_checksum_header, target.sha256) {
+        (Some(name), Some(sha256)) => {
+            let name = HeaderName::from_str(name)
+                .map_err(|e| Error::InvalidConfig(format!("upload_checksum_header: {e}")))?;
+            Some((name, checksum_header_value(sha256)))
+        }
+        _ => None,
+    };
+    // The semaphore is never closed.
+    let _permit = upload_permits.acquire().await.unwrap();
+    let upload_timeout = options.upload_timeout;
+    let mut request = http_client
+        .put(target.url.deref())
+        .version(options.upload_http_version)
+        .body(body)
+        .header("Authorization", target.auth_token)
+        .header(CONTENT_TYPE, content_type);
+    // HTTP/2 frames the body itself, so the length is only set explicitly for HTTP/1.1.
+    // Compressed content is sent chunked instead, as its length is not known beforehand.
+    if target.gzip {
+        request = request.header(CONTENT_ENCODING, "gzip");
+    } else if options.upload_http_version == Version::HTTP_11 {
+        request = request.header("Content-Length", content_length);
+    }
+    if let Some((name, value)) = checksum {
+        request = request.header(name, value);
+    }
+    for (name, value) in propagation::trace_context().into_iter().flatten() {
+        match (HeaderName::from_str(&name), HeaderValue::from_str(&value)) {
+            (Ok(name), Ok(value)) => request = request.header(name, value),
+            _ => warn!(header = %name, "Dropping a trace context header which is not a valid header"),
+        }
+    }
+    if let Some(upload_timeout) = upload_timeout {
+        request = request.timeout(upload_timeout);
+    }
+
+    // The number of bytes sent before a timeout is not reported by reqwest.
+    let response = request.send().await.map_err(|e| match upload_timeout {
+        Some(upload_timeout) if e.is_timeout() => Error::UploadTimeout(upload_timeout),
+        _ => e.into(),
+    })?;
+
+    Ok(response)
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        // Abort the spawned threads in the case that the connection
+        // is dropped.
+        // If the threads are not aborted, they will live past the connection
+        // lifetime which is not what the user expects.
+        // Abort is only safe if we never block or wait for mutex in the thread.
+        // If we had a mutex in the thread blocked and aborted the thread, we would deadlock.
+        self.reader_thread.abort();
+        self.writer_thread.abort();
+        if let Some(keep_alive_thread) = &self.keep_alive_thread {
+            keep_alive_thread.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_websocket::{verdict_response, MockServer};
+    use crate::retry::RetryPolicy;
+    use crate::hash_lists::HashLists;
+    use crate::rate_limiter::RateLimiter;
+    use crate::verdict_cache::VerdictCache;
+    use serde_json::Value;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::sleep;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Starts an upload endpoint which answers each request after a short delay
+    /// and records the highest number of requests handled at the same time.
+    async fn start_upload_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let recorded_max_active = max_active.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let active = active.clone();
+                let max_active = max_active.clone();
+                tokio::spawn(async move {
+                    // The request is complete once the body of 1024 zero bytes has been received.
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    while !request.ends_with(&[0; 1024]) {
+                        let read = stream.read(&mut buf).await.unwrap();
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..read]);
+                    }
+                    let current = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active.fetch_max(current, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                        )
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+        (url, recorded_max_active)
+    }
+
+    /// Answers uploads of 1024 bytes with keep-alive responses and counts the accepted connections.
+    async fn start_keep_alive_upload_server() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                accepted.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    loop {
+                        let read = stream.read(&mut buf).await.unwrap();
+                        if read == 0 {
+                            return;
+                        }
+                        request.extend_from_slice(&buf[..read]);
+                        let header_end = request.windows(4).position(|w| w == b"\r\n\r\n");
+                        match header_end {
+                            Some(end) if request.len() >= end + 4 + 1024 => {
+                                request.drain(..end + 4 + 1024);
+                                stream
+                                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                                    .await
+                                    .unwrap();
+                            }
+                            _ => continue,
+                        }
+                    }
+                });
+            }
+        });
+        (url, connections)
+    }
+
+    const SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+    async fn connect_with_max_in_flight(
+        max_in_flight: usize,
+        delay: Duration,
+    ) -> (MockServer, Connection) {
+        let (server, sink, source) = MockServer::answering(delay, "Clean");
+        let options = Options {
+            max_in_flight: Some(max_in_flight),
+            keep_alive: false,
+            ..Options::default()
+        };
+        (server, MockServer::connect(sink, source, options).await)
+    }
+
+    #[tokio::test]
+    async fn requests_beyond_max_in_flight_wait_for_a_free_slot() {
+        let (server, connection) = connect_with_max_in_flight(5, Duration::from_millis(50)).await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+        let ct = CancellationToken::from_seconds(10);
+
+        let requests = join_all((0..15).map(|_| connection.for_sha256(&sha256, &ct)));
+        let in_flight = async {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+            connection.stats().in_flight
+        };
+        let (verdicts, in_flight) = tokio::join!(requests, in_flight);
+
+        assert_eq!(15, verdicts.len());
+        for verdict in verdicts {
+            assert_eq!(Verdict::Clean, verdict.unwrap().verdict);
+        }
+        assert_eq!(5, in_flight);
+        assert_eq!(5, server.max_pending());
+        assert_eq!(0, connection.stats().in_flight);
+        assert_eq!(15, connection.stats().requests_sent);
+    }
+
+    #[tokio::test]
+    async fn wait_for_free_slot_is_bounded_by_cancellation_token() {
+        let (_server, connection) = connect_with_max_in_flight(1, Duration::from_millis(200)).await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+        let long = CancellationToken::from_seconds(10);
+        let short = CancellationToken::from(Duration::from_millis(20));
+
+        let (first, second) = tokio::join!(
+            connection.for_sha256(&sha256, &long),
+            connection.for_sha256(&sha256, &short),
+        );
+
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::Cancelled)));
+        assert_eq!(1, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().timeouts);
+    }
+
+    fn rate_limited_options(requests_per_second: u32, burst: u32) -> Options {
+        Options {
+            rate_limiter: Some(Arc::new(RateLimiter::new(requests_per_second, burst))),
+            keep_alive: false,
+            ..Options::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limit_paces_list_requests_of_all_connections() {
+        let options = rate_limited_options(10, 2);
+        let (_first_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let first = MockServer::connect(sink, source, options.clone()).await;
+        let (_second_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let second = MockServer::connect(sink, source, options).await;
+        let sha256_list = vec![Sha256::try_from(SHA256).unwrap(); 3];
+        let ct = CancellationToken::from_seconds(10);
+        let started = Instant::now();
+
+        let (first_verdicts, second_verdicts) = tokio::join!(
+            first.for_sha256_list(&sha256_list, &ct),
+            second.for_sha256_list(&sha256_list, &ct),
+        );
+
+        assert!(first_verdicts
+            .iter()
+            .chain(&second_verdicts)
+            .all(Result::is_ok));
+        // Two requests are sent at once, the other four at intervals of 100 ms.
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!(started.elapsed() < Duration::from_millis(450));
+        let throttled = first.stats().throttled_millis + second.stats().throttled_millis;
+        assert!(throttled >= 900, "throttled for {throttled} ms");
+        assert_eq!(
+            6,
+            first.stats().requests_sent + second.stats().requests_sent
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_rate_limit_is_bounded_by_cancellation_token() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let connection = MockServer::connect(sink, source, rate_limited_options(1, 1)).await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+        let short = CancellationToken::from(Duration::from_millis(200));
+
+        let first = connection.for_sha256(&sha256, &short).await;
+        let second = connection.for_sha256(&sha256, &short).await;
+
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(Error::Cancelled)));
+        assert_eq!(1, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().timeouts);
+        assert_eq!(200, connection.stats().throttled_millis);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cached_files_and_buffers_do_not_use_up_rate_limit() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            local_cache: Some(Arc::new(VerdictCache::new(10, Duration::from_secs(60)))),
+            ..rate_limited_options(1, 1)
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"cached content").unwrap();
+        let ct = CancellationToken::from_seconds(10);
+        connection
+            .for_buf(b"cached content".to_vec(), &ct)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            let from_buf = connection.for_buf(b"cached content".to_vec(), &ct).await;
+            let from_file = connection.for_file(file.path(), &ct).await;
+            assert_eq!(VerdictSource::LocalCache, from_buf.unwrap().source);
+            assert_eq!(VerdictSource::LocalCache, from_file.unwrap().source);
+        }
+        // The slot which was freed in the meantime is still available.
+        connection
+            .for_buf(b"other content".to_vec(), &ct)
+            .await
+            .unwrap();
+
+        assert_eq!(Duration::ZERO, started.elapsed());
+        assert_eq!(0, connection.stats().throttled_millis);
+        assert_eq!(6, connection.stats().cache_hits);
+        assert_eq!(2, connection.stats().requests_sent);
+    }
+
+    #[tokio::test]
+    async fn repeated_request_is_answered_from_local_cache_until_invalidated() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            local_cache: Some(Arc::new(VerdictCache::new(10, Duration::from_secs(60)))),
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+
+        let verdicts = [
+            connection.for_sha256(&sha256, None).await.unwrap(),
+            connection.for_sha256(&sha256, None).await.unwrap(),
+            connection.for_sha256(&sha256, None).await.unwrap(),
+        ];
+        assert_eq!(Verdict::Clean, verdicts[2].verdict);
+        assert_eq!(VerdictSource::Vaas, verdicts[0].source);
+        assert_eq!(VerdictSource::LocalCache, verdicts[2].source);
+        assert_eq!(1, connection.stats().requests_sent);
+        assert_eq!(2, connection.stats().cache_hits);
+        assert_eq!(1, connection.stats().clean_verdicts);
+
+        connection.invalidate(&sha256);
+        connection.for_sha256(&sha256, None).await.unwrap();
+        connection.clear_cache();
+        connection.for_sha256(&sha256, None).await.unwrap();
+        assert_eq!(3, connection.stats().requests_sent);
+        assert_eq!(2, connection.stats().cache_hits);
+    }
+
+    #[tokio::test]
+    async fn for_buf_is_answered_from_local_cache_of_sha256_request() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            local_cache: Some(Arc::new(VerdictCache::new(10, Duration::from_secs(60)))),
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let content = b"cached content".to_vec();
+
+        connection
+            .for_sha256(&Sha256::from(content.as_slice()), None)
+            .await
+            .unwrap();
+        let verdict = connection.for_buf(content, None).await.unwrap();
+
+        assert_eq!(Verdict::Clean, verdict.verdict);
+        assert_eq!(1, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().cache_hits);
+    }
+
+    #[tokio::test]
+    async fn listed_hashes_are_answered_without_a_request() {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let allowed = b"allowed content".to_vec();
+        let blocked = Sha256::from(&b"blocked content"[..]);
+        let options = Options {
+            hash_lists: Arc::new(
+                HashLists::default().with_allowed([Sha256::from(allowed.as_slice())]),
+            ),
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        connection.add_to_blocklist(blocked.clone());
+
+        let allowed = connection.for_buf(allowed, None).await.unwrap();
+        let blocked = connection.for_sha256(&blocked, None).await.unwrap();
+
+        assert_eq!(Verdict::Clean, allowed.verdict);
+        assert_eq!(VerdictSource::LocalList, allowed.source);
+        assert!(matches!(blocked.verdict, Verdict::Malicious { .. }));
+        assert_eq!(VerdictSource::LocalList, blocked.source);
+        assert_eq!(0, server.max_pending());
+        assert_eq!(0, connection.stats().requests_sent);
+    }
+
+    #[tokio::test]
+    async fn for_file_with_cancellable_hashing_is_cancelled_while_hashing() {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            cancellable_hashing: true,
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let file = tempfile::NamedTempFile::new().unwrap();
+        file.as_file().set_len(64 * 1024 * 1024 * 1024).unwrap();
+
+        let result = connection
+            .for_file(
+                file.path(),
+                &CancellationToken::from(Duration::from_millis(100)),
+            )
+            .await;
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(0, server.max_pending());
+        assert_eq!(0, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().timeouts);
+    }
+
+    #[tokio::test]
+    async fn failure_to_hash_file_is_counted_the_same_with_and_without_cancellable_hashing() {
+        for cancellable_hashing in [false, true] {
+            let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+            let options = Options {
+                cancellable_hashing,
+                keep_alive: false,
+                ..Options::default()
+            };
+            let connection = MockServer::connect(sink, source, options).await;
+            let directory = tempfile::tempdir().unwrap();
+
+            let result = connection
+                .for_file(
+                    &directory.path().join("missing"),
+                    &CancellationToken::from_seconds(1),
+                )
+                .await;
+
+            assert!(matches!(result, Err(Error::IoError(_))), "{result:?}");
+            let stats = connection.stats();
+            assert_eq!(0, stats.requests_sent, "cancellable: {cancellable_hashing}");
+            assert_eq!(0, stats.timeouts, "cancellable: {cancellable_hashing}");
+            assert_eq!(0, stats.in_flight, "cancellable: {cancellable_hashing}");
+        }
+    }
+
+    /// Answers verdict requests with `Unknown` and sends the final verdict as soon as the upload arrives,
+    /// before the upload itself is answered.
+    async fn connect_with_verdict_during_upload(
+        upload_server: &mut mockito::ServerGuard,
+        sha256: &Sha256,
+    ) -> Connection {
+        connect_with_verdict_during_upload_and_options(upload_server, sha256, Options::default())
+            .await
+    }
+
+    async fn connect_with_verdict_during_upload_and_options(
+        upload_server: &mut mockito::ServerGuard,
+        sha256: &Sha256,
+        options: Options,
+    ) -> Connection {
+        let guid = Arc::new(std::sync::Mutex::new(String::new()));
+        let upload_url = format!("{}/upload", upload_server.url());
+        let sha256 = sha256.to_string();
+        let requested_guid = guid.clone();
+        let unknown_sha256 = sha256.clone();
+        let (server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            *requested_guid.lock().unwrap() = request["guid"].as_str().unwrap().to_string();
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": unknown_sha256,
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": "upload-token",
+            });
+            Some(response.to_string())
+        });
+        upload_server
+            .mock("PUT", "/upload")
+            .with_body_from_request(move |_| {
+                let guid = guid.lock().unwrap().clone();
+                let request = serde_json::json!({"guid": guid, "sha256": sha256});
+                server.send(verdict_response(&request, "Malicious"));
+                Vec::new()
+            })
+            .create_async()
+            .await;
+        let options = Options {
+            keep_alive: false,
+            allowed_upload_hosts: None,
+            ..options
+        };
+        MockServer::connect(sink, source, options).await
+    }
+
+    #[tokio::test]
+    async fn for_buf_receives_verdict_sent_before_upload_is_answered() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+
+        let verdict = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+    }
+
+    #[tokio::test]
+    async fn verdict_after_slow_upload_has_monotone_timing() {
+        let mut upload_server = mockito::Server::new_async().await;
+        upload_server
+            .mock("PUT", "/upload")
+            .with_status(502)
+            .with_body_from_request(|_| {
+                sleep(Duration::from_millis(200));
+                Vec::new()
+            })
+            .expect(1)
+            .create_async()
+            .await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            retry_options(2),
+        )
+        .await;
+        let before = std::time::SystemTime::now();
+
+        let verdict = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        let timing = verdict.timing.unwrap();
+        let upload_duration = timing.upload_duration.unwrap();
+        assert!(before <= timing.requested_at);
+        assert!(timing.requested_at <= timing.responded_at);
+        assert!(upload_duration >= Duration::from_millis(200));
+        assert!(timing.lookup_duration() + upload_duration <= timing.total);
+        assert!(timing.total < Duration::from_secs(2));
+        assert_eq!(Some(15), timing.uploaded_bytes);
+    }
+
+    #[tokio::test]
+    async fn verdict_of_hash_lookup_is_timed_unless_cached() {
+        let (_server, sink, source) = MockServer::answering(Duration::from_millis(50), "Clean");
+        let options = Options {
+            local_cache: Some(Arc::new(VerdictCache::new(10, Duration::from_secs(60)))),
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+
+        let requested = connection.for_sha256(&sha256, None).await.unwrap();
+        let cached = connection.for_sha256(&sha256, None).await.unwrap();
+
+        let timing = requested.timing.unwrap();
+        assert!(timing.lookup_duration() >= Duration::from_millis(50));
+        assert!(timing.lookup_duration() <= timing.total);
+        assert_eq!(None, timing.upload_duration);
+        assert_eq!(None, timing.uploaded_bytes);
+        assert_eq!(None, cached.timing);
+    }
+
+    fn retry_options(max_attempts: u32) -> Options {
+        Options {
+            retry_policy: RetryPolicy {
+                max_attempts,
+                base_delay: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            },
+            ..Options::default()
+        }
+    }
+
+    /// Answers the next `count` uploads with the status, before the uploads are accepted.
+    async fn fail_uploads(upload_server: &mut mockito::ServerGuard, status: usize, count: usize) {
+        upload_server
+            .mock("PUT", "/upload")
+            .with_status(status)
+            .expect(count)
+            .create_async()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn for_file_retries_upload_after_server_error() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 502, 1).await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"unknown content").unwrap();
+        let sha256 = Sha256::from(&b"unknown content"[..]);
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            retry_options(3),
+        )
+        .await;
+
+        let verdict = connection
+            .for_file(file.path(), &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+        assert_eq!(1, connection.stats().uploads);
+    }
+
+    #[tokio::test]
+    async fn upload_which_fails_on_every_attempt_reports_the_attempts() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 503, 3).await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            retry_options(3),
+        )
+        .await;
+
+        let result = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await;
+
+        match result {
+            Err(Error::RetriesExhausted { attempts, last }) => {
+                assert_eq!(3, attempts);
+                assert!(matches!(*last, Error::FailedUploadFile(status, _) if status == 503));
+            }
+            other => panic!("expected exhausted retries, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_with_client_error_is_not_retried() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 413, 1).await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            retry_options(3),
+        )
+        .await;
+
+        let result = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await;
+
+        assert!(matches!(result, Err(Error::FailedUploadFile(status, _)) if status == 413));
+    }
+
+    #[tokio::test]
+    async fn rejected_upload_is_retried_with_a_new_upload_token() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 401, 1).await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"unknown content").unwrap();
+        let sha256 = Sha256::from(&b"unknown content"[..]);
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+
+        let verdict = connection
+            .for_file(file.path(), &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+        assert_eq!(2, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().uploads);
+    }
+
+    #[tokio::test]
+    async fn upload_token_is_refreshed_only_once() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 403, 2).await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+
+        let result = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await;
+
+        assert!(matches!(result, Err(Error::FailedUploadFile(status, _)) if status == 403));
+        assert_eq!(2, connection.stats().requests_sent);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn request_and_upload_are_traced_without_tokens() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+
+        connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(logs_contain("vaas_connection{session_id=session}"));
+        assert!(logs_contain("vaas_request{guid="));
+        assert!(logs_contain(&format!("sha256={sha256}")));
+        assert!(logs_contain("Verdict request sent"));
+        assert!(logs_contain("Verdict received verdict=Unknown"));
+        assert!(logs_contain("vaas_upload{"));
+        assert!(logs_contain("Upload started bytes=15"));
+        assert!(logs_contain("Upload finished bytes=15"));
+        assert!(logs_contain("Verdict received verdict=Malicious"));
+        assert!(!logs_contain("upload-token"));
+        assert!(!logs_contain("/upload"));
+    }
+
+    #[derive(Default)]
+    struct HookCalls {
+        verdicts: AtomicUsize,
+        errors: AtomicUsize,
+        uploaded_bytes: AtomicUsize,
+    }
+
+    impl HookCalls {
+        fn options(self: &Arc<Self>) -> Options {
+            let (verdicts, errors, uploads) = (self.clone(), self.clone(), self.clone());
+            let mut options = Options::default();
+            options.hooks.on_verdict.push(Arc::new(move |_| {
+                verdicts.verdicts.fetch_add(1, Ordering::Relaxed);
+            }));
+            options.hooks.on_error.push(Arc::new(move |_| {
+                errors.errors.fetch_add(1, Ordering::Relaxed);
+            }));
+            options.hooks.on_upload.push(Arc::new(move |_, bytes| {
+                uploads
+                    .uploaded_bytes
+                    .fetch_add(bytes as usize, Ordering::Relaxed);
+            }));
+            options
+        }
+    }
+
+    #[tokio::test]
+    async fn hooks_are_called_for_each_request_of_a_batch() {
+        let unanswered = Sha256::from(&b"unanswered"[..]);
+        let unanswered_hex = unanswered.to_string();
+        let (_server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            (request["sha256"] != unanswered_hex.as_str())
+                .then(|| verdict_response(request, "Clean"))
+        });
+        let calls = Arc::new(HookCalls::default());
+        let connection = MockServer::connect(sink, source, calls.options()).await;
+        let hashes = vec![
+            Sha256::from(&b"first"[..]),
+            unanswered,
+            Sha256::from(&b"second"[..]),
+        ];
+        let ct = CancellationToken {
+            duration: Duration::from_millis(50),
+        };
+
+        let results = connection.for_sha256_list(&hashes, &ct).await;
+
+        assert!(matches!(results[1], Err(Error::Cancelled)), "{results:?}");
+        assert_eq!(2, calls.verdicts.load(Ordering::Relaxed));
+        assert_eq!(1, calls.errors.load(Ordering::Relaxed));
+        assert_eq!(0, calls.uploaded_bytes.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn upload_hook_is_called_with_the_uploaded_bytes() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let calls = Arc::new(HookCalls::default());
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            calls.options(),
+        )
+        .await;
+
+        connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert_eq!(1, calls.verdicts.load(Ordering::Relaxed));
+        assert_eq!(0, calls.errors.load(Ordering::Relaxed));
+        assert_eq!(15, calls.uploaded_bytes.load(Ordering::Relaxed));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn scan_with_upload_is_counted_in_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+        use metrics_util::MetricKind;
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        metrics::with_local_recorder(&recorder, || {
+            runtime.block_on(async {
+                let mut upload_server = mockito::Server::new_async().await;
+                let content = b"unknown content".to_vec();
+                let sha256 = Sha256::from(content.as_slice());
+                let connection =
+                    connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+                connection
+                    .for_buf(content, &CancellationToken::from_seconds(2))
+                    .await
+                    .unwrap();
+
+                let (_server, sink, source) = MockServer::new(Duration::ZERO, |_| None);
+                let unanswered = MockServer::connect(sink, source, Options::default()).await;
+                let ct = CancellationToken {
+                    duration: Duration::from_millis(10),
+                };
+                let result = unanswered.for_sha256(&sha256, &ct).await;
+                assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+            })
+        });
+
+        let metrics: Vec<_> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key, value))
+            .collect();
+        let value = |kind: MetricKind, name: &str, labels: &[(&str, &str)]| {
+            metrics
+                .iter()
+                .find(|(key, _)| {
+                    let labels_match = key
+                        .key()
+                        .labels()
+                        .map(|label| (label.key(), label.value()))
+                        .eq(labels.iter().copied());
+                    key.kind() == kind && key.key().name() == name && labels_match
+                })
+                .map(|(_, value)| value)
+        };
+        let counter =
+            |name: &str, labels: &[(&str, &str)]| match value(MetricKind::Counter, name, labels) {
+                Some(DebugValue::Counter(count)) => *count,
+                other => panic!("{name} {labels:?} is {other:?}"),
+            };
+        assert_eq!(2, counter("vaas_connections_total", &[]));
+        assert_eq!(2, counter("vaas_requests_total", &[]));
+        let verdicts = |verdict| counter("vaas_verdicts_total", &[("verdict", verdict)]);
+        assert_eq!(1, verdicts("Unknown"));
+        assert_eq!(1, verdicts("Malicious"));
+        assert_eq!(15, counter("vaas_upload_bytes_total", &[]));
+        let errors = |kind| counter("vaas_errors_total", &[("error_kind", kind)]);
+        assert_eq!(1, errors("cancelled"));
+        assert!(matches!(
+            value(MetricKind::Histogram, "vaas_request_duration_seconds", &[]),
+            Some(DebugValue::Histogram(durations)) if durations.len() == 2
+        ));
+        assert!(matches!(
+            value(MetricKind::Histogram, "vaas_upload_duration_seconds", &[]),
+            Some(DebugValue::Histogram(durations)) if durations.len() == 1
+        ));
+    }
+
+    #[tokio::test]
+    async fn deferred_upload_is_completed_with_a_requested_upload_url() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+        let ct = CancellationToken::from_seconds(2);
+
+        let upload = match connection.request_upload_url(&sha256, &ct).await.unwrap() {
+            UploadUrlResponse::Upload(upload) => upload,
+            response => panic!("expected an upload, got {response:?}"),
+        };
+        assert_eq!(
+            format!("{}/upload", upload_server.url()),
+            *upload.upload_url
+        );
+        assert_eq!("upload-token", upload.upload_token);
+        let verdict = connection
+            .upload_and_await(content, upload, &ct)
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+        assert_eq!(1, connection.stats().requests_sent);
+        assert_eq!(1, connection.stats().uploads);
+    }
+
+    #[tokio::test]
+    async fn expired_upload_url_is_requested_again_before_uploading() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let expired = upload_server
+            .mock("PUT", "/expired")
+            .expect(0)
+            .create_async()
+            .await;
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+        let upload = PendingUpload {
+            guid: RequestId::new(),
+            upload_url: UploadUrl(format!("{}/expired?Expires=1", upload_server.url())),
+            upload_token: "expired-token".to_string(),
+        };
+
+        let verdict = connection
+            .upload_and_await(content, upload, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+        assert_eq!(1, connection.stats().requests_sent);
+        expired.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn upload_url_of_known_file_is_not_needed() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let connection = MockServer::connect(sink, source, Options::default()).await;
+        let sha256 = Sha256::from(&b"known content"[..]);
+
+        let response = connection
+            .request_upload_url(&sha256, &CancellationToken::from_seconds(1))
+            .await
+            .unwrap();
+
+        assert!(
+            matches!(&response, UploadUrlResponse::Verdict(verdict) if verdict.verdict == Verdict::Clean),
+            "{response:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn upload_url_is_not_requested_if_uploads_are_disabled() {
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            upload: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        let sha256 = Sha256::from(&b"unknown content"[..]);
+
+        let result = connection
+            .request_upload_url(&sha256, &CancellationToken::from_seconds(1))
+            .await;
+
+        assert!(matches!(result, Err(Error::UploadDisabled)), "{result:?}");
+        assert_eq!(0, connection.stats().requests_sent);
+    }
+
+    /// Answers verdict requests with `Unknown` and an upload URL of the upload server, but never sends the final
+    /// verdict.
+    async fn connect_answering_unknown(
+        upload_server: &mockito::ServerGuard,
+        options: Options,
+    ) -> Connection {
+        let upload_url = format!("{}/upload", upload_server.url());
+        let (_server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": request["sha256"],
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": "upload-token",
+            });
+            Some(response.to_string())
+        });
+        let options = Options {
+            keep_alive: false,
+            allowed_upload_hosts: None,
+            ..options
+        };
+        MockServer::connect(sink, source, options).await
+    }
+
+    #[tokio::test]
+    async fn unknown_buffers_and_files_are_not_uploaded_if_uploads_are_disabled() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let uploads = upload_server
+            .mock("PUT", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+        let options = Options {
+            upload: false,
+            ..Options::default()
+        };
+        let connection = connect_answering_unknown(&upload_server, options).await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"unknown file").unwrap();
+        let ct = CancellationToken::from_seconds(2);
+
+        let buf_verdict = connection
+            .for_buf(b"unknown buffer".to_vec(), &ct)
+            .await
+            .unwrap();
+        let file_verdict = connection.for_file(file.path(), &ct).await.unwrap();
+
+        assert!(matches!(buf_verdict.verdict, Verdict::Unknown { .. }));
+        assert!(matches!(file_verdict.verdict, Verdict::Unknown { .. }));
+        assert_eq!(0, connection.stats().uploads);
+        uploads.assert_async().await;
+    }
+
+    /// Waits for the verdict of an uploaded buffer, which never arrives, and returns the result and how long it took.
+    async fn wait_for_verdict_after_upload(
+        verdict_timeout: Option<Duration>,
+        ct: Duration,
+    ) -> (VResult<VaasVerdict>, Duration) {
+        let mut upload_server = mockito::Server::new_async().await;
+        upload_server.mock("PUT", "/upload").create_async().await;
+        let options = Options {
+            verdict_timeout,
+            ..Options::default()
+        };
+        let connection = connect_answering_unknown(&upload_server, options).await;
+        let started = Instant::now();
+
+        let verdict = tokio::time::timeout(
+            Duration::from_secs(10),
+            connection.for_buf(b"unknown content".to_vec(), &CancellationToken::from(ct)),
+        )
+        .await
+        .expect("the cancellation token was ignored");
+
+        assert_eq!(1, connection.stats().uploads);
+        (verdict, started.elapsed())
+    }
+
+    #[tokio::test]
+    async fn verdict_timeout_is_capped_by_cancellation_token() {
+        let (verdict, elapsed) = wait_for_verdict_after_upload(
+            Some(Duration::from_secs(600)),
+            Duration::from_millis(500),
+        )
+        .await;
+
+        assert!(matches!(verdict, Err(Error::Cancelled)), "{verdict:?}");
+        assert!(elapsed < Duration::from_secs(2), "waited {elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn verdict_timeout_shorter_than_cancellation_token_ends_the_wait() {
+        let (verdict, elapsed) = wait_for_verdict_after_upload(
+            Some(Duration::from_millis(200)),
+            Duration::from_secs(600),
+        )
+        .await;
+
+        assert!(matches!(verdict, Err(Error::Cancelled)), "{verdict:?}");
+        assert!(elapsed < Duration::from_secs(2), "waited {elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn upload_of_stream_is_not_retried() {
+        let mut upload_server = mockito::Server::new_async().await;
+        fail_uploads(&mut upload_server, 502, 1).await;
+        let content: &'static [u8] = b"unknown content";
+        let sha256 = Sha256::from(content);
+        let connection = connect_with_verdict_during_upload_and_options(
+            &mut upload_server,
+            &sha256,
+            retry_options(3),
+        )
+        .await;
+        let stream = futures::stream::iter([Ok::<_, std::io::Error>(content)]);
+
+        let result = connection
+            .for_stream(stream, content.len(), &CancellationToken::from_seconds(2))
+            .await;
+
+        assert!(matches!(result, Err(Error::FailedUploadFile(status, _)) if status == 502));
+    }
+
+    /// Starts an upload endpoint which reads 1 KiB every 100 ms and never answers.
+    async fn start_throttled_upload_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while stream.read(&mut buf).await.is_ok_and(|read| read > 0) {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn upload_is_cancelled_at_the_deadline() {
+        let upload_url = start_throttled_upload_server().await;
+        let content = vec![0; 1024 * 1024];
+        let sha256 = Sha256::from(content.as_slice()).to_string();
+        let (_server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": sha256,
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": "upload-token",
+            });
+            Some(response.to_string())
+        });
+        let options = Options {
+            keep_alive: false,
+            allowed_upload_hosts: None,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+
+        let started = Instant::now();
+        let result = connection
+            .for_buf(content, &CancellationToken::from_seconds(1))
+            .await;
+
+        let elapsed = started.elapsed();
+        assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+        assert!(elapsed >= Duration::from_millis(950), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(1300), "{elapsed:?}");
+        assert_eq!(1, connection.stats().timeouts);
+        assert_eq!(0, connection.stats().uploads);
+    }
+
+    async fn connect_requesting_upload_to(upload_url: &'static str) -> Connection {
+        let (_server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": request["sha256"].as_str().unwrap_or_default(),
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": "upload-token",
+            });
+            Some(response.to_string())
+        });
+        let options = Options {
+            keep_alive: false,
+            ..Options::default()
+        };
+        MockServer::connect(sink, source, options).await
+    }
+
+    #[tokio::test]
+    async fn upload_to_untrusted_host_is_refused() {
+        let connection = connect_requesting_upload_to("https://upload.example.com/files").await;
+
+        let result = connection
+            .for_buf(
+                b"unknown content".to_vec(),
+                &CancellationToken::from_seconds(1),
+            )
+            .await;
+
+        assert!(
+            matches!(&result, Err(Error::UntrustedUploadHost(url)) if url == "https://upload.example.com/files"),
+            "{result:?}"
+        );
+        assert_eq!(0, connection.stats().uploads);
+    }
+
+    #[tokio::test]
+    async fn upload_of_stream_over_http_is_refused() {
+        let connection =
+            connect_requesting_upload_to("http://upload.production.vaas.gdatasecurity.de/files")
+                .await;
+        let content = futures::stream::iter([Ok::<_, std::io::Error>(b"unknown".to_vec())]);
+
+        let result = connection
+            .for_stream(content, 7, &CancellationToken::from_seconds(1))
+            .await;
+
+        assert!(
+            matches!(result, Err(Error::UntrustedUploadHost(_))),
+            "{result:?}"
+        );
+        assert_eq!(0, connection.stats().uploads);
+    }
+
+    #[tokio::test]
+    async fn for_stream_receives_verdict_sent_before_upload_is_answered() {
+        let mut upload_server = mockito::Server::new_async().await;
+        let content: &'static [u8] = b"unknown content";
+        let sha256 = Sha256::from(content);
+        let connection = connect_with_verdict_during_upload(&mut upload_server, &sha256).await;
+        let stream = futures::stream::iter([Ok::<_, std::io::Error>(content)]);
+
+        let verdict = connection
+            .for_stream(stream, content.len(), &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+    }
+
+    fn auth_response(success: bool) -> AuthResponse {
+        let json = format!(
+            r#"{{"kind":"AuthResponse","success":{success},"session_id":"session","text":"rejected"}}"#
+        );
+        AuthResponse::try_from(&json).unwrap()
+    }
+
+    #[tokio::test]
+    async fn dispatch_passes_auth_response_to_pending_refresh() {
+        let responses = VaasResponseBroker::new();
+        let auth_responses = AuthResponseBroker::new();
+        let pending = auth_responses.get_response(AUTH_RESPONSE_ID.to_string());
+
+        Connection::dispatch(
+            Ok(MessageType::AuthResponse(auth_response(false))),
+            &responses,
+            &auth_responses,
+        );
+
+        let response = pending.await.unwrap();
+        assert!(!response.success);
+        assert_eq!("rejected", response.text);
+    }
+
+    #[tokio::test]
+    async fn dispatch_close_fails_pending_refresh() {
+        let responses = VaasResponseBroker::new();
+        let auth_responses = AuthResponseBroker::new();
+        let pending = auth_responses.get_response(AUTH_RESPONSE_ID.to_string());
+
+        Connection::dispatch(Ok(MessageType::Close), &responses, &auth_responses);
+
+        assert!(matches!(pending.await, Err(Error::ConnectionClosed)));
+    }
+
+    /// A server which does not answer, and the requests it received, to be answered by the test.
+    async fn connect_to_silent_server(
+    ) -> (MockServer, Connection, Arc<std::sync::Mutex<Vec<Value>>>) {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = requests.clone();
+        let (server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            received.lock().unwrap().push(request.clone());
+            None
+        });
+        let options = Options {
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        (server, connection, requests)
+    }
+
+    async fn received_requests(
+        requests: &std::sync::Mutex<Vec<Value>>,
+        count: usize,
+    ) -> Vec<Value> {
+        loop {
+            let received = requests.lock().unwrap().clone();
+            if received.len() == count {
+                return received;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_message_between_responses_does_not_fail_other_requests() {
+        let (server, connection, requests) = connect_to_silent_server().await;
+        let first = Sha256::from(&b"first"[..]);
+        let second = Sha256::from(&b"second"[..]);
+        let ct = CancellationToken::from_seconds(10);
+
+        let answers = async {
+            let requests = received_requests(&requests, 2).await;
+            server.send(verdict_response(&requests[0], "Clean"));
+            server.send(r#"{"kind":"VerdictResponse","verdict":"Cle"#.to_string());
+            server.send(verdict_response(&requests[1], "Clean"));
+        };
+        let (first, second, ()) = tokio::join!(
+            connection.for_sha256(&first, &ct),
+            connection.for_sha256(&second, &ct),
+            answers,
+        );
+
+        assert_eq!(Verdict::Clean, first.unwrap().verdict);
+        assert_eq!(Verdict::Clean, second.unwrap().verdict);
+        assert_eq!(1, connection.stats().malformed_messages);
+    }
+
+    #[tokio::test]
+    async fn malformed_message_fails_the_request_with_its_guid() {
+        let (server, connection, requests) = connect_to_silent_server().await;
+        let first = Sha256::from(&b"first"[..]);
+        let second = Sha256::from(&b"second"[..]);
+        let ct = CancellationToken::from_seconds(10);
+
+        let answers = async {
+            let mut requests = received_requests(&requests, 2).await;
+            requests.sort_by_key(|request| request["sha256"] != first.to_string());
+            let guid = requests[0]["guid"].as_str().unwrap();
+            server.send(format!(
+                r#"{{"kind":"VerdictResponse","guid":"{guid}","verdict":"Cle"#
+            ));
+            server.send(verdict_response(&requests[1], "Clean"));
+        };
+        let (first, second, ()) = tokio::join!(
+            connection.for_sha256(&first, &ct),
+            connection.for_sha256(&second, &ct),
+            answers,
+        );
+
+        assert!(matches!(first, Err(Error::InvalidMessage(_))));
+        assert_eq!(Verdict::Clean, second.unwrap().verdict);
+        assert!(!connection.is_closed());
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn responses_for_guids_which_were_not_sent_are_dropped() {
+        let (server, connection, requests) = connect_to_silent_server().await;
+        let sha256 = Sha256::from(&b"content"[..]);
+        let ct = CancellationToken::from_seconds(10);
+
+        let answers = async {
+            let request = received_requests(&requests, 1).await.remove(0);
+            let sent = request["guid"].as_str().unwrap().to_uppercase();
+            let not_sent = RequestId::new().to_string();
+            for (guid, verdict) in [
+                ("guid", "Malicious"),
+                (&not_sent, "Malicious"),
+                (&sent, "Clean"),
+            ] {
+                let response = serde_json::json!({"sha256": request["sha256"], "guid": guid});
+                server.send(verdict_response(&response, verdict));
+            }
+        };
+        let (verdict, ()) = tokio::join!(connection.for_sha256(&sha256, &ct), answers);
+
+        assert_eq!(Verdict::Clean, verdict.unwrap().verdict);
+        assert!(logs_contain("Invalid request id: `guid`"));
+        assert!(logs_contain("Can't find receiver"));
+        assert!(!connection.is_closed());
+    }
+
+    const HUNDRED_MB: usize = 100 * 1024 * 1024;
+
+    #[test]
+    fn parse_frame_rejects_payloads_beyond_the_maximum_unparsed() {
+        // Without the size check, this would be a malformed message instead of an error.
+        let payload = format!(
+            r#"{{"kind":"Unexpected","text":"{}"}}"#,
+            " ".repeat(HUNDRED_MB)
+        );
+        let size = payload.len();
+        let frame = Frame::Text {
+            payload,
+            continuation: false,
+            fin: true,
+        };
+
+        let message = Connection::parse_frame(Ok(frame), DEFAULT_MAX_MESSAGE_SIZE);
+
+        assert!(
+            matches!(message, Err(Error::MessageTooLarge(s)) if s == size),
+            "{:?}",
+            message.err()
+        );
+    }
+
+    #[tokio::test]
+    async fn message_beyond_max_message_size_closes_the_connection() {
+        let (server, connection, requests) = connect_to_silent_server().await;
+        let sha256 = Sha256::try_from(SHA256).unwrap();
+        let ct = CancellationToken::from_seconds(10);
+
+        let oversized = async {
+            received_requests(&requests, 1).await;
+            server.send("x".repeat(HUNDRED_MB));
+        };
+        let (verdict, ()) = tokio::join!(connection.for_sha256(&sha256, &ct), oversized);
+
+        assert!(matches!(verdict, Err(Error::MessageTooLarge(HUNDRED_MB))));
+        assert!(connection.is_closed());
+        assert!(matches!(
+            connection.for_sha256(&sha256, &ct).await,
+            Err(Error::MessageTooLarge(HUNDRED_MB))
+        ));
+    }
+
+    fn fragment(payload: &str, continuation: bool, fin: bool) -> Result<Frame, WebSocketError> {
+        Ok(Frame::Text {
+            payload: payload.to_string(),
+            continuation,
+            fin,
+        })
+    }
+
+    #[test]
+    fn fragmented_message_is_parsed_once_complete_with_ping_in_between() {
+        let json = verdict_response(
+            &serde_json::json!({"sha256": SHA256, "guid": "guid"}),
+            "Clean",
+        );
+        let (first, rest) = json.split_at(json.len() / 3);
+        let (second, third) = rest.split_at(rest.len() / 2);
+        let mut fragments = Fragments::new(DEFAULT_MAX_MESSAGE_SIZE);
+
+        assert!(fragments.message(fragment(first, false, false)).is_none());
+        let ping = fragments.message(Ok(Frame::Ping));
+        assert!(matches!(ping, Some(Ok(MessageType::Ping))));
+        assert!(fragments.message(fragment(second, true, false)).is_none());
+        let message = fragments.message(fragment(third, true, true));
+
+        assert!(matches!(
+            message,
+            Some(Ok(MessageType::VerdictResponse(response)))
+                if response.guid == "guid" && response.verdict == "Clean"
+        ));
+        let unfragmented = fragments.message(fragment(&json, false, true));
+        assert!(matches!(
+            unfragmented,
+            Some(Ok(MessageType::VerdictResponse(_)))
+        ));
+    }
+
+    #[test]
+    fn fragments_beyond_max_message_size_are_rejected_before_the_last_one() {
+        let mut fragments = Fragments::new(10);
+
+        assert!(fragments.message(fragment("12345", false, false)).is_none());
+        let message = fragments.message(fragment("678901", true, false));
+
+        assert!(matches!(message, Some(Err(Error::MessageTooLarge(11)))));
+    }
+
+    #[test]
+    fn continuation_without_a_started_message_is_invalid() {
+        let mut fragments = Fragments::new(DEFAULT_MAX_MESSAGE_SIZE);
+
+        let message = fragments.message(fragment("{}", true, true));
+
+        assert!(matches!(message, Some(Err(Error::InvalidFrame))));
+    }
+
+    #[test]
+    fn jittered_delay_stays_within_jitter() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let delay = Duration::from_secs(10);
+        let jitter = Duration::from_secs(2);
+
+        let delays = (0..1000)
+            .map(|_| jittered_delay(delay, jitter, &mut rng))
+            .collect::<Vec<_>>();
+
+        let bounds = delay - jitter..=delay + jitter;
+        assert!(delays.iter().all(|d| bounds.contains(d)));
+        assert!(delays.iter().any(|d| *d < delay));
+        assert!(delays.iter().any(|d| *d > delay));
+    }
+
+    #[test]
+    fn jittered_delay_without_jitter_is_delay() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let delay = Duration::from_secs(10);
+
+        assert_eq!(delay, jittered_delay(delay, Duration::ZERO, &mut rng));
+    }
+
+    fn keep_alive_options(delay: Duration, jitter: Duration) -> Options {
+        Options {
+            keep_alive_delay: delay,
+            keep_alive_jitter: jitter,
+            ..Options::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_alive_pings_at_configured_delay() {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = keep_alive_options(Duration::from_secs(3), Duration::ZERO);
+        let _connection = MockServer::connect(sink, source, options).await;
+
+        tokio::time::sleep(Duration::from_millis(2900)).await;
+        assert_eq!(0, server.pings());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(1, server.pings());
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        assert_eq!(2, server.pings());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_alive_pings_within_jitter_of_delay() {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = keep_alive_options(Duration::from_secs(3), Duration::from_secs(1));
+        let _connection = MockServer::connect(sink, source, options).await;
+
+        tokio::time::sleep(Duration::from_millis(1900)).await;
+        assert_eq!(0, server.pings());
+        tokio::time::sleep(Duration::from_millis(2200)).await;
+        assert_eq!(1, server.pings());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keep_alive_stops_when_connection_is_dropped() {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = keep_alive_options(Duration::from_secs(3), Duration::ZERO);
+        let connection = MockServer::connect(sink, source, options).await;
+
+        tokio::time::sleep(Duration::from_millis(3100)).await;
+        drop(connection);
+        tokio::time::sleep(Duration::from_secs(30)).await;
+
+        assert_eq!(1, server.pings());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn uploads_are_limited_by_permits() {
+        let (url, max_active) = start_upload_server().await;
+        let http_client = reqwest::Client::new();
+        let options = Options::default();
+        let upload_permits = Semaphore::new(2);
+
+        let uploads = (0..6).map(|_| {
+            upload_buf(
+                Bytes::from(vec![0; 1024]),
+                UploadTarget::new(UploadUrl(url.clone()), "token"),
+                &http_client,
+                &options,
+                &upload_permits,
+            )
+        });
+        let responses = join_all(uploads).await;
+
+        assert!(responses.iter().all(|response| response.is_ok()));
+        assert_eq!(2, max_active.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn uploads_reuse_connections_of_shared_client() {
+        let (url, connections) = start_keep_alive_upload_server().await;
+        let http_client = reqwest::Client::new();
+        let options = Options::default();
+        let upload_permits = Semaphore::new(1);
+
+        for _ in 0..5 {
+            let response = upload_buf(
+                Bytes::from(vec![0; 1024]),
+                UploadTarget::new(UploadUrl(url.clone()), "token"),
+                &http_client,
+                &options,
+                &upload_permits,
+            )
+            .await
+            .unwrap();
+            assert_eq!(200, response.status());
+        }
+
+        assert_eq!(1, connections.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn upload_with_slow_endpoint_times_out() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("PUT", "/upload")
+            .with_body_from_request(|_| {
+                sleep(Duration::from_millis(500));
+                Vec::new()
+            })
+            .create_async()
+            .await;
+        let upload_url = UploadUrl(format!("{}/upload", server.url()));
+
+        let options = Options {
+            upload_timeout: Some(Duration::from_millis(100)),
+            ..Options::default()
+        };
+
+        let result = upload_buf(
+            Bytes::from(vec![0; 1024]),
+            UploadTarget::new(upload_url, "token"),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::UploadTimeout(_))));
+    }
+
+    #[tokio::test]
+    async fn upload_without_timeout_waits_for_slow_endpoint() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("PUT", "/upload")
+            .with_body_from_request(|_| {
+                sleep(Duration::from_millis(200));
+                Vec::new()
+            })
+            .create_async()
+            .await;
+        let upload_url = UploadUrl(format!("{}/upload", server.url()));
+
+        let response = upload_buf(
+            Bytes::from(vec![0; 1024]),
+            UploadTarget::new(upload_url, "token"),
+            &reqwest::Client::new(),
+            &Options::default(),
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(200, response.status());
+    }
+
+    #[tokio::test]
+    async fn upload_with_http2_uses_http2() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/upload")
+            .match_header("Authorization", "token")
+            .match_body(vec![1; 1024])
+            .create_async()
+            .await;
+        let upload_url = UploadUrl(format!("{}/upload", server.url()));
+        let http_client = reqwest::Client::builder()
+            .http2_prior_knowledge()
+            .build()
+            .unwrap();
+        let options = Options {
+            upload_http_version: Version::HTTP_2,
+            ..Options::default()
+        };
+
+        let content = futures::stream::iter([Ok::<_, std::io::Error>(vec![1; 1024])]);
+        let (stream, _) = HashingStream::new(content, 1024);
+
+        let response = upload_stream(
+            stream.boxed(),
+            1024,
+            UploadTarget::new(upload_url, "token"),
+            &http_client,
+            &options,
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(Version::HTTP_2, response.version());
+        assert_eq!(200, response.status());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn upload_sends_content_type_and_checksum_headers() {
+        let mut server = mockito::Server::new_async().await;
+        let content = Bytes::from_static(b"abc");
+        let sha256 = Sha256::from(content.as_ref());
+        let mock = server
+            .mock("PUT", "/upload")
+            .match_header("content-type", "application/x-binary")
+            .match_header(
+                "x-amz-checksum-sha256",
+                "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=",
+            )
+            .create_async()
+            .await;
+        let options = Options {
+            upload_content_type: "application/x-binary".to_string(),
+            upload_checksum_header: Some("x-amz-checksum-sha256".to_string()),
+            ..Options::default()
+        };
+
+        upload_buf(
+            content,
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token")
+                .with_sha256(&sha256),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn upload_without_known_sha256_sends_default_content_type_only() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/upload")
+            .match_header("content-type", "application/octet-stream")
+            .match_header("x-amz-checksum-sha256", mockito::Matcher::Missing)
+            .create_async()
+            .await;
+        let options = Options {
+            upload_checksum_header: Some("x-amz-checksum-sha256".to_string()),
+            ..Options::default()
+        };
+        let content = futures::stream::iter([Ok::<_, std::io::Error>(vec![1; 1024])]);
+        let (stream, _) = HashingStream::new(content, 1024);
+
+        upload_stream(
+            stream.boxed(),
+            1024,
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token"),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+    }
+
+    /// A mock of an upload endpoint which decompresses gzip encoded uploads and compares their SHA256.
+    async fn gzip_upload_mock(server: &mut mockito::ServerGuard, sha256: Sha256) -> mockito::Mock {
+        server
+            .mock("PUT", "/upload")
+            .match_header("content-encoding", "gzip")
+            .match_header("content-length", mockito::Matcher::Missing)
+            .match_header("transfer-encoding", "chunked")
+            .match_request(move |request| {
+                let mut content = Vec::new();
+                let body = request.body().unwrap().as_slice();
+                let mut decoder = flate2::read::GzDecoder::new(body);
+                std::io::Read::read_to_end(&mut decoder, &mut content).unwrap();
+                body.len() < content.len() && Sha256::from(content.as_slice()) == sha256
+            })
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn compressed_upload_of_buffer_is_gzip_encoded() {
+        let mut server = mockito::Server::new_async().await;
+        let content = Bytes::from("#!/bin/sh\necho hello\n".repeat(10_000));
+        let sha256 = Sha256::from(content.as_ref());
+        let mock = gzip_upload_mock(&mut server, sha256.clone()).await;
+
+        upload_buf(
+            content,
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token")
+                .with_sha256(&sha256)
+                .compressed(true),
+            &reqwest::Client::new(),
+            &Options::default(),
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn compressed_upload_of_file_is_gzip_encoded_and_rate_limited() {
+        let mut server = mockito::Server::new_async().await;
+        let content = "<w:document><w:body/></w:document>".repeat(30_000);
+        let sha256 = Sha256::from(content.as_bytes());
+        let mock = gzip_upload_mock(&mut server, sha256.clone()).await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, content.as_bytes()).unwrap();
+        let reader = tokio::fs::File::open(file.path()).await.unwrap();
+        let (stream, digest) = HashingStream::new(ReaderStream::new(reader), content.len());
+        // The uncompressed content would take more than a second at this rate.
+        let options = Options {
+            upload_rate_limit: Some(512 * 1024),
+            ..Options::default()
+        };
+
+        let started = Instant::now();
+        upload_stream(
+            stream.boxed(),
+            content.len(),
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token")
+                .with_sha256(&sha256)
+                .compressed(true),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(Some(sha256), digest.digest());
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_upload_header_options_fail_instead_of_panicking() {
+        let options = Options {
+            upload_content_type: "text/plain\n".to_string(),
+            ..Options::default()
+        };
+
+        let result = upload_buf(
+            Bytes::from_static(b"abc"),
+            UploadTarget::new(UploadUrl("http://localhost/upload".to_string()), "token"),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn upload_with_rate_limit_is_paced() {
+        let mut server = mockito::Server::new_async().await;
+        let content = Bytes::from(vec![7; 1024 * 1024]);
+        let mock = server
+            .mock("PUT", "/upload")
+            .match_body(content.to_vec())
+            .create_async()
+            .await;
+        let options = Options {
+            upload_rate_limit: Some(256 * 1024),
+            ..Options::default()
+        };
+
+        let started = Instant::now();
+        upload_buf(
+            content,
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token"),
+            &reqwest::Client::new(),
+            &options,
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(3500), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(6), "{elapsed:?}");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn upload_of_file_stream_hashes_uploaded_content() {
+        let mut server = mockito::Server::new_async().await;
+        let content: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let mock = server
+            .mock("PUT", "/upload")
+            .match_body(content.clone())
+            .create_async()
+            .await;
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, &content).unwrap();
+        let reader = tokio::fs::File::open(file.path()).await.unwrap();
+        // A capacity which does not divide the file size, so the last chunk is shorter.
+        let reader = ReaderStream::with_capacity(reader, 3000);
+        let (stream, digest) = HashingStream::new(reader, content.len());
+
+        upload_stream(
+            stream.boxed(),
+            content.len(),
+            UploadTarget::new(UploadUrl(format!("{}/upload", server.url())), "token"),
+            &reqwest::Client::new(),
+            &Options::default(),
+            &Semaphore::new(1),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(Some(Sha256::from(content.as_slice())), digest.digest());
+        mock.assert_async().await;
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn trace_context_is_propagated_to_request_and_upload() {
+        use opentelemetry::context::FutureExt as _;
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry_sdk::propagation::TraceContextPropagator::new(),
+        );
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let context = opentelemetry::Context::new().with_remote_span_context(span_context);
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let mut upload_server = mockito::Server::new_async().await;
+        let upload_url = format!("{}/upload", upload_server.url());
+        let request = Arc::new(std::sync::Mutex::new(serde_json::Value::Null));
+        let sent_request = request.clone();
+        let unknown_sha256 = sha256.to_string();
+        let (server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            *sent_request.lock().unwrap() = request.clone();
+            let response = serde_json::json!({
+                "kind": "VerdictResponse",
+                "sha256": unknown_sha256,
+                "guid": request["guid"],
+                "verdict": "Unknown",
+                "url": upload_url,
+                "upload_token": "upload-token",
+            });
+            Some(response.to_string())
+        });
+        let uploaded_request = request.clone();
+        // Uploads without the header are answered with 501 by mockito.
+        let upload = upload_server
+            .mock("PUT", "/upload")
+            .match_header("traceparent", traceparent)
+            .with_body_from_request(move |_| {
+                let request = uploaded_request.lock().unwrap().clone();
+                server.send(verdict_response(&request, "Malicious"));
+                Vec::new()
+            })
+            .create_async()
+            .await;
+        let options = Options {
+            keep_alive: false,
+            allowed_upload_hosts: None,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+
+        let verdict = connection
+            .for_buf(content, &CancellationToken::from_seconds(2))
+            .with_context(context)
+            .await
+            .unwrap();
+
+        assert!(matches!(verdict.verdict, Verdict::Malicious { .. }));
+        upload.assert_async().await;
+        let request = request.lock().unwrap().clone();
+        assert_eq!(
+            traceparent,
+            request["verdict_request_attributes"]["traceparent"]
+        );
+        assert!(logs_contain("trace_id=4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+
+    #[test]
+    fn ensure_sha256_with_different_content_is_mismatch() {
+        let expected = Sha256::from(&b"scanned"[..]);
+        let actual = Sha256::from(&b"modified"[..]);
+
+        let result = ensure_sha256(&expected, Some(actual.clone()));
+
+        assert!(matches!(
+            result,
+            Err(Error::Sha256Mismatch { expected: e, actual: a }) if e == expected && a == actual
+        ));
+    }
+
+    #[test]
+    fn ensure_sha256_with_same_or_unknown_content_passes() {
+        let expected = Sha256::from(&b"scanned"[..]);
+
+        assert!(ensure_sha256(&expected, Some(expected.clone())).is_ok());
+        assert!(ensure_sha256(&expected, None).is_ok());
+    }
+}
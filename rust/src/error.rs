@@ -1,13 +1,17 @@
 //! The `Error` type is returned by the `vaas` API everywhere, where an error can occur.
 
 use crate::message::{ErrorResponse, VerdictResponse};
+use crate::retry::RetryClass;
+use crate::sha256::Sha256;
+use crate::websocket::WebSocketError;
 use reqwest::StatusCode;
-use std::sync::PoisonError;
+use std::fmt;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::broadcast::error::SendError;
 use tokio::sync::oneshot::error::RecvError;
 use tokio::time::error::Elapsed;
-use websockets::WebSocketError;
+use tokio_tungstenite::tungstenite::error::CapacityError;
 
 /// VaaS Result type.
 pub type VResult<T> = Result<T, Error>;
@@ -37,6 +41,10 @@ pub enum Error {
     /// Received an invalid message from the endpoint.
     #[error("Invalid message received: `{0}`")]
     InvalidMessage(String),
+    /// Received a message of this many bytes, more than [`Builder::max_message_size`](crate::Builder::max_message_size).
+    /// The connection is closed.
+    #[error("Received a message of {0} bytes, which exceeds the maximum message size")]
+    MessageTooLarge(usize),
     /// No connection was established between the client and server. Did you forget to call `connect()`?
     #[error("No connection established. Did you forget to connect?")]
     NoConnection,
@@ -47,8 +55,14 @@ pub enum Error {
     #[error("IO Error: `{0}`")]
     IoError(String),
     /// The provided string is not a valid SHA256.
-    #[error("Invalid SHA256: `{0}`")]
+    #[error("Invalid SHA256: {0}")]
     InvalidSha256(String),
+    /// The provided string is not a valid request GUID.
+    #[error("Invalid request id: {0}")]
+    InvalidRequestId(String),
+    /// The provided bytes are not an e-mail in RFC 5322 format.
+    #[error("Invalid e-mail: {0}")]
+    InvalidMail(String),
     /// Failed create a request to upload a file.
     #[error("Failed to send file: `{0}`")]
     FailedRequest(String),
@@ -68,8 +82,20 @@ pub enum Error {
     #[error("Error response from the server")]
     ErrorResponse(ErrorResponse),
     /// Failed to get authentication token from the OpenID provider.
+    /// The provider did not answer with a standard OAuth error response.
     #[error("Failed to get authentication token. Status code `{0}` with message `{1}`")]
     FailedAuthTokenRequest(StatusCode, String),
+    /// The OpenID provider rejected the token request with an OAuth error other than invalid credentials,
+    /// e.g. `invalid_scope` or `unauthorized_client`.
+    #[error("Token endpoint answered with status code `{status}` and error `{error}`: `{}`", description.as_deref().unwrap_or_default())]
+    AuthServer {
+        /// The OAuth error code.
+        error: String,
+        /// The human readable description of the error, if provided.
+        description: Option<String>,
+        /// The HTTP status code of the response.
+        status: StatusCode,
+    },
     /// For an successful authentication response, a session id has to be send by the server.
     /// If no session id is send, but the response has the success flag that, this error is used.
     #[error("No session id in authentication response set")]
@@ -77,17 +103,136 @@ pub enum Error {
     /// Connection was closed, reconnect is necessary
     #[error("Connection was closed")]
     ConnectionClosed,
+    /// The request requires to send data to VaaS, but uploads are disabled.
+    #[error("Uploads are disabled")]
+    UploadDisabled,
+    /// The upload of a file took longer than the configured upload timeout.
+    #[error("Upload timed out after {0:?}")]
+    UploadTimeout(Duration),
+    /// Establishing the connection took longer than the configured timeout.
+    #[error("Connect timed out during the {0}")]
+    ConnectTimeout(ConnectPhase),
+    /// The configuration passed to the builder or an authenticator is invalid.
+    #[error("Invalid configuration: `{0}`")]
+    InvalidConfig(String),
+    /// VaaS sent an upload URL which does not use `https` or whose host is not allowed,
+    /// see [`Builder::allowed_upload_hosts`](crate::Builder::allowed_upload_hosts). Nothing was uploaded.
+    #[error("Untrusted upload URL: `{0}`")]
+    UntrustedUploadHost(String),
+    /// The [blocking API](crate::blocking) was used from within an async runtime, where it would block
+    /// a thread of the runtime. The async API has to be used there instead.
+    #[error("The blocking API can't be used from within an async runtime")]
+    BlockingInAsyncContext,
+    /// An operation retried according to the [`RetryPolicy`](crate::retry::RetryPolicy) failed on every attempt.
+    #[error("Failed after {attempts} attempts: {last}")]
+    RetriesExhausted {
+        /// The number of attempts, including the first one.
+        attempts: u32,
+        /// The error of the last attempt.
+        last: Box<Error>,
+    },
+    /// The SHA256 of the uploaded content differs from the expected one,
+    /// e.g. because a file was modified while it was scanned.
+    #[error("SHA256 mismatch: expected {expected}, got {actual}")]
+    Sha256Mismatch {
+        /// The SHA256 the verdict was requested for or VaaS reported.
+        expected: Sha256,
+        /// The SHA256 of the uploaded content.
+        actual: Sha256,
+    },
 }
 
-impl From<PoisonError<std::sync::MutexGuard<'_, websockets::WebSocketWriteHalf>>> for Error {
-    fn from(e: PoisonError<std::sync::MutexGuard<'_, websockets::WebSocketWriteHalf>>) -> Self {
-        Self::Lock(e.to_string())
+impl Error {
+    /// Whether the failed request may succeed if it is sent again, because the error is transient,
+    /// like a broken connection, a timeout or a server error.
+    ///
+    /// Errors caused by the request or the configuration, like an invalid SHA256 or rejected credentials, are not.
+    /// A request which failed because VaaS closed the connection only succeeds on a new connection,
+    /// e.g. the one a [`LazyConnection`](crate::LazyConnection) establishes.
+    pub fn is_retryable(&self) -> bool {
+        if let Error::RetriesExhausted { last, .. } = self {
+            return last.is_retryable();
+        }
+        RetryClass::of(self).is_some()
+            || matches!(
+                self,
+                Error::WebSocket(_)
+                    | Error::Cancelled
+                    | Error::ConnectionClosed
+                    | Error::ConnectTimeout(_)
+                    | Error::ResultChannelError(_)
+            )
+    }
+
+    /// The name of the variant in snake case, used as the `error_kind` label of the metrics.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Error::WebSocket(_) => "web_socket",
+            Error::DeSerialization(_) => "de_serialization",
+            Error::Lock(_) => "lock",
+            Error::InvalidVerdict(_) => "invalid_verdict",
+            Error::Cancelled => "cancelled",
+            Error::InvalidFrame => "invalid_frame",
+            Error::InvalidMessage(_) => "invalid_message",
+            Error::MessageTooLarge(_) => "message_too_large",
+            Error::NoConnection => "no_connection",
+            Error::NoUploadUrl => "no_upload_url",
+            Error::IoError(_) => "io_error",
+            Error::InvalidSha256(_) => "invalid_sha256",
+            Error::InvalidRequestId(_) => "invalid_request_id",
+            Error::InvalidMail(_) => "invalid_mail",
+            Error::FailedRequest(_) => "failed_request",
+            Error::FailedUploadFile(_, _) => "failed_upload_file",
+            Error::MissingAuthToken => "missing_auth_token",
+            Error::Unauthorized(_) => "unauthorized",
+            Error::ResultChannelError(_) => "result_channel_error",
+            Error::ErrorResponse(_) => "error_response",
+            Error::FailedAuthTokenRequest(_, _) => "failed_auth_token_request",
+            Error::AuthServer { .. } => "auth_server",
+            Error::NoSessionIdInAuthResp => "no_session_id_in_auth_resp",
+            Error::ConnectionClosed => "connection_closed",
+            Error::UploadDisabled => "upload_disabled",
+            Error::UploadTimeout(_) => "upload_timeout",
+            Error::ConnectTimeout(_) => "connect_timeout",
+            Error::InvalidConfig(_) => "invalid_config",
+            Error::UntrustedUploadHost(_) => "untrusted_upload_host",
+            Error::BlockingInAsyncContext => "blocking_in_async_context",
+            Error::RetriesExhausted { .. } => "retries_exhausted",
+            Error::Sha256Mismatch { .. } => "sha256_mismatch",
+        }
+    }
+}
+
+/// The phase of establishing a connection, used to identify which phase timed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectPhase {
+    /// Requesting the authentication token from the token endpoint.
+    TokenRequest,
+    /// The TCP, TLS and websocket handshake with the VaaS gateway.
+    WebSocketHandshake,
+    /// Authenticating the session with the token on the websocket connection.
+    Authentication,
+}
+
+impl fmt::Display for ConnectPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectPhase::TokenRequest => write!(f, "token request"),
+            ConnectPhase::WebSocketHandshake => write!(f, "websocket handshake"),
+            ConnectPhase::Authentication => write!(f, "authentication"),
+        }
     }
 }
 
 impl From<WebSocketError> for Error {
     fn from(e: WebSocketError) -> Self {
-        Self::WebSocket(e.to_string())
+        match e {
+            WebSocketError::Capacity(CapacityError::MessageTooLong { size, .. }) => {
+                Self::MessageTooLarge(size)
+            }
+            e => Self::WebSocket(e.to_string()),
+        }
     }
 }
 
@@ -126,3 +271,51 @@ impl From<Elapsed> for Error {
         Self::Cancelled
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transient_errors_are_retryable() {
+        let retryable = [
+            Error::WebSocket("reset".to_string()),
+            Error::Cancelled,
+            Error::ConnectionClosed,
+            Error::ConnectTimeout(ConnectPhase::WebSocketHandshake),
+            Error::FailedRequest("unreachable".to_string()),
+            Error::FailedUploadFile(StatusCode::BAD_GATEWAY, String::new()),
+            Error::UploadTimeout(Duration::from_secs(1)),
+            Error::RetriesExhausted {
+                attempts: 3,
+                last: Box::new(Error::FailedRequest("unreachable".to_string())),
+            },
+        ];
+        for error in retryable {
+            assert!(error.is_retryable(), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn errors_caused_by_the_request_are_not_retryable() {
+        let permanent = [
+            Error::InvalidSha256("abc".to_string()),
+            Error::InvalidRequestId("guid".to_string()),
+            Error::InvalidMail("no header".to_string()),
+            Error::Unauthorized("invalid credentials".to_string()),
+            Error::FailedUploadFile(StatusCode::FORBIDDEN, String::new()),
+            Error::IoError("No such file or directory".to_string()),
+            Error::UploadDisabled,
+            Error::InvalidConfig("CLIENT_ID is not set".to_string()),
+            Error::UntrustedUploadHost("http://evil.com".to_string()),
+            Error::BlockingInAsyncContext,
+            Error::RetriesExhausted {
+                attempts: 2,
+                last: Box::new(Error::UploadDisabled),
+            },
+        ];
+        for error in permanent {
+            assert!(!error.is_retryable(), "{error:?}");
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! # C API
+//!
+//! `extern "C"` functions to request verdicts from C and C++, built on the [blocking API](crate::blocking).
+//! Requires the `ffi` feature. The declarations are in `include/vaas.h`, which is generated with
+//! `cbindgen --config cbindgen.toml --output include/vaas.h`.
+//!
+//! A handle owns a Tokio runtime and a connection, which are shut down with [`vaas_close`]. It can be used from
+//! several threads at once. Functions return [`VAAS_OK`] or one of the `VAAS_ERROR_*` codes, and store the
+//! message of the error for [`vaas_last_error`]. Verdicts are returned as JSON strings, which have to be freed
+//! with [`vaas_free_string`]:
+//!
+//! ```json
+//! {"sha256":"275a…","verdict":"Malicious","detection":"EICAR-Test-File","file_type":null,"mime_type":null}
+//! ```
+
+use crate::auth::authenticators::ClientCredentials;
+use crate::blocking::{Connection, Vaas};
+use crate::error::{Error, VResult};
+use crate::message::Verdict;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+/// The function succeeded.
+pub const VAAS_OK: c_int = 0;
+/// An argument is `NULL` or invalid, e.g. not UTF-8 or not a SHA256.
+pub const VAAS_ERROR_INVALID_ARGUMENT: c_int = 1;
+/// The credentials were rejected.
+pub const VAAS_ERROR_UNAUTHORIZED: c_int = 2;
+/// The request or the connect timed out.
+pub const VAAS_ERROR_TIMEOUT: c_int = 3;
+/// The connection to VaaS failed or was closed.
+pub const VAAS_ERROR_CONNECTION: c_int = 4;
+/// A file could not be read.
+pub const VAAS_ERROR_IO: c_int = 5;
+/// VaaS answered with an error or an invalid response.
+pub const VAAS_ERROR_SERVER: c_int = 6;
+/// Any other error, e.g. a bug in the SDK.
+pub const VAAS_ERROR_INTERNAL: c_int = 7;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A connection to VaaS, created with [`vaas_connect`] and freed with [`vaas_close`].
+pub struct VaasHandle {
+    connection: Connection,
+}
+
+/// Connect to VaaS with client credentials and store the handle of the connection in `out_handle`.
+///
+/// # Safety
+///
+/// `client_id` and `client_secret` must be `NULL` or valid C strings, `out_handle` must be `NULL` or valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vaas_connect(
+    client_id: *const c_char,
+    client_secret: *const c_char,
+    out_handle: *mut *mut VaasHandle,
+) -> c_int {
+    ffi_call(|| {
+        let out_handle = out_ptr(out_handle)?;
+        let client_id = str_arg(client_id, "client_id")?;
+        let client_secret = str_arg(client_secret, "client_secret")?;
+        let authenticator =
+            ClientCredentials::new(client_id.to_string(), client_secret.to_string());
+        let connection = Vaas::new(authenticator)?.connect()?;
+        *out_handle = Box::into_raw(Box::new(VaasHandle { connection }));
+        Ok(())
+    })
+}
+
+/// Request a verdict for the file at `path` and store it as JSON string in `out_verdict_json`.
+///
+/// # Safety
+///
+/// `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed. `path` must be
+/// `NULL` or a valid C string, `out_verdict_json` must be `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vaas_for_file(
+    handle: *const VaasHandle,
+    path: *const c_char,
+    out_verdict_json: *mut *mut c_char,
+) -> c_int {
+    ffi_call(|| {
+        let out_verdict_json = out_ptr(out_verdict_json)?;
+        let handle = handle_arg(handle)?;
+        let path = Path::new(str_arg(path, "path")?);
+        let verdict = handle.connection.for_file(path, None)?;
+        *out_verdict_json = verdict_json(&verdict)?.into_raw();
+        Ok(())
+    })
+}
+
+/// Request a verdict for the SHA256 given as 64 hex digits and store it as JSON string in `out_verdict_json`.
+///
+/// # Safety
+///
+/// `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed. `sha256` must be
+/// `NULL` or a valid C string, `out_verdict_json` must be `NULL` or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn vaas_for_sha256(
+    handle: *const VaasHandle,
+    sha256: *const c_char,
+    out_verdict_json: *mut *mut c_char,
+) -> c_int {
+    ffi_call(|| {
+        let out_verdict_json = out_ptr(out_verdict_json)?;
+        let handle = handle_arg(handle)?;
+        let sha256 = Sha256::try_from(str_arg(sha256, "sha256")?)?;
+        let verdict = handle.connection.for_sha256(&sha256, None)?;
+        *out_verdict_json = verdict_json(&verdict)?.into_raw();
+        Ok(())
+    })
+}
+
+/// The message of the last error on the calling thread, or `NULL` if no function failed on it yet.
+/// The string is owned by the SDK and valid until the next call of a `vaas_*` function on the thread.
+#[no_mangle]
+pub extern "C" fn vaas_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Free a string returned by the SDK. Does nothing for `NULL`.
+///
+/// # Safety
+///
+/// `string` must be `NULL` or a string returned in an `out_verdict_json` argument, which has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vaas_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Close the connection and free the handle. Does nothing for `NULL`.
+///
+/// # Safety
+///
+/// `handle` must be `NULL` or a handle returned by [`vaas_connect`] which has not been closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn vaas_close(handle: *mut VaasHandle) {
+    if !handle.is_null() {
+        // A panic while shutting down the runtime must not unwind into C.
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(handle))));
+    }
+}
+
+/// Run the function, store its error for [`vaas_last_error`] and return the error code.
+fn ffi_call(f: impl FnOnce() -> VResult<()>) -> c_int {
+    let (code, message) = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (VAAS_OK, None),
+        Ok(Err(e)) => (error_code(&e), Some(e.to_string())),
+        Err(_) => (VAAS_ERROR_INTERNAL, Some("The SDK panicked".to_string())),
+    };
+    // Messages with NUL bytes can't be passed to C, which only happens for messages of the server.
+    let message =
+        message.map(|message| CString::new(message.replace('\0', "")).unwrap_or_default());
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+fn error_code(error: &Error) -> c_int {
+    match error {
+        Error::InvalidSha256(_)
+        | Error::InvalidRequestId(_)
+        | Error::InvalidMail(_)
+        | Error::InvalidConfig(_)
+        | Error::BlockingInAsyncContext => VAAS_ERROR_INVALID_ARGUMENT,
+        Error::Unauthorized(_)
+        | Error::FailedAuthTokenRequest(_, _)
+        | Error::AuthServer { .. }
+        | Error::MissingAuthToken
+        | Error::NoSessionIdInAuthResp => VAAS_ERROR_UNAUTHORIZED,
+        Error::Cancelled | Error::UploadTimeout(_) | Error::ConnectTimeout(_) => VAAS_ERROR_TIMEOUT,
+        Error::WebSocket(_)
+        | Error::ConnectionClosed
+        | Error::NoConnection
+        | Error::FailedRequest(_)
+        | Error::FailedUploadFile(_, _) => VAAS_ERROR_CONNECTION,
+        Error::IoError(_) => VAAS_ERROR_IO,
+        Error::ErrorResponse(_)
+        | Error::InvalidMessage(_)
+        | Error::MessageTooLarge(_)
+        | Error::InvalidVerdict(_)
+        | Error::InvalidFrame
+        | Error::DeSerialization(_)
+        | Error::NoUploadUrl
+        | Error::UntrustedUploadHost(_)
+        | Error::Sha256Mismatch { .. } => VAAS_ERROR_SERVER,
+        Error::Lock(_) | Error::ResultChannelError(_) | Error::UploadDisabled => {
+            VAAS_ERROR_INTERNAL
+        }
+        Error::RetriesExhausted { last, .. } => error_code(last),
+    }
+}
+
+fn verdict_json(verdict: &VaasVerdict) -> VResult<CString> {
+    let (name, detection) = match &verdict.verdict {
+        Verdict::Clean => ("Clean", None),
+        Verdict::Malicious { detection } => ("Malicious", Some(detection.as_str())),
+        Verdict::Pup { detection } => ("Pup", Some(detection.as_str())),
+        Verdict::Unknown { .. } => ("Unknown", None),
+        Verdict::Other(name) => (name.as_str(), None),
+    };
+    let json = serde_json::json!({
+        "sha256": verdict.sha256.to_string(),
+        "verdict": name,
+        "detection": detection,
+        "file_type": verdict.file_type,
+        "mime_type": verdict.mime_type,
+    });
+    CString::new(json.to_string()).map_err(|e| Error::DeSerialization(e.to_string()))
+}
+
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> VResult<&'a str> {
+    if arg.is_null() {
+        return Err(Error::InvalidConfig(format!("{name} is NULL")));
+    }
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| Error::InvalidConfig(format!("{name} is not UTF-8")))
+}
+
+unsafe fn handle_arg<'a>(handle: *const VaasHandle) -> VResult<&'a VaasHandle> {
+    handle
+        .as_ref()
+        .ok_or_else(|| Error::InvalidConfig("handle is NULL".to_string()))
+}
+
+unsafe fn out_ptr<'a, T>(out: *mut *mut T) -> VResult<&'a mut *mut T> {
+    let out = out
+        .as_mut()
+        .ok_or_else(|| Error::InvalidConfig("output argument is NULL".to_string()))?;
+    *out = ptr::null_mut();
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vaas_verdict::VerdictSource;
+
+    fn last_error() -> String {
+        let message = vaas_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn null_arguments_are_rejected() {
+        let mut handle = ptr::null_mut();
+        let secret = CString::new("secret").unwrap();
+
+        let code = unsafe { vaas_connect(ptr::null(), secret.as_ptr(), &mut handle) };
+
+        assert_eq!(VAAS_ERROR_INVALID_ARGUMENT, code);
+        assert!(handle.is_null());
+        assert!(
+            last_error().contains("client_id is NULL"),
+            "{}",
+            last_error()
+        );
+    }
+
+    #[test]
+    fn null_handle_is_rejected() {
+        let mut json = ptr::null_mut();
+        let sha256 = CString::new(Sha256::from(&b"content"[..]).to_string()).unwrap();
+
+        let code = unsafe { vaas_for_sha256(ptr::null(), sha256.as_ptr(), &mut json) };
+        assert_eq!(VAAS_ERROR_INVALID_ARGUMENT, code);
+        assert!(last_error().contains("handle is NULL"));
+        assert!(json.is_null());
+    }
+
+    #[test]
+    fn success_clears_the_last_error() {
+        ffi_call(|| Err(Error::Cancelled));
+        assert!(!vaas_last_error().is_null());
+
+        assert_eq!(VAAS_OK, ffi_call(|| Ok(())));
+        assert!(vaas_last_error().is_null());
+    }
+
+    #[test]
+    fn exhausted_retries_have_the_code_of_the_last_error() {
+        let error = Error::RetriesExhausted {
+            attempts: 3,
+            last: Box::new(Error::ConnectionClosed),
+        };
+        assert_eq!(VAAS_ERROR_CONNECTION, error_code(&error));
+    }
+
+    #[test]
+    fn verdict_is_serialized_with_its_detection() {
+        let verdict = VaasVerdict {
+            sha256: Sha256::from(&b"content"[..]),
+            verdict: Verdict::Malicious {
+                detection: "EICAR-Test-File".to_string(),
+            },
+            file_type: None,
+            mime_type: Some("text/plain".to_string()),
+            source: VerdictSource::Vaas,
+            timing: None,
+        };
+
+        let json = verdict_json(&verdict).unwrap();
+        let json: serde_json::Value = serde_json::from_str(json.to_str().unwrap()).unwrap();
+
+        assert_eq!("Malicious", json["verdict"]);
+        assert_eq!("EICAR-Test-File", json["detection"]);
+        assert_eq!(verdict.sha256.to_string(), json["sha256"]);
+        assert_eq!("text/plain", json["mime_type"]);
+        assert!(json["file_type"].is_null());
+    }
+}
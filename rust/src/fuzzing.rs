@@ -0,0 +1,44 @@
+//! Entry points for the fuzz targets in `fuzz/`. Only available with the `fuzzing` feature and not part of the
+//! public API.
+
+use crate::connection::{Connection, DEFAULT_MAX_MESSAGE_SIZE};
+use crate::message::{MessageType, Verdict, VerdictResponse, DEFAULT_ALLOWED_UPLOAD_HOSTS};
+use crate::vaas_verdict::VaasVerdict;
+use crate::websocket::Frame;
+use std::convert::TryFrom;
+
+/// Parse the data as the payload of a text frame received by the reader loop, and convert a verdict response to
+/// the verdict a request returns.
+pub fn parse_frame(data: &[u8]) {
+    let frame = Frame::Text {
+        payload: String::from_utf8_lossy(data).into_owned(),
+        continuation: false,
+        fin: true,
+    };
+    if let Ok(MessageType::VerdictResponse(response)) =
+        Connection::parse_frame(Ok(frame), DEFAULT_MAX_MESSAGE_SIZE)
+    {
+        verdict(response);
+    }
+}
+
+/// Deserialize the data as a verdict response and convert it to the verdict a request returns.
+pub fn verdict_response(json: &str) {
+    if let Ok(response) = VerdictResponse::try_from(&json.to_string()) {
+        verdict(response);
+    }
+}
+
+fn verdict(response: VerdictResponse) {
+    let Ok(verdict) = VaasVerdict::try_from(response) else {
+        return;
+    };
+    if let Verdict::Unknown { upload_url } = verdict.verdict {
+        let allowed_hosts = DEFAULT_ALLOWED_UPLOAD_HOSTS
+            .iter()
+            .map(|host| host.to_string())
+            .collect::<Vec<_>>();
+        let _ = upload_url.ensure_trusted(&allowed_hosts);
+        let _ = upload_url.is_expired();
+    }
+}
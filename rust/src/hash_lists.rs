@@ -0,0 +1,135 @@
+//! Local allowlist and blocklist of hashes, see [`Builder::allowlist`](crate::Builder::allowlist).
+
+use crate::message::Verdict;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::{VaasVerdict, VerdictSource};
+use std::collections::HashSet;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The detection of the [`Verdict::Malicious`] for hashes on the blocklist.
+pub(crate) const BLOCKLIST_DETECTION: &str = "Blocklist";
+
+/// Hashes which are answered locally, without a request to VaaS. The blocklist takes precedence.
+#[derive(Debug, Default)]
+pub(crate) struct HashLists {
+    lists: RwLock<Lists>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Lists {
+    allowed: HashSet<Sha256>,
+    blocked: HashSet<Sha256>,
+}
+
+impl HashLists {
+    /// A copy with the hashes added to the allowlist.
+    pub fn with_allowed(&self, hashes: impl IntoIterator<Item = Sha256>) -> Self {
+        let mut lists = self.read().clone();
+        lists.allowed.extend(hashes);
+        Self::from(lists)
+    }
+
+    /// A copy with the hashes added to the blocklist.
+    pub fn with_blocked(&self, hashes: impl IntoIterator<Item = Sha256>) -> Self {
+        let mut lists = self.read().clone();
+        lists.blocked.extend(hashes);
+        Self::from(lists)
+    }
+
+    pub fn allow(&self, sha256: Sha256) {
+        self.write().allowed.insert(sha256);
+    }
+
+    pub fn block(&self, sha256: Sha256) {
+        self.write().blocked.insert(sha256);
+    }
+
+    /// `Malicious` for blocked hashes, `Clean` for allowed ones, and `None` for all others.
+    pub fn verdict(&self, sha256: &Sha256) -> Option<VaasVerdict> {
+        let lists = self.read();
+        let verdict = if lists.blocked.contains(sha256) {
+            Verdict::Malicious {
+                detection: BLOCKLIST_DETECTION.to_string(),
+            }
+        } else if lists.allowed.contains(sha256) {
+            Verdict::Clean
+        } else {
+            return None;
+        };
+        Some(VaasVerdict {
+            sha256: sha256.clone(),
+            verdict,
+            file_type: None,
+            mime_type: None,
+            source: VerdictSource::LocalList,
+            timing: None,
+        })
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Lists> {
+        self.lists.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Lists> {
+        self.lists.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl From<Lists> for HashLists {
+    fn from(lists: Lists) -> Self {
+        Self {
+            lists: RwLock::new(lists),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_hashes_are_answered_locally() {
+        let allowed = Sha256::from(&b"allowed"[..]);
+        let blocked = Sha256::from(&b"blocked"[..]);
+        let lists = HashLists::default()
+            .with_allowed([allowed.clone()])
+            .with_blocked([blocked.clone()]);
+
+        let allowed = lists.verdict(&allowed).unwrap();
+        let blocked = lists.verdict(&blocked).unwrap();
+
+        assert_eq!(Verdict::Clean, allowed.verdict);
+        assert_eq!(VerdictSource::LocalList, allowed.source);
+        assert_eq!(
+            Verdict::Malicious {
+                detection: BLOCKLIST_DETECTION.to_string()
+            },
+            blocked.verdict
+        );
+        assert!(lists.verdict(&Sha256::from(&b"other"[..])).is_none());
+    }
+
+    #[test]
+    fn blocklist_takes_precedence_over_allowlist() {
+        let sha256 = Sha256::from(&b"both"[..]);
+        let lists = HashLists::default().with_allowed([sha256.clone()]);
+
+        lists.block(sha256.clone());
+
+        assert!(matches!(
+            lists.verdict(&sha256).unwrap().verdict,
+            Verdict::Malicious { .. }
+        ));
+    }
+
+    #[test]
+    fn copy_does_not_change_the_original() {
+        let sha256 = Sha256::from(&b"allowed"[..]);
+        let original = HashLists::default();
+
+        let copy = original.with_allowed([sha256.clone()]);
+
+        assert!(original.verdict(&sha256).is_none());
+        assert!(copy.verdict(&sha256).is_some());
+    }
+}
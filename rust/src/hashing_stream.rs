@@ -0,0 +1,177 @@
+use crate::sha256::Sha256;
+use bytes::Bytes;
+use futures_util::stream::{Stream, TryStream, TryStreamExt};
+use sha2::Digest;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+
+/// Computes the SHA256 of the content of a stream while it is uploaded, so the content is read only once.
+///
+/// The HTTP client stops polling the body once it has sent `Content-Length` bytes,
+/// so the digest is completed as soon as the expected length is read, without waiting for the end of the stream.
+pub(crate) struct HashingStream<S> {
+    inner: Pin<Box<S>>,
+    hasher: sha2::Sha256,
+    remaining: usize,
+    digest: Option<oneshot::Sender<Sha256>>,
+}
+
+/// Receives the SHA256 of a [`HashingStream`] after it has been read.
+pub(crate) struct PendingDigest(oneshot::Receiver<Sha256>);
+
+impl<S> HashingStream<S>
+where
+    S: TryStream,
+    Bytes: From<S::Ok>,
+{
+    pub fn new(inner: S, content_length: usize) -> (Self, PendingDigest) {
+        let (sender, receiver) = oneshot::channel();
+        let stream = Self {
+            inner: Box::pin(inner),
+            hasher: sha2::Sha256::new(),
+            remaining: content_length,
+            digest: Some(sender),
+        };
+        (stream, PendingDigest(receiver))
+    }
+
+    fn complete_digest(&mut self) {
+        if let Some(digest) = self.digest.take() {
+            let hasher = std::mem::take(&mut self.hasher);
+            digest.send(Sha256::from_digest(hasher)).ok();
+        }
+    }
+}
+
+impl<S> Stream for HashingStream<S>
+where
+    S: TryStream,
+    Bytes: From<S::Ok>,
+{
+    type Item = Result<Bytes, S::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        match this.inner.as_mut().try_poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                let chunk = Bytes::from(chunk);
+                if this.digest.is_some() {
+                    this.hasher.update(&chunk);
+                    this.remaining = this.remaining.saturating_sub(chunk.len());
+                    if this.remaining == 0 {
+                        this.complete_digest();
+                    }
+                }
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Ready(None) => {
+                this.complete_digest();
+                Poll::Ready(None)
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S> HashingStream<S>
+where
+    S: TryStream + Send + Sync + 'static,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    Bytes: From<S::Ok>,
+{
+    /// Erase the type of the inner stream, so the upload does not need to be generic over it.
+    pub fn boxed(self) -> BoxedByteStream {
+        Box::pin(self.map_err(Into::into))
+    }
+}
+
+pub(crate) type BoxedByteStream = Pin<
+    Box<dyn Stream<Item = Result<Bytes, Box<dyn std::error::Error + Send + Sync>>> + Send + Sync>,
+>;
+
+impl PendingDigest {
+    /// The SHA256 of the content, or `None` if the stream was not read to the end.
+    pub fn digest(mut self) -> Option<Sha256> {
+        self.0.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, StreamExt};
+    use std::convert::TryFrom;
+    use std::io;
+
+    const HELLO_WORLD_SHA256: &str =
+        "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    async fn hash_chunks(chunks: Vec<&'static [u8]>) -> (Vec<u8>, Option<Sha256>) {
+        let content_length = chunks.iter().map(|chunk| chunk.len()).sum();
+        let chunks = chunks.into_iter().map(Ok::<_, io::Error>);
+        let (stream, digest) = HashingStream::new(stream::iter(chunks), content_length);
+        let content = stream.map(|chunk| chunk.unwrap().to_vec()).concat().await;
+        (content, digest.digest())
+    }
+
+    #[tokio::test]
+    async fn digest_is_independent_of_chunk_boundaries() {
+        let splits: Vec<Vec<&'static [u8]>> = vec![
+            vec![b"hello world"],
+            vec![b"hello", b" world"],
+            vec![b"h", b"ello wor", b"ld"],
+            vec![b"", b"hello world", b""],
+            b"hello world".chunks(1).collect(),
+        ];
+
+        for chunks in splits {
+            let (content, digest) = hash_chunks(chunks).await;
+
+            assert_eq!(b"hello world".to_vec(), content);
+            assert_eq!(Sha256::try_from(HELLO_WORLD_SHA256).ok(), digest);
+        }
+    }
+
+    #[tokio::test]
+    async fn digest_of_empty_stream() {
+        let (_, digest) = hash_chunks(vec![]).await;
+
+        assert_eq!(Some(Sha256::from(&[][..])), digest);
+    }
+
+    #[tokio::test]
+    async fn digest_is_complete_once_content_length_is_read() {
+        let chunks = vec![Ok::<_, io::Error>(&b"hello"[..]), Ok(&b" world"[..])];
+        let (mut stream, digest) = HashingStream::new(stream::iter(chunks), 11);
+
+        stream.next().await.unwrap().unwrap();
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        assert_eq!(Sha256::try_from(HELLO_WORLD_SHA256).ok(), digest.digest());
+    }
+
+    #[tokio::test]
+    async fn digest_of_partially_read_stream_is_none() {
+        let chunks = vec![Ok::<_, io::Error>(&b"hello"[..]), Ok(&b" world"[..])];
+        let (mut stream, digest) = HashingStream::new(stream::iter(chunks), 11);
+
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        assert_eq!(None, digest.digest());
+    }
+
+    #[tokio::test]
+    async fn errors_of_the_inner_stream_are_passed_on() {
+        let chunks = vec![Ok(&b"hello"[..]), Err(io::Error::other("broken pipe"))];
+        let (stream, _) = HashingStream::new(stream::iter(chunks), 11);
+
+        let items: Vec<_> = stream.collect().await;
+
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+    }
+}
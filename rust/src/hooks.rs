@@ -0,0 +1,132 @@
+//! Callbacks registered with [`Builder::on_verdict`](crate::Builder::on_verdict),
+//! [`Builder::on_error`](crate::Builder::on_error) and [`Builder::on_upload`](crate::Builder::on_upload).
+
+use crate::error::Error;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use std::any::Any;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use tracing::warn;
+
+pub(crate) type VerdictHook = Arc<dyn Fn(&VaasVerdict) + Send + Sync>;
+pub(crate) type ErrorHook = Arc<dyn Fn(&Error) + Send + Sync>;
+pub(crate) type UploadHook = Arc<dyn Fn(&Sha256, u64) + Send + Sync>;
+
+/// The registered hooks. They are called on the task of the request, never on the task reading the responses,
+/// so a slow hook only delays its own request. A panic in a hook is caught and logged as a warning.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub on_verdict: Vec<VerdictHook>,
+    pub on_error: Vec<ErrorHook>,
+    pub on_upload: Vec<UploadHook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_verdict", &self.on_verdict.len())
+            .field("on_error", &self.on_error.len())
+            .field("on_upload", &self.on_upload.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    /// Call the verdict or error hooks with the result of a request.
+    pub fn result(&self, result: &Result<VaasVerdict, Error>) {
+        match result {
+            Ok(verdict) => self.verdict(verdict),
+            Err(e) => self.error(e),
+        }
+    }
+
+    pub fn verdict(&self, verdict: &VaasVerdict) {
+        for hook in &self.on_verdict {
+            call("verdict", || hook(verdict));
+        }
+    }
+
+    pub fn error(&self, error: &Error) {
+        for hook in &self.on_error {
+            call("error", || hook(error));
+        }
+    }
+
+    pub fn upload(&self, sha256: &Sha256, bytes: u64) {
+        for hook in &self.on_upload {
+            call("upload", || hook(sha256, bytes));
+        }
+    }
+}
+
+fn call(kind: &str, hook: impl FnOnce()) {
+    if let Err(panic) = catch_unwind(AssertUnwindSafe(hook)) {
+        warn!(
+            hook = kind,
+            panic = panic_message(panic.as_ref()),
+            "A hook panicked"
+        );
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tracing_test::traced_test;
+
+    #[test]
+    #[traced_test]
+    fn panicking_hook_is_logged_and_later_hooks_are_called() {
+        let called = Arc::new(AtomicUsize::new(0));
+        let counter = called.clone();
+        let hooks = Hooks {
+            on_error: vec![
+                Arc::new(|e| panic!("hook failed on {e}")),
+                Arc::new(move |_| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }),
+            ],
+            ..Hooks::default()
+        };
+
+        hooks.error(&Error::Cancelled);
+
+        assert_eq!(1, called.load(Ordering::Relaxed));
+        assert!(logs_contain(
+            "A hook panicked hook=\"error\" panic=\"hook failed on Request was cancelled\""
+        ));
+    }
+
+    #[test]
+    fn result_calls_the_verdict_or_error_hooks() {
+        let verdicts = Arc::new(AtomicUsize::new(0));
+        let errors = Arc::new(AtomicUsize::new(0));
+        let (verdict_counter, error_counter) = (verdicts.clone(), errors.clone());
+        let hooks = Hooks {
+            on_verdict: vec![Arc::new(move |_| {
+                verdict_counter.fetch_add(1, Ordering::Relaxed);
+            })],
+            on_error: vec![Arc::new(move |_| {
+                error_counter.fetch_add(1, Ordering::Relaxed);
+            })],
+            ..Hooks::default()
+        };
+
+        hooks.result(&Err(Error::Cancelled));
+        hooks.result(&Err(Error::UploadDisabled));
+
+        assert_eq!(0, verdicts.load(Ordering::Relaxed));
+        assert_eq!(2, errors.load(Ordering::Relaxed));
+    }
+}
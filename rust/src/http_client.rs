@@ -0,0 +1,130 @@
+use crate::error::VResult;
+use crate::options::Options;
+
+#[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+compile_error!("vaas requires a TLS backend, enable the `native-tls` or `rustls-tls` feature");
+
+/// Identifies the SDK and its version towards the VaaS endpoints.
+pub(crate) const SDK_USER_AGENT: &str = concat!("vaas-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Create the HTTP client used by authenticators which are not configured through the [`Builder`](crate::Builder).
+pub(crate) fn default_http_client() -> reqwest::Client {
+    client_builder()
+        .user_agent(SDK_USER_AGENT)
+        .build()
+        .unwrap_or_default()
+}
+
+/// Create the HTTP client used for file uploads and token requests from the connection options.
+pub(crate) fn http_client(options: &Options) -> VResult<reqwest::Client> {
+    let mut builder = client_builder().user_agent(options.user_agent());
+    if let Some(proxy) = &options.proxy {
+        // An explicit proxy disables the proxy detection from the environment.
+        builder = builder.proxy(proxy.to_reqwest_proxy()?);
+    }
+    for certificate in &options.root_certificates {
+        builder = builder.add_root_certificate(certificate.to_reqwest()?);
+    }
+    if let Some(identity) = &options.identity {
+        builder = builder.identity(identity.to_reqwest()?);
+    }
+    builder = builder.danger_accept_invalid_certs(options.danger_accept_invalid_certs);
+    Ok(builder.build()?)
+}
+
+/// A client builder with the TLS backend selected by the features. rustls is preferred if both are enabled.
+fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    #[cfg(feature = "rustls-tls")]
+    let builder = builder.use_rustls_tls();
+    builder
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::tls::Certificate;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    pub(crate) const TEST_CA_PEM: &[u8] = include_bytes!("../tests/fixtures/test_ca.pem");
+    const LOCALHOST_PEM: &[u8] = include_bytes!("../tests/fixtures/localhost.pem");
+    const LOCALHOST_KEY_PEM: &[u8] = include_bytes!("../tests/fixtures/localhost_key.pem");
+
+    /// Serve one HTTPS request for `localhost` with a certificate issued by the test CA, and return the port.
+    pub(crate) fn serve_one_https_request() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let certificates = CertificateDer::pem_slice_iter(LOCALHOST_PEM)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(LOCALHOST_KEY_PEM).unwrap();
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = rustls::ServerConnection::new(Arc::new(config)).unwrap();
+            // The handshake, which is completed by the first read, fails if the client does not trust the
+            // certificate.
+            let mut stream = rustls::StreamOwned::new(connection, stream);
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok");
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+        });
+        port
+    }
+
+    async fn get_with(options: &Options) -> VResult<String> {
+        let port = serve_one_https_request();
+        let client = http_client(options)?;
+        let response = client
+            .get(format!("https://localhost:{port}/"))
+            .send()
+            .await?;
+        Ok(response.text().await?)
+    }
+
+    fn trusting_test_ca() -> Options {
+        Options {
+            root_certificates: vec![Certificate::from_pem(TEST_CA_PEM).unwrap()],
+            ..Options::default()
+        }
+    }
+
+    #[cfg(all(feature = "native-tls", not(feature = "rustls-tls")))]
+    #[tokio::test]
+    async fn https_request_with_native_tls_completes_the_handshake() {
+        assert_eq!("ok", get_with(&trusting_test_ca()).await.unwrap());
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[tokio::test]
+    async fn https_request_with_rustls_completes_the_handshake() {
+        assert_eq!("ok", get_with(&trusting_test_ca()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn https_request_to_untrusted_server_fails() {
+        let result = get_with(&Options::default()).await;
+
+        assert!(matches!(result, Err(Error::FailedRequest(_))), "{result:?}");
+    }
+}
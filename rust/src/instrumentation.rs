@@ -0,0 +1,74 @@
+//! Spans and events for connections, verdict requests and uploads, which are only emitted with the `tracing` feature.
+//! Warnings about failures are logged regardless of the feature.
+//!
+//! Tokens, secrets and upload URLs are never recorded.
+//!
+//! With the `metrics` feature, counters and histograms are recorded at the same points with the [`metrics`] facade.
+//! Without it, the [`metric!`] macro expands to nothing. The names and labels are listed in the Readme.
+
+/// Whether the spans and events are emitted.
+pub(crate) const ENABLED: bool = cfg!(feature = "tracing");
+
+/// A `debug` event, like [`tracing::debug!`].
+macro_rules! debug_event {
+    ($($arg:tt)+) => {
+        if $crate::instrumentation::ENABLED {
+            tracing::debug!($($arg)+);
+        }
+    };
+}
+
+/// An `info` span, like [`tracing::info_span!`]. Without the `tracing` feature, the span is disabled.
+macro_rules! span {
+    ($($arg:tt)+) => {
+        if $crate::instrumentation::ENABLED {
+            tracing::info_span!($($arg)+)
+        } else {
+            tracing::Span::none()
+        }
+    };
+}
+
+/// The names of the metrics, which are in scope in [`metric!`].
+#[cfg(feature = "metrics")]
+pub(crate) mod names {
+    /// Established connections.
+    pub(crate) const CONNECTIONS: &str = "vaas_connections_total";
+    /// Sent verdict requests.
+    pub(crate) const REQUESTS: &str = "vaas_requests_total";
+    /// Seconds from sending a verdict request until its response or failure.
+    pub(crate) const REQUEST_DURATION: &str = "vaas_request_duration_seconds";
+    /// Received verdicts, labeled with `verdict`, including the `Unknown` verdicts which ask for an upload.
+    pub(crate) const VERDICTS: &str = "vaas_verdicts_total";
+    /// Bytes of finished uploads.
+    pub(crate) const UPLOAD_BYTES: &str = "vaas_upload_bytes_total";
+    /// Seconds an upload took, including retries.
+    pub(crate) const UPLOAD_DURATION: &str = "vaas_upload_duration_seconds";
+    /// Requests answered from the local cache.
+    pub(crate) const CACHE_HITS: &str = "vaas_cache_hits_total";
+    /// Messages from VaaS which could not be parsed.
+    pub(crate) const MALFORMED_MESSAGES: &str = "vaas_malformed_messages_total";
+    /// Failed requests and uploads, labeled with `error_kind`.
+    pub(crate) const ERRORS: &str = "vaas_errors_total";
+}
+
+/// Records a metric with the [`metrics`] facade, e.g. `metric!(counter!(REQUESTS).increment(1))`.
+#[cfg(feature = "metrics")]
+macro_rules! metric {
+    ($($metric:tt)+) => {{
+        use $crate::instrumentation::names::*;
+        ::metrics::$($metric)+
+    }};
+}
+
+/// Without the `metrics` feature, the metric and its arguments are not even evaluated.
+#[cfg(not(feature = "metrics"))]
+macro_rules! metric {
+    ($($metric:tt)+) => {
+        ()
+    };
+}
+
+pub(crate) use debug_event;
+pub(crate) use metric;
+pub(crate) use span;
@@ -0,0 +1,332 @@
+//! The `LazyConnection` module provides a connection which is established on the first request, see [`Vaas::lazy`](crate::Vaas::lazy).
+
+use crate::cancellation::CancellationToken;
+use crate::connection::Connection;
+use crate::error::VResult;
+use crate::pending_upload::{PendingUpload, UploadUrlResponse};
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// Establishes a new connection, abstracted so the lazy connection can be tested without a server.
+#[async_trait]
+pub(crate) trait Connector: Send + Sync {
+    async fn connect(&self) -> VResult<Connection>;
+}
+
+type SharedConnection = Arc<OnceCell<Arc<Connection>>>;
+
+/// Connection to the verdict server which is established on the first request.
+///
+/// All requests share the same connection. Concurrent first requests wait for the same connection
+/// instead of establishing one each. If VaaS closed the connection, the next request establishes a new one.
+/// If connecting fails, the request returns the error and the next request tries again.
+///
+/// The requests behave like the ones of [`Connection`], see there for details.
+pub struct LazyConnection {
+    connector: Box<dyn Connector>,
+    current: Mutex<SharedConnection>,
+}
+
+impl std::fmt::Debug for LazyConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyConnection")
+            .field("connection", &self.current().get())
+            .finish()
+    }
+}
+
+impl LazyConnection {
+    pub(crate) fn new(connector: Box<dyn Connector>) -> Self {
+        Self {
+            connector,
+            current: Mutex::new(SharedConnection::default()),
+        }
+    }
+
+    /// The current connection. It is established if there is none yet or VaaS closed the previous one.
+    pub async fn connection(&self) -> VResult<Arc<Connection>> {
+        let shared = self.current();
+        let connection = self.get_or_connect(&shared).await?;
+        if !connection.is_closed() {
+            return Ok(connection);
+        }
+
+        // Only the first request noticing the closed connection replaces it,
+        // the others wait for the same new connection.
+        {
+            let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+            if Arc::ptr_eq(&current, &shared) {
+                *current = SharedConnection::default();
+            }
+        }
+        self.get_or_connect(&self.current()).await
+    }
+
+    /// A new connection, which is not shared with the requests of this instance.
+    #[cfg(feature = "blocking")]
+    pub(crate) async fn connect_new(&self) -> VResult<Connection> {
+        self.connector.connect().await
+    }
+
+    fn current(&self) -> SharedConnection {
+        self.current
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    async fn get_or_connect(&self, shared: &SharedConnection) -> VResult<Arc<Connection>> {
+        shared
+            .get_or_try_init(|| async { self.connector.connect().await.map(Arc::new) })
+            .await
+            .cloned()
+    }
+
+    /// Request a verdict for a file behind a URL, see [`Connection::for_url`].
+    pub async fn for_url(
+        &self,
+        url: &Url,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        self.connection().await?.for_url(url, ct).await
+    }
+
+    /// Request verdicts for files behind a list of URLs, see [`Connection::for_url_list`].
+    pub async fn for_url_list(
+        &self,
+        url_list: &[Url],
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> Vec<VResult<VaasVerdict>> {
+        match self.connection().await {
+            Ok(connection) => connection.for_url_list(url_list, ct).await,
+            Err(e) => url_list.iter().map(|_| Err(e.clone())).collect(),
+        }
+    }
+
+    /// Request a verdict for a SHA256 file hash, see [`Connection::for_sha256`].
+    pub async fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        self.connection().await?.for_sha256(sha256, ct).await
+    }
+
+    /// Request verdicts for a list of SHA256 file hashes, see [`Connection::for_sha256_list`].
+    pub async fn for_sha256_list(
+        &self,
+        sha256_list: &[Sha256],
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> Vec<VResult<VaasVerdict>> {
+        match self.connection().await {
+            Ok(connection) => connection.for_sha256_list(sha256_list, ct).await,
+            Err(e) => sha256_list.iter().map(|_| Err(e.clone())).collect(),
+        }
+    }
+
+    /// Request a verdict for a stream, see [`Connection::for_stream`].
+    pub async fn for_stream<S>(
+        &self,
+        stream: S,
+        content_length: usize,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict>
+    where
+        S: futures_util::stream::TryStream + Send + Sync + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        self.connection()
+            .await?
+            .for_stream(stream, content_length, ct)
+            .await
+    }
+
+    /// Request a verdict for a file, see [`Connection::for_file`].
+    pub async fn for_file(
+        &self,
+        file: &Path,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        self.connection().await?.for_file(file, ct).await
+    }
+
+    /// Request verdicts for a list of files, see [`Connection::for_file_list`].
+    pub async fn for_file_list(
+        &self,
+        files: &[PathBuf],
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> Vec<VResult<VaasVerdict>> {
+        match self.connection().await {
+            Ok(connection) => connection.for_file_list(files, ct).await,
+            Err(e) => files.iter().map(|_| Err(e.clone())).collect(),
+        }
+    }
+
+    /// Request a verdict for a buffer, see [`Connection::for_buf`].
+    pub async fn for_buf(
+        &self,
+        buf: Vec<u8>,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        self.connection().await?.for_buf(buf, ct).await
+    }
+
+    /// Request a new upload URL for a file, see [`Connection::request_upload_url`].
+    pub async fn request_upload_url(
+        &self,
+        sha256: &Sha256,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<UploadUrlResponse> {
+        self.connection()
+            .await?
+            .request_upload_url(sha256, ct)
+            .await
+    }
+
+    /// Upload a buffer and wait for its verdict, see [`Connection::upload_and_await`].
+    pub async fn upload_and_await(
+        &self,
+        buf: Vec<u8>,
+        upload: PendingUpload,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<VaasVerdict> {
+        self.connection()
+            .await?
+            .upload_and_await(buf, upload, ct)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::mock_websocket::MockServer;
+    use crate::options::Options;
+    use futures::future::join_all;
+    use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Connects to a new mock server each time, after a short delay so concurrent requests overlap.
+    #[derive(Default)]
+    struct MockConnector {
+        servers: Mutex<Vec<MockServer>>,
+        failures: AtomicUsize,
+    }
+
+    impl MockConnector {
+        fn failing(failures: usize) -> Self {
+            Self {
+                failures: AtomicUsize::new(failures),
+                ..Self::default()
+            }
+        }
+
+        fn connects(&self) -> usize {
+            self.servers.lock().unwrap().len()
+        }
+
+        fn server(&self, index: usize) -> MockServer {
+            self.servers.lock().unwrap()[index].clone()
+        }
+    }
+
+    #[async_trait]
+    impl Connector for Arc<MockConnector> {
+        async fn connect(&self) -> VResult<Connection> {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let failures = self.failures.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.failures.store(failures - 1, Ordering::SeqCst);
+                return Err(Error::ConnectionClosed);
+            }
+            let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+            self.servers.lock().unwrap().push(server);
+            let options = Options {
+                keep_alive: false,
+                ..Options::default()
+            };
+            Ok(MockServer::connect(sink, source, options).await)
+        }
+    }
+
+    fn lazy(connector: &Arc<MockConnector>) -> LazyConnection {
+        LazyConnection::new(Box::new(connector.clone()))
+    }
+
+    fn sha256() -> Sha256 {
+        Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn connects_on_first_request_and_reuses_connection() {
+        let connector = Arc::new(MockConnector::default());
+        let connection = lazy(&connector);
+        assert_eq!(0, connector.connects());
+
+        connection.for_sha256(&sha256(), None).await.unwrap();
+        connection.for_sha256(&sha256(), None).await.unwrap();
+
+        assert_eq!(1, connector.connects());
+        assert_eq!(
+            2,
+            connection.connection().await.unwrap().stats().requests_sent
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_first_requests_share_one_connection() {
+        let connector = Arc::new(MockConnector::default());
+        let connection = lazy(&connector);
+        let sha256 = sha256();
+
+        let verdicts = join_all((0..10).map(|_| connection.for_sha256(&sha256, None))).await;
+
+        assert!(verdicts.iter().all(Result::is_ok));
+        assert_eq!(1, connector.connects());
+    }
+
+    #[tokio::test]
+    async fn closed_connection_is_established_again() {
+        let connector = Arc::new(MockConnector::default());
+        let connection = lazy(&connector);
+        let first = connection.connection().await.unwrap();
+        let sha256 = sha256();
+
+        connector.server(0).close();
+        while !first.is_closed() {
+            tokio::task::yield_now().await;
+        }
+        let verdicts = join_all((0..10).map(|_| connection.for_sha256(&sha256, None))).await;
+
+        assert!(verdicts.iter().all(Result::is_ok));
+        assert_eq!(2, connector.connects());
+    }
+
+    #[tokio::test]
+    async fn failed_connect_is_retried_by_next_request() {
+        let connector = Arc::new(MockConnector::failing(1));
+        let connection = lazy(&connector);
+
+        let failed = connection
+            .for_sha256_list(&[sha256(), sha256()], None)
+            .await;
+        let verdict = connection.for_sha256(&sha256(), None).await;
+
+        assert!(matches!(
+            failed.as_slice(),
+            [Err(Error::ConnectionClosed), Err(Error::ConnectionClosed)]
+        ));
+        assert!(verdict.is_ok());
+        assert_eq!(1, connector.connects());
+    }
+}
@@ -97,20 +97,75 @@
 #![warn(missing_docs)]
 
 pub mod auth;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod builder;
 pub mod cancellation;
 pub mod connection;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+pub(crate) mod hash_lists;
+pub(crate) mod hashing_stream;
+pub(crate) mod hooks;
+pub(crate) mod http_client;
+pub(crate) mod instrumentation;
+pub mod lazy_connection;
+#[cfg(feature = "mail")]
+pub mod mail;
 pub mod message;
+#[cfg(any(test, feature = "bench"))]
+pub(crate) mod mock_websocket;
 mod options;
+pub mod pending_upload;
+pub(crate) mod propagation;
+pub mod proxy;
+pub(crate) mod rate_limiter;
+#[cfg(feature = "recording")]
+pub mod recording;
+#[cfg(feature = "rest")]
+pub(crate) mod rest;
+pub mod retry;
+pub mod scanner;
+#[cfg(feature = "tower")]
+pub mod service;
 pub mod sha256;
+pub mod stats;
+pub(crate) mod throttled_stream;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod tls;
+pub mod transport;
 pub mod vaas;
 pub mod vaas_verdict;
+pub(crate) mod verdict_cache;
 pub(crate) mod response_broker;
+pub(crate) mod websocket;
+pub(crate) mod ws_writer;
 
 pub use crate::vaas::Vaas;
 pub use builder::Builder;
 pub use cancellation::CancellationToken;
 pub use connection::Connection;
+pub use lazy_connection::LazyConnection;
+#[cfg(feature = "mail")]
+pub use mail::AttachmentVerdict;
+pub use pending_upload::{PendingUpload, UploadUrlResponse};
+pub use proxy::ProxyConfig;
+#[cfg(feature = "test-utils")]
+pub use scanner::MockScanner;
+pub use scanner::VaasScanner;
+#[cfg(feature = "tower")]
+pub use service::{ScanRequest, VaasService};
 pub use sha256::Sha256;
-pub use vaas_verdict::VaasVerdict;
+pub use transport::Transport;
+pub use vaas_verdict::{ScanTiming, VaasVerdict, VerdictSource};
+
+/// The version of this SDK, e.g. for reports of tools built on top of it.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -0,0 +1,221 @@
+//! Scanning the attachments of e-mails, see [`Connection::for_eml`].
+
+use crate::error::{Error, VResult};
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use crate::{CancellationToken, Connection};
+use futures::future::join_all;
+use mail_parser::{Message, MessageParser, MessagePart, MimeHeaders, PartType};
+
+/// How many levels of attached e-mails (`message/rfc822`) are unpacked. Deeper e-mails are scanned as a whole.
+const MAX_NESTING: usize = 4;
+
+/// The verdict for an attachment of an e-mail, see [`Connection::for_eml`].
+#[derive(Debug)]
+pub struct AttachmentVerdict {
+    /// The file name of the attachment, if the e-mail names it.
+    pub filename: Option<String>,
+    /// The MIME type of the attachment, e.g. `application/pdf`, if the e-mail declares it.
+    pub content_type: Option<String>,
+    /// The SHA256 of the decoded attachment.
+    pub sha256: Sha256,
+    /// The verdict for the attachment, or why it could not be scanned.
+    pub verdict: VResult<VaasVerdict>,
+}
+
+/// A decoded attachment which is yet to be scanned.
+#[derive(Debug)]
+struct Attachment {
+    filename: Option<String>,
+    content_type: Option<String>,
+    sha256: Sha256,
+    contents: Vec<u8>,
+}
+
+impl Connection {
+    /// Request a verdict for each attachment of an e-mail in RFC 5322 format, e.g. the contents of an `.eml` file.
+    ///
+    /// Attachments and inline binary parts, e.g. images, are scanned with [`Connection::for_buf`], the text and
+    /// HTML bodies are not. Attached e-mails are unpacked up to 4 levels deep and their attachments scanned, too,
+    /// deeper ones are scanned as a whole. The verdicts are in the order of the parts in the e-mail. A failed scan
+    /// of one attachment does not fail the others, this only fails if the e-mail cannot be parsed.
+    pub async fn for_eml(
+        &self,
+        raw_message: impl AsRef<[u8]>,
+        ct: impl Into<Option<&CancellationToken>>,
+    ) -> VResult<Vec<AttachmentVerdict>> {
+        let ct = ct.into();
+        let raw_message = raw_message.as_ref().to_vec();
+        let attachments = tokio::task::spawn_blocking(move || attachments(&raw_message))
+            .await
+            .map_err(std::io::Error::other)??;
+
+        let scans = attachments.into_iter().map(|attachment| async move {
+            AttachmentVerdict {
+                filename: attachment.filename,
+                content_type: attachment.content_type,
+                sha256: attachment.sha256,
+                verdict: self.for_buf(attachment.contents, ct).await,
+            }
+        });
+        Ok(join_all(scans).await)
+    }
+}
+
+/// The decoded attachments of the e-mail, including the ones of attached e-mails.
+fn attachments(raw_message: &[u8]) -> VResult<Vec<Attachment>> {
+    let message = MessageParser::default()
+        .parse(raw_message)
+        .filter(|message| !message.parts.is_empty())
+        .ok_or_else(|| Error::InvalidMail("no RFC 5322 header found".to_string()))?;
+    let mut attachments = Vec::new();
+    collect_attachments(&message, 0, &mut attachments);
+    Ok(attachments)
+}
+
+fn collect_attachments(message: &Message, nesting: usize, attachments: &mut Vec<Attachment>) {
+    for (id, part) in message.parts.iter().enumerate() {
+        let is_attachment = message.attachments.iter().any(|&a| a as usize == id);
+        match &part.body {
+            PartType::Multipart(_) => {}
+            PartType::Message(nested) if nesting < MAX_NESTING => {
+                collect_attachments(nested, nesting + 1, attachments)
+            }
+            PartType::Text(_) | PartType::Html(_) if !is_attachment => {}
+            _ => attachments.push(Attachment::from(part)),
+        }
+    }
+}
+
+impl From<&MessagePart<'_>> for Attachment {
+    fn from(part: &MessagePart<'_>) -> Self {
+        let contents = part.contents().to_vec();
+        Self {
+            filename: part.attachment_name().map(str::to_string),
+            content_type: part.content_type().map(|ct| match ct.subtype() {
+                Some(subtype) => format!("{}/{}", ct.ctype(), subtype).to_lowercase(),
+                None => ct.ctype().to_lowercase(),
+            }),
+            sha256: Sha256::from(contents.as_slice()),
+            contents,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Verdict;
+    use crate::mock_websocket::{verdict_response, MockServer};
+    use crate::options::Options;
+    use std::time::Duration;
+
+    const MULTIPART: &[u8] = include_bytes!("../tests/fixtures/mail/multipart.eml");
+    const NESTED: &[u8] = include_bytes!("../tests/fixtures/mail/nested.eml");
+
+    fn names(attachments: &[Attachment]) -> Vec<(Option<&str>, Option<&str>)> {
+        attachments
+            .iter()
+            .map(|a| (a.filename.as_deref(), a.content_type.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn attachments_and_inline_images_are_extracted_but_bodies_are_not() {
+        let attachments = attachments(MULTIPART).unwrap();
+
+        assert_eq!(
+            vec![
+                (Some("logo.png"), Some("image/png")),
+                (Some("invoice.exe"), Some("application/x-msdownload")),
+                (Some("notes.txt"), Some("text/plain")),
+            ],
+            names(&attachments)
+        );
+        assert_eq!(
+            b"MZ\x90\x00 not really an executable",
+            &attachments[1].contents[..]
+        );
+        assert_eq!(
+            Sha256::from(&b"MZ\x90\x00 not really an executable"[..]),
+            attachments[1].sha256
+        );
+    }
+
+    #[test]
+    fn attachments_of_attached_mails_are_extracted() {
+        let attachments = attachments(NESTED).unwrap();
+
+        assert_eq!(
+            vec![(Some("report.pdf"), Some("application/pdf"))],
+            names(&attachments)
+        );
+        assert_eq!(b"nested attachment", &attachments[0].contents[..]);
+    }
+
+    #[test]
+    fn mails_nested_too_deep_are_extracted_as_a_whole() {
+        let mut mail = NESTED.to_vec();
+        for _ in 0..MAX_NESTING {
+            mail = [
+                &b"Subject: Fwd\nMIME-Version: 1.0\nContent-Type: message/rfc822\n\n"[..],
+                &mail,
+            ]
+            .concat();
+        }
+
+        let attachments = attachments(&mail).unwrap();
+
+        assert_eq!(
+            vec![(Some("report.eml"), Some("message/rfc822"))],
+            names(&attachments)
+        );
+        assert!(attachments[0].contents.starts_with(b"From: Alice"));
+    }
+
+    #[test]
+    fn empty_input_is_not_a_mail() {
+        assert!(matches!(attachments(b""), Err(Error::InvalidMail(_))));
+    }
+
+    #[tokio::test]
+    async fn each_attachment_gets_its_own_verdict() {
+        let malicious = Sha256::from(&b"MZ\x90\x00 not really an executable"[..]).to_string();
+        let broken = Sha256::from(&b"plain text attachment"[..]).to_string();
+        let (_server, sink, source) = MockServer::new(Duration::ZERO, move |request| {
+            let verdict = match request["sha256"].as_str() {
+                Some(sha256) if sha256 == malicious => "Malicious",
+                Some(sha256) if sha256 == broken => "",
+                _ => "Clean",
+            };
+            Some(verdict_response(request, verdict))
+        });
+        let options = Options {
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+
+        let verdicts = connection
+            .for_eml(MULTIPART, &CancellationToken::from_seconds(2))
+            .await
+            .unwrap();
+
+        assert_eq!(3, verdicts.len());
+        assert_eq!(Some("logo.png"), verdicts[0].filename.as_deref());
+        assert_eq!(
+            Verdict::Clean,
+            verdicts[0].verdict.as_ref().unwrap().verdict
+        );
+        assert_eq!(Some("invoice.exe"), verdicts[1].filename.as_deref());
+        assert!(matches!(
+            verdicts[1].verdict.as_ref().unwrap().verdict,
+            Verdict::Malicious { .. }
+        ));
+        assert_eq!(
+            verdicts[1].sha256,
+            verdicts[1].verdict.as_ref().unwrap().sha256
+        );
+        assert!(matches!(verdicts[2].verdict, Err(Error::InvalidVerdict(_))));
+    }
+}
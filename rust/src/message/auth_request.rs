@@ -1,15 +1,23 @@
 use crate::error::VResult;
 use crate::message::kind::Kind;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Authenticates the websocket session with a token of the identity provider. The token is redacted in the
+/// `Debug` output.
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct AuthRequest {
+    /// Always [`Kind::AuthRequest`].
     pub kind: Kind,
+    /// The access token.
     pub token: String,
+    /// The session to authenticate again, or `None` for a new session.
     pub session_id: Option<String>,
 }
 
 impl AuthRequest {
+    /// A request to authenticate a new session, or the given one again.
     pub fn new(token: String, session_id: Option<String>) -> Self {
         Self {
             kind: Kind::AuthRequest,
@@ -18,7 +26,29 @@ impl AuthRequest {
         }
     }
 
+    /// The request as sent over the websocket.
     pub fn to_json(&self) -> VResult<String> {
         serde_json::to_string(self).map_err(|e| e.into())
     }
 }
+
+impl fmt::Debug for AuthRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthRequest")
+            .field("kind", &self.kind)
+            .field("token", &"***")
+            .field("session_id", &self.session_id)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_redacts_token() {
+        let request = AuthRequest::new("secret-token".to_string(), None);
+        assert!(!format!("{:?}", request).contains("secret-token"));
+    }
+}
@@ -3,11 +3,17 @@ use crate::message::kind::Kind;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 
+/// The answer to an [`AuthRequest`](crate::message::AuthRequest).
+#[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AuthResponse {
+    /// Always [`Kind::AuthResponse`].
     pub kind: Kind,
+    /// Whether the session is authenticated.
     pub success: bool,
+    /// The id of the authenticated session, to be sent with each verdict request.
     pub session_id: Option<String>,
+    /// The reason if the authentication failed.
     pub text: String,
 }
 impl TryFrom<&String> for AuthResponse {
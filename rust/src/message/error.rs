@@ -2,12 +2,20 @@ use crate::error::Error;
 use crate::message::kind::Kind;
 use serde::{Deserialize, Serialize};
 
+/// An error VaaS reports, e.g. for an invalid request.
+#[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ErrorResponse {
+    /// The type of the error, e.g. `ClientError` or `ServerError`.
     #[serde(alias = "type")]
     pub error_type: String,
+    /// The description of the error.
     pub text: String,
+    /// Always [`Kind::Error`].
     pub kind: Kind,
+    /// The guid of the verdict request which failed, if the error concerns a single request.
+    #[serde(default, alias = "requestId", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl TryFrom<&String> for ErrorResponse {
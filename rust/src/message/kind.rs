@@ -1,12 +1,21 @@
 use serde::{Deserialize, Serialize};
 
+/// The `kind` field of a message, which names its type.
+#[non_exhaustive]
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum Kind {
+    /// An [`AuthRequest`](crate::message::AuthRequest).
     AuthRequest,
+    /// An [`AuthResponse`](crate::message::AuthResponse).
     AuthResponse,
+    /// A [`VerdictRequestFile`](crate::message::VerdictRequestFile).
     VerdictRequest,
+    /// A [`VerdictResponse`](crate::message::VerdictResponse).
     VerdictResponse,
+    /// An [`ErrorResponse`](crate::message::ErrorResponse).
     Error,
+    /// A [`VerdictRequestForUrl`](crate::message::VerdictRequestForUrl).
     VerdictRequestForUrl,
+    /// A [`VerdictRequestForStream`](crate::message::VerdictRequestForStream).
     VerdictRequestForStream,
 }
@@ -1,13 +1,30 @@
 use crate::error::Error;
 use crate::message::error::ErrorResponse;
+use crate::message::kind::Kind;
+use crate::message::AuthResponse;
 use crate::message::VerdictResponse;
 use std::convert::TryFrom;
 
+/// A message received over the websocket, parsed from a text frame with [`MessageType::try_from`] or given by
+/// the type of a control frame.
+#[non_exhaustive]
+#[derive(Debug, Clone)]
 pub enum MessageType {
+    /// A ping frame.
     Ping,
+    /// A pong frame, the answer to a ping.
     Pong,
+    /// A close frame, VaaS ends the session.
     Close,
+    /// The verdict of a request.
     VerdictResponse(VerdictResponse),
+    /// The answer to an authentication request.
+    AuthResponse(AuthResponse),
+    /// An error concerning a single verdict request. Errors without a request id fail all requests.
+    RequestError(String, ErrorResponse),
+    /// A text frame which could not be parsed, with the GUIDs found in it. Only the requests with these GUIDs
+    /// fail, as a corrupted frame says nothing about the others.
+    Malformed(Vec<String>, Error),
 }
 
 impl TryFrom<&String> for MessageType {
@@ -17,16 +34,61 @@ impl TryFrom<&String> for MessageType {
         if let Ok(resp) = VerdictResponse::try_from(json) {
             return Ok(MessageType::VerdictResponse(resp));
         }
+        if let Ok(resp) = AuthResponse::try_from(json) {
+            if matches!(resp.kind, Kind::AuthResponse) {
+                return Ok(MessageType::AuthResponse(resp));
+            }
+        }
         if let Ok(err) = ErrorResponse::try_from(json) {
-            return Err(Error::ErrorResponse(err));
+            return match err.request_id.clone() {
+                Some(guid) => Ok(MessageType::RequestError(guid, err)),
+                None => Err(Error::ErrorResponse(err)),
+            };
+        }
+        Err(Error::InvalidMessage(excerpt(json)))
+    }
+}
+
+impl MessageType {
+    /// Parse a text frame. A frame which is no known message is [`MessageType::Malformed`] instead of an error.
+    pub(crate) fn from_text(json: &String) -> Result<Self, Error> {
+        match Self::try_from(json) {
+            Err(e @ Error::InvalidMessage(_)) => Ok(MessageType::Malformed(guids_in(json), e)),
+            message => message,
         }
-        Err(Error::InvalidMessage(json.to_string()))
     }
 }
 
+/// The strings in the text which are formatted like a GUID, even if the JSON around them is broken.
+fn guids_in(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_ascii_hexdigit() || c == '-'))
+        .filter(|word| word.len() == 36 && uuid::Uuid::try_parse(word).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The longest part of an invalid message kept in [`Error::InvalidMessage`]. The error is cloned for every pending
+/// request, so it must not hold a large payload.
+const MAX_EXCERPT_LEN: usize = 256;
+
+fn excerpt(json: &str) -> String {
+    if json.len() <= MAX_EXCERPT_LEN {
+        return json.to_string();
+    }
+    let end = (0..=MAX_EXCERPT_LEN)
+        .rev()
+        .find(|&end| json.is_char_boundary(end))
+        .unwrap_or(0);
+    format!("{}... ({} bytes)", &json[..end], json.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+    use serde_json::{json, Value};
+
+    const VERDICT_RESPONSE: &str = r#"{"kind":"VerdictResponse","sha256":"275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f","guid":"ed7207a5-d65a-4400-b91c-673ff39cfd8b","verdict":"Unknown","url":"https://upload.vaas.gdatasecurity.de/upload","upload_token":"token","detection":null,"file_type":null,"mime_type":null}"#;
 
     #[test]
     fn deserialize_verdict_response() {
@@ -51,6 +113,22 @@ mod tests {
         assert!(is_correct_type);
     }
 
+    #[test]
+    fn deserialize_auth_response() {
+        let msg = r#"
+        {
+            "kind": "AuthResponse",
+            "success": true,
+            "session_id": "session",
+            "text": ""
+        }
+        "#
+        .to_string();
+        let message_type = MessageType::try_from(&msg).unwrap();
+        let is_correct_type = matches!(message_type, MessageType::AuthResponse(_));
+        assert!(is_correct_type);
+    }
+
     #[test]
     fn deserialize_error_response() {
         let msg = r#"
@@ -67,4 +145,121 @@ mod tests {
 
         assert!(is_correct_type);
     }
+
+    #[test]
+    fn deserialize_error_response_for_a_request() {
+        let msg = r#"
+        {
+            "kind": "Error",
+            "type": "UniqueErrorType",
+            "text": "Something went wrong...",
+            "requestId": "9dae843d-e947-41db-ad39-ec73704529ed"
+        }
+        "#
+        .to_string();
+
+        let message_type = MessageType::try_from(&msg);
+
+        assert!(matches!(
+            message_type,
+            Ok(MessageType::RequestError(guid, _)) if guid == "9dae843d-e947-41db-ad39-ec73704529ed"
+        ));
+    }
+
+    #[test]
+    fn invalid_message_keeps_an_excerpt_of_large_payloads() {
+        let msg = format!(
+            r#"{{"kind":"Unexpected","text":"{}"}}"#,
+            "ä".repeat(1024 * 1024)
+        );
+
+        let message_type = MessageType::try_from(&msg);
+
+        assert!(matches!(
+            message_type,
+            Err(Error::InvalidMessage(excerpt))
+                if excerpt.len() < 2 * MAX_EXCERPT_LEN && excerpt.ends_with(&format!("({} bytes)", msg.len()))
+        ));
+    }
+
+    #[test]
+    fn truncated_message_is_malformed_with_its_guid() {
+        let msg = &VERDICT_RESPONSE[..VERDICT_RESPONSE.find("verdict\"").unwrap()].to_string();
+
+        let message_type = MessageType::from_text(msg);
+
+        assert!(matches!(
+            message_type,
+            Ok(MessageType::Malformed(guids, Error::InvalidMessage(_)))
+                if guids == ["ed7207a5-d65a-4400-b91c-673ff39cfd8b"]
+        ));
+    }
+
+    /// Any JSON value other than a string.
+    fn non_string() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            Just(Value::Null),
+            any::<bool>().prop_map(Value::from),
+            any::<i64>().prop_map(Value::from),
+            any::<f64>().prop_map(Value::from),
+            any::<Vec<u8>>().prop_map(Value::from),
+            ".*".prop_map(|key| json!({ key: 1 })),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn random_bytes_do_not_panic(bytes in any::<Vec<u8>>()) {
+            let msg = String::from_utf8_lossy(&bytes).into_owned();
+
+            let _ = MessageType::try_from(&msg);
+        }
+
+        #[test]
+        fn truncated_json_is_invalid(len in 0..VERDICT_RESPONSE.len()) {
+            let msg = VERDICT_RESPONSE[..len].to_string();
+
+            let message_type = MessageType::try_from(&msg);
+
+            prop_assert!(matches!(message_type, Err(Error::InvalidMessage(_))));
+        }
+
+        #[test]
+        fn wrong_field_types_do_not_panic(
+            field in prop::sample::select(vec!["kind", "sha256", "guid", "verdict", "url", "upload_token", "detection"]),
+            value in non_string(),
+        ) {
+            let mut response: Value = serde_json::from_str(VERDICT_RESPONSE).unwrap();
+            response[field] = value;
+            let msg = response.to_string();
+
+            let message_type = MessageType::try_from(&msg);
+
+            if let Ok(MessageType::VerdictResponse(response)) = message_type {
+                let _ = crate::vaas_verdict::VaasVerdict::try_from(response);
+            }
+        }
+
+        #[test]
+        fn enormous_strings_are_parsed(len in 0..256 * 1024usize) {
+            let mut response: Value = serde_json::from_str(VERDICT_RESPONSE).unwrap();
+            response["detection"] = Value::from("x".repeat(len));
+            let msg = response.to_string();
+
+            let message_type = MessageType::try_from(&msg);
+
+            prop_assert!(matches!(
+                message_type,
+                Ok(MessageType::VerdictResponse(response)) if response.detection.as_ref().map(String::len) == Some(len)
+            ));
+        }
+
+        #[test]
+        fn deeply_nested_objects_do_not_overflow_the_stack(depth in 0..100_000usize) {
+            let nested = format!("{}{}", r#"{"a":["#.repeat(depth), "]}".repeat(depth));
+            let msg = VERDICT_RESPONSE.replace(r#""detection":null"#, &format!(r#""detection":null,"nested":{nested}"#));
+
+            let _ = MessageType::try_from(&msg);
+        }
+    }
 }
@@ -1,11 +1,22 @@
 //! Contains messages (requests and responses) between the client and the server endpoints.
+//!
+//! The [`Connection`](crate::Connection) sends and parses these messages itself. They are public for tools which
+//! work on the protocol level, like debugging proxies or test servers: requests can be created with their
+//! constructors, e.g. [`VerdictRequestFile::new`], and serialized with [`VerdictRequest::to_json`], and any text
+//! frame of VaaS can be parsed with [`MessageType::try_from`].
+//!
+//! The messages mirror the JSON of the VaaS websocket protocol, which VaaS may extend. The structs and enums are
+//! `#[non_exhaustive]`, so new fields, message kinds and message types can be added in minor versions. Secrets like
+//! the upload token of a [`VerdictResponse`] are accessible, but redacted in the `Debug` output.
 
 mod auth_request;
 mod auth_response;
 mod error;
 mod kind;
 mod message_type;
+mod oauth_error_response;
 mod open_id_connect_token_response;
+mod request_id;
 mod upload_url;
 mod verdict;
 mod verdict_request;
@@ -14,15 +25,19 @@ mod verdict_request_for_stream;
 mod verdict_request_for_url;
 mod verdict_response;
 
-pub(super) use auth_request::AuthRequest;
-pub(super) use auth_response::AuthResponse;
-pub(super) use error::ErrorResponse;
-pub(super) use message_type::MessageType;
+pub use auth_request::AuthRequest;
+pub use auth_response::AuthResponse;
+pub use error::ErrorResponse;
+pub use kind::Kind;
+pub use message_type::MessageType;
+pub(super) use oauth_error_response::OAuthErrorResponse;
 pub(super) use open_id_connect_token_response::OpenIdConnectTokenResponse;
-pub(super) use upload_url::UploadUrl;
+pub use request_id::RequestId;
+pub use upload_url::UploadUrl;
+pub(super) use upload_url::{HostPattern, DEFAULT_ALLOWED_UPLOAD_HOSTS};
 pub use verdict::Verdict;
-pub(super) use verdict_request::VerdictRequest;
-pub(super) use verdict_request_for_file::VerdictRequestFile;
-pub(super) use verdict_request_for_stream::VerdictRequestForStream;
-pub(super) use verdict_request_for_url::VerdictRequestForUrl;
-pub(super) use verdict_response::VerdictResponse;
+pub use verdict_request::VerdictRequest;
+pub use verdict_request_for_file::VerdictRequestFile;
+pub use verdict_request_for_stream::VerdictRequestForStream;
+pub use verdict_request_for_url::VerdictRequestForUrl;
+pub use verdict_response::VerdictResponse;
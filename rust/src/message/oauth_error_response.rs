@@ -0,0 +1,16 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+
+/// The error response of an OAuth token endpoint as defined in RFC 6749, section 5.2.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    pub error_description: Option<String>,
+}
+
+impl TryFrom<&String> for OAuthErrorResponse {
+    type Error = Error;
+    fn try_from(value: &String) -> Result<Self, Self::Error> {
+        serde_json::from_str(value).map_err(|e| e.into())
+    }
+}
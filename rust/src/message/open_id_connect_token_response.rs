@@ -4,6 +4,12 @@ use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OpenIdConnectTokenResponse {
     pub access_token: String,
+    /// Lifetime of the access token in seconds.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    /// The granted scope. The provider may omit it if it is the requested scope.
+    #[serde(default)]
+    pub scope: Option<String>,
 }
 
 impl TryFrom<&String> for OpenIdConnectTokenResponse {
@@ -0,0 +1,108 @@
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// The GUID which identifies a verdict request and its responses. Serialized as a lowercase, hyphenated UUID,
+/// e.g. `ed7207a5-d65a-4400-b91c-673ff39cfd8b`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestId(Uuid);
+
+impl RequestId {
+    /// A new random id.
+    pub fn new() -> Self {
+        Self(Uuid::new_v4())
+    }
+
+    /// The UUID of the id.
+    pub fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Uuid> for RequestId {
+    fn from(uuid: Uuid) -> Self {
+        Self(uuid)
+    }
+}
+
+impl TryFrom<&str> for RequestId {
+    type Error = Error;
+
+    /// Parses a UUID in any of the formats of [`Uuid::try_parse`], e.g. hyphenated or without hyphens, in any case.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Uuid::try_parse(value)
+            .map(Self)
+            .map_err(|e| Error::InvalidRequestId(format!("`{value}`: {e}")))
+    }
+}
+
+impl FromStr for RequestId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "ed7207a5-d65a-4400-b91c-673ff39cfd8b";
+
+    #[test]
+    fn serializes_as_hyphenated_string() {
+        let id = RequestId::try_from(GUID).unwrap();
+
+        assert_eq!(format!("\"{GUID}\""), serde_json::to_string(&id).unwrap());
+        assert_eq!(GUID, id.to_string());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let id = RequestId::new();
+
+        let json = serde_json::to_string(&id).unwrap();
+
+        assert_eq!(id, serde_json::from_str(&json).unwrap());
+    }
+
+    #[test]
+    fn parsing_normalizes_to_lowercase_hyphenated() {
+        let id: RequestId = "ED7207A5D65A4400B91C673FF39CFD8B".parse().unwrap();
+
+        assert_eq!(GUID, id.to_string());
+    }
+
+    #[test]
+    fn invalid_id_is_rejected() {
+        assert!(matches!(
+            RequestId::try_from("guid"),
+            Err(Error::InvalidRequestId(_))
+        ));
+        assert!(serde_json::from_str::<RequestId>("\"guid\"").is_err());
+    }
+
+    #[test]
+    fn new_ids_are_random_v4_uuids() {
+        let (a, b) = (RequestId::new(), RequestId::new());
+
+        assert_ne!(a, b);
+        assert_eq!(Some(uuid::Version::Random), a.as_uuid().get_version());
+    }
+}
@@ -1,8 +1,12 @@
+use crate::error::{Error, VResult};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::Formatter;
 use std::ops::Deref;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+/// Pre-signed URL to upload a file to, which VaaS sends for files it does not know.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct UploadUrl(pub String);
 
@@ -14,6 +18,123 @@ impl Deref for UploadUrl {
     }
 }
 
+/// The hosts VaaS uploads files to by default.
+pub(crate) const DEFAULT_ALLOWED_UPLOAD_HOSTS: &[&str] = &["*.vaas.gdatasecurity.de"];
+
+impl UploadUrl {
+    /// When the URL expires, if it is a pre-signed URL which says so, either with the `X-Amz-Date` and
+    /// `X-Amz-Expires` parameters of AWS or an `Expires` parameter with a Unix timestamp.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        let url = Url::parse(&self.0).ok()?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.into_owned())
+        };
+        if let (Some(date), Some(expires)) = (param("X-Amz-Date"), param("X-Amz-Expires")) {
+            let expires = Duration::from_secs(expires.parse().ok()?);
+            return parse_amz_date(&date)?.checked_add(expires);
+        }
+        let expires = Duration::from_secs(param("Expires")?.parse().ok()?);
+        UNIX_EPOCH.checked_add(expires)
+    }
+
+    /// Whether the URL expired according to [`UploadUrl::expires_at`]. A new one can be requested with
+    /// [`Connection::request_upload_url`](crate::Connection::request_upload_url).
+    pub fn is_expired(&self) -> bool {
+        self.expires_at()
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+
+    /// Ensure that the URL uses `https` and its host is one of the allowed hosts, before anything is sent to it.
+    /// A host starting with `*.` allows all of its subdomains, but not the host itself.
+    /// Internationalized domain names are compared in their punycode form.
+    pub(crate) fn ensure_trusted(&self, allowed_hosts: &[String]) -> VResult<()> {
+        let untrusted = || Error::UntrustedUploadHost(self.0.clone());
+        let url = Url::parse(&self.0).map_err(|_| untrusted())?;
+        let host = url.host_str().ok_or_else(untrusted)?;
+        if url.scheme() != "https" {
+            return Err(untrusted());
+        }
+        let trusted = allowed_hosts
+            .iter()
+            .filter_map(|pattern| HostPattern::parse(pattern))
+            .any(|pattern| pattern.matches(host));
+        if trusted {
+            Ok(())
+        } else {
+            Err(untrusted())
+        }
+    }
+}
+
+/// Parse a timestamp like `20240229T235959Z`, which is always in UTC.
+fn parse_amz_date(date: &str) -> Option<SystemTime> {
+    let field = |range: std::ops::Range<usize>| -> Option<u64> {
+        let digits = date.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    if date.len() != 16 || date.get(8..9) != Some("T") || date.get(15..) != Some("Z") {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(9..11)?, field(11..13)?, field(13..15)?);
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let seconds = days_since_epoch(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second;
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+/// The number of days from 1970-01-01 to the given date in the proleptic Gregorian calendar.
+fn days_since_epoch(year: u64, month: u64, day: u64) -> u64 {
+    // Count years from March, so the leap day is the last day of a year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = year * 365 + year / 4 - year / 100 + year / 400 + day_of_year;
+    // The same count for 1970-01-01.
+    days - 719_468
+}
+
+/// An allowed upload host, in punycode and lowercase.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum HostPattern {
+    Host(String),
+    Subdomains(String),
+}
+
+impl HostPattern {
+    /// Returns `None` if the pattern is not a valid host name.
+    pub(crate) fn parse(pattern: &str) -> Option<Self> {
+        let normalize = |host: &str| {
+            let url = Url::parse(&format!("https://{host}/")).ok()?;
+            // A port or a path would have been parsed along with the host.
+            (url.port().is_none() && url.path() == "/")
+                .then(|| url.host_str().map(str::to_string))
+                .flatten()
+        };
+        match pattern.strip_prefix("*.") {
+            Some(domain) => normalize(domain).map(HostPattern::Subdomains),
+            None => normalize(pattern).map(HostPattern::Host),
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Host(allowed) => host == allowed,
+            HostPattern::Subdomains(domain) => host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.')),
+        }
+    }
+}
+
 impl fmt::Display for UploadUrl {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -22,8 +143,22 @@ impl fmt::Display for UploadUrl {
 
 #[cfg(test)]
 mod tests {
-    use crate::message::upload_url::UploadUrl;
+    use crate::error::Error;
+    use crate::message::upload_url::{HostPattern, UploadUrl, DEFAULT_ALLOWED_UPLOAD_HOSTS};
     use std::ops::Deref;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn is_trusted(url: &str, allowed_hosts: &[&str]) -> bool {
+        let allowed_hosts: Vec<String> = allowed_hosts.iter().map(|h| h.to_string()).collect();
+        match UploadUrl(url.to_string()).ensure_trusted(&allowed_hosts) {
+            Ok(()) => true,
+            Err(Error::UntrustedUploadHost(rejected)) => {
+                assert_eq!(url, rejected);
+                false
+            }
+            Err(e) => panic!("unexpected error {e:?}"),
+        }
+    }
 
     #[test]
     fn upload_url_to_string() {
@@ -32,4 +167,139 @@ mod tests {
             UploadUrl("https://test.com".to_string()).deref()
         );
     }
+
+    #[test]
+    fn default_hosts_allow_vaas_uploads() {
+        assert!(is_trusted(
+            "https://upload.production.vaas.gdatasecurity.de/files/abc",
+            DEFAULT_ALLOWED_UPLOAD_HOSTS
+        ));
+        assert!(is_trusted(
+            "https://UPLOAD.staging.vaas.gdatasecurity.de:443/files",
+            DEFAULT_ALLOWED_UPLOAD_HOSTS
+        ));
+        assert!(!is_trusted(
+            "https://upload.example.com/files",
+            DEFAULT_ALLOWED_UPLOAD_HOSTS
+        ));
+    }
+
+    #[test]
+    fn only_https_is_trusted() {
+        assert!(!is_trusted(
+            "http://upload.example.com/",
+            &["upload.example.com"]
+        ));
+        assert!(!is_trusted(
+            "ftp://upload.example.com/",
+            &["upload.example.com"]
+        ));
+        assert!(!is_trusted("not a url", &["upload.example.com"]));
+    }
+
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        let allowed = ["*.gdatasecurity.de"];
+
+        assert!(is_trusted("https://upload.gdatasecurity.de/", &allowed));
+        assert!(is_trusted("https://a.b.gdatasecurity.de/", &allowed));
+        assert!(!is_trusted("https://gdatasecurity.de/", &allowed));
+        assert!(!is_trusted("https://evilgdatasecurity.de/", &allowed));
+        assert!(!is_trusted("https://gdatasecurity.de.evil.com/", &allowed));
+    }
+
+    #[test]
+    fn exact_host_matches_only_itself() {
+        let allowed = ["upload.example.com"];
+
+        assert!(is_trusted("https://upload.example.com/files", &allowed));
+        assert!(!is_trusted(
+            "https://sub.upload.example.com/files",
+            &allowed
+        ));
+        assert!(!is_trusted(
+            "https://user@evil.com/upload.example.com",
+            &allowed
+        ));
+    }
+
+    #[test]
+    fn internationalized_hosts_are_compared_as_punycode() {
+        assert!(is_trusted(
+            "https://upload.bücher.example/",
+            &["*.xn--bcher-kva.example"]
+        ));
+        assert!(is_trusted(
+            "https://upload.xn--bcher-kva.example/",
+            &["*.Bücher.example"]
+        ));
+        assert!(!is_trusted(
+            "https://upload.bucher.example/",
+            &["*.bücher.example"]
+        ));
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert_eq!(None, HostPattern::parse("upload.example.com:8443"));
+        assert_eq!(None, HostPattern::parse("upload.example.com/files"));
+        assert_eq!(None, HostPattern::parse(""));
+        assert_eq!(
+            Some(HostPattern::Subdomains("xn--bcher-kva.example".to_string())),
+            HostPattern::parse("*.bücher.example")
+        );
+    }
+
+    fn expires_at(url: &str) -> Option<u64> {
+        let expires_at = UploadUrl(url.to_string()).expires_at()?;
+        Some(expires_at.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn expiry_of_aws_pre_signed_url() {
+        let url = "https://upload.test/files/abc?X-Amz-Algorithm=AWS4-HMAC-SHA256\
+                   &X-Amz-Date=20240229T235959Z&X-Amz-Expires=900&X-Amz-Signature=123";
+        assert_eq!(Some(1_709_251_199 + 900), expires_at(url));
+
+        let url = "https://upload.test/abc?x-amz-date=19700101T000000Z&x-amz-expires=60";
+        assert_eq!(Some(60), expires_at(url));
+    }
+
+    #[test]
+    fn expiry_of_url_with_unix_timestamp() {
+        assert_eq!(
+            Some(1_700_000_000),
+            expires_at("https://upload.test/abc?Signature=123&Expires=1700000000")
+        );
+    }
+
+    #[test]
+    fn url_without_or_with_invalid_expiry_never_expires() {
+        for url in [
+            "https://upload.test/abc",
+            "https://upload.test/abc?X-Amz-Date=20240229T235959Z",
+            "https://upload.test/abc?X-Amz-Date=20241329T235959Z&X-Amz-Expires=900",
+            "https://upload.test/abc?X-Amz-Date=2024-02-29T23:59Z&X-Amz-Expires=900",
+            "https://upload.test/abc?X-Amz-Date=20240229T235959Z&X-Amz-Expires=-1",
+            "https://upload.test/abc?Expires=soon",
+            "not a url?Expires=0",
+        ] {
+            assert_eq!(None, expires_at(url), "{url}");
+            assert!(!UploadUrl(url.to_string()).is_expired(), "{url}");
+        }
+    }
+
+    #[test]
+    fn is_expired_compares_with_now() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let url = |expires: Duration| {
+            UploadUrl(format!(
+                "https://upload.test/abc?Expires={}",
+                expires.as_secs()
+            ))
+        };
+
+        assert!(url(now - Duration::from_secs(60)).is_expired());
+        assert!(!url(now + Duration::from_secs(60)).is_expired());
+    }
 }
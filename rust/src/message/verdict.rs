@@ -7,8 +7,12 @@ use std::convert::TryFrom;
 use std::fmt;
 
 /// A `Verdict` is a response from the server that indicates whether the
-/// submission is `Clean`, `Malicious`, `Pup` or `Unknown`.
+/// submission is `Clean`, `Malicious`, `Pup` or `Unknown`. Categories VaaS introduces later are
+/// returned as `Other` until they have a variant of their own.
+///
+/// New categories may become variants in minor releases, so matches outside the SDK need a wildcard arm.
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Verdict {
     /// No malicious content found.
     Clean,
@@ -27,12 +31,16 @@ pub enum Verdict {
         /// Pre-signed URL to submit a file for further analysis to get a `Clean` or `Malicious` verdict.
         upload_url: UploadUrl,
     },
+    /// A category this version of the SDK does not know, e.g. one introduced after its release.
+    /// Contains the verdict string as sent by VaaS.
+    Other(String),
 }
 
 impl fmt::Display for Verdict {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Verdict::Unknown { upload_url: _ } => write!(f, "Unknown"),
+            Verdict::Other(verdict) => write!(f, "{verdict}"),
             _ => write!(f, "{self:?}"),
         }
     }
@@ -59,7 +67,78 @@ impl TryFrom<&VerdictResponse> for Verdict {
             "Unknown" => Ok(Verdict::Unknown {
                 upload_url: UploadUrl(value.url.to_owned().ok_or(NoUploadUrl)?),
             }),
-            v => Err(Error::InvalidVerdict(v.to_string())),
+            "" => Err(Error::InvalidVerdict(String::new())),
+            v => Ok(Verdict::Other(v.to_string())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdict(json: &str) -> Result<Verdict, Error> {
+        let response: VerdictResponse = serde_json::from_str(json).unwrap();
+        Verdict::try_from(&response)
+    }
+
+    #[test]
+    fn clean() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Clean"}"#;
+        assert_eq!(Verdict::Clean, verdict(json).unwrap());
+    }
+
+    #[test]
+    fn malicious() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Malicious","detection":"EICAR-Test-File"}"#;
+        assert_eq!(
+            Verdict::Malicious {
+                detection: "EICAR-Test-File".to_string()
+            },
+            verdict(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn pup() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Pup","detection":"Adware"}"#;
+        assert_eq!(
+            Verdict::Pup {
+                detection: "Adware".to_string()
+            },
+            verdict(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Unknown","url":"https://upload.test"}"#;
+        assert_eq!(
+            Verdict::Unknown {
+                upload_url: UploadUrl("https://upload.test".to_string())
+            },
+            verdict(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_without_upload_url_fails() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Unknown"}"#;
+        assert!(matches!(verdict(json), Err(Error::NoUploadUrl)));
+    }
+
+    #[test]
+    fn unexpected_category_is_other() {
+        let json = r#"{"sha256":"","guid":"","verdict":"Suspicious","detection":"Heuristic"}"#;
+        let verdict = verdict(json).unwrap();
+
+        assert_eq!(Verdict::Other("Suspicious".to_string()), verdict);
+        assert_eq!("Suspicious", verdict.to_string());
+    }
+
+    #[test]
+    fn empty_verdict_is_invalid() {
+        let json = r#"{"sha256":"","guid":"","verdict":""}"#;
+        assert!(matches!(verdict(json), Err(Error::InvalidVerdict(_))));
+    }
+}
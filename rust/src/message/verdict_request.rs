@@ -1,7 +1,13 @@
 use crate::error::VResult;
+use crate::instrumentation::span;
+use crate::message::RequestId;
 use serde::Serialize;
+use tracing::field::Empty;
+use tracing::Span;
 
+/// A request for a verdict, answered by a [`VerdictResponse`](crate::message::VerdictResponse) with the same GUID.
 pub trait VerdictRequest {
+    /// The request as sent over the websocket.
     fn to_json(&self) -> VResult<String>
     where
         Self: Serialize,
@@ -9,5 +15,13 @@ pub trait VerdictRequest {
         serde_json::to_string(self).map_err(|e| e.into())
     }
 
-    fn guid(&self) -> &str;
+    /// The GUID which identifies the request and its response.
+    fn guid(&self) -> RequestId;
+
+    /// The span the request is sent and answered in, with what is requested.
+    /// The `trace_id` is recorded if the request carries an OpenTelemetry context.
+    #[doc(hidden)]
+    fn span(&self, parent: &Span) -> Span {
+        span!(parent: parent, "vaas_request", guid = %self.guid(), trace_id = Empty)
+    }
 }
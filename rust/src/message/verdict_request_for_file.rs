@@ -1,39 +1,82 @@
+use crate::instrumentation::span;
 use crate::message::kind::Kind;
+use crate::propagation::trace_context;
 use crate::sha256::Sha256;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::field::Empty;
+use tracing::Span;
 
-use super::VerdictRequest;
+use super::{RequestId, VerdictRequest};
 
+/// Requests the verdict of a file by its SHA256. VaaS asks for an upload if it does not know the file.
+#[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VerdictRequestFile {
+    /// The SHA256 of the file.
     pub sha256: String,
+    /// Always [`Kind::VerdictRequest`].
     pub kind: Kind,
-    pub guid: String,
-    pub session_id: String,
+    /// A new GUID, which identifies the response.
+    pub guid: RequestId,
+    /// The id of the authenticated session.
+    pub session_id: Arc<str>,
+    /// Whether VaaS may answer with a verdict from its hash lookup.
     pub use_hash_lookup: bool,
+    /// Whether VaaS may answer with a cached verdict.
     pub use_cache: bool,
+    /// Key value pairs VaaS passes on to its logs, e.g. the OpenTelemetry context of the caller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict_request_attributes: Option<HashMap<String, String>>,
 }
 
 impl VerdictRequestFile {
+    /// A request with a new GUID, which carries the OpenTelemetry context of the caller, if any.
     pub fn new(
         sha256: &Sha256,
-        session_id: String,
+        session_id: Arc<str>,
         use_cache: bool,
         use_hash_lookup: bool,
     ) -> Self {
         Self {
-            guid: uuid::Uuid::new_v4().to_string(),
+            guid: RequestId::new(),
             sha256: sha256.to_string(),
             kind: Kind::VerdictRequest,
             session_id,
             use_cache,
             use_hash_lookup,
+            verdict_request_attributes: trace_context(),
         }
     }
 }
 
 impl VerdictRequest for VerdictRequestFile {
-    fn guid(&self) -> &str {
-        &self.guid
+    fn guid(&self) -> RequestId {
+        self.guid
+    }
+
+    fn span(&self, parent: &Span) -> Span {
+        span!(parent: parent, "vaas_request", guid = %self.guid, sha256 = %self.sha256, trace_id = Empty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn shared_session_id_is_serialized_as_string() {
+        let sha256 =
+            Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")
+                .unwrap();
+        let session_id: Arc<str> = "session".into();
+
+        let request = VerdictRequestFile::new(&sha256, session_id.clone(), true, false);
+        let json: serde_json::Value = serde_json::from_str(&request.to_json().unwrap()).unwrap();
+
+        assert_eq!("session", json["session_id"]);
+        assert_eq!(2, Arc::strong_count(&session_id));
     }
 }
@@ -1,30 +1,45 @@
-use super::VerdictRequest;
+use super::{RequestId, VerdictRequest};
 use crate::message::kind::Kind;
+use crate::propagation::trace_context;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
 
+/// Requests an upload URL for content whose SHA256 is not known yet. The verdict follows after the upload.
+#[non_exhaustive]
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VerdictRequestForStream {
+    /// Always [`Kind::VerdictRequestForStream`].
     pub kind: Kind,
-    pub guid: String,
-    pub session_id: String,
+    /// A new GUID, which identifies the response.
+    pub guid: RequestId,
+    /// The id of the authenticated session.
+    pub session_id: Arc<str>,
+    /// Whether VaaS may answer with a verdict from its hash lookup.
     pub use_shed: bool,
+    /// Whether VaaS may answer with a cached verdict.
     pub use_cache: bool,
+    /// Key value pairs VaaS passes on to its logs, e.g. the OpenTelemetry context of the caller.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict_request_attributes: Option<HashMap<String, String>>,
 }
 
 impl VerdictRequestForStream {
-    pub fn new(session_id: String, use_cache: bool, use_shed: bool) -> Self {
+    /// A request with a new GUID, which carries the OpenTelemetry context of the caller, if any.
+    pub fn new(session_id: Arc<str>, use_cache: bool, use_shed: bool) -> Self {
         Self {
-            guid: uuid::Uuid::new_v4().to_string(),
+            guid: RequestId::new(),
             kind: Kind::VerdictRequestForStream,
             session_id,
             use_cache,
             use_shed,
+            verdict_request_attributes: trace_context(),
         }
     }
 }
 
 impl VerdictRequest for VerdictRequestForStream {
-    fn guid(&self) -> &str {
-        &self.guid
+    fn guid(&self) -> RequestId {
+        self.guid
     }
 }
@@ -1,16 +1,28 @@
 use crate::error::Error;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+/// The answer to a [`VerdictRequest`](crate::message::VerdictRequest). The upload token is redacted in the `Debug`
+/// output.
+#[non_exhaustive]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct VerdictResponse {
+    /// The SHA256 of the file.
     pub sha256: String,
+    /// The GUID of the request.
     pub guid: String,
+    /// The verdict, e.g. `Clean`, `Malicious`, `Pup` or `Unknown`, see [`Verdict`](crate::message::Verdict).
     pub verdict: String,
+    /// The URL to upload the file to, if the verdict is `Unknown`.
     pub url: Option<String>,
+    /// The token which authorizes the upload, if the verdict is `Unknown`.
     pub upload_token: Option<String>,
+    /// The name of the detected malware, if any.
     pub detection: Option<String>,
+    /// The file type VaaS detected, if it analyzed the file.
     pub file_type: Option<String>,
+    /// The MIME type VaaS detected, if it analyzed the file.
     pub mime_type: Option<String>,
 }
 
@@ -21,6 +33,21 @@ impl TryFrom<&String> for VerdictResponse {
     }
 }
 
+impl fmt::Debug for VerdictResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerdictResponse")
+            .field("sha256", &self.sha256)
+            .field("guid", &self.guid)
+            .field("verdict", &self.verdict)
+            .field("url", &self.url)
+            .field("upload_token", &self.upload_token.as_ref().map(|_| "***"))
+            .field("detection", &self.detection)
+            .field("file_type", &self.file_type)
+            .field("mime_type", &self.mime_type)
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::message::VerdictResponse;
@@ -45,4 +72,15 @@ mod tests {
             verdict_response
         );
     }
+
+    #[test]
+    fn debug_redacts_upload_token() {
+        let json = r#"{"sha256":"275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f","verdict":"Unknown","upload_token":"secret-upload-token","url":"https://upload.test","guid":"ed7207a5-d65a-4400-b91c-673ff39cfd8b"}"#;
+        let verdict_response: VerdictResponse = serde_json::from_str(json).unwrap();
+
+        let debug = format!("{:?}", verdict_response);
+
+        assert!(debug.contains("https://upload.test"));
+        assert!(!debug.contains("secret-upload-token"));
+    }
 }
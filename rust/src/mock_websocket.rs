@@ -0,0 +1,161 @@
+//! An in-process replacement for the websocket to VaaS, so a [`Connection`] can be tested and benchmarked without a server.
+#![cfg_attr(not(test), allow(dead_code))]
+
+use crate::auth::Authenticator;
+use crate::connection::FrameSource;
+use crate::error::VResult;
+use crate::options::Options;
+use crate::websocket::{Frame, WebSocketError};
+use crate::ws_writer::FrameSink;
+use crate::Connection;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+type Handler = dyn Fn(&Value) -> Option<String> + Send + Sync;
+
+/// The server side of the mock websocket.
+///
+/// Each request sent by the connection is passed to the handler, and its answer is sent back after the delay.
+/// Further messages can be pushed to the connection at any time with [`MockServer::send`].
+#[derive(Clone)]
+pub(crate) struct MockServer {
+    frames: UnboundedSender<Frame>,
+    handler: Arc<Handler>,
+    delay: Duration,
+    pending: Arc<AtomicUsize>,
+    max_pending: Arc<AtomicUsize>,
+    pings: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    /// A server which answers each request with the message returned by the handler, if any.
+    pub fn new<F>(delay: Duration, handler: F) -> (Self, MockSink, MockSource)
+    where
+        F: Fn(&Value) -> Option<String> + Send + Sync + 'static,
+    {
+        let (frames, receiver) = unbounded_channel();
+        let server = Self {
+            frames,
+            handler: Arc::new(handler),
+            delay,
+            pending: Arc::new(AtomicUsize::new(0)),
+            max_pending: Arc::new(AtomicUsize::new(0)),
+            pings: Arc::new(AtomicUsize::new(0)),
+        };
+        (server.clone(), MockSink(server), MockSource(receiver))
+    }
+
+    /// A server which answers each verdict request with the given verdict.
+    pub fn answering(delay: Duration, verdict: &'static str) -> (Self, MockSink, MockSource) {
+        Self::new(delay, move |request| {
+            Some(verdict_response(request, verdict))
+        })
+    }
+
+    /// Push a message to the connection.
+    pub fn send(&self, message: String) {
+        self.frames.send(Frame::text(message)).ok();
+    }
+
+    /// Close the websocket like VaaS does when the session ends.
+    pub fn close(&self) {
+        self.frames.send(Frame::Close).ok();
+    }
+
+    /// The highest number of requests which waited for their answer at the same time.
+    pub fn max_pending(&self) -> usize {
+        self.max_pending.load(Ordering::SeqCst)
+    }
+
+    /// The number of keep-alive pings the connection sent.
+    pub fn pings(&self) -> usize {
+        self.pings.load(Ordering::SeqCst)
+    }
+
+    fn receive(&self, text: String) {
+        let request: Value = serde_json::from_str(&text).unwrap();
+        let current = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_pending.fetch_max(current, Ordering::SeqCst);
+        let server = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(server.delay).await;
+            server.pending.fetch_sub(1, Ordering::SeqCst);
+            if let Some(answer) = (server.handler)(&request) {
+                server.send(answer);
+            }
+        });
+    }
+
+    /// Start a connection on top of the mock websocket, or of wrappers around it.
+    pub async fn connect(
+        sink: impl FrameSink,
+        source: impl FrameSource,
+        options: Options,
+    ) -> Connection {
+        Connection::start(
+            sink,
+            source,
+            "session".to_string(),
+            options,
+            reqwest::Client::new(),
+            Arc::new(StaticToken),
+        )
+        .await
+    }
+}
+
+/// A verdict response to the request with the given verdict.
+pub(crate) fn verdict_response(request: &Value, verdict: &str) -> String {
+    serde_json::json!({
+        "kind": "VerdictResponse",
+        "sha256": request["sha256"].as_str().unwrap_or_default(),
+        "guid": request["guid"],
+        "verdict": verdict,
+        "url": null,
+        "upload_token": null,
+    })
+    .to_string()
+}
+
+/// The write half of the mock websocket.
+pub(crate) struct MockSink(MockServer);
+
+#[async_trait]
+impl FrameSink for MockSink {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+        self.0.receive(text);
+        Ok(())
+    }
+
+    async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        self.0.pings.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// The read half of the mock websocket.
+pub(crate) struct MockSource(UnboundedReceiver<Frame>);
+
+#[async_trait]
+impl FrameSource for MockSource {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError> {
+        match self.0.recv().await {
+            Some(frame) => Ok(frame),
+            // The server is never closed while the connection is alive.
+            None => std::future::pending().await,
+        }
+    }
+}
+
+struct StaticToken;
+
+#[async_trait]
+impl Authenticator for StaticToken {
+    async fn get_token(&self) -> VResult<String> {
+        Ok("token".to_string())
+    }
+}
@@ -1,7 +1,116 @@
+use crate::cancellation::CancellationToken;
+use crate::connection::DEFAULT_MAX_MESSAGE_SIZE;
+use crate::hash_lists::HashLists;
+use crate::hooks::Hooks;
+use crate::http_client::SDK_USER_AGENT;
+use crate::message::DEFAULT_ALLOWED_UPLOAD_HOSTS;
+use crate::proxy::ProxyConfig;
+use crate::rate_limiter::RateLimiter;
+use crate::retry::RetryPolicy;
+use crate::sha256::DEFAULT_HASH_BUFFER_SIZE;
+use crate::tls::{Certificate, Identity};
+use crate::transport::Transport;
+use crate::verdict_cache::VerdictCache;
+use reqwest::Version;
+use std::sync::Arc;
+use std::time::Duration;
+
 #[derive(Debug, Clone)]
 pub(crate) struct Options {
-    pub keep_alive_delay_ms: u64,
+    pub keep_alive_delay: Duration,
+    pub keep_alive_jitter: Duration,
     pub keep_alive: bool,
     pub use_cache: bool,
     pub use_hash_lookup: bool,
+    pub upload: bool,
+    pub default_timeout: Duration,
+    pub connect_timeout: Duration,
+    pub auth_timeout: Duration,
+    pub upload_timeout: Option<Duration>,
+    pub upload_http_version: Version,
+    pub upload_content_type: String,
+    pub upload_checksum_header: Option<String>,
+    pub max_concurrent_uploads: Option<usize>,
+    pub upload_rate_limit: Option<u64>,
+    pub compress_uploads: bool,
+    /// `None` skips the check, which is only used to upload to mock servers in tests.
+    pub allowed_upload_hosts: Option<Vec<String>>,
+    pub max_in_flight: Option<usize>,
+    pub max_message_size: usize,
+    /// Shared by all connections of a `Vaas` instance, so the limit applies to all of them together.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    pub retry_policy: RetryPolicy,
+    pub hash_buffer_size: usize,
+    pub cancellable_hashing: bool,
+    pub verdict_timeout: Option<Duration>,
+    /// Shared by all connections of a `Vaas` instance.
+    pub local_cache: Option<Arc<VerdictCache>>,
+    /// Shared by all connections of a `Vaas` instance, so hashes added at runtime apply to all of them.
+    pub hash_lists: Arc<HashLists>,
+    pub proxy: Option<ProxyConfig>,
+    pub root_certificates: Vec<Certificate>,
+    pub identity: Option<Identity>,
+    pub danger_accept_invalid_certs: bool,
+    pub app_info: Option<(String, String)>,
+    pub hooks: Hooks,
+    pub transport: Transport,
+    #[cfg(feature = "recording")]
+    pub record_session: Option<std::path::PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            keep_alive_delay: Duration::from_secs(10),
+            keep_alive_jitter: Duration::ZERO,
+            keep_alive: true,
+            use_cache: true,
+            use_hash_lookup: true,
+            upload: true,
+            default_timeout: CancellationToken::default().duration,
+            connect_timeout: Duration::from_secs(30),
+            auth_timeout: Duration::from_secs(30),
+            upload_timeout: None,
+            upload_http_version: Version::HTTP_11,
+            upload_content_type: "application/octet-stream".to_string(),
+            upload_checksum_header: None,
+            max_concurrent_uploads: None,
+            upload_rate_limit: None,
+            compress_uploads: false,
+            allowed_upload_hosts: Some(
+                DEFAULT_ALLOWED_UPLOAD_HOSTS
+                    .iter()
+                    .map(|host| host.to_string())
+                    .collect(),
+            ),
+            max_in_flight: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            rate_limiter: None,
+            retry_policy: RetryPolicy::default(),
+            hash_buffer_size: DEFAULT_HASH_BUFFER_SIZE,
+            cancellable_hashing: false,
+            verdict_timeout: None,
+            local_cache: None,
+            hash_lists: Arc::default(),
+            proxy: None,
+            root_certificates: Vec::new(),
+            identity: None,
+            danger_accept_invalid_certs: false,
+            app_info: None,
+            hooks: Hooks::default(),
+            transport: Transport::default(),
+            #[cfg(feature = "recording")]
+            record_session: None,
+        }
+    }
+}
+
+impl Options {
+    /// The user agent sent with the websocket connection, the file uploads and the token requests.
+    pub fn user_agent(&self) -> String {
+        match &self.app_info {
+            Some((name, version)) => format!("{SDK_USER_AGENT} {name}/{version}"),
+            None => SDK_USER_AGENT.to_string(),
+        }
+    }
 }
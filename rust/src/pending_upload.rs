@@ -0,0 +1,128 @@
+//! # Pending Upload
+//!
+//! A `PendingUpload` is everything needed to upload a file which VaaS does not know yet, so the upload can be
+//! deferred, e.g. to a batch window, and completed later with [`Connection::upload_and_await`](crate::Connection::upload_and_await).
+
+use crate::error::{Error, VResult};
+use crate::message::{RequestId, UploadUrl, Verdict, VerdictResponse};
+use crate::vaas_verdict::VaasVerdict;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// An upload VaaS asked for. The URL and token expire after a while, see [`UploadUrl::expires_at`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct PendingUpload {
+    /// The id of the verdict request, which VaaS sends the verdict for after the upload.
+    pub guid: RequestId,
+    /// Pre-signed URL to upload the file to.
+    pub upload_url: UploadUrl,
+    /// Authorizes the upload.
+    pub upload_token: String,
+}
+
+impl PendingUpload {
+    pub(crate) fn new(
+        guid: RequestId,
+        upload_url: UploadUrl,
+        response: VerdictResponse,
+    ) -> VResult<Self> {
+        Ok(Self {
+            guid,
+            upload_url,
+            upload_token: response.upload_token.ok_or(Error::MissingAuthToken)?,
+        })
+    }
+}
+
+impl fmt::Debug for PendingUpload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingUpload")
+            .field("guid", &self.guid)
+            .field("upload_url", &self.upload_url)
+            .field("upload_token", &"***")
+            .finish()
+    }
+}
+
+/// The answer to [`Connection::request_upload_url`](crate::Connection::request_upload_url).
+#[derive(Debug, Clone)]
+pub enum UploadUrlResponse {
+    /// VaaS needs the content of the file to determine its verdict.
+    Upload(PendingUpload),
+    /// VaaS knows the file by now, so it does not have to be uploaded anymore.
+    Verdict(VaasVerdict),
+}
+
+impl UploadUrlResponse {
+    pub(crate) fn new(guid: RequestId, response: VerdictResponse) -> VResult<Self> {
+        match Verdict::try_from(&response)? {
+            Verdict::Unknown { upload_url } => Ok(UploadUrlResponse::Upload(PendingUpload::new(
+                guid, upload_url, response,
+            )?)),
+            _ => Ok(UploadUrlResponse::Verdict(VaasVerdict::try_from(response)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "ed7207a5-d65a-4400-b91c-673ff39cfd8b";
+
+    fn guid() -> RequestId {
+        RequestId::try_from(GUID).unwrap()
+    }
+
+    fn response(verdict: &str, url: Option<&str>, upload_token: Option<&str>) -> VerdictResponse {
+        VerdictResponse {
+            sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+            guid: GUID.to_string(),
+            verdict: verdict.to_string(),
+            url: url.map(str::to_string),
+            upload_token: upload_token.map(str::to_string),
+            detection: None,
+            file_type: None,
+            mime_type: None,
+        }
+    }
+
+    #[test]
+    fn unknown_verdict_asks_for_upload() {
+        let response = response("Unknown", Some("https://upload.test/abc"), Some("token"));
+
+        let result = UploadUrlResponse::new(guid(), response).unwrap();
+
+        let UploadUrlResponse::Upload(upload) = &result else {
+            panic!("expected an upload, got {result:?}");
+        };
+        assert_eq!(guid(), upload.guid);
+        assert_eq!(
+            UploadUrl("https://upload.test/abc".to_string()),
+            upload.upload_url
+        );
+        assert_eq!("token", upload.upload_token);
+        assert!(!format!("{upload:?}").contains("\"token\""));
+    }
+
+    #[test]
+    fn unknown_verdict_without_token_fails() {
+        let response = response("Unknown", Some("https://upload.test/abc"), None);
+
+        let result = UploadUrlResponse::new(guid(), response);
+
+        assert!(matches!(result, Err(Error::MissingAuthToken)));
+    }
+
+    #[test]
+    fn known_verdict_needs_no_upload() {
+        let response = response("Clean", None, None);
+
+        let result = UploadUrlResponse::new(guid(), response).unwrap();
+
+        assert!(
+            matches!(&result, UploadUrlResponse::Verdict(verdict) if verdict.verdict == Verdict::Clean),
+            "{result:?}"
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! Propagation of the OpenTelemetry context of the caller to VaaS, which requires the `opentelemetry` feature.
+//! Without it, nothing is propagated.
+//!
+//! The context is injected with the globally installed propagator, see
+//! [`opentelemetry::global::set_text_map_propagator`], into the headers of uploads and into the
+//! `verdict_request_attributes` of verdict requests. The trace id is recorded on the `vaas_request` span.
+
+use std::collections::HashMap;
+
+/// The fields of the current OpenTelemetry context as the global propagator injects them, e.g. `traceparent`.
+/// `None` without an active span or propagator.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn trace_context() -> Option<HashMap<String, String>> {
+    use opentelemetry::trace::TraceContextExt;
+
+    let context = opentelemetry::Context::current();
+    if !context.span().span_context().is_valid() {
+        return None;
+    }
+    let mut fields = HashMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut fields)
+    });
+    (!fields.is_empty()).then_some(fields)
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+pub(crate) fn trace_context() -> Option<HashMap<String, String>> {
+    None
+}
+
+/// The trace id of the current OpenTelemetry context, if it has an active span.
+#[cfg(feature = "opentelemetry")]
+pub(crate) fn trace_id() -> Option<String> {
+    use opentelemetry::trace::TraceContextExt;
+
+    let context = opentelemetry::Context::current();
+    let span_context = context.span().span_context().clone();
+    span_context
+        .is_valid()
+        .then(|| span_context.trace_id().to_string())
+}
+
+#[cfg(not(feature = "opentelemetry"))]
+pub(crate) fn trace_id() -> Option<String> {
+    None
+}
@@ -0,0 +1,85 @@
+//! # Proxy
+//!
+//! Allows to route the HTTP traffic of the SDK (file uploads and token requests) through a proxy.
+//! If no proxy is configured, the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are honored.
+
+use crate::error::VResult;
+use reqwest::Url;
+use std::fmt;
+
+/// Configuration of an HTTP proxy with optional basic authentication.
+/// ```rust
+/// use vaas::ProxyConfig;
+/// use reqwest::Url;
+///
+/// let proxy = ProxyConfig::new(Url::parse("http://proxy.local:3128").unwrap())
+///     .with_basic_auth("user".to_string(), "password".to_string());
+/// ```
+#[derive(Clone)]
+pub struct ProxyConfig {
+    url: Url,
+    credentials: Option<(String, String)>,
+}
+
+impl ProxyConfig {
+    /// Create a new proxy configuration for the given proxy URL.
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            credentials: None,
+        }
+    }
+
+    /// Authenticate against the proxy with the given user name and password.
+    pub fn with_basic_auth(mut self, user_name: String, password: String) -> Self {
+        self.credentials = Some((user_name, password));
+        self
+    }
+
+    pub(crate) fn to_reqwest_proxy(&self) -> VResult<reqwest::Proxy> {
+        let proxy = reqwest::Proxy::all(self.url.clone())?;
+        Ok(match &self.credentials {
+            Some((user_name, password)) => proxy.basic_auth(user_name, password),
+            None => proxy,
+        })
+    }
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field(
+                "credentials",
+                &self
+                    .credentials
+                    .as_ref()
+                    .map(|(user_name, _)| (user_name, "***")),
+            )
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_with_basic_auth_converts_to_reqwest_proxy() {
+        let proxy = ProxyConfig::new(Url::parse("http://proxy.local:3128").unwrap())
+            .with_basic_auth("user".to_string(), "password".to_string());
+
+        assert!(proxy.to_reqwest_proxy().is_ok());
+    }
+
+    #[test]
+    fn debug_redacts_password() {
+        let proxy = ProxyConfig::new(Url::parse("http://proxy.local:3128").unwrap())
+            .with_basic_auth("user".to_string(), "top-secret".to_string());
+
+        let debug = format!("{:?}", proxy);
+
+        assert!(debug.contains("proxy.local"));
+        assert!(!debug.contains("top-secret"));
+    }
+}
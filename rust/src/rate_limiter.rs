@@ -0,0 +1,119 @@
+//! A client-side limit of the verdict requests per second, see [`Builder::rate_limit`](crate::Builder::rate_limit).
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A token bucket which holds up to `burst` requests and is refilled with `requests_per_second`.
+///
+/// Waiting requests do not reserve a token, so a request which is cancelled while waiting does not use one up.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    requests_per_second: u32,
+    burst: u32,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    /// A limiter with a full bucket, so the first `burst` requests are not delayed.
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        Self {
+            requests_per_second,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(burst),
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn requests_per_second(&self) -> u32 {
+        self.requests_per_second
+    }
+
+    pub fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Wait until a token is available and take it. Returns the time waited.
+    pub async fn acquire(&self) -> Duration {
+        let started = Instant::now();
+        while let Err(wait) = self.try_acquire() {
+            tokio::time::sleep(wait).await;
+        }
+        started.elapsed()
+    }
+
+    /// Take a token if one is available, or else return the time until the next one is.
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let rate = f64::from(self.requests_per_second);
+        let mut bucket = self.lock();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(f64::from(self.burst));
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn requests_beyond_burst_are_paced() {
+        let limiter = RateLimiter::new(10, 3);
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(Duration::ZERO, limiter.acquire().await);
+        }
+        let waited = limiter.acquire().await;
+        limiter.acquire().await;
+
+        assert!(waited >= Duration::from_millis(100));
+        assert!(waited < Duration::from_millis(110));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(started.elapsed() < Duration::from_millis(220));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bucket_refills_up_to_burst_while_idle() {
+        let limiter = RateLimiter::new(10, 2);
+        limiter.acquire().await;
+        limiter.acquire().await;
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+
+        assert_eq!(Duration::ZERO, limiter.acquire().await);
+        assert_eq!(Duration::ZERO, limiter.acquire().await);
+        assert!(limiter.acquire().await > Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cancelled_wait_does_not_use_up_a_token() {
+        let limiter = RateLimiter::new(1, 1);
+        limiter.acquire().await;
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(500), limiter.acquire()).await;
+        let waited = limiter.acquire().await;
+
+        assert!(cancelled.is_err());
+        assert!(waited >= Duration::from_millis(500));
+        assert!(waited < Duration::from_millis(510));
+    }
+}
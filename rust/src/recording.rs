@@ -0,0 +1,567 @@
+//! # Session recording
+//!
+//! Websocket sessions can be recorded with [`Builder::record_session`](crate::Builder::record_session) and
+//! replayed in tests with [`ReplayTransport`], to reproduce the exact message sequence of a protocol issue.
+//! Requires the `recording` feature.
+//!
+//! A recording is an NDJSON file with one line per frame, e.g.
+//!
+//! ```json
+//! {"elapsed_ms":12,"direction":"outbound","frame":"text","payload":"{\"guid\":\"...\",\"kind\":\"VerdictRequest\",...}"}
+//! ```
+//!
+//! `elapsed_ms` is the time since the websocket was opened, `direction` is `outbound` or `inbound` and `frame`
+//! one of `text`, `binary`, `ping`, `pong`, `close` and `error`. Finished uploads are recorded as `upload` frames
+//! with the SHA256 of the file as payload, as they are not sent over the websocket.
+//!
+//! Secrets are redacted before they are written: the token of the authentication request and upload tokens are
+//! replaced with `***`, and the query of upload URLs, which holds their signature, is removed.
+
+use crate::connection::FrameSource;
+use crate::error::{Error, VResult};
+use crate::hooks::UploadHook;
+use crate::sha256::Sha256;
+use crate::websocket::{Frame, WebSocketError};
+use crate::ws_writer::FrameSink;
+use async_trait::async_trait;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// What secrets are replaced with in recordings. Replayed sessions are authenticated with it as token.
+pub(crate) const REDACTED: &str = "***";
+
+/// One line of a recorded session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordedFrame {
+    elapsed_ms: u64,
+    direction: Direction,
+    frame: FrameKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FrameKind {
+    Text,
+    Binary,
+    Ping,
+    Pong,
+    Close,
+    Error,
+    Upload,
+}
+
+/// Writes the frames of a session to its recording. Write errors are logged once and do not affect the session.
+#[derive(Debug, Clone)]
+pub(crate) struct SessionRecorder {
+    inner: Arc<Mutex<RecorderState>>,
+}
+
+#[derive(Debug)]
+struct RecorderState {
+    file: LineWriter<File>,
+    started: Instant,
+    /// Whether the last inbound frame was an error, so a reader failing repeatedly is recorded once.
+    failing: bool,
+    write_failed: bool,
+}
+
+impl SessionRecorder {
+    /// Create the recording, replacing an existing file.
+    pub(crate) fn create(path: &Path) -> VResult<Self> {
+        let file = File::create(path).map_err(|e| {
+            Error::IoError(format!("Can't create the session recording {path:?}: {e}"))
+        })?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(RecorderState {
+                file: LineWriter::new(file),
+                started: Instant::now(),
+                failing: false,
+                write_failed: false,
+            })),
+        })
+    }
+
+    /// Record the writes to the sink and the frames received from the source.
+    pub(crate) fn wrap<W: FrameSink, R: FrameSource>(
+        &self,
+        sink: W,
+        source: R,
+    ) -> (RecordingSink<W>, RecordingSource<R>) {
+        let sink = RecordingSink {
+            inner: sink,
+            recorder: self.clone(),
+        };
+        let source = RecordingSource {
+            inner: source,
+            recorder: self.clone(),
+        };
+        (sink, source)
+    }
+
+    /// A hook recording finished uploads, see [`Builder::on_upload`](crate::Builder::on_upload).
+    pub(crate) fn upload_hook(&self) -> UploadHook {
+        let recorder = self.clone();
+        Arc::new(move |sha256: &Sha256, _| {
+            recorder.record(
+                Direction::Outbound,
+                FrameKind::Upload,
+                Some(sha256.to_string()),
+            );
+        })
+    }
+
+    fn record(&self, direction: Direction, frame: FrameKind, payload: Option<String>) {
+        let mut state = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if direction == Direction::Inbound {
+            let failing = frame == FrameKind::Error;
+            if failing && state.failing {
+                return;
+            }
+            state.failing = failing;
+        }
+        let line = RecordedFrame {
+            elapsed_ms: state.started.elapsed().as_millis() as u64,
+            direction,
+            frame,
+            payload: payload.map(|payload| redact(&payload)),
+        };
+        let written = serde_json::to_string(&line)
+            .map_err(std::io::Error::other)
+            .and_then(|line| writeln!(state.file, "{line}"));
+        if let Err(e) = written {
+            if !state.write_failed {
+                warn!(error = %e, "Writing the session recording failed");
+            }
+            state.write_failed = true;
+        }
+    }
+}
+
+/// Replace the secrets of a message, see the [module documentation](self).
+fn redact(payload: &str) -> String {
+    let Ok(mut message) = serde_json::from_str::<Value>(payload) else {
+        return payload.to_string();
+    };
+    let Some(fields) = message.as_object_mut() else {
+        return payload.to_string();
+    };
+    let kind = fields
+        .get("kind")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    if kind == "AuthRequest" && fields.contains_key("token") {
+        fields.insert("token".to_string(), REDACTED.into());
+    }
+    if kind == "VerdictResponse" {
+        if let Some(Value::String(url)) = fields.get_mut("url") {
+            if let Ok(mut parsed) = Url::parse(url) {
+                parsed.set_query(None);
+                *url = parsed.to_string();
+            }
+        }
+    }
+    if fields.get("upload_token").is_some_and(Value::is_string) {
+        fields.insert("upload_token".to_string(), REDACTED.into());
+    }
+    message.to_string()
+}
+
+/// A [`FrameSink`] recording the frames it writes.
+pub(crate) struct RecordingSink<W> {
+    inner: W,
+    recorder: SessionRecorder,
+}
+
+#[async_trait]
+impl<W: FrameSink> FrameSink for RecordingSink<W> {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+        self.recorder
+            .record(Direction::Outbound, FrameKind::Text, Some(text.clone()));
+        self.inner.send_text(text).await
+    }
+
+    async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        self.recorder
+            .record(Direction::Outbound, FrameKind::Ping, None);
+        self.inner.send_ping().await
+    }
+}
+
+/// A [`FrameSource`] recording the frames it receives.
+pub(crate) struct RecordingSource<R> {
+    inner: R,
+    recorder: SessionRecorder,
+}
+
+#[async_trait]
+impl<R: FrameSource> FrameSource for RecordingSource<R> {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError> {
+        let frame = self.inner.receive().await;
+        let (kind, payload) = match &frame {
+            Ok(Frame::Text { payload, .. }) => (FrameKind::Text, Some(payload.clone())),
+            Ok(Frame::Binary) => (FrameKind::Binary, None),
+            Ok(Frame::Ping) => (FrameKind::Ping, None),
+            Ok(Frame::Pong) => (FrameKind::Pong, None),
+            Ok(Frame::Close) => (FrameKind::Close, None),
+            Err(e) => (FrameKind::Error, Some(e.to_string())),
+        };
+        self.recorder.record(Direction::Inbound, kind, payload);
+        frame
+    }
+}
+
+/// Replays a recorded session to a [`Connection`](crate::Connection), see
+/// [`Vaas::connect_replay`](crate::Vaas::connect_replay).
+///
+/// An inbound frame of the recording is received once the connection sent all outbound frames recorded before it.
+/// Recorded verdict requests are matched with the sent ones by their kind and SHA256 or URL, uploads by their
+/// SHA256, so concurrent requests may be sent in a different order than recorded. The guids of the replayed
+/// responses are replaced with the ones of the matched requests. Requests without a match in the recording are
+/// never answered. After the last inbound frame, the connection stays open.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    frames: Vec<RecordedFrame>,
+    upload_url: Option<Url>,
+}
+
+impl ReplayTransport {
+    /// Read a recording written with [`Builder::record_session`](crate::Builder::record_session).
+    pub fn from_file(path: impl AsRef<Path>) -> VResult<Self> {
+        let path = path.as_ref();
+        let recording = std::fs::read_to_string(path).map_err(|e| {
+            Error::IoError(format!("Can't read the session recording {path:?}: {e}"))
+        })?;
+        Self::from_ndjson(&recording)
+    }
+
+    /// Parse a recording, see the [module documentation](self) for its format.
+    pub fn from_ndjson(recording: &str) -> VResult<Self> {
+        let frames = recording
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str::<RecordedFrame>(line).map_err(|e| {
+                    Error::DeSerialization(format!(
+                        "Line {} of the session recording: {e}",
+                        index + 1
+                    ))
+                })
+            })
+            // Keep-alive pings depend on timing and do not affect the session.
+            .filter(|frame| {
+                !matches!(frame, Ok(f) if f.direction == Direction::Outbound && f.frame == FrameKind::Ping)
+            })
+            .collect::<VResult<Vec<_>>>()?;
+        Ok(Self {
+            frames,
+            upload_url: None,
+        })
+    }
+
+    /// Replace the upload URLs of replayed verdict responses with the given one, e.g. of a mock HTTP server.
+    /// Uploads to it are not checked against [`Builder::allowed_upload_hosts`](crate::Builder::allowed_upload_hosts).
+    pub fn upload_to(self, url: Url) -> Self {
+        Self {
+            upload_url: Some(url),
+            ..self
+        }
+    }
+
+    /// Whether the upload URL is replaced, so uploads must not be checked against the allowed hosts.
+    pub(crate) fn replaces_upload_url(&self) -> bool {
+        self.upload_url.is_some()
+    }
+
+    /// The sink and source of the replayed session, and a hook reporting finished uploads to it.
+    pub(crate) fn into_session(self) -> (ReplaySink, ReplaySource, UploadHook) {
+        let matched = vec![false; self.frames.len()];
+        let replay = Arc::new(Replay {
+            state: Mutex::new(ReplayState {
+                frames: self.frames,
+                matched,
+                next: 0,
+                guids: HashMap::new(),
+                upload_url: self.upload_url,
+            }),
+            changed: Notify::new(),
+        });
+        let uploads = replay.clone();
+        let upload_hook: UploadHook = Arc::new(move |sha256: &Sha256, _| {
+            uploads.sent(FrameKind::Upload, sha256);
+        });
+        (
+            ReplaySink(replay.clone()),
+            ReplaySource(replay),
+            upload_hook,
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Replay {
+    state: Mutex<ReplayState>,
+    /// Notified whenever an outbound frame was matched, so more inbound frames may be due.
+    changed: Notify,
+}
+
+#[derive(Debug)]
+struct ReplayState {
+    frames: Vec<RecordedFrame>,
+    /// Which outbound frames were matched with sent ones.
+    matched: Vec<bool>,
+    /// The index of the next frame to replay.
+    next: usize,
+    /// The sent guid for each recorded one.
+    guids: HashMap<String, String>,
+    upload_url: Option<Url>,
+}
+
+/// What outbound frames are matched by: the kind of a message and its SHA256 or URL, or the SHA256 of an upload.
+fn match_key(frame: FrameKind, payload: &str) -> (String, Option<String>, Option<String>) {
+    if frame == FrameKind::Upload {
+        return ("Upload".to_string(), Some(payload.to_string()), None);
+    }
+    let message = serde_json::from_str::<Value>(payload).unwrap_or_default();
+    let field = |name: &str| message[name].as_str().map(str::to_string);
+    (
+        field("kind").unwrap_or_default(),
+        field("sha256").map(|sha256| sha256.to_lowercase()),
+        field("url"),
+    )
+}
+
+impl Replay {
+    fn lock(&self) -> std::sync::MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Match a frame the connection sent with the first unmatched recorded one.
+    fn sent(&self, frame: FrameKind, payload: &str) {
+        let mut state = self.lock();
+        let key = match_key(frame, payload);
+        let recorded = (0..state.frames.len()).find(|&index| {
+            let recorded = &state.frames[index];
+            !state.matched[index]
+                && recorded.direction == Direction::Outbound
+                && recorded.frame == frame
+                && match_key(frame, recorded.payload.as_deref().unwrap_or_default()) == key
+        });
+        let Some(index) = recorded else {
+            warn!(kind = key.0, "The replayed session has no matching frame");
+            return;
+        };
+        state.matched[index] = true;
+        let guid = |payload: &str| {
+            let message = serde_json::from_str::<Value>(payload).ok()?;
+            message["guid"].as_str().map(str::to_string)
+        };
+        let recorded_guid = state.frames[index].payload.as_deref().and_then(guid);
+        if let (Some(recorded), Some(sent)) = (recorded_guid, guid(payload)) {
+            state.guids.insert(recorded, sent);
+        }
+        drop(state);
+        self.changed.notify_one();
+    }
+
+    /// The next inbound frame, if all outbound frames recorded before it were sent.
+    fn next_inbound(&self) -> Option<Result<Frame, WebSocketError>> {
+        let mut state = self.lock();
+        while state.next < state.frames.len() {
+            let index = state.next;
+            let frame = state.frames[index].clone();
+            if frame.direction == Direction::Outbound {
+                if !state.matched[index] {
+                    return None;
+                }
+                state.next += 1;
+                continue;
+            }
+            state.next += 1;
+            let payload = frame.payload.unwrap_or_default();
+            return Some(match frame.frame {
+                FrameKind::Text => Ok(Frame::Text {
+                    payload: state.replay_text(&payload),
+                    continuation: false,
+                    fin: true,
+                }),
+                FrameKind::Binary => Ok(Frame::Binary),
+                FrameKind::Ping => Ok(Frame::Ping),
+                FrameKind::Pong => Ok(Frame::Pong),
+                FrameKind::Close => Ok(Frame::Close),
+                FrameKind::Error | FrameKind::Upload => Err(WebSocketError::ConnectionClosed),
+            });
+        }
+        None
+    }
+}
+
+impl ReplayState {
+    /// The recorded message with the guids of the sent requests and the replaced upload URL.
+    fn replay_text(&self, payload: &str) -> String {
+        let Ok(mut message) = serde_json::from_str::<Value>(payload) else {
+            return payload.to_string();
+        };
+        for field in ["guid", "requestId"] {
+            let sent = message[field]
+                .as_str()
+                .and_then(|guid| self.guids.get(guid));
+            if let Some(sent) = sent {
+                message[field] = Value::from(sent.as_str());
+            }
+        }
+        if let Some(upload_url) = &self.upload_url {
+            if message["url"].is_string() && message["kind"] == "VerdictResponse" {
+                message["url"] = Value::from(upload_url.as_str());
+            }
+        }
+        message.to_string()
+    }
+}
+
+/// The write half of a replayed session.
+pub(crate) struct ReplaySink(Arc<Replay>);
+
+#[async_trait]
+impl FrameSink for ReplaySink {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+        self.0.sent(FrameKind::Text, &text);
+        Ok(())
+    }
+
+    async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        Ok(())
+    }
+}
+
+/// The read half of a replayed session.
+pub(crate) struct ReplaySource(Arc<Replay>);
+
+#[async_trait]
+impl FrameSource for ReplaySource {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError> {
+        loop {
+            if let Some(frame) = self.0.next_inbound() {
+                return frame;
+            }
+            self.0.changed.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_websocket::MockServer;
+    use crate::options::Options;
+    use std::time::Duration;
+
+    fn recorded_lines(path: &Path) -> Vec<RecordedFrame> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn redact_replaces_tokens_and_upload_url_signatures() {
+        let auth = r#"{"kind":"AuthRequest","token":"secret-token","session_id":null}"#;
+        let response = r#"{"kind":"VerdictResponse","verdict":"Unknown","url":"https://upload.vaas.gdatasecurity.de/upload/1?X-Amz-Signature=secret","upload_token":"secret-upload-token"}"#;
+
+        let auth = redact(auth);
+        let response = redact(response);
+
+        assert!(!auth.contains("secret") && auth.contains(r#""token":"***""#));
+        assert!(!response.contains("secret"), "{response}");
+        assert!(response.contains(r#""url":"https://upload.vaas.gdatasecurity.de/upload/1""#));
+        assert!(response.contains(r#""upload_token":"***""#));
+    }
+
+    #[test]
+    fn redact_keeps_the_url_of_url_requests() {
+        let request = r#"{"kind":"VerdictRequestForUrl","url":"https://example.com/file?id=1"}"#;
+
+        assert!(redact(request).contains("https://example.com/file?id=1"));
+    }
+
+    #[tokio::test]
+    async fn recorded_session_replays_the_same_verdict() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.ndjson");
+        let recorder = SessionRecorder::create(&path).unwrap();
+        let (_server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let (sink, source) = recorder.wrap(sink, source);
+        let options = Options {
+            keep_alive: false,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options.clone()).await;
+        let sha256 = Sha256::from(&b"content"[..]);
+        let recorded = connection.for_sha256(&sha256, None).await.unwrap();
+        drop(connection);
+
+        let frames = recorded_lines(&path);
+        assert_eq!(
+            vec![Direction::Outbound, Direction::Inbound],
+            frames.iter().map(|f| f.direction).collect::<Vec<_>>()
+        );
+
+        let replay = ReplayTransport::from_file(&path).unwrap();
+        let (sink, source, _) = replay.into_session();
+        let connection = MockServer::connect(sink, source, options).await;
+        let replayed = connection.for_sha256(&sha256, None).await.unwrap();
+
+        assert_eq!(recorded.sha256, replayed.sha256);
+        assert_eq!(recorded.verdict, replayed.verdict);
+    }
+
+    #[tokio::test]
+    async fn failing_reader_is_recorded_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.ndjson");
+        let recorder = SessionRecorder::create(&path).unwrap();
+
+        for _ in 0..3 {
+            recorder.record(
+                Direction::Inbound,
+                FrameKind::Error,
+                Some("closed".to_string()),
+            );
+        }
+
+        assert_eq!(1, recorded_lines(&path).len());
+    }
+
+    #[test]
+    fn invalid_recording_names_the_line() {
+        let recording =
+            "{\"elapsed_ms\":0,\"direction\":\"inbound\",\"frame\":\"ping\"}\n\nnot json\n";
+
+        let replay = ReplayTransport::from_ndjson(recording);
+
+        assert!(
+            matches!(&replay, Err(Error::DeSerialization(e)) if e.starts_with("Line 3")),
+            "{replay:?}"
+        );
+    }
+}
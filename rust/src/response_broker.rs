@@ -1,37 +1,60 @@
-use futures_util::FutureExt;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::future::Future;
-use std::sync::Mutex;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::error::RecvError;
-use tokio::sync::oneshot::Sender;
-use tracing::{error, debug};
+use tokio::sync::oneshot::{Receiver, Sender};
+use tracing::{debug, error};
 
+type Senders<T, E> = Arc<Mutex<HashMap<String, Sender<Result<T, E>>>>>;
+
+/// Routes each response to the one request waiting for it.
 #[derive(Debug)]
 pub(crate) struct ResponseBroker<T: Clone + Debug, E: std::error::Error + Clone + From<RecvError>> {
-    responses: Mutex<HashMap<String, Sender<Result<T, E>>>>,
+    responses: Senders<T, E>,
+    /// The response to every request registered after [`ResponseBroker::close`].
+    closed: Mutex<Option<Result<T, E>>>,
 }
 
 impl<T: Clone + Debug, E: From<RecvError> + Clone + std::error::Error> ResponseBroker<T, E> {
     pub fn new() -> Self {
         Self {
-            responses: Mutex::new(HashMap::new()),
+            responses: Arc::new(Mutex::new(HashMap::new())),
+            closed: Mutex::new(None),
         }
     }
 
-    pub fn get_response(&self, request_id: String) -> impl Future<Output = Result<T, E>> {
-        let receiver = {
-            let mut requests = self.responses.lock().unwrap_or_else(|e| e.into_inner());
-            let (sender, receiver) = oneshot::channel();
-            requests.insert(request_id, sender);
-            receiver
-        };
-        receiver.map(|r| r?)
+    /// Register the request. Has to be called before the request is sent, so the response can't arrive earlier.
+    /// The registration is removed when the returned future is dropped, e.g. because of a timeout.
+    pub fn get_response(&self, request_id: String) -> PendingResponse<T, E> {
+        let (sender, receiver) = oneshot::channel();
+        match self
+            .closed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .as_ref()
+        {
+            Some(response) => {
+                sender.send(response.clone()).ok();
+            }
+            None => {
+                lock(&self.responses).insert(request_id.clone(), sender);
+            }
+        }
+        PendingResponse {
+            request_id,
+            receiver,
+            responses: self.responses.clone(),
+            completed: false,
+        }
     }
 
     pub fn set_response(&self, request_id: &str, response: Result<T, E>) {
-        let mut requests = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+        let mut requests = lock(&self.responses);
         if let Some(r) = requests.remove(request_id) {
             if r.send(response).is_err() {
                 debug!("Receiver for {request_id} has been dropped");
@@ -41,9 +64,16 @@ impl<T: Clone + Debug, E: From<RecvError> + Clone + std::error::Error> ResponseB
         }
     }
 
+    /// Like [`ResponseBroker::set_response`], but a request id which is not pending is ignored silently.
+    pub fn set_pending_response(&self, request_id: &str, response: Result<T, E>) {
+        if let Some(r) = lock(&self.responses).remove(request_id) {
+            r.send(response).ok();
+        }
+    }
+
     pub fn set_all_responses(&self, response: Result<T, E>) {
         let senders: Vec<Sender<Result<T, E>>> = {
-            let mut responses = self.responses.lock().unwrap_or_else(|e| e.into_inner());
+            let mut responses = lock(&self.responses);
             responses.drain().map(|(_, value)| value).collect()
         };
 
@@ -51,12 +81,65 @@ impl<T: Clone + Debug, E: From<RecvError> + Clone + std::error::Error> ResponseB
             s.send(response.clone()).ok();
         }
     }
+
+    /// Answer all pending requests and every later one with the response, e.g. once the connection is closed.
+    pub fn close(&self, response: Result<T, E>) {
+        let mut closed = self.closed.lock().unwrap_or_else(|e| e.into_inner());
+        self.set_all_responses(response.clone());
+        *closed = Some(response);
+    }
+}
+
+fn lock<T, E>(
+    responses: &Mutex<HashMap<String, Sender<Result<T, E>>>>,
+) -> std::sync::MutexGuard<'_, HashMap<String, Sender<Result<T, E>>>> {
+    responses.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The response to a request registered with [`ResponseBroker::get_response`].
+pub(crate) struct PendingResponse<T, E> {
+    request_id: String,
+    receiver: Receiver<Result<T, E>>,
+    responses: Senders<T, E>,
+    completed: bool,
+}
+
+impl<T, E: From<RecvError>> Future for PendingResponse<T, E> {
+    type Output = Result<T, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let response = match Pin::new(&mut self.receiver).poll(cx) {
+            Poll::Ready(response) => response,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.completed = true;
+        Poll::Ready(response?)
+    }
+}
+
+impl<T, E> Drop for PendingResponse<T, E> {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        // Closing the receiver marks the sender as closed, so a later registration
+        // with the same request id is left alone.
+        self.receiver.close();
+        if let Entry::Occupied(entry) = lock(&self.responses).entry(self.request_id.clone()) {
+            if entry.get().is_closed() {
+                entry.remove();
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use tracing_test::traced_test;
     use super::ResponseBroker;
+    use crate::error::Error;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tracing_test::traced_test;
 
     const TEST_REQUEST_ID: &str = "1234";
 
@@ -86,4 +169,83 @@ mod tests {
         responses.set_response(TEST_REQUEST_ID, Ok(42));
         assert!(logs_contain("Can't find receiver for 1234"));
     }
+
+    #[tokio::test]
+    async fn response_set_before_polling_is_returned() {
+        let responses: ResponseBroker<i32, Error> = ResponseBroker::new();
+        let response_future = responses.get_response(TEST_REQUEST_ID.to_string());
+
+        responses.set_response(TEST_REQUEST_ID, Ok(42));
+        tokio::task::yield_now().await;
+
+        assert_eq!(42, response_future.await.unwrap());
+        assert!(responses.responses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn response_set_while_waiting_is_returned() {
+        let responses: Arc<ResponseBroker<i32, Error>> = Arc::new(ResponseBroker::new());
+        let response_future = responses.get_response(TEST_REQUEST_ID.to_string());
+        let reader = responses.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            reader.set_response(TEST_REQUEST_ID, Ok(42));
+        });
+
+        assert_eq!(42, response_future.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn dropped_waiter_is_removed() {
+        let responses: ResponseBroker<i32, Error> = ResponseBroker::new();
+
+        drop(responses.get_response(TEST_REQUEST_ID.to_string()));
+
+        assert!(responses.responses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn timed_out_waiter_is_removed() {
+        let responses: ResponseBroker<i32, Error> = ResponseBroker::new();
+        let response_future = responses.get_response(TEST_REQUEST_ID.to_string());
+
+        let result = tokio::time::timeout(Duration::from_millis(10), response_future).await;
+
+        assert!(result.is_err());
+        assert!(responses.responses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_waiter_keeps_later_registration_with_same_id() {
+        let responses: ResponseBroker<i32, Error> = ResponseBroker::new();
+        let stale = responses.get_response(TEST_REQUEST_ID.to_string());
+        let current = responses.get_response(TEST_REQUEST_ID.to_string());
+
+        drop(stale);
+        responses.set_response(TEST_REQUEST_ID, Ok(42));
+
+        assert_eq!(42, current.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn concurrent_responses_reach_their_waiters() {
+        let responses: Arc<ResponseBroker<usize, Error>> = Arc::new(ResponseBroker::new());
+        let waiters: Vec<_> = (0..100)
+            .map(|i| responses.get_response(i.to_string()))
+            .collect();
+        let reader = responses.clone();
+
+        tokio::spawn(async move {
+            for i in (0..100).rev() {
+                reader.set_response(&i.to_string(), Ok(i));
+            }
+        });
+        let results = futures::future::join_all(waiters).await;
+
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(i, result.unwrap());
+        }
+        assert!(responses.responses.lock().unwrap().is_empty());
+    }
 }
@@ -0,0 +1,293 @@
+//! The REST transport, see [`Transport::Rest`](crate::transport::Transport::Rest).
+//!
+//! [`RestSink`] and [`RestSource`] translate the verdict requests to HTTP requests and their results back to the
+//! frames of the websocket protocol, so [`Connection`](crate::Connection) works the same with both transports.
+//! Each verdict request is POSTed on its own task. VaaS answers with the verdict, or with `202 Accepted` if it is not
+//! known yet, in which case the verdict is polled until it is. After an `Unknown` verdict, polling continues until the
+//! verdict of the uploaded file is known.
+
+use crate::auth::Authenticator;
+use crate::connection::FrameSource;
+use crate::error::{Error, VResult};
+use crate::message::{AuthResponse, ErrorResponse, Kind};
+use crate::websocket::{Frame, WebSocketError};
+use crate::ws_writer::FrameSink;
+use async_trait::async_trait;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
+use reqwest::{RequestBuilder, Response, StatusCode, Url};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+use tracing::warn;
+
+/// The first delay between two polls of a verdict. It doubles with every poll up to [`MAX_POLL_INTERVAL`].
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a verdict is polled, e.g. while waiting for an upload which never happens.
+const MAX_POLL_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// The sink and source of a connection using the REST transport.
+pub(crate) fn rest_frames(
+    url: &Url,
+    session_id: String,
+    http_client: reqwest::Client,
+    authenticator: Arc<dyn Authenticator + Send + Sync>,
+) -> VResult<(RestSink, RestSource)> {
+    let (frames, receiver) = unbounded_channel();
+    let client = RestClient {
+        verdicts_url: verdicts_url(url)?,
+        http_client,
+        authenticator,
+    };
+    let sink = RestSink {
+        client: Arc::new(client),
+        session_id,
+        frames,
+    };
+    Ok((sink, RestSource { frames: receiver }))
+}
+
+/// The `verdicts` endpoint relative to the websocket URL, with the scheme changed to `https` or `http`.
+fn verdicts_url(url: &Url) -> VResult<Url> {
+    let scheme = match url.scheme() {
+        "wss" => "https",
+        "ws" => "http",
+        scheme => {
+            return Err(Error::InvalidConfig(format!(
+                "url must use the wss or ws scheme, got `{scheme}`"
+            )))
+        }
+    };
+    let mut base = url.clone();
+    base.set_scheme(scheme)
+        .map_err(|_| Error::InvalidConfig(format!("url can't be used for REST: `{url}`")))?;
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    base.join("verdicts")
+        .map_err(|e| Error::InvalidConfig(e.to_string()))
+}
+
+/// The fields of a message the sink needs to route it.
+#[derive(Deserialize)]
+struct Envelope {
+    kind: Kind,
+    guid: Option<String>,
+}
+
+/// The fields of a verdict response the poller needs.
+#[derive(Deserialize)]
+struct PolledVerdict {
+    verdict: String,
+}
+
+pub(crate) struct RestSink {
+    client: Arc<RestClient>,
+    session_id: String,
+    frames: UnboundedSender<String>,
+}
+
+#[async_trait]
+impl FrameSink for RestSink {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+        let envelope = match serde_json::from_str::<Envelope>(&text) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!(error = %e, "Dropping a message the REST transport can't route");
+                return Ok(());
+            }
+        };
+        match (envelope.kind, envelope.guid) {
+            // Every HTTP request carries its own token, so the session needs no authentication.
+            (Kind::AuthRequest, _) => {
+                let response = AuthResponse {
+                    kind: Kind::AuthResponse,
+                    success: true,
+                    session_id: Some(self.session_id.clone()),
+                    text: String::new(),
+                };
+                if let Ok(json) = serde_json::to_string(&response) {
+                    self.frames.send(json).ok();
+                }
+            }
+            (_, Some(guid)) => {
+                tokio::spawn(request_verdict(
+                    self.client.clone(),
+                    guid,
+                    text,
+                    self.frames.clone(),
+                ));
+            }
+            (kind, None) => warn!(?kind, "Dropping a message without guid"),
+        }
+        Ok(())
+    }
+
+    /// HTTP requests need no keep-alive.
+    async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        Ok(())
+    }
+}
+
+pub(crate) struct RestSource {
+    frames: UnboundedReceiver<String>,
+}
+
+#[async_trait]
+impl FrameSource for RestSource {
+    async fn receive(&mut self) -> Result<Frame, WebSocketError> {
+        match self.frames.recv().await {
+            Some(message) => Ok(Frame::text(message)),
+            // The sink has been dropped with the connection.
+            None => std::future::pending().await,
+        }
+    }
+}
+
+/// Request the verdict and pass it, or the error, to the source as message.
+async fn request_verdict(
+    client: Arc<RestClient>,
+    guid: String,
+    request: String,
+    frames: UnboundedSender<String>,
+) {
+    if let Err((error_type, text)) = client.verdict(&guid, request, &frames).await {
+        let error = ErrorResponse {
+            error_type: error_type.to_string(),
+            text,
+            kind: Kind::Error,
+            request_id: Some(guid),
+        };
+        if let Ok(json) = serde_json::to_string(&error) {
+            frames.send(json).ok();
+        }
+    }
+}
+
+type Failure = (&'static str, String);
+
+struct RestClient {
+    verdicts_url: Url,
+    http_client: reqwest::Client,
+    authenticator: Arc<dyn Authenticator + Send + Sync>,
+}
+
+impl RestClient {
+    async fn verdict(
+        &self,
+        guid: &str,
+        request: String,
+        frames: &UnboundedSender<String>,
+    ) -> Result<(), Failure> {
+        let verdict_url = self
+            .verdicts_url
+            .join(&format!("verdicts/{guid}"))
+            .map_err(|e| ("InvalidRequest", e.to_string()))?;
+        let mut response = self
+            .send(|| {
+                self.http_client
+                    .post(self.verdicts_url.clone())
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(request.clone())
+            })
+            .await?;
+        let deadline = Instant::now() + MAX_POLL_DURATION;
+        let mut interval = POLL_INTERVAL;
+        let mut unknown_sent = false;
+        loop {
+            if let Some(message) = response {
+                let verdict = serde_json::from_str::<PolledVerdict>(&message)
+                    .map_err(|e| ("InvalidResponse", e.to_string()))?
+                    .verdict;
+                let unknown = verdict == "Unknown";
+                if !(unknown && unknown_sent) && frames.send(message).is_err() {
+                    return Ok(());
+                }
+                if !unknown {
+                    return Ok(());
+                }
+                unknown_sent = true;
+            }
+            if frames.is_closed() {
+                return Ok(());
+            }
+            if Instant::now() + interval > deadline {
+                return match unknown_sent {
+                    true => Ok(()),
+                    false => Err(("Timeout", format!("No verdict after {MAX_POLL_DURATION:?}"))),
+                };
+            }
+            tokio::time::sleep(interval).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+            response = self
+                .send(|| self.http_client.get(verdict_url.clone()))
+                .await?;
+        }
+    }
+
+    /// Send the request with the token of the authenticator and return the verdict response, or `None` if it is
+    /// pending. If VaaS rejects the token, the request is sent once more with a refreshed token.
+    async fn send(&self, request: impl Fn() -> RequestBuilder) -> Result<Option<String>, Failure> {
+        let token = self.authenticator.get_token().await.map_err(auth_failure)?;
+        let mut response = authorized(request(), &token).await?;
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let token = self
+                .authenticator
+                .refresh_token()
+                .await
+                .map_err(auth_failure)?;
+            response = authorized(request(), &token).await?;
+        }
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| ("RequestFailed", e.to_string()))?;
+        match status {
+            StatusCode::OK => Ok(Some(body)),
+            StatusCode::ACCEPTED => Ok(None),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                Err(("Unauthorized", format!("{status}: {body}")))
+            }
+            _ => Err(("HttpError", format!("{status}: {body}"))),
+        }
+    }
+}
+
+async fn authorized(request: RequestBuilder, token: &str) -> Result<Response, Failure> {
+    request
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .send()
+        .await
+        .map_err(|e| ("RequestFailed", e.to_string()))
+}
+
+fn auth_failure(e: Error) -> Failure {
+    ("Unauthorized", e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdicts_url_keeps_the_path_of_the_websocket_url() {
+        let cases = [
+            (
+                "wss://gateway.vaas.example",
+                "https://gateway.vaas.example/verdicts",
+            ),
+            ("ws://127.0.0.1:8080/", "http://127.0.0.1:8080/verdicts"),
+            (
+                "wss://proxy.example/vaas",
+                "https://proxy.example/vaas/verdicts",
+            ),
+        ];
+        for (url, expected) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(expected, verdicts_url(&url).unwrap().as_str());
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! # Retries
+//!
+//! Token requests and file uploads can fail because of transient network or server errors.
+//! A [`RetryPolicy`] configured with [`Builder::retry_policy`](crate::Builder::retry_policy) retries these
+//! operations with an exponential backoff. By default, nothing is retried.
+//!
+//! Each retry and each final failure is reported as a `tracing` event with the operation, the attempt
+//! and the reason of the failure. If all attempts fail, [`Error::RetriesExhausted`] is returned with the
+//! number of attempts and the last error.
+//!
+//! ```rust
+//! # fn main() -> vaas::error::VResult<()> {
+//! use std::time::Duration;
+//! use vaas::auth::authenticators::ClientCredentials;
+//! use vaas::retry::RetryPolicy;
+//! use vaas::Vaas;
+//!
+//! let authenticator = ClientCredentials::new("client_id".to_string(), "client_secret".to_string());
+//! let vaas = Vaas::builder(authenticator)
+//!     .retry_policy(RetryPolicy {
+//!         max_attempts: 3,
+//!         base_delay: Duration::from_millis(200),
+//!         ..RetryPolicy::default()
+//!     })
+//!     .build()?;
+//! # Ok(()) }
+//! ```
+
+use crate::error::{ConnectPhase, Error, VResult};
+use async_trait::async_trait;
+use rand::Rng;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Defines how often and how fast failed operations are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// The delay before the first retry. The delay is doubled for each further retry.
+    pub base_delay: Duration,
+    /// The upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// The classes of errors which are retried.
+    pub retry_on: RetryClasses,
+}
+
+impl Default for RetryPolicy {
+    /// No retries. The delays and classes are used as soon as `max_attempts` is increased.
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            retry_on: RetryClasses::default(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, starting with `1` for the first retry.
+    ///
+    /// The delay grows exponentially from `base_delay` up to `max_delay`. To avoid that many clients
+    /// retry at the same time, a random jitter of up to half of the delay is subtracted.
+    pub(crate) fn backoff<R: Rng>(&self, retry: u32, rng: &mut R) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)));
+        let delay = exponential.min(self.max_delay);
+        let jitter = rng.gen_range(0..=delay.as_millis() as u64 / 2);
+        delay.saturating_sub(Duration::from_millis(jitter))
+    }
+
+    fn retries(&self, class: RetryClass) -> bool {
+        match class {
+            RetryClass::Connection => self.retry_on.connection,
+            RetryClass::ServerError => self.retry_on.server_error,
+            RetryClass::Timeout => self.retry_on.timeout,
+        }
+    }
+}
+
+/// The classes of errors which are retried by a [`RetryPolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryClasses {
+    /// The request could not be sent or the connection broke, e.g. the server is unreachable.
+    pub connection: bool,
+    /// The server answered with a 5xx status code.
+    pub server_error: bool,
+    /// The request took longer than the configured timeout.
+    pub timeout: bool,
+}
+
+impl Default for RetryClasses {
+    /// Retry all classes of transient errors.
+    fn default() -> Self {
+        Self {
+            connection: true,
+            server_error: true,
+            timeout: true,
+        }
+    }
+}
+
+/// The class of a transient error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RetryClass {
+    Connection,
+    ServerError,
+    Timeout,
+}
+
+impl RetryClass {
+    /// Classify the error. Returns `None` for errors which will not go away by retrying.
+    pub(crate) fn of(error: &Error) -> Option<Self> {
+        match error {
+            Error::FailedRequest(_) => Some(RetryClass::Connection),
+            Error::FailedUploadFile(status, _)
+            | Error::FailedAuthTokenRequest(status, _)
+            | Error::AuthServer { status, .. }
+                if status.is_server_error() =>
+            {
+                Some(RetryClass::ServerError)
+            }
+            Error::UploadTimeout(_) | Error::ConnectTimeout(ConnectPhase::TokenRequest) => {
+                Some(RetryClass::Timeout)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RetryClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryClass::Connection => write!(f, "connection error"),
+            RetryClass::ServerError => write!(f, "server error"),
+            RetryClass::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+/// Waits between two attempts. Allows tests to retry without actually sleeping.
+#[async_trait]
+pub(crate) trait Clock: Send + Sync {
+    async fn sleep(&self, duration: Duration);
+}
+
+/// The [`Clock`] used outside of tests.
+pub(crate) struct TokioClock;
+
+#[async_trait]
+impl Clock for TokioClock {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Run the operation until it succeeds, fails with an error which is not retried by the policy
+/// or the maximum number of attempts is reached. In the last case, the error of the last attempt is
+/// wrapped in [`Error::RetriesExhausted`], unless the policy allows only one attempt.
+pub(crate) async fn retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    clock: &dyn Clock,
+    operation: &str,
+    mut f: F,
+) -> VResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = VResult<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        let error = match f().await {
+            Ok(result) => return Ok(result),
+            Err(error) => error,
+        };
+        let class = match RetryClass::of(&error) {
+            Some(class) if policy.retries(class) => class,
+            _ => return Err(error),
+        };
+        if attempt >= policy.max_attempts {
+            if policy.max_attempts == 1 {
+                return Err(error);
+            }
+            warn!(operation, attempt, reason = %class, "giving up after {attempt} attempts: {error}");
+            return Err(Error::RetriesExhausted {
+                attempts: attempt,
+                last: Box::new(error),
+            });
+        }
+        let delay = policy.backoff(attempt, &mut rand::thread_rng());
+        warn!(operation, attempt, reason = %class, delay_ms = delay.as_millis() as u64, "retrying: {error}");
+        clock.sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use reqwest::StatusCode;
+    use std::sync::Mutex;
+    use tracing_test::traced_test;
+
+    #[derive(Default)]
+    struct RecordingClock {
+        sleeps: Mutex<Vec<Duration>>,
+    }
+
+    #[async_trait]
+    impl Clock for RecordingClock {
+        async fn sleep(&self, duration: Duration) {
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            retry_on: RetryClasses::default(),
+        }
+    }
+
+    fn server_error() -> Error {
+        Error::FailedUploadFile(StatusCode::BAD_GATEWAY, String::new())
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_within_jitter_bounds() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let policy = policy(10);
+        for (retry, full_delay) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (9, 1000)] {
+            for _ in 0..100 {
+                let delay = policy.backoff(retry, &mut rng).as_millis();
+                assert!(delay >= full_delay / 2 && delay <= full_delay, "{delay}ms");
+            }
+        }
+    }
+
+    #[test]
+    fn backoff_with_seeded_rng_is_deterministic() {
+        let policy = policy(10);
+        let delays = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (1..5)
+                .map(|retry| policy.backoff(retry, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(delays(7), delays(7));
+    }
+
+    #[test]
+    fn errors_are_classified() {
+        let classify = |error| RetryClass::of(&error);
+        assert_eq!(
+            Some(RetryClass::Connection),
+            classify(Error::FailedRequest("connection refused".to_string()))
+        );
+        assert_eq!(Some(RetryClass::ServerError), classify(server_error()));
+        assert_eq!(
+            Some(RetryClass::Timeout),
+            classify(Error::UploadTimeout(Duration::from_secs(1)))
+        );
+        assert_eq!(
+            None,
+            classify(Error::FailedAuthTokenRequest(
+                StatusCode::UNAUTHORIZED,
+                String::new()
+            ))
+        );
+        assert_eq!(None, classify(Error::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn retry_until_success() {
+        let clock = RecordingClock::default();
+        let mut attempts = 0;
+
+        let result = retry(&policy(5), &clock, "upload", || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                if attempt < 3 {
+                    Err(server_error())
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(3, result.unwrap());
+        assert_eq!(2, clock.sleeps.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn retry_gives_up_after_max_attempts() {
+        let clock = RecordingClock::default();
+        let mut attempts = 0;
+
+        let result: VResult<()> = retry(&policy(3), &clock, "upload", || {
+            attempts += 1;
+            async { Err(server_error()) }
+        })
+        .await;
+
+        match result {
+            Err(Error::RetriesExhausted { attempts, last }) => {
+                assert_eq!(3, attempts);
+                assert!(matches!(*last, Error::FailedUploadFile(_, _)));
+            }
+            other => panic!("expected exhausted retries, got {other:?}"),
+        }
+        assert_eq!(3, attempts);
+        assert_eq!(2, clock.sleeps.lock().unwrap().len());
+        assert!(logs_contain("giving up after 3 attempts"));
+        assert!(logs_contain("reason=server error"));
+    }
+
+    #[tokio::test]
+    async fn retry_skips_errors_which_are_not_transient() {
+        let clock = RecordingClock::default();
+        let mut attempts = 0;
+
+        let result: VResult<()> = retry(&policy(3), &clock, "upload", || {
+            attempts += 1;
+            async { Err(Error::MissingAuthToken) }
+        })
+        .await;
+
+        assert!(matches!(result, Err(Error::MissingAuthToken)));
+        assert_eq!(1, attempts);
+        assert!(clock.sleeps.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn retry_skips_disabled_classes() {
+        let clock = RecordingClock::default();
+        let mut attempts = 0;
+        let policy = RetryPolicy {
+            retry_on: RetryClasses {
+                server_error: false,
+                ..RetryClasses::default()
+            },
+            ..policy(3)
+        };
+
+        let result: VResult<()> = retry(&policy, &clock, "upload", || {
+            attempts += 1;
+            async { Err(server_error()) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(1, attempts);
+    }
+}
@@ -0,0 +1,304 @@
+//! # Scanner
+//!
+//! The [`VaasScanner`] trait abstracts the verdict requests of [`Connection`] and [`LazyConnection`], so code which
+//! requests verdicts can be tested without VaaS. With the `test-utils` feature, [`MockScanner`] answers requests
+//! with pre-programmed verdicts.
+//!
+//! ```rust
+//! use std::sync::Arc;
+//! use vaas::error::VResult;
+//! use vaas::{Sha256, VaasScanner};
+//!
+//! async fn is_malicious(scanner: Arc<dyn VaasScanner>, sha256: &Sha256) -> VResult<bool> {
+//!     let verdict = scanner.for_sha256(sha256, None).await?;
+//!     Ok(matches!(verdict.verdict, vaas::message::Verdict::Malicious { .. }))
+//! }
+//! ```
+
+use crate::cancellation::CancellationToken;
+use crate::connection::Connection;
+use crate::error::VResult;
+use crate::lazy_connection::LazyConnection;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use async_trait::async_trait;
+use reqwest::Url;
+use std::path::Path;
+
+/// The verdict requests of a connection to VaaS, see [`Connection`] for details.
+/// Without a [`CancellationToken`], the default timeout of the connection is used.
+#[async_trait]
+pub trait VaasScanner: Send + Sync {
+    /// Request a verdict for a SHA256 file hash, see [`Connection::for_sha256`].
+    async fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict>;
+
+    /// Request a verdict for a file, see [`Connection::for_file`].
+    async fn for_file(&self, file: &Path, ct: Option<&CancellationToken>) -> VResult<VaasVerdict>;
+
+    /// Request a verdict for a buffer, see [`Connection::for_buf`].
+    async fn for_buf(&self, buf: Vec<u8>, ct: Option<&CancellationToken>) -> VResult<VaasVerdict>;
+
+    /// Request a verdict for a file behind a URL, see [`Connection::for_url`].
+    async fn for_url(&self, url: &Url, ct: Option<&CancellationToken>) -> VResult<VaasVerdict>;
+}
+
+#[async_trait]
+impl VaasScanner for Connection {
+    async fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        Connection::for_sha256(self, sha256, ct).await
+    }
+
+    async fn for_file(&self, file: &Path, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        Connection::for_file(self, file, ct).await
+    }
+
+    async fn for_buf(&self, buf: Vec<u8>, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        Connection::for_buf(self, buf, ct).await
+    }
+
+    async fn for_url(&self, url: &Url, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        Connection::for_url(self, url, ct).await
+    }
+}
+
+#[async_trait]
+impl VaasScanner for LazyConnection {
+    async fn for_sha256(
+        &self,
+        sha256: &Sha256,
+        ct: Option<&CancellationToken>,
+    ) -> VResult<VaasVerdict> {
+        LazyConnection::for_sha256(self, sha256, ct).await
+    }
+
+    async fn for_file(&self, file: &Path, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        LazyConnection::for_file(self, file, ct).await
+    }
+
+    async fn for_buf(&self, buf: Vec<u8>, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        LazyConnection::for_buf(self, buf, ct).await
+    }
+
+    async fn for_url(&self, url: &Url, ct: Option<&CancellationToken>) -> VResult<VaasVerdict> {
+        LazyConnection::for_url(self, url, ct).await
+    }
+}
+
+#[cfg(feature = "test-utils")]
+pub use mock::MockScanner;
+
+#[cfg(feature = "test-utils")]
+mod mock {
+    use super::*;
+    use crate::message::Verdict;
+    use crate::vaas_verdict::VerdictSource;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    /// A [`VaasScanner`] answering with pre-programmed verdicts, for tests. Requires the `test-utils` feature.
+    ///
+    /// Files are looked up by their path first and by the SHA256 of their content second, buffers by their SHA256.
+    /// Requests without a pre-programmed verdict get the default one, which is [`Verdict::Clean`] unless set with
+    /// [`MockScanner::with_default`]. The verdicts for URLs carry the SHA256 of the URL, as nothing is downloaded.
+    ///
+    /// ```rust
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// use vaas::message::Verdict;
+    /// use vaas::{MockScanner, Sha256, VaasScanner};
+    ///
+    /// let eicar = Sha256::from(&b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*"[..]);
+    /// let malicious = Verdict::Malicious { detection: "EICAR".to_string() };
+    /// let scanner = MockScanner::new().with_sha256(eicar.clone(), Ok(malicious.clone()));
+    ///
+    /// let verdict = scanner.for_sha256(&eicar, None).await.unwrap();
+    /// assert_eq!(malicious, verdict.verdict);
+    /// # }
+    /// ```
+    #[derive(Debug, Clone)]
+    pub struct MockScanner {
+        sha256s: HashMap<Sha256, VResult<Verdict>>,
+        files: HashMap<PathBuf, VResult<Verdict>>,
+        urls: HashMap<Url, VResult<Verdict>>,
+        default: VResult<Verdict>,
+    }
+
+    impl Default for MockScanner {
+        fn default() -> Self {
+            Self {
+                sha256s: HashMap::new(),
+                files: HashMap::new(),
+                urls: HashMap::new(),
+                default: Ok(Verdict::Clean),
+            }
+        }
+    }
+
+    impl MockScanner {
+        /// A scanner answering every request with [`Verdict::Clean`].
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Answer requests without a pre-programmed verdict with the given verdict or error.
+        pub fn with_default(self, verdict: VResult<Verdict>) -> Self {
+            Self {
+                default: verdict,
+                ..self
+            }
+        }
+
+        /// Answer requests for the SHA256, and for files and buffers with this SHA256, with the given verdict or error.
+        pub fn with_sha256(mut self, sha256: Sha256, verdict: VResult<Verdict>) -> Self {
+            self.sha256s.insert(sha256, verdict);
+            self
+        }
+
+        /// Answer requests for the file at the path with the given verdict or error.
+        pub fn with_file(mut self, file: impl Into<PathBuf>, verdict: VResult<Verdict>) -> Self {
+            self.files.insert(file.into(), verdict);
+            self
+        }
+
+        /// Answer requests for the URL with the given verdict or error.
+        pub fn with_url(mut self, url: Url, verdict: VResult<Verdict>) -> Self {
+            self.urls.insert(url, verdict);
+            self
+        }
+
+        fn answer(
+            &self,
+            sha256: Sha256,
+            verdict: Option<&VResult<Verdict>>,
+        ) -> VResult<VaasVerdict> {
+            let verdict = verdict
+                .or_else(|| self.sha256s.get(&sha256))
+                .unwrap_or(&self.default)
+                .clone()?;
+            Ok(VaasVerdict {
+                sha256,
+                verdict,
+                file_type: None,
+                mime_type: None,
+                source: VerdictSource::Vaas,
+                timing: None,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl VaasScanner for MockScanner {
+        async fn for_sha256(
+            &self,
+            sha256: &Sha256,
+            _ct: Option<&CancellationToken>,
+        ) -> VResult<VaasVerdict> {
+            self.answer(sha256.clone(), None)
+        }
+
+        async fn for_file(
+            &self,
+            file: &Path,
+            _ct: Option<&CancellationToken>,
+        ) -> VResult<VaasVerdict> {
+            let sha256 = match Sha256::try_from(file) {
+                Ok(sha256) => sha256,
+                // Pre-programmed files do not have to exist.
+                Err(_) if self.files.contains_key(file) => {
+                    Sha256::from(file.as_os_str().as_encoded_bytes())
+                }
+                Err(e) => return Err(e),
+            };
+            self.answer(sha256, self.files.get(file))
+        }
+
+        async fn for_buf(
+            &self,
+            buf: Vec<u8>,
+            _ct: Option<&CancellationToken>,
+        ) -> VResult<VaasVerdict> {
+            self.answer(Sha256::from(buf.as_slice()), None)
+        }
+
+        async fn for_url(
+            &self,
+            url: &Url,
+            _ct: Option<&CancellationToken>,
+        ) -> VResult<VaasVerdict> {
+            let sha256 = Sha256::from(url.as_str().as_bytes());
+            match self.urls.get(url) {
+                Some(verdict) => self.answer(sha256, Some(verdict)),
+                None => self.answer(sha256, Some(&self.default)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::error::Error;
+        use std::io::Write;
+        use std::sync::Arc;
+
+        fn malicious() -> Verdict {
+            Verdict::Malicious {
+                detection: "Test".to_string(),
+            }
+        }
+
+        #[tokio::test]
+        async fn verdicts_are_looked_up_by_sha256() {
+            let sha256 = Sha256::from(&b"malicious"[..]);
+            let scanner: Arc<dyn VaasScanner> =
+                Arc::new(MockScanner::new().with_sha256(sha256.clone(), Ok(malicious())));
+
+            let verdict = scanner.for_buf(b"malicious".to_vec(), None).await.unwrap();
+            assert_eq!(malicious(), verdict.verdict);
+            assert_eq!(sha256, verdict.sha256);
+
+            let verdict = scanner.for_buf(b"other".to_vec(), None).await.unwrap();
+            assert_eq!(Verdict::Clean, verdict.verdict);
+        }
+
+        #[tokio::test]
+        async fn files_are_looked_up_by_path_and_content() {
+            let mut by_content = tempfile::NamedTempFile::new().unwrap();
+            by_content.write_all(b"malicious").unwrap();
+            let scanner = MockScanner::new()
+                .with_sha256(Sha256::from(&b"malicious"[..]), Ok(malicious()))
+                .with_file("/does/not/exist", Err(Error::Cancelled));
+
+            let verdict = scanner.for_file(by_content.path(), None).await.unwrap();
+            assert_eq!(malicious(), verdict.verdict);
+
+            let result = scanner.for_file(Path::new("/does/not/exist"), None).await;
+            assert!(matches!(result, Err(Error::Cancelled)), "{result:?}");
+
+            let result = scanner.for_file(Path::new("/not/programmed"), None).await;
+            assert!(matches!(result, Err(Error::IoError(_))), "{result:?}");
+        }
+
+        #[tokio::test]
+        async fn urls_get_their_verdict_or_the_default() {
+            let url = Url::parse("https://example.com/malicious").unwrap();
+            let scanner = MockScanner::new()
+                .with_url(url.clone(), Ok(malicious()))
+                .with_default(Err(Error::UploadDisabled));
+
+            let verdict = scanner.for_url(&url, None).await.unwrap();
+            assert_eq!(malicious(), verdict.verdict);
+
+            let other = Url::parse("https://example.com/other").unwrap();
+            let result = scanner.for_url(&other, None).await;
+            assert!(matches!(result, Err(Error::UploadDisabled)), "{result:?}");
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! # Tower service
+//!
+//! [`VaasService`] adapts a [`Connection`] to [`tower::Service`](tower_service::Service), so verdict requests can be
+//! composed with tower middleware like retries, rate limits or metrics. Requires the `tower` feature.
+//!
+//! Requests use the default timeout of the connection, see
+//! [`Builder::default_timeout`](crate::Builder::default_timeout). See `examples/tower_service.rs` for a service
+//! with retries and a concurrency limit.
+
+use crate::connection::Connection;
+use crate::error::{Error, VResult};
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use reqwest::Url;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::PollSemaphore;
+use tower_service::Service;
+
+/// What a [`VaasService`] requests a verdict for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanRequest {
+    /// A SHA256 file hash, see [`Connection::for_sha256`].
+    Sha256(Sha256),
+    /// A file, see [`Connection::for_file`].
+    File(PathBuf),
+    /// A file behind a URL, see [`Connection::for_url`].
+    Url(Url),
+    /// A buffer, see [`Connection::for_buf`].
+    Buf(Bytes),
+}
+
+/// A [`tower::Service`](tower_service::Service) requesting verdicts on a [`Connection`].
+///
+/// [`poll_ready`](Service::poll_ready) fails with [`Error::ConnectionClosed`] once VaaS closed the connection, and
+/// is pending while [`Builder::max_in_flight`](crate::Builder::max_in_flight) requests of the service and its
+/// clones are running. Clones share the connection and the limit.
+#[derive(Debug)]
+pub struct VaasService {
+    connection: Arc<Connection>,
+    permits: PollSemaphore,
+    /// The slot reserved by `poll_ready` for the next call.
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl VaasService {
+    /// Create a service requesting verdicts on the connection.
+    pub fn new(connection: impl Into<Arc<Connection>>) -> Self {
+        let connection = connection.into();
+        let max_in_flight = connection.max_in_flight().unwrap_or(Semaphore::MAX_PERMITS);
+        Self {
+            connection,
+            permits: PollSemaphore::new(Arc::new(Semaphore::new(max_in_flight))),
+            permit: None,
+        }
+    }
+
+    /// The connection the verdicts are requested on.
+    pub fn connection(&self) -> &Arc<Connection> {
+        &self.connection
+    }
+}
+
+/// The clone shares the connection and the limit, but not a slot reserved by `poll_ready`.
+impl Clone for VaasService {
+    fn clone(&self) -> Self {
+        Self {
+            connection: self.connection.clone(),
+            permits: self.permits.clone(),
+            permit: None,
+        }
+    }
+}
+
+impl Service<ScanRequest> for VaasService {
+    type Response = VaasVerdict;
+    type Error = Error;
+    type Future = BoxFuture<'static, VResult<VaasVerdict>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<VResult<()>> {
+        if self.connection.is_closed() {
+            return Poll::Ready(Err(Error::ConnectionClosed));
+        }
+        if self.permit.is_none() {
+            // The semaphore is never closed.
+            self.permit = ready!(self.permits.poll_acquire(cx));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ScanRequest) -> Self::Future {
+        let permit = self
+            .permit
+            .take()
+            .expect("VaasService::call without poll_ready");
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            let _permit = permit;
+            match request {
+                ScanRequest::Sha256(sha256) => connection.for_sha256(&sha256, None).await,
+                ScanRequest::File(file) => connection.for_file(&file, None).await,
+                ScanRequest::Url(url) => connection.for_url(&url, None).await,
+                ScanRequest::Buf(buf) => connection.for_buf(buf.to_vec(), None).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Verdict;
+    use crate::mock_websocket::MockServer;
+    use crate::options::Options;
+    use futures::future::poll_fn;
+    use std::time::Duration;
+
+    async fn service(max_in_flight: Option<usize>) -> (MockServer, VaasService) {
+        let (server, sink, source) = MockServer::answering(Duration::ZERO, "Clean");
+        let options = Options {
+            keep_alive: false,
+            max_in_flight,
+            ..Options::default()
+        };
+        let connection = MockServer::connect(sink, source, options).await;
+        (server, VaasService::new(connection))
+    }
+
+    #[tokio::test]
+    async fn call_requests_the_verdict() {
+        let (_server, mut service) = service(None).await;
+        let sha256 = Sha256::from(&b"content"[..]);
+
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let verdict = service
+            .call(ScanRequest::Buf(Bytes::from_static(b"content")))
+            .await
+            .unwrap();
+
+        assert_eq!(Verdict::Clean, verdict.verdict);
+        assert_eq!(sha256, verdict.sha256);
+    }
+
+    #[tokio::test]
+    async fn service_is_not_ready_beyond_max_in_flight() {
+        let (_server, mut service) = service(Some(1)).await;
+        let mut clone = service.clone();
+
+        poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(clone.poll_ready(&mut cx).is_pending());
+
+        let sha256 = Sha256::from(&b"content"[..]);
+        service.call(ScanRequest::Sha256(sha256)).await.unwrap();
+        assert!(matches!(clone.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+    }
+
+    #[tokio::test]
+    async fn service_of_closed_connection_fails() {
+        let (server, mut service) = service(None).await;
+
+        server.close();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !service.connection().is_closed() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let ready = poll_fn(|cx| service.poll_ready(cx)).await;
+        assert!(matches!(ready, Err(Error::ConnectionClosed)), "{ready:?}");
+    }
+}
@@ -1,10 +1,31 @@
 //! Implements a SHA256 structure that guarantees that a given hash string is in the correct format.
 
-use regex::Regex;
+use crate::error::{Error, VResult};
+use crate::CancellationToken;
+use sha2::Digest;
+use std::borrow::Borrow;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, Read};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
 use std::{convert::TryFrom, fmt, ops::Deref};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
-/// Represents a SHA256 hash in its hexadecimal string form.
+/// The default size of the chunks in which files are read for hashing.
+pub(crate) const DEFAULT_HASH_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Represents a SHA256 hash. It dereferences to its lowercase hexadecimal string form,
+/// the raw digest is available with [`Sha256::as_bytes`].
+///
+/// Parsing accepts any case and ignores surrounding whitespace, so hashes copied from
+/// reports compare equal to the lowercase ones sent by the server.
+///
+/// The string form returned by [`Display`](fmt::Display), [`AsRef<str>`] and [`Deref`] is
+/// always the 64 lowercase hex digits; this is a stable guarantee. Equality and hashing
+/// agree with that string, so a `HashMap<Sha256, _>` can be queried with a `&str` via
+/// [`Borrow<str>`]. With the `serde` feature the hash is (de)serialized as that string.
 ///
 /// # Examples
 /// ```rust
@@ -16,38 +37,284 @@ use std::{convert::TryFrom, fmt, ops::Deref};
 /// # Ok(()) }
 /// ```
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
-pub struct Sha256(String);
+#[derive(PartialEq, Eq, Clone)]
+pub struct Sha256 {
+    hex: String,
+    bytes: [u8; 32],
+}
 
-impl From<&[u8]> for Sha256 {
-    fn from(value: &[u8]) -> Self {
-        use sha2::Digest;
+impl Sha256 {
+    /// Create the hash from a raw digest, e.g. one stored in a database.
+    ///
+    /// ```rust
+    /// use vaas::Sha256;
+    ///
+    /// let sha256 = Sha256::from_bytes([0xab; 32]);
+    /// assert_eq!("ab".repeat(32), sha256.to_string());
+    /// ```
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
         use std::fmt::Write;
 
+        let hex = bytes.iter().fold(String::with_capacity(64), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        });
+        Self { hex, bytes }
+    }
+
+    /// The raw digest.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+
+    /// Parse a list of hashes, one per line, e.g. the output of `sha256sum`. Only the first
+    /// column of each line is used, so `HASH  path` and `HASH *path` lines work. Blank
+    /// lines and lines starting with `#` are skipped.
+    ///
+    /// Returns the line number, counted from 1, with the result of each remaining line.
+    /// An error reading the list ends it with an [`Error::IoError`](crate::error::Error::IoError).
+    ///
+    /// ```rust
+    /// use vaas::Sha256;
+    ///
+    /// let list = "# nightly\n\
+    ///     275A021BBFB6489E54D471899F7DB9D1663FC695EC2FE2A2C4538AABF651FD0F  eicar.com\n\
+    ///     no-hash\n";
+    /// let parsed = Sha256::parse_list(list.as_bytes());
+    ///
+    /// assert_eq!(2, parsed[0].0);
+    /// assert!(parsed[0].1.is_ok());
+    /// assert_eq!(3, parsed[1].0);
+    /// assert!(parsed[1].1.is_err());
+    /// ```
+    pub fn parse_list(reader: impl BufRead) -> Vec<(usize, VResult<Self>)> {
+        let mut parsed = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    parsed.push((index + 1, Err(e.into())));
+                    break;
+                }
+            };
+            let Some(hash) = line.split_whitespace().next() else {
+                continue;
+            };
+            if hash.starts_with('#') {
+                continue;
+            }
+            parsed.push((index + 1, Self::try_from(hash)));
+        }
+        parsed
+    }
+
+    /// Like [`Sha256::parse_list`], but fails on the first line which is not a valid hash.
+    /// The error names the line.
+    pub fn parse_list_strict(reader: impl BufRead) -> VResult<Vec<Self>> {
+        Self::parse_list(reader)
+            .into_iter()
+            .map(|(line, result)| {
+                result.map_err(|e| match e {
+                    Error::InvalidSha256(message) => {
+                        Error::InvalidSha256(format!("line {line}: {message}"))
+                    }
+                    e => e,
+                })
+            })
+            .collect()
+    }
+
+    /// Hash everything the reader returns, reading chunks of 1 MiB. This blocks, use
+    /// [`Sha256::from_async_reader`] in async code.
+    pub fn from_reader(reader: impl Read) -> VResult<Self> {
+        Self::hash_reader(reader, DEFAULT_HASH_BUFFER_SIZE)
+    }
+
+    /// Hash everything the async reader returns, reading chunks of 1 MiB.
+    ///
+    /// ```rust
+    /// # async fn run() -> vaas::error::VResult<()> {
+    /// use vaas::Sha256;
+    ///
+    /// let sha256 = Sha256::from_async_reader(&b"hello world"[..]).await?;
+    /// assert_eq!(Sha256::from(&b"hello world"[..]), sha256);
+    /// # Ok(()) }
+    /// ```
+    pub async fn from_async_reader(mut reader: impl AsyncRead + Unpin) -> VResult<Self> {
+        let mut buf = vec![0; DEFAULT_HASH_BUFFER_SIZE];
         let mut hasher = sha2::Sha256::new();
-        hasher.update(value);
-        let result = hasher.finalize();
+        loop {
+            let read = reader.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(Self::from_digest(hasher));
+            }
+            hasher.update(&buf[..read]);
+        }
+    }
 
-        let hex_string = result.iter().fold(String::new(), |mut output, b| {
-            let _ = write!(output, "{b:02x}");
-            output
-        });
-        Self(hex_string)
+    /// Hash a file without blocking the async runtime. The file is read in chunks of 1 MiB on a blocking thread.
+    ///
+    /// ```rust,no_run
+    /// # async fn run() -> vaas::error::VResult<()> {
+    /// use std::path::Path;
+    /// use vaas::Sha256;
+    ///
+    /// let sha256 = Sha256::from_file(Path::new("sample.exe")).await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn from_file(path: &Path) -> VResult<Self> {
+        Self::from_file_with_buffer_size(path, DEFAULT_HASH_BUFFER_SIZE).await
+    }
+
+    /// Hash a file like [`Sha256::from_file`], reading chunks of the given size.
+    pub async fn from_file_with_buffer_size(path: &Path, buffer_size: usize) -> VResult<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || Self::hash_file(&path, buffer_size))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    /// Hash a file like [`Sha256::from_file`] and report the progress after each chunk, as the
+    /// number of bytes hashed and the size of the file.
+    ///
+    /// The cancellation token is checked between chunks. Once its duration has passed since
+    /// hashing started, [`Error::Cancelled`](crate::error::Error::Cancelled) is returned.
+    ///
+    /// ```rust,no_run
+    /// # async fn run() -> vaas::error::VResult<()> {
+    /// use std::path::Path;
+    /// use vaas::{CancellationToken, Sha256};
+    ///
+    /// let ct = CancellationToken::from_minutes(10);
+    /// let sha256 = Sha256::of_file_with_progress(Path::new("disk.img"), &ct, |hashed, total| {
+    ///     eprint!("\r{hashed} of {total} bytes");
+    /// })
+    /// .await?;
+    /// # Ok(()) }
+    /// ```
+    pub async fn of_file_with_progress(
+        path: &Path,
+        ct: &CancellationToken,
+        progress: impl Fn(u64, u64) + Send + 'static,
+    ) -> VResult<Self> {
+        Self::from_file_until(
+            path,
+            DEFAULT_HASH_BUFFER_SIZE,
+            Instant::now() + ct.duration,
+            progress,
+        )
+        .await
+    }
+
+    /// Hash a file on a blocking thread, failing with [`Error::Cancelled`] if the deadline passes.
+    pub(crate) async fn from_file_until(
+        path: &Path,
+        buffer_size: usize,
+        deadline: Instant,
+        progress: impl Fn(u64, u64) + Send + 'static,
+    ) -> VResult<Self> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            Self::hash_file_until(&path, buffer_size, deadline, progress)
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
+    fn hash_file_until(
+        path: &Path,
+        buffer_size: usize,
+        deadline: Instant,
+        progress: impl Fn(u64, u64),
+    ) -> VResult<Self> {
+        let mut file = File::open(path)?;
+        let total = file.metadata()?.len();
+        let mut buf = vec![0; buffer_size.max(1)];
+        let mut hasher = sha2::Sha256::new();
+        let mut hashed = 0;
+        loop {
+            if Instant::now() >= deadline {
+                return Err(Error::Cancelled);
+            }
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                return Ok(Self::from_digest(hasher));
+            }
+            hasher.update(&buf[..read]);
+            hashed += read as u64;
+            progress(hashed, total);
+        }
+    }
+
+    fn hash_file(path: &Path, buffer_size: usize) -> VResult<Self> {
+        Self::hash_reader(File::open(path)?, buffer_size)
+    }
+
+    fn hash_reader(mut reader: impl Read, buffer_size: usize) -> VResult<Self> {
+        let mut buf = vec![0; buffer_size.max(1)];
+        let mut hasher = sha2::Sha256::new();
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                return Ok(Self::from_digest(hasher));
+            }
+            hasher.update(&buf[..read]);
+        }
+    }
+
+    pub(crate) fn from_digest(hasher: sha2::Sha256) -> Self {
+        Self::from_bytes(hasher.finalize().into())
+    }
+}
+
+impl From<&[u8]> for Sha256 {
+    fn from(value: &[u8]) -> Self {
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(value);
+        Self::from_digest(hasher)
     }
 }
 
 impl TryFrom<&str> for Sha256 {
     type Error = crate::error::Error;
 
+    /// Parses 64 hex digits in any case, ignoring surrounding whitespace. The error names
+    /// the first character which is not a hex digit, counting positions from 1 after
+    /// trimming, or the length if it is not 64.
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let value = value.to_lowercase();
-        let re = Regex::new(r"^[A-Fa-f0-9]{64}$").unwrap();
+        let value = value.trim();
+        let mut digits = Vec::with_capacity(64);
+        for (index, c) in value.chars().enumerate() {
+            match c.to_digit(16) {
+                Some(digit) => digits.push(digit as u8),
+                None => {
+                    return Err(Self::Error::InvalidSha256(format!(
+                        "`{value}`: `{c}` at position {} is not a hex digit",
+                        index + 1
+                    )))
+                }
+            }
+        }
+        if digits.len() != 64 {
+            return Err(Self::Error::InvalidSha256(format!(
+                "`{value}`: expected 64 hex digits, found {}",
+                digits.len()
+            )));
+        }
 
-        if re.is_match(&value) {
-            Ok(Self(value.to_lowercase()))
-        } else {
-            Err(Self::Error::InvalidSha256(value))
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks(2)) {
+            *byte = pair[0] << 4 | pair[1];
         }
+        Ok(Self::from_bytes(bytes))
+    }
+}
+
+impl FromStr for Sha256 {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
     }
 }
 
@@ -55,7 +322,7 @@ impl Deref for Sha256 {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.hex
     }
 }
 
@@ -78,13 +345,59 @@ impl TryFrom<&Path> for Sha256 {
 
 impl fmt::Display for Sha256 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.hex)
+    }
+}
+
+impl AsRef<str> for Sha256 {
+    fn as_ref(&self) -> &str {
+        &self.hex
+    }
+}
+
+impl Borrow<str> for Sha256 {
+    fn borrow(&self) -> &str {
+        &self.hex
+    }
+}
+
+// `Borrow<str>` requires hashing like the borrowed string. The bytes are derived from the
+// hex string, so it is enough to hash that.
+impl Hash for Sha256 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hex.hash(state);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Sha256 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.hex)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Sha256 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = std::borrow::Cow::<str>::deserialize(deserializer)?;
+        Self::try_from(value.as_ref()).map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Debug for Sha256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Sha256").field(&self.hex).finish()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+    use std::hash::{BuildHasher, RandomState};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     fn try_from_valid_sha256() {
@@ -123,4 +436,352 @@ mod tests {
         )
         .is_err());
     }
+
+    #[test]
+    fn from_str_validates_like_try_from() {
+        assert_eq!(
+            Sha256::try_from(ABC).unwrap(),
+            ABC.to_uppercase().parse::<Sha256>().unwrap()
+        );
+        assert_eq!(
+            Sha256::try_from("abc").unwrap_err().to_string(),
+            "abc".parse::<Sha256>().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn hash_map_can_be_queried_with_str() {
+        let mut verdicts = std::collections::HashMap::new();
+        verdicts.insert(Sha256::try_from(ABC).unwrap(), "Clean");
+
+        assert_eq!(Some(&"Clean"), verdicts.get(ABC));
+        assert_eq!(ABC, Sha256::try_from(ABC).unwrap().as_ref());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_as_lowercase_string() {
+        let sha256: Sha256 = serde_json::from_str(&format!("\"{}\"", ABC.to_uppercase())).unwrap();
+
+        assert_eq!(Sha256::try_from(ABC).unwrap(), sha256);
+        assert_eq!(
+            format!("\"{ABC}\""),
+            serde_json::to_string(&sha256).unwrap()
+        );
+        let map: std::collections::HashMap<Sha256, u32> =
+            serde_json::from_str(&format!("{{\"{ABC}\": 1}}")).unwrap();
+        assert_eq!(Some(&1), map.get(ABC));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserializing_invalid_string_fails_with_validation_message() {
+        let error = serde_json::from_str::<Sha256>("\"abc\"").unwrap_err();
+
+        assert!(
+            error
+                .to_string()
+                .contains("Invalid SHA256: `abc`: expected 64 hex digits, found 3"),
+            "{error}"
+        );
+    }
+
+    const EICAR: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+    #[test]
+    fn parse_list_accepts_sha256sum_output_and_crlf() {
+        let list = format!(
+            "# generated by sha256sum\r\n\
+             {}  eicar.com\r\n\
+             \r\n\
+             {ABC} *abc.bin \t\r\n\
+             \t{ABC}   \n\
+             {EICAR}",
+            EICAR.to_uppercase()
+        );
+
+        let parsed = Sha256::parse_list(list.as_bytes())
+            .into_iter()
+            .map(|(line, result)| (line, result.unwrap().to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (2, EICAR.to_string()),
+                (4, ABC.to_string()),
+                (5, ABC.to_string()),
+                (6, EICAR.to_string()),
+            ],
+            parsed
+        );
+    }
+
+    #[test]
+    fn parse_list_reports_invalid_lines_with_their_number() {
+        let list = format!("{ABC}\r\nnot-a-hash  file\r\n{EICAR}\r\n");
+
+        let parsed = Sha256::parse_list(list.as_bytes());
+
+        assert_eq!(
+            vec![1, 2, 3],
+            parsed.iter().map(|(line, _)| *line).collect::<Vec<_>>()
+        );
+        assert!(parsed[0].1.is_ok());
+        assert!(matches!(parsed[1].1, Err(Error::InvalidSha256(_))));
+        assert!(parsed[2].1.is_ok());
+    }
+
+    #[test]
+    fn parse_list_strict_fails_on_the_first_invalid_line() {
+        let list = format!("{ABC}\n\n{}\n{}\n", &ABC[1..], "x".repeat(64));
+
+        let error = Sha256::parse_list_strict(list.as_bytes()).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "Invalid SHA256: line 3: `{}`: expected 64 hex digits, found 63",
+                &ABC[1..]
+            ),
+            error.to_string()
+        );
+        assert_eq!(
+            vec![
+                Sha256::try_from(ABC).unwrap(),
+                Sha256::try_from(EICAR).unwrap()
+            ],
+            Sha256::parse_list_strict(format!("{ABC}  a\r\n# b\r\n{EICAR}  c \r\n").as_bytes())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn try_from_trims_whitespace() {
+        let sha256 = Sha256::try_from(&format!(" \t{ABC}\n")[..]).unwrap();
+
+        assert_eq!(ABC, sha256.deref());
+    }
+
+    #[test]
+    fn try_from_reports_the_position_of_a_non_hex_character() {
+        let error = Sha256::try_from(&format!("  {}g{}", &ABC[..9], &ABC[10..])[..]).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "Invalid SHA256: `{}g{}`: `g` at position 10 is not a hex digit",
+                &ABC[..9],
+                &ABC[10..]
+            ),
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn try_from_reports_the_wrong_length() {
+        let error = Sha256::try_from(&ABC[1..]).unwrap_err();
+
+        assert_eq!(
+            format!(
+                "Invalid SHA256: `{}`: expected 64 hex digits, found 63",
+                &ABC[1..]
+            ),
+            error.to_string()
+        );
+        assert!(Sha256::try_from("").is_err());
+        assert!(Sha256::try_from(&format!("{ABC} {ABC}")[..]).is_err());
+    }
+
+    fn random_case(hex: &str, upper: &[bool]) -> String {
+        hex.chars()
+            .zip(upper.iter().cycle())
+            .map(|(c, upper)| if *upper { c.to_ascii_uppercase() } else { c })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn parsing_random_case_is_normalized(
+            bytes in any::<[u8; 32]>(),
+            upper in proptest::collection::vec(any::<bool>(), 64),
+        ) {
+            let lower = Sha256::from_bytes(bytes);
+            let input = random_case(&lower, &upper);
+
+            let parsed = Sha256::try_from(input.as_str()).unwrap();
+
+            prop_assert_eq!(&lower, &parsed);
+            prop_assert_eq!(lower.to_string(), parsed.to_string());
+            prop_assert_eq!(&bytes, parsed.as_bytes());
+        }
+
+        #[test]
+        fn parsing_is_idempotent(
+            bytes in any::<[u8; 32]>(),
+            upper in proptest::collection::vec(any::<bool>(), 64),
+        ) {
+            let input = random_case(&Sha256::from_bytes(bytes), &upper);
+
+            let once = Sha256::try_from(input.as_str()).unwrap();
+            let twice = Sha256::try_from(once.to_string().as_str()).unwrap();
+
+            prop_assert_eq!(&once, &twice);
+            prop_assert_eq!(once.to_string(), twice.to_string());
+            let state = RandomState::new();
+            prop_assert_eq!(state.hash_one(&once), state.hash_one(&twice));
+        }
+    }
+
+    #[tokio::test]
+    async fn of_file_with_progress_reports_every_chunk() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let content: Vec<u8> = (0..=255).cycle().take(2 * 1024 * 1024 + 5).collect();
+        file.write_all(&content).unwrap();
+        file.flush().unwrap();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = reports.clone();
+
+        let sha256 = Sha256::of_file_with_progress(
+            file.path(),
+            &CancellationToken::from_seconds(10),
+            move |hashed, total| recorded.lock().unwrap().push((hashed, total)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(Sha256::from(content.as_slice()), sha256);
+        let total = content.len() as u64;
+        assert_eq!(Some(&(total, total)), reports.lock().unwrap().last());
+        assert!(reports.lock().unwrap().windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[tokio::test]
+    async fn of_file_with_progress_is_cancelled_mid_hash() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        // Sparse, so it takes no space but far longer to hash than the token allows.
+        let size = 64 * 1024 * 1024 * 1024;
+        file.as_file().set_len(size).unwrap();
+        let hashed = Arc::new(AtomicUsize::new(0));
+        let recorded = hashed.clone();
+
+        let result = Sha256::of_file_with_progress(
+            file.path(),
+            &CancellationToken::from(std::time::Duration::from_millis(200)),
+            move |bytes, total| {
+                assert_eq!(size, total);
+                recorded.store(bytes as usize, Ordering::Relaxed);
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        let hashed = hashed.load(Ordering::Relaxed) as u64;
+        assert!(hashed > 0 && hashed < size, "hashed {hashed} bytes");
+    }
+
+    #[tokio::test]
+    async fn from_file_hashes_large_file_like_sync_hash() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let chunk: Vec<u8> = (0..=255).cycle().take(1024 * 1024).collect();
+        for _ in 0..100 {
+            file.write_all(&chunk).unwrap();
+        }
+        file.flush().unwrap();
+
+        let sha256 = Sha256::from_file(file.path()).await.unwrap();
+
+        assert_eq!(Sha256::try_from(file.path()).unwrap(), sha256);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn from_file_does_not_block_the_runtime() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let chunk: Vec<u8> = (0..=255).cycle().take(1024 * 1024).collect();
+        for _ in 0..32 {
+            file.write_all(&chunk).unwrap();
+        }
+        file.flush().unwrap();
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let ticks = ticks.clone();
+            tokio::spawn(async move {
+                loop {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    tokio::task::yield_now().await;
+                }
+            })
+        };
+
+        Sha256::from_file(file.path()).await.unwrap();
+
+        // A hash computed on the only runtime thread would not let the ticker run before it is done.
+        assert!(ticks.load(Ordering::SeqCst) > 0);
+        ticker.abort();
+    }
+
+    #[tokio::test]
+    async fn from_file_with_buffer_size_not_dividing_file_size() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"hello world").unwrap();
+
+        let sha256 = Sha256::from_file_with_buffer_size(file.path(), 3)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            sha256.deref()
+        );
+    }
+
+    // The digest of "abc" from FIPS 180-2.
+    const ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn hex_string_and_bytes_round_trip() {
+        let sha256 = Sha256::try_from(ABC.to_uppercase().as_str()).unwrap();
+
+        assert_eq!(0xba, sha256.as_bytes()[0]);
+        assert_eq!(0xad, sha256.as_bytes()[31]);
+        let from_bytes = Sha256::from_bytes(*sha256.as_bytes());
+        assert_eq!(sha256, from_bytes);
+        assert_eq!(ABC, from_bytes.deref());
+    }
+
+    #[test]
+    fn from_reader_matches_known_digest() {
+        let sha256 = Sha256::from_reader(&b"abc"[..]).unwrap();
+
+        assert_eq!(ABC, sha256.deref());
+        assert_eq!(Sha256::from(&b"abc"[..]), sha256);
+    }
+
+    #[tokio::test]
+    async fn from_async_reader_matches_sync_hash() {
+        let content: Vec<u8> = (0..=255).cycle().take(3 * 1024 * 1024 + 7).collect();
+
+        let sha256 = Sha256::from_async_reader(content.as_slice()).await.unwrap();
+
+        assert_eq!(Sha256::from(content.as_slice()), sha256);
+        assert_eq!(
+            ABC,
+            Sha256::from_async_reader(&b"abc"[..])
+                .await
+                .unwrap()
+                .deref()
+        );
+    }
+
+    #[test]
+    fn debug_shows_the_hex_string() {
+        assert_eq!(
+            format!("Sha256(\"{ABC}\")"),
+            format!("{:?}", Sha256::try_from(ABC).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn from_file_with_missing_file_fails() {
+        let result = Sha256::from_file(Path::new("does/not/exist")).await;
+
+        assert!(matches!(result, Err(crate::error::Error::IoError(_))));
+    }
 }
@@ -0,0 +1,219 @@
+//! Runtime statistics of a [`Connection`](crate::Connection), see [`Connection::stats`](crate::Connection::stats).
+
+use crate::error::{Error, VResult};
+use crate::message::VerdictResponse;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Counters of a connection which are updated while requests are processed.
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    requests_sent: AtomicU64,
+    clean_verdicts: AtomicU64,
+    malicious_verdicts: AtomicU64,
+    pup_verdicts: AtomicU64,
+    unknown_verdicts: AtomicU64,
+    uploads: AtomicU64,
+    bytes_uploaded: AtomicU64,
+    timeouts: AtomicU64,
+    in_flight: AtomicU64,
+    cache_hits: AtomicU64,
+    throttled_micros: AtomicU64,
+    malformed_messages: AtomicU64,
+}
+
+impl Stats {
+    pub fn request_sent(&self) {
+        self.requests_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the verdict of a successful response, or the timeout of a failed one.
+    pub fn response_received(&self, response: &VResult<VerdictResponse>) {
+        let response = match response {
+            Ok(response) => response,
+            Err(e) => return self.request_failed(e),
+        };
+        let counter = match response.verdict.as_str() {
+            "Clean" => &self.clean_verdicts,
+            "Malicious" => &self.malicious_verdicts,
+            "Pup" => &self.pup_verdicts,
+            "Unknown" => &self.unknown_verdicts,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn request_failed(&self, error: &Error) {
+        if matches!(error, Error::Cancelled | Error::UploadTimeout(_)) {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn upload_completed(&self, bytes: usize) {
+        self.uploads.fetch_add(1, Ordering::Relaxed);
+        self.bytes_uploaded
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn cache_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn malformed_message(&self) {
+        self.malformed_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Add the time a request waited for the rate limit.
+    pub fn throttled(&self, waited: Duration) {
+        let micros = u64::try_from(waited.as_micros()).unwrap_or(u64::MAX);
+        self.throttled_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Count a request as in flight until the returned guard is dropped.
+    pub fn request_started(&self) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(&self.in_flight)
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            clean_verdicts: self.clean_verdicts.load(Ordering::Relaxed),
+            malicious_verdicts: self.malicious_verdicts.load(Ordering::Relaxed),
+            pup_verdicts: self.pup_verdicts.load(Ordering::Relaxed),
+            unknown_verdicts: self.unknown_verdicts.load(Ordering::Relaxed),
+            uploads: self.uploads.load(Ordering::Relaxed),
+            bytes_uploaded: self.bytes_uploaded.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            cache_hits: self.cache_hits.load(Ordering::Relaxed),
+            throttled_millis: self.throttled_micros.load(Ordering::Relaxed) / 1000,
+            malformed_messages: self.malformed_messages.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A request counted as in flight, see [`Stats::request_started`].
+pub(crate) struct InFlight<'a>(&'a AtomicU64);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A copy of the statistics of a connection at one point in time.
+///
+/// An `Unknown` verdict is counted when VaaS does not know a file and requests an upload.
+/// The verdict after the upload is counted again, so the sum of the verdicts can exceed the number of requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StatsSnapshot {
+    /// Number of verdict requests sent to VaaS.
+    pub requests_sent: u64,
+    /// Number of `Clean` verdicts received.
+    pub clean_verdicts: u64,
+    /// Number of `Malicious` verdicts received.
+    pub malicious_verdicts: u64,
+    /// Number of `Pup` verdicts received.
+    pub pup_verdicts: u64,
+    /// Number of `Unknown` verdicts received.
+    pub unknown_verdicts: u64,
+    /// Number of successful file uploads.
+    pub uploads: u64,
+    /// Number of bytes of successful file uploads.
+    pub bytes_uploaded: u64,
+    /// Number of requests which were cancelled or whose upload timed out.
+    pub timeouts: u64,
+    /// Number of verdict requests which are being processed. Requests waiting for a free slot
+    /// of [`Builder::max_in_flight`](crate::Builder::max_in_flight) are not counted.
+    pub in_flight: u64,
+    /// Number of requests answered from the local cache without a request to VaaS, see
+    /// [`Builder::local_cache`](crate::Builder::local_cache). They are not counted as received verdicts.
+    pub cache_hits: u64,
+    /// Total time in milliseconds requests waited for the rate limit of
+    /// [`Builder::rate_limit`](crate::Builder::rate_limit).
+    pub throttled_millis: u64,
+    /// Number of messages from VaaS which could not be parsed. They only fail the requests whose GUID they
+    /// contain, if any.
+    pub malformed_messages: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(verdict: &str) -> VResult<VerdictResponse> {
+        let json = format!(
+            r#"{{"sha256":"275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f","guid":"ed7207a5-d65a-4400-b91c-673ff39cfd8b","verdict":"{verdict}","url":"https://upload.test"}}"#
+        );
+        Ok(serde_json::from_str(&json).unwrap())
+    }
+
+    #[test]
+    fn snapshot_counts_requests_verdicts_uploads_and_timeouts() {
+        let stats = Stats::default();
+
+        for verdict in ["Unknown", "Clean", "Malicious", "Malicious", "Pup"] {
+            stats.request_sent();
+            stats.response_received(&response(verdict));
+        }
+        stats.upload_completed(1024);
+        stats.upload_completed(512);
+        stats.response_received(&Err(Error::Cancelled));
+        stats.response_received(&Err(Error::UploadTimeout(Duration::from_secs(1))));
+        stats.response_received(&Err(Error::ConnectionClosed));
+
+        assert_eq!(
+            StatsSnapshot {
+                requests_sent: 5,
+                clean_verdicts: 1,
+                malicious_verdicts: 2,
+                pup_verdicts: 1,
+                unknown_verdicts: 1,
+                uploads: 2,
+                bytes_uploaded: 1536,
+                timeouts: 2,
+                in_flight: 0,
+                cache_hits: 0,
+                throttled_millis: 0,
+                malformed_messages: 0,
+            },
+            stats.snapshot()
+        );
+    }
+
+    #[test]
+    fn in_flight_counts_started_requests_until_dropped() {
+        let stats = Stats::default();
+
+        let first = stats.request_started();
+        let second = stats.request_started();
+        assert_eq!(2, stats.snapshot().in_flight);
+
+        drop(first);
+        drop(second);
+        assert_eq!(0, stats.snapshot().in_flight);
+    }
+
+    #[test]
+    fn throttled_time_is_summed_in_milliseconds() {
+        let stats = Stats::default();
+
+        stats.throttled(Duration::from_micros(1500));
+        stats.throttled(Duration::from_micros(700));
+
+        assert_eq!(2, stats.snapshot().throttled_millis);
+    }
+
+    #[test]
+    fn snapshot_serializes_to_json() {
+        let stats = Stats::default();
+        stats.request_sent();
+
+        let json = serde_json::to_value(stats.snapshot()).unwrap();
+
+        assert_eq!(1, json["requests_sent"]);
+        assert_eq!(0, json["bytes_uploaded"]);
+    }
+}
@@ -0,0 +1,695 @@
+//! # Test utilities
+//!
+//! [`MockVaas`] is a local VaaS server for integration tests, which speaks the websocket protocol of VaaS and
+//! accepts uploads over plain HTTP. It answers verdict requests with scripted verdicts, and records the requests and
+//! uploads for assertions. Requires the `test-utils` feature, which also provides [`MockScanner`] to test code
+//! which takes a [`VaasScanner`](crate::VaasScanner) without any server.
+//!
+//! ```rust
+//! # #[tokio::main]
+//! # async fn main() -> vaas::error::VResult<()> {
+//! use vaas::message::Verdict;
+//! use vaas::test_utils::MockVaas;
+//! use vaas::Sha256;
+//!
+//! let content = b"unknown content".to_vec();
+//! let sha256 = Sha256::from(content.as_slice());
+//! let malicious = Verdict::Malicious { detection: "EICAR".to_string() };
+//! let server = MockVaas::new()
+//!     .verdict_for(sha256.clone(), malicious.clone())
+//!     .expect_upload_for(sha256.clone())
+//!     .start()
+//!     .await?;
+//!
+//! let connection = server.connect().await?;
+//! let verdict = connection.for_buf(content, None).await?;
+//!
+//! assert_eq!(malicious, verdict.verdict);
+//! server.assert_expected_uploads();
+//! # Ok(())
+//! # }
+//! ```
+
+pub use crate::scanner::MockScanner;
+
+use crate::auth::Authenticator;
+use crate::builder::Builder;
+use crate::connection::Connection;
+use crate::error::VResult;
+use crate::message::Verdict;
+use crate::sha256::Sha256;
+use crate::vaas::Vaas;
+use async_compression::tokio::bufread::GzipDecoder;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_ENCODING};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::convert::{Infallible, TryFrom};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+/// The token [`MockAuthenticator`] and the token endpoint of [`MockVaasServer`] hand out.
+pub const MOCK_TOKEN: &str = "mock-token";
+/// The token uploads to [`MockVaasServer`] have to be authorized with.
+const UPLOAD_TOKEN: &str = "mock-upload-token";
+
+/// The script of a [`MockVaasServer`]: which verdicts it answers with and which files it asks to be uploaded.
+///
+/// Hashes without a scripted verdict are answered with the default verdict, which is [`Verdict::Clean`] unless
+/// set with [`MockVaas::default_verdict`]. URLs are answered with the SHA256 of the URL, as nothing is downloaded.
+/// Streams are always uploaded, their verdict is the one scripted for the SHA256 of the uploaded content.
+#[derive(Debug, Clone)]
+pub struct MockVaas {
+    verdicts: HashMap<Sha256, Verdict>,
+    url_verdicts: HashMap<String, Verdict>,
+    uploads: HashSet<Sha256>,
+    default_verdict: Verdict,
+    faults: FaultPlan,
+}
+
+impl Default for MockVaas {
+    fn default() -> Self {
+        Self {
+            verdicts: HashMap::new(),
+            url_verdicts: HashMap::new(),
+            uploads: HashSet::new(),
+            default_verdict: Verdict::Clean,
+            faults: FaultPlan::default(),
+        }
+    }
+}
+
+impl MockVaas {
+    /// A script answering every request with [`Verdict::Clean`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer requests for the SHA256 with the verdict, or upload the file first if
+    /// [`expect_upload_for`](MockVaas::expect_upload_for) is set for it.
+    pub fn verdict_for(mut self, sha256: Sha256, verdict: Verdict) -> Self {
+        self.verdicts.insert(sha256, verdict);
+        self
+    }
+
+    /// Answer requests for the URL with the verdict.
+    pub fn verdict_for_url(mut self, url: &Url, verdict: Verdict) -> Self {
+        self.url_verdicts.insert(url.to_string(), verdict);
+        self
+    }
+
+    /// Answer requests without a scripted verdict with the given one.
+    pub fn default_verdict(self, verdict: Verdict) -> Self {
+        Self {
+            default_verdict: verdict,
+            ..self
+        }
+    }
+
+    /// Answer requests for the SHA256 with `Unknown` and an upload URL of the server. The scripted verdict is sent
+    /// once the file is uploaded. See [`MockVaasServer::assert_expected_uploads`].
+    pub fn expect_upload_for(mut self, sha256: Sha256) -> Self {
+        self.uploads.insert(sha256);
+        self
+    }
+
+    /// Inject the faults of the plan, e.g. to test reconnects, retries and timeouts.
+    pub fn with_faults(self, faults: FaultPlan) -> Self {
+        Self { faults, ..self }
+    }
+
+    /// Start the server on random local ports. It is shut down when the returned server is dropped.
+    pub async fn start(self) -> VResult<MockVaasServer> {
+        let websocket = TcpListener::bind("127.0.0.1:0").await?;
+        let http = TcpListener::bind("127.0.0.1:0").await?;
+        let websocket_addr = websocket.local_addr()?;
+        let http_addr = http.local_addr()?;
+        let state = Arc::new(State {
+            script: self,
+            upload_url: format!("http://{http_addr}/upload/"),
+            sessions: AtomicUsize::new(0),
+            rejected_uploads: AtomicUsize::new(0),
+            requests: Mutex::new(Vec::new()),
+            uploads: Mutex::new(Vec::new()),
+            pending_uploads: Mutex::new(HashMap::new()),
+        });
+        let tasks = vec![
+            tokio::spawn(accept_websockets(websocket, state.clone())),
+            tokio::spawn(accept_http(http, state.clone())),
+        ];
+        Ok(MockVaasServer {
+            websocket_addr,
+            http_addr,
+            state,
+            tasks,
+        })
+    }
+}
+
+/// Faults a [`MockVaasServer`] injects, to test how clients cope with an unreliable VaaS.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// use vaas::test_utils::{FaultPlan, MockVaas};
+///
+/// let script = MockVaas::new().with_faults(
+///     FaultPlan::new()
+///         .drop_connection_after(3)
+///         .delay_responses(Duration::from_millis(100))
+///         .reject_uploads(1),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FaultPlan {
+    drop_after: Option<usize>,
+    response_delay: Duration,
+    garbage_for: HashSet<Sha256>,
+    rejected_uploads: usize,
+    stalled_pongs: Option<Duration>,
+}
+
+impl FaultPlan {
+    /// A plan without any faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop each websocket connection without a close frame once it received `messages` messages, including the
+    /// authentication request. Answers to them are still sent, unless they are delayed.
+    pub fn drop_connection_after(self, messages: usize) -> Self {
+        Self {
+            drop_after: Some(messages),
+            ..self
+        }
+    }
+
+    /// Send every message, including the authentication response and verdicts after uploads, only after the delay.
+    pub fn delay_responses(self, delay: Duration) -> Self {
+        Self {
+            response_delay: delay,
+            ..self
+        }
+    }
+
+    /// Answer requests for the SHA256 with truncated JSON, which still contains the guid of the request.
+    pub fn garbage_for(mut self, sha256: Sha256) -> Self {
+        self.garbage_for.insert(sha256);
+        self
+    }
+
+    /// Answer the first `attempts` uploads with `503 Service Unavailable`. The request stays pending, so a retried
+    /// upload succeeds.
+    pub fn reject_uploads(self, attempts: usize) -> Self {
+        Self {
+            rejected_uploads: attempts,
+            ..self
+        }
+    }
+
+    /// Neither read nor write on the connection for the duration after receiving a ping, so the pong and any
+    /// answers are stalled.
+    pub fn stall_pongs(self, duration: Duration) -> Self {
+        Self {
+            stalled_pongs: Some(duration),
+            ..self
+        }
+    }
+}
+
+/// A running [`MockVaas`] server, see the [module documentation](self).
+#[derive(Debug)]
+pub struct MockVaasServer {
+    websocket_addr: SocketAddr,
+    http_addr: SocketAddr,
+    state: Arc<State>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl MockVaasServer {
+    /// The websocket URL to connect to, see [`Builder::url`].
+    pub fn url(&self) -> Url {
+        Url::parse(&format!("ws://{}", self.websocket_addr))
+            .expect("socket address is a valid host")
+    }
+
+    /// The URL of a token endpoint, which grants [`MOCK_TOKEN`] for any credentials, e.g. for
+    /// [`ClientCredentials::with_token_url`](crate::auth::authenticators::ClientCredentials::with_token_url).
+    pub fn token_url(&self) -> Url {
+        Url::parse(&format!("http://{}/token", self.http_addr))
+            .expect("socket address is a valid host")
+    }
+
+    /// A builder for a [`Vaas`] instance connecting to this server, which uploads to its plain HTTP endpoint.
+    pub fn builder<A: Authenticator>(&self, authenticator: A) -> Builder<A> {
+        Vaas::builder(authenticator)
+            .url(self.url())
+            .allow_any_upload_host()
+    }
+
+    /// Connect to this server with [`MockAuthenticator`] and the default configuration.
+    pub async fn connect(&self) -> VResult<Connection> {
+        self.builder(MockAuthenticator).build()?.connect().await
+    }
+
+    /// The number of authenticated websocket sessions so far, e.g. to assert a reconnect.
+    pub fn sessions(&self) -> usize {
+        self.state.sessions.load(Ordering::Relaxed)
+    }
+
+    /// The number of uploads rejected by [`FaultPlan::reject_uploads`] so far.
+    pub fn rejected_uploads(&self) -> usize {
+        self.state.rejected_uploads.load(Ordering::Relaxed)
+    }
+
+    /// The verdict requests received so far, as JSON messages.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// The uploads received so far.
+    pub fn uploads(&self) -> Vec<RecordedUpload> {
+        self.state.uploads.lock().unwrap().clone()
+    }
+
+    /// Panic if a file set with [`MockVaas::expect_upload_for`] has not been uploaded.
+    #[track_caller]
+    pub fn assert_expected_uploads(&self) {
+        let uploaded = self
+            .uploads()
+            .into_iter()
+            .map(|upload| upload.sha256)
+            .collect::<HashSet<_>>();
+        let missing = self
+            .state
+            .script
+            .uploads
+            .iter()
+            .filter(|sha256| !uploaded.contains(*sha256))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        assert!(missing.is_empty(), "expected uploads of {missing:?}");
+    }
+}
+
+impl Drop for MockVaasServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// An upload received by a [`MockVaasServer`].
+#[derive(Debug, Clone)]
+pub struct RecordedUpload {
+    /// The SHA256 of the verdict request, or of the content for streams.
+    pub sha256: Sha256,
+    /// The uploaded content, decompressed if it was sent gzip compressed.
+    pub content: Bytes,
+    /// The headers of the upload request.
+    pub headers: HeaderMap,
+}
+
+/// An [`Authenticator`] returning [`MOCK_TOKEN`], which [`MockVaasServer`] accepts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MockAuthenticator;
+
+#[async_trait]
+impl Authenticator for MockAuthenticator {
+    async fn get_token(&self) -> VResult<String> {
+        Ok(MOCK_TOKEN.to_string())
+    }
+}
+
+/// A request waiting for its upload, by guid.
+#[derive(Debug)]
+struct PendingUpload {
+    /// `None` for streams, whose SHA256 is only known after the upload.
+    sha256: Option<Sha256>,
+    session: UnboundedSender<String>,
+}
+
+#[derive(Debug)]
+struct State {
+    script: MockVaas,
+    /// The URL uploads are sent to, followed by the guid of the request.
+    upload_url: String,
+    sessions: AtomicUsize,
+    rejected_uploads: AtomicUsize,
+    requests: Mutex<Vec<Value>>,
+    uploads: Mutex<Vec<RecordedUpload>>,
+    pending_uploads: Mutex<HashMap<String, PendingUpload>>,
+}
+
+impl State {
+    /// The answer to a message of a client, if any.
+    fn answer(&self, message: &str, session: &UnboundedSender<String>) -> Option<String> {
+        let request: Value = serde_json::from_str(message).ok()?;
+        let guid = request["guid"].as_str().unwrap_or_default().to_string();
+        match request["kind"].as_str()? {
+            "AuthRequest" => {
+                let session_id = self.sessions.fetch_add(1, Ordering::Relaxed);
+                let response = json!({
+                    "kind": "AuthResponse",
+                    "success": true,
+                    "session_id": format!("mock-session-{session_id}"),
+                    "text": "",
+                });
+                Some(response.to_string())
+            }
+            "VerdictRequest" => {
+                self.requests.lock().unwrap().push(request.clone());
+                let sha256 = Sha256::try_from(request["sha256"].as_str()?).ok()?;
+                if self.script.faults.garbage_for.contains(&sha256) {
+                    return Some(format!(
+                        r#"{{"kind":"VerdictResponse","guid":"{guid}","verdict":"#
+                    ));
+                }
+                if self.script.uploads.contains(&sha256) {
+                    return Some(self.request_upload(guid, Some(sha256), session));
+                }
+                Some(verdict_response(&guid, &sha256, self.verdict(&sha256)))
+            }
+            "VerdictRequestForUrl" => {
+                self.requests.lock().unwrap().push(request.clone());
+                let url = request["url"].as_str()?;
+                let verdict = self
+                    .script
+                    .url_verdicts
+                    .get(url)
+                    .unwrap_or(&self.script.default_verdict);
+                Some(verdict_response(
+                    &guid,
+                    &Sha256::from(url.as_bytes()),
+                    verdict,
+                ))
+            }
+            "VerdictRequestForStream" => {
+                self.requests.lock().unwrap().push(request.clone());
+                Some(self.request_upload(guid, None, session))
+            }
+            _ => None,
+        }
+    }
+
+    /// Send the message to the session, after the delay of [`FaultPlan::delay_responses`].
+    fn send(&self, session: &UnboundedSender<String>, message: String) {
+        let delay = self.script.faults.response_delay;
+        if delay.is_zero() {
+            session.send(message).ok();
+            return;
+        }
+        let session = session.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            session.send(message).ok();
+        });
+    }
+
+    fn verdict(&self, sha256: &Sha256) -> &Verdict {
+        self.script
+            .verdicts
+            .get(sha256)
+            .unwrap_or(&self.script.default_verdict)
+    }
+
+    /// Register the pending upload and answer with `Unknown` and its upload URL.
+    fn request_upload(
+        &self,
+        guid: String,
+        sha256: Option<Sha256>,
+        session: &UnboundedSender<String>,
+    ) -> String {
+        let response = json!({
+            "kind": "VerdictResponse",
+            "sha256": sha256.as_ref().map(ToString::to_string).unwrap_or_default(),
+            "guid": guid,
+            "verdict": "Unknown",
+            "url": format!("{}{guid}", self.upload_url),
+            "upload_token": UPLOAD_TOKEN,
+        });
+        let upload = PendingUpload {
+            sha256,
+            session: session.clone(),
+        };
+        self.pending_uploads.lock().unwrap().insert(guid, upload);
+        response.to_string()
+    }
+
+    /// Record the upload and send the verdict of the uploaded file to the session which requested it.
+    async fn upload(&self, request: Request<Incoming>) -> StatusCode {
+        let authorized = request
+            .headers()
+            .get(AUTHORIZATION)
+            .is_some_and(|token| token == UPLOAD_TOKEN);
+        if !authorized {
+            return StatusCode::UNAUTHORIZED;
+        }
+        let guid = request
+            .uri()
+            .path()
+            .trim_start_matches("/upload/")
+            .to_string();
+        let rejected =
+            self.rejected_uploads
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |rejected| {
+                    (rejected < self.script.faults.rejected_uploads).then_some(rejected + 1)
+                });
+        if rejected.is_ok() {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        let Some(pending) = self.pending_uploads.lock().unwrap().remove(&guid) else {
+            return StatusCode::NOT_FOUND;
+        };
+        let headers = request.headers().clone();
+        let Ok(body) = request.into_body().collect().await else {
+            return StatusCode::BAD_REQUEST;
+        };
+        let mut content = body.to_bytes();
+        if headers.get(CONTENT_ENCODING).is_some_and(|e| e == "gzip") {
+            let mut decompressed = Vec::new();
+            let mut decoder = GzipDecoder::new(content.as_ref());
+            if decoder.read_to_end(&mut decompressed).await.is_err() {
+                return StatusCode::BAD_REQUEST;
+            }
+            content = Bytes::from(decompressed);
+        }
+        let sha256 = pending
+            .sha256
+            .unwrap_or_else(|| Sha256::from(content.as_ref()));
+        let verdict = verdict_response(&guid, &sha256, self.verdict(&sha256));
+        self.uploads.lock().unwrap().push(RecordedUpload {
+            sha256,
+            content,
+            headers,
+        });
+        self.send(&pending.session, verdict);
+        StatusCode::OK
+    }
+}
+
+fn verdict_response(guid: &str, sha256: &Sha256, verdict: &Verdict) -> String {
+    let (name, detection, url) = match verdict {
+        Verdict::Clean => ("Clean", None, None),
+        Verdict::Malicious { detection } => ("Malicious", Some(detection), None),
+        Verdict::Pup { detection } => ("Pup", Some(detection), None),
+        Verdict::Unknown { upload_url } => ("Unknown", None, Some(upload_url.to_string())),
+        Verdict::Other(name) => (name.as_str(), None, None),
+    };
+    json!({
+        "kind": "VerdictResponse",
+        "sha256": sha256.to_string(),
+        "guid": guid,
+        "verdict": name,
+        "detection": detection,
+        "url": url,
+        "upload_token": url.as_ref().map(|_| UPLOAD_TOKEN),
+    })
+    .to_string()
+}
+
+async fn accept_websockets(listener: TcpListener, state: Arc<State>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(serve_websocket(stream, state.clone()));
+    }
+}
+
+async fn serve_websocket(stream: TcpStream, state: Arc<State>) {
+    let Ok(mut websocket) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    let faults = &state.script.faults;
+    let (session, mut outgoing) = unbounded_channel::<String>();
+    let mut received = 0;
+    loop {
+        tokio::select! {
+            message = websocket.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if let Some(answer) = state.answer(text.as_str(), &session) {
+                        state.send(&session, answer);
+                    }
+                    received += 1;
+                }
+                Some(Ok(Message::Ping(_))) => {
+                    // Tungstenite queues the pong, which is flushed on the next read or write.
+                    if let Some(stall) = faults.stalled_pongs {
+                        tokio::time::sleep(stall).await;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            Some(message) = outgoing.recv() => {
+                if websocket.send(Message::text(message)).await.is_err() {
+                    break;
+                }
+            }
+        }
+        if faults
+            .drop_after
+            .is_some_and(|messages| received >= messages)
+        {
+            // Answers are sent before the connection is dropped.
+            while let Ok(message) = outgoing.try_recv() {
+                websocket.send(Message::text(message)).await.ok();
+            }
+            break;
+        }
+    }
+}
+
+async fn accept_http(listener: TcpListener, state: Arc<State>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let state = state.clone();
+        let service = service_fn(move |request| {
+            let state = state.clone();
+            async move { Ok::<_, Infallible>(serve_http(&state, request).await) }
+        });
+        tokio::spawn(async move {
+            let connection = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service);
+            connection.await.ok();
+        });
+    }
+}
+
+async fn serve_http(state: &State, request: Request<Incoming>) -> Response<Full<Bytes>> {
+    let (status, body) = match (request.method(), request.uri().path()) {
+        (&Method::POST, "/token") => {
+            let token = json!({
+                "access_token": MOCK_TOKEN,
+                "expires_in": 300,
+                "token_type": "Bearer",
+            });
+            (StatusCode::OK, token.to_string())
+        }
+        (&Method::PUT, path) if path.starts_with("/upload/") => {
+            (state.upload(request).await, String::new())
+        }
+        _ => (StatusCode::NOT_FOUND, String::new()),
+    };
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::authenticators::ClientCredentials;
+
+    #[tokio::test]
+    async fn scripted_verdicts_are_answered() {
+        let malicious = Verdict::Malicious {
+            detection: "EICAR".to_string(),
+        };
+        let sha256 = Sha256::from(&b"malicious"[..]);
+        let server = MockVaas::new()
+            .verdict_for(sha256.clone(), malicious.clone())
+            .start()
+            .await
+            .unwrap();
+        let connection = server.connect().await.unwrap();
+
+        let verdict = connection.for_sha256(&sha256, None).await.unwrap();
+        let clean = connection
+            .for_sha256(&Sha256::from(&b"clean"[..]), None)
+            .await
+            .unwrap();
+
+        assert_eq!(malicious, verdict.verdict);
+        assert_eq!(Verdict::Clean, clean.verdict);
+        assert_eq!(2, server.requests().len());
+        assert!(server.uploads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn compressed_upload_is_recorded_decompressed() {
+        let content = b"unknown content".to_vec();
+        let sha256 = Sha256::from(content.as_slice());
+        let server = MockVaas::new()
+            .expect_upload_for(sha256.clone())
+            .start()
+            .await
+            .unwrap();
+        let connection = server
+            .builder(MockAuthenticator)
+            .compress_uploads(true)
+            .build()
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+
+        let verdict = connection.for_buf(content.clone(), None).await.unwrap();
+
+        assert_eq!(Verdict::Clean, verdict.verdict);
+        server.assert_expected_uploads();
+        let uploads = server.uploads();
+        assert_eq!(content, uploads[0].content);
+        assert_eq!("gzip", uploads[0].headers[CONTENT_ENCODING]);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "expected uploads")]
+    async fn missing_upload_fails_the_assertion() {
+        let server = MockVaas::new()
+            .expect_upload_for(Sha256::from(&b"never uploaded"[..]))
+            .start()
+            .await
+            .unwrap();
+
+        server.assert_expected_uploads();
+    }
+
+    #[tokio::test]
+    async fn token_endpoint_grants_tokens_for_client_credentials() {
+        let server = MockVaas::new().start().await.unwrap();
+        let authenticator = ClientCredentials::new("id".to_string(), "secret".to_string())
+            .with_token_url(server.token_url());
+
+        let connection = server
+            .builder(authenticator)
+            .build()
+            .unwrap()
+            .connect()
+            .await;
+
+        assert!(connection.is_ok(), "{connection:?}");
+    }
+}
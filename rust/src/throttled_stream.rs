@@ -0,0 +1,120 @@
+use crate::hashing_stream::BoxedByteStream;
+use bytes::Bytes;
+use futures_util::stream::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant, Sleep};
+
+/// The largest chunk which is passed on at once. Smaller chunks keep the rate steady.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Limits the rate at which the content of a stream is passed on, to not saturate slow network links with uploads.
+///
+/// The content is split into chunks of about 1/20 of the rate. Before each chunk, the stream sleeps until the previous
+/// ones would have been sent at the configured rate, so at most one chunk is sent ahead of it.
+pub(crate) struct ThrottledStream {
+    inner: BoxedByteStream,
+    bytes_per_sec: u64,
+    chunk_size: usize,
+    pending: Bytes,
+    next_chunk_at: Instant,
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl ThrottledStream {
+    pub fn new(inner: BoxedByteStream, bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1);
+        Self {
+            inner,
+            bytes_per_sec,
+            chunk_size: (bytes_per_sec / 20).clamp(1, MAX_CHUNK_SIZE as u64) as usize,
+            pending: Bytes::new(),
+            next_chunk_at: Instant::now(),
+            delay: None,
+        }
+    }
+
+    /// The time it takes to send the given number of bytes at the configured rate.
+    fn send_time(&self, len: usize) -> Duration {
+        Duration::from_secs_f64(len as f64 / self.bytes_per_sec as f64)
+    }
+}
+
+impl Stream for ThrottledStream {
+    type Item = Result<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.pending.is_empty() {
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(chunk)) => this.pending = chunk,
+                other => return Poll::Ready(other),
+            }
+        }
+        // Only wait if there is more to send, so the end of the stream is not delayed.
+        if let Some(delay) = &mut this.delay {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+
+        let len = this.pending.len().min(this.chunk_size);
+        let chunk = this.pending.split_to(len);
+        this.next_chunk_at = this.next_chunk_at.max(Instant::now()) + this.send_time(len);
+        this.delay = Some(Box::pin(sleep_until(this.next_chunk_at)));
+        Poll::Ready(Some(Ok(chunk)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, StreamExt};
+
+    fn throttled(chunks: Vec<Vec<u8>>, bytes_per_sec: u64) -> ThrottledStream {
+        let chunks = chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk)));
+        ThrottledStream::new(Box::pin(stream::iter(chunks)), bytes_per_sec)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn content_is_passed_on_at_the_rate() {
+        let started = Instant::now();
+
+        let chunks: Vec<Bytes> = throttled(vec![vec![1; 40_000], vec![2; 60_000]], 10_000)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        // The first chunk of 500 bytes is sent right away, the other 99,500 bytes take 9.95 seconds.
+        assert_eq!(Duration::from_millis(9950), started.elapsed());
+        assert!(chunks.iter().all(|chunk| chunk.len() == 500));
+        let content = chunks.concat();
+        assert_eq!(vec![1; 40_000], content[..40_000]);
+        assert_eq!(vec![2; 60_000], content[40_000..]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn chunks_are_at_most_64_kib() {
+        let chunks: Vec<usize> = throttled(vec![vec![0; 200_000]], 10 * 1024 * 1024)
+            .map(|chunk| chunk.unwrap().len())
+            .collect()
+            .await;
+
+        assert_eq!(vec![65_536, 65_536, 65_536, 3_392], chunks);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn errors_of_the_inner_stream_are_passed_on() {
+        let chunks = vec![
+            Ok(Bytes::from_static(b"hello")),
+            Err(std::io::Error::other("broken pipe").into()),
+        ];
+        let stream = ThrottledStream::new(Box::pin(stream::iter(chunks)), 1024);
+
+        let items: Vec<_> = stream.collect().await;
+
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+    }
+}
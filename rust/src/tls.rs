@@ -0,0 +1,508 @@
+//! # TLS
+//!
+//! Allows to customize the TLS configuration of the SDK, e.g. to trust the certificate authority of a
+//! TLS-inspecting corporate gateway or to authenticate with a client certificate. The configuration is applied
+//! to the websocket connection, the file uploads and the token requests of the SDK authenticators.
+//!
+//! They all use rustls with the `rustls-tls` feature, which is enabled by default, and native-tls with the
+//! `native-tls` feature. rustls is used if both are enabled.
+
+use crate::error::{Error, VResult};
+use crate::options::Options;
+#[cfg(feature = "rustls-tls")]
+use rustls::pki_types::pem::PemObject;
+#[cfg(feature = "rustls-tls")]
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fmt;
+#[cfg(feature = "rustls-tls")]
+use std::sync::Arc;
+use tokio_tungstenite::Connector;
+
+/// A root certificate that is trusted in addition to the default ones of the TLS backend, the Mozilla root
+/// certificates with rustls and the system trust store with native-tls.
+/// ```rust,no_run
+/// # fn main() -> vaas::error::VResult<()> {
+/// use vaas::tls::Certificate;
+///
+/// let pem = std::fs::read("corporate-ca.pem")?;
+/// let certificate = Certificate::from_pem(&pem)?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Certificate {
+    bytes: Vec<u8>,
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Pem,
+    Der,
+}
+
+impl Certificate {
+    /// Create a certificate from a PEM encoded certificate.
+    pub fn from_pem(pem: &[u8]) -> VResult<Self> {
+        let certificate = Self {
+            bytes: pem.to_vec(),
+            encoding: Encoding::Pem,
+        };
+        certificate.validate()?;
+        Ok(certificate)
+    }
+
+    /// Create a certificate from a DER encoded certificate.
+    pub fn from_der(der: &[u8]) -> VResult<Self> {
+        let certificate = Self {
+            bytes: der.to_vec(),
+            encoding: Encoding::Der,
+        };
+        certificate.validate()?;
+        Ok(certificate)
+    }
+
+    /// Check that each enabled TLS backend can read the certificate.
+    fn validate(&self) -> VResult<()> {
+        #[cfg(feature = "native-tls")]
+        self.to_native_tls()?;
+        #[cfg(feature = "rustls-tls")]
+        self.add_to(&mut rustls::RootCertStore::empty())?;
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    pub(crate) fn to_native_tls(&self) -> VResult<native_tls::Certificate> {
+        match self.encoding {
+            Encoding::Pem => native_tls::Certificate::from_pem(&self.bytes),
+            Encoding::Der => native_tls::Certificate::from_der(&self.bytes),
+        }
+        .map_err(|e| Error::InvalidConfig(format!("invalid certificate: {e}")))
+    }
+
+    /// Add the certificate to the trust anchors of rustls.
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) fn add_to(&self, roots: &mut rustls::RootCertStore) -> VResult<()> {
+        let certificate = match self.encoding {
+            Encoding::Pem => CertificateDer::from_pem_slice(&self.bytes)
+                .map_err(|e| Error::InvalidConfig(format!("invalid certificate: {e}")))?,
+            Encoding::Der => CertificateDer::from(self.bytes.clone()),
+        };
+        roots
+            .add(certificate)
+            .map_err(|e| Error::InvalidConfig(format!("invalid certificate: {e}")))
+    }
+
+    pub(crate) fn to_reqwest(&self) -> VResult<reqwest::Certificate> {
+        Ok(match self.encoding {
+            Encoding::Pem => reqwest::Certificate::from_pem(&self.bytes)?,
+            Encoding::Der => reqwest::Certificate::from_der(&self.bytes)?,
+        })
+    }
+}
+
+/// A client certificate with its private key, used for mutual TLS authentication.
+/// ```rust,no_run
+/// # fn main() -> vaas::error::VResult<()> {
+/// use vaas::tls::Identity;
+///
+/// let certificate = std::fs::read("client.pem")?;
+/// let key = std::fs::read("client-key.pem")?;
+/// let identity = Identity::from_pem(&certificate, &key)?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct Identity {
+    source: IdentitySource,
+}
+
+// Only native-tls reads the PKCS #12 archives.
+#[cfg_attr(not(feature = "native-tls"), allow(dead_code))]
+#[derive(Clone)]
+enum IdentitySource {
+    Pkcs12 { der: Vec<u8>, password: String },
+    Pem { certificate: Vec<u8>, key: Vec<u8> },
+}
+
+impl Identity {
+    /// Create an identity from a DER encoded PKCS #12 archive, which contains the client certificate,
+    /// the private key and optionally the chain of certificates, protected by the password.
+    /// Only native-tls can read PKCS #12 archives: this fails without the `native-tls` feature, and building the
+    /// [`Vaas`](crate::Vaas) instance fails if the `rustls-tls` feature is enabled, too.
+    pub fn from_pkcs12_der(der: &[u8], password: &str) -> VResult<Self> {
+        let identity = Self {
+            source: IdentitySource::Pkcs12 {
+                der: der.to_vec(),
+                password: password.to_string(),
+            },
+        };
+        identity.validate()?;
+        Ok(identity)
+    }
+
+    /// Create an identity from a PEM encoded certificate chain and a PEM encoded PKCS #8 private key.
+    pub fn from_pem(certificate: &[u8], key: &[u8]) -> VResult<Self> {
+        let identity = Self {
+            source: IdentitySource::Pem {
+                certificate: certificate.to_vec(),
+                key: key.to_vec(),
+            },
+        };
+        identity.validate()?;
+        Ok(identity)
+    }
+
+    /// Check that the identity can be read, by native-tls if it is enabled and by rustls otherwise.
+    fn validate(&self) -> VResult<()> {
+        #[cfg(feature = "native-tls")]
+        self.to_native_tls()?;
+        #[cfg(not(feature = "native-tls"))]
+        self.to_rustls()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "native-tls")]
+    pub(crate) fn to_native_tls(&self) -> VResult<native_tls::Identity> {
+        match &self.source {
+            IdentitySource::Pkcs12 { der, password } => {
+                native_tls::Identity::from_pkcs12(der, password)
+            }
+            IdentitySource::Pem { certificate, key } => {
+                native_tls::Identity::from_pkcs8(certificate, key)
+            }
+        }
+        .map_err(|e| Error::InvalidConfig(format!("invalid identity: {e}")))
+    }
+
+    /// The certificate chain and private key for rustls.
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) fn to_rustls(
+        &self,
+    ) -> VResult<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+        let IdentitySource::Pem { certificate, key } = &self.source else {
+            return Err(pkcs12_not_supported());
+        };
+        let invalid = |e| Error::InvalidConfig(format!("invalid identity: {e}"));
+        let chain = CertificateDer::pem_slice_iter(certificate)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(invalid)?;
+        if chain.is_empty() {
+            return Err(Error::InvalidConfig(
+                "invalid identity: no certificate found".to_string(),
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(invalid)?;
+        Ok((chain, key))
+    }
+
+    #[cfg(not(feature = "rustls-tls"))]
+    pub(crate) fn to_reqwest(&self) -> VResult<reqwest::Identity> {
+        Ok(match &self.source {
+            IdentitySource::Pkcs12 { der, password } => {
+                reqwest::Identity::from_pkcs12_der(der, password)?
+            }
+            IdentitySource::Pem { certificate, key } => {
+                reqwest::Identity::from_pkcs8_pem(certificate, key)?
+            }
+        })
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    pub(crate) fn to_reqwest(&self) -> VResult<reqwest::Identity> {
+        match &self.source {
+            IdentitySource::Pkcs12 { .. } => Err(pkcs12_not_supported()),
+            IdentitySource::Pem { certificate, key } => {
+                let pem = [certificate.as_slice(), b"\n", key.as_slice()].concat();
+                Ok(reqwest::Identity::from_pem(&pem)?)
+            }
+        }
+    }
+}
+
+/// rustls only reads PEM identities, the PKCS #12 archives can only be read by native-tls.
+#[cfg(feature = "rustls-tls")]
+fn pkcs12_not_supported() -> Error {
+    Error::InvalidConfig(
+        "PKCS #12 identities are not supported with the rustls-tls feature, use a PEM identity"
+            .to_string(),
+    )
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoding = match self.source {
+            IdentitySource::Pkcs12 { .. } => "PKCS #12",
+            IdentitySource::Pem { .. } => "PEM",
+        };
+        f.debug_struct("Identity")
+            .field("encoding", &encoding)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Create the TLS connector for the websocket connection with rustls.
+///
+/// The connector is always created, so the websocket uses the same crypto provider and trust anchors as the
+/// file uploads and token requests, independent of the process-wide default of rustls.
+#[cfg(feature = "rustls-tls")]
+pub(crate) fn tls_connector(options: &Options) -> VResult<Option<Connector>> {
+    Ok(Some(Connector::Rustls(Arc::new(rustls_config(options)?))))
+}
+
+/// Create the TLS connector for the websocket connection with native-tls.
+/// Returns `None` if the default TLS configuration of native-tls can be used.
+#[cfg(not(feature = "rustls-tls"))]
+pub(crate) fn tls_connector(options: &Options) -> VResult<Option<Connector>> {
+    Ok(native_tls_connector(options)?.map(Connector::NativeTls))
+}
+
+#[cfg(not(feature = "rustls-tls"))]
+fn native_tls_connector(options: &Options) -> VResult<Option<native_tls::TlsConnector>> {
+    if options.root_certificates.is_empty()
+        && options.identity.is_none()
+        && !options.danger_accept_invalid_certs
+    {
+        return Ok(None);
+    }
+
+    let mut builder = native_tls::TlsConnector::builder();
+    for certificate in &options.root_certificates {
+        builder.add_root_certificate(certificate.to_native_tls()?);
+    }
+    if let Some(identity) = &options.identity {
+        builder.identity(identity.to_native_tls()?);
+    }
+    builder.danger_accept_invalid_certs(options.danger_accept_invalid_certs);
+    let connector = builder
+        .build()
+        .map_err(|e| Error::InvalidConfig(format!("failed to create TLS connector: {e}")))?;
+    Ok(Some(connector))
+}
+
+/// The rustls configuration with the ring crypto provider and the Mozilla root certificates, like the one reqwest
+/// uses with the `rustls-tls` feature, and the root certificates, identity and certificate checks of the options.
+#[cfg(feature = "rustls-tls")]
+fn rustls_config(options: &Options) -> VResult<rustls::ClientConfig> {
+    let failed =
+        |e: rustls::Error| Error::InvalidConfig(format!("failed to create TLS connector: {e}"));
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(failed)?;
+    let builder = if options.danger_accept_invalid_certs {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+    } else {
+        let mut roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        for certificate in &options.root_certificates {
+            certificate.add_to(&mut roots)?;
+        }
+        builder.with_root_certificates(roots)
+    };
+    match &options.identity {
+        Some(identity) => {
+            let (chain, key) = identity.to_rustls()?;
+            builder.with_client_auth_cert(chain, key).map_err(failed)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Accepts any server certificate, see [`Builder::danger_accept_invalid_certs`](crate::Builder::danger_accept_invalid_certs).
+/// The handshake signatures are still verified, so the server has to own the key of the certificate it presents.
+#[cfg(feature = "rustls-tls")]
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<rustls::crypto::CryptoProvider>);
+
+#[cfg(feature = "rustls-tls")]
+impl rustls::client::danger::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &rustls::pki_types::ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: rustls::pki_types::UnixTime,
+    ) -> Result<rustls::client::danger::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::danger::ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::tests::{serve_one_https_request, TEST_CA_PEM};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    const SELF_SIGNED_PEM: &[u8] = include_bytes!("../tests/fixtures/self_signed.pem");
+    const CLIENT_PEM: &[u8] = include_bytes!("../tests/fixtures/client.pem");
+    const CLIENT_KEY_PEM: &[u8] = include_bytes!("../tests/fixtures/client_key.pem");
+    const CLIENT_P12: &[u8] = include_bytes!("../tests/fixtures/client.p12");
+
+    #[test]
+    fn from_pem_with_valid_certificate() {
+        let certificate = Certificate::from_pem(SELF_SIGNED_PEM).unwrap();
+        assert!(certificate.to_reqwest().is_ok());
+    }
+
+    #[test]
+    fn from_pem_with_invalid_certificate_fails() {
+        let certificate = Certificate::from_pem(b"not a certificate");
+        assert!(matches!(certificate, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn identity_from_pem_with_valid_identity() {
+        let identity = Identity::from_pem(CLIENT_PEM, CLIENT_KEY_PEM).unwrap();
+        assert!(identity.to_reqwest().is_ok());
+    }
+
+    #[cfg(not(feature = "rustls-tls"))]
+    #[test]
+    fn identity_from_pkcs12_with_valid_identity() {
+        let identity = Identity::from_pkcs12_der(CLIENT_P12, "password").unwrap();
+        assert!(identity.to_reqwest().is_ok());
+    }
+
+    #[cfg(all(feature = "native-tls", feature = "rustls-tls"))]
+    #[test]
+    fn identity_from_pkcs12_is_not_supported_by_rustls() {
+        let identity = Identity::from_pkcs12_der(CLIENT_P12, "password").unwrap();
+        assert!(matches!(
+            identity.to_reqwest(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(not(feature = "native-tls"))]
+    #[test]
+    fn identity_from_pkcs12_requires_native_tls() {
+        let identity = Identity::from_pkcs12_der(CLIENT_P12, "password");
+        assert!(matches!(identity, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn identity_from_pkcs12_with_wrong_password_fails() {
+        let identity = Identity::from_pkcs12_der(CLIENT_P12, "wrong");
+        assert!(matches!(identity, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn identity_from_pem_with_missing_key_fails() {
+        let identity = Identity::from_pem(CLIENT_PEM, b"not a key");
+        assert!(matches!(identity, Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn identity_debug_redacts_key_and_password() {
+        let identity = Identity {
+            source: IdentitySource::Pkcs12 {
+                der: CLIENT_P12.to_vec(),
+                password: "password".to_string(),
+            },
+        };
+        assert!(!format!("{:?}", identity).contains("password"));
+    }
+
+    fn trusting_test_ca() -> Options {
+        Options {
+            root_certificates: vec![Certificate::from_pem(TEST_CA_PEM).unwrap()],
+            ..Options::default()
+        }
+    }
+
+    /// Send a request over the TLS stream and return the response.
+    fn get(mut stream: impl Read + Write) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[cfg(not(feature = "rustls-tls"))]
+    #[test]
+    fn websocket_tls_connector_completes_the_handshake() {
+        let port = serve_one_https_request();
+        let connector = native_tls_connector(&trusting_test_ca()).unwrap().unwrap();
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let stream = connector.connect("localhost", stream).unwrap();
+
+        let response = get(stream);
+        assert!(response.ends_with("\r\n\r\nok"), "{response}");
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    fn get_over_rustls(options: &Options) -> String {
+        let port = serve_one_https_request();
+        let config = Arc::new(rustls_config(options).unwrap());
+        let server_name = "localhost".try_into().unwrap();
+        let connection = rustls::ClientConnection::new(config, server_name).unwrap();
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        get(rustls::StreamOwned::new(connection, stream))
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn websocket_rustls_config_completes_the_handshake() {
+        let response = get_over_rustls(&trusting_test_ca());
+        assert!(response.ends_with("\r\n\r\nok"), "{response}");
+    }
+
+    #[cfg(feature = "rustls-tls")]
+    #[test]
+    fn websocket_rustls_config_accepting_invalid_certs_completes_the_handshake() {
+        let options = Options {
+            danger_accept_invalid_certs: true,
+            ..Options::default()
+        };
+        let response = get_over_rustls(&options);
+        assert!(response.ends_with("\r\n\r\nok"), "{response}");
+    }
+
+    #[test]
+    fn tls_connector_with_identity_is_created() {
+        let options = Options {
+            identity: Some(Identity::from_pem(CLIENT_PEM, CLIENT_KEY_PEM).unwrap()),
+            ..Options::default()
+        };
+        assert!(tls_connector(&options).unwrap().is_some());
+    }
+}
@@ -0,0 +1,17 @@
+//! The `Transport` enum selects how the SDK talks to VaaS, see [`Builder::transport`](crate::Builder::transport).
+
+/// The protocol the verdict requests of a [`Connection`](crate::Connection) are sent with.
+/// The requests and their results are the same with every transport.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transport {
+    /// One websocket connection per [`Connection`](crate::Connection), on which VaaS pushes the verdicts.
+    #[default]
+    WebSocket,
+    /// Plain HTTPS requests, e.g. for networks whose proxies do not allow websockets. Requires the `rest` feature.
+    ///
+    /// Verdict requests are POSTed to `verdicts` relative to the configured URL, with its scheme changed from
+    /// `wss` to `https`. If VaaS does not know the verdict yet, `verdicts/<guid>` is polled until it does.
+    #[cfg(feature = "rest")]
+    Rest,
+}
@@ -1,20 +1,37 @@
 //! The `Vaas` module provides all needed functions to check a hash or file for malicious content.
 
+use crate::auth::authenticators::{ClientCredentials, Password};
 use crate::auth::Authenticator;
 use crate::builder::Builder;
-use crate::connection::Connection;
-use crate::error::{Error, VResult};
+use crate::connection::{Connection, FrameSource};
+use crate::error::{ConnectPhase, Error, VResult};
+use crate::instrumentation::{debug_event, span};
+use crate::lazy_connection::{Connector, LazyConnection};
 use crate::message::{AuthRequest, AuthResponse};
 use crate::options::Options;
+#[cfg(feature = "recording")]
+use crate::recording::{ReplayTransport, SessionRecorder, REDACTED};
+#[cfg(feature = "rest")]
+use crate::rest::rest_frames;
+use crate::retry::{retry, TokioClock};
+use crate::transport::Transport;
+use crate::websocket::{self, Frame, WebSocketReadHalf, WebSocketWriteHalf};
+use crate::ws_writer::FrameSink;
+use async_trait::async_trait;
 use reqwest::Url;
-use websockets::{Frame, WebSocket, WebSocketReadHalf, WebSocketWriteHalf};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{warn, Instrument};
 
 /// Provides all functionality needed to check a hash or file for malicious content.
 #[derive(Debug, Clone)]
 pub struct Vaas<A: Authenticator> {
-    pub(super) authenticator: A,
+    pub(super) authenticator: Arc<A>,
     pub(super) url: Url,
     pub(super) options: Options,
+    pub(super) http_client: reqwest::Client,
 }
 
 impl<A: Authenticator> Vaas<A> {
@@ -24,35 +41,216 @@ impl<A: Authenticator> Vaas<A> {
     }
 
     /// Connect to the server endpoints to request a verdict for a hash or file.
-    pub async fn connect(self) -> VResult<Connection> {
-        let (mut ws_reader, mut ws_writer) = self.open_websocket().await?;
-        let session_id = self.authenticate(&mut ws_reader, &mut ws_writer).await?;
-        let connection = Connection::start(ws_writer, ws_reader, session_id, self.options).await;
+    ///
+    /// The token request, the websocket handshake and the authentication of the session are each bounded by the
+    /// timeouts configured with [`Builder::auth_timeout`] and [`Builder::connect_timeout`].
+    /// If a phase takes longer, [`Error::ConnectTimeout`] is returned.
+    /// Failed token requests are retried according to [`Builder::retry_policy`].
+    /// If VaaS rejects the token, the authentication is retried once with a refreshed token
+    /// before [`Error::Unauthorized`] with the reason of VaaS is returned.
+    pub async fn connect(self) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        self.open_connection().await
+    }
+
+    /// Connect on the first request instead of now, e.g. to not wait for the network at startup
+    /// if verdicts are requested rarely.
+    ///
+    /// The connection is established like with [`Vaas::connect`], shared by all requests of the returned
+    /// [`LazyConnection`] and established again if it has been closed.
+    pub fn lazy(self) -> LazyConnection
+    where
+        A: Send + Sync + 'static,
+    {
+        LazyConnection::new(Box::new(self))
+    }
+
+    async fn open_connection(&self) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let span = span!("vaas_connect", url = %self.url);
+        self.connect_and_authenticate().instrument(span).await
+    }
+
+    async fn connect_and_authenticate(&self) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let token = retry(
+            &self.options.retry_policy,
+            &TokioClock,
+            "token request",
+            || {
+                with_timeout(
+                    self.options.auth_timeout,
+                    ConnectPhase::TokenRequest,
+                    self.authenticator.get_token(),
+                )
+            },
+        )
+        .await?;
+        debug_event!("Token received");
+        match self.options.transport {
+            Transport::WebSocket => self.connect_websocket(token).await,
+            #[cfg(feature = "rest")]
+            Transport::Rest => self.connect_rest().await,
+        }
+    }
+
+    async fn connect_websocket(&self, token: String) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let (ws_reader, ws_writer) = with_timeout(
+            self.options.connect_timeout,
+            ConnectPhase::WebSocketHandshake,
+            self.open_websocket(),
+        )
+        .await?;
+        debug_event!("WebSocket connected");
+        #[cfg(feature = "recording")]
+        if let Some(path) = &self.options.record_session {
+            let recorder = SessionRecorder::create(path)?;
+            let (ws_writer, ws_reader) = recorder.wrap(ws_writer, ws_reader);
+            let mut options = self.options.clone();
+            options.hooks.on_upload.push(recorder.upload_hook());
+            return self
+                .start_session(token, ws_writer, ws_reader, options)
+                .await;
+        }
+        self.start_session(token, ws_writer, ws_reader, self.options.clone())
+            .await
+    }
+
+    /// Authenticate on the websocket and start the connection.
+    async fn start_session(
+        &self,
+        token: String,
+        mut ws_writer: impl FrameSink,
+        mut ws_reader: impl FrameSource,
+        options: Options,
+    ) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let mut channel = WebSocketAuthChannel {
+            reader: &mut ws_reader,
+            writer: &mut ws_writer,
+        };
+        let session_id = self.authenticate(token, &mut channel).await?;
+        debug_event!(session_id, "Authenticated");
+        let connection = Connection::start(
+            ws_writer,
+            ws_reader,
+            session_id,
+            options,
+            self.http_client.clone(),
+            self.authenticator.clone(),
+        )
+        .await;
+        Ok(connection)
+    }
+
+    /// Connect to a recorded session instead of VaaS, e.g. to reproduce a protocol issue in a test. The session is
+    /// authenticated with the recorded response, without requesting a token. Requires the `recording` feature.
+    ///
+    /// ```rust,no_run
+    /// # async fn run() -> vaas::error::VResult<()> {
+    /// use vaas::auth::authenticators::ClientCredentials;
+    /// use vaas::recording::ReplayTransport;
+    /// use vaas::{Sha256, Vaas};
+    ///
+    /// let replay = ReplayTransport::from_file("tests/fixtures/sessions/clean_hash_lookup.ndjson")?;
+    /// let authenticator = ClientCredentials::new("client".to_string(), "secret".to_string());
+    /// let connection = Vaas::builder(authenticator).build()?.connect_replay(replay).await?;
+    /// let sha256 = Sha256::try_from("275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f")?;
+    /// let verdict = connection.for_sha256(&sha256, None).await?;
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "recording")]
+    pub async fn connect_replay(self, replay: ReplayTransport) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let mut options = self.options.clone();
+        if replay.replaces_upload_url() {
+            options.allowed_upload_hosts = None;
+        }
+        let (sink, source, upload_hook) = replay.into_session();
+        options.hooks.on_upload.push(upload_hook);
+        self.start_session(REDACTED.to_string(), sink, source, options)
+            .await
+    }
+
+    /// Start a connection using the REST transport. The token has been validated by requesting it,
+    /// so there is no session to authenticate and the session id is generated locally.
+    #[cfg(feature = "rest")]
+    async fn connect_rest(&self) -> VResult<Connection>
+    where
+        A: Send + Sync + 'static,
+    {
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let (sink, source) = rest_frames(
+            &self.url,
+            session_id.clone(),
+            self.http_client.clone(),
+            self.authenticator.clone(),
+        )?;
+        debug_event!(session_id, "REST transport ready");
+        let connection = Connection::start(
+            sink,
+            source,
+            session_id,
+            self.options.clone(),
+            self.http_client.clone(),
+            self.authenticator.clone(),
+        )
+        .await;
         Ok(connection)
     }
 
     async fn open_websocket(&self) -> VResult<(WebSocketReadHalf, WebSocketWriteHalf)> {
-        let (reader, writer) = WebSocket::builder()
-            .connect(self.url.as_str())
-            .await?
-            .split();
-        Ok((reader, writer))
+        websocket::connect(&self.url, &self.options).await
     }
 
-    async fn authenticate(
+    /// Authenticate the session. If VaaS rejects the token, e.g. because of clock skew or a just rotated secret,
+    /// the authentication is retried once with a refreshed token.
+    async fn authenticate(&self, token: String, channel: &mut dyn AuthChannel) -> VResult<String>
+    where
+        A: Sync,
+    {
+        match self.authenticate_once(token, channel).await {
+            Err(Error::Unauthorized(reason)) => {
+                warn!(
+                    reason,
+                    "VaaS rejected the token, retrying with a refreshed token"
+                );
+                let token = with_timeout(
+                    self.options.auth_timeout,
+                    ConnectPhase::TokenRequest,
+                    self.authenticator.refresh_token(),
+                )
+                .await?;
+                self.authenticate_once(token, channel).await
+            }
+            result => result,
+        }
+    }
+
+    async fn authenticate_once(
         &self,
-        ws_reader: &mut WebSocketReadHalf,
-        ws_writer: &mut WebSocketWriteHalf,
+        token: String,
+        channel: &mut dyn AuthChannel,
     ) -> VResult<String> {
-        let token = self.authenticator.get_token().await?;
-        let auth_request = AuthRequest::new(token, None).to_json()?;
-        ws_writer.send_text(auth_request).await?;
-
-        let frame = ws_reader.receive().await?;
-        let response = match frame {
-            Frame::Text { payload: json, .. } => AuthResponse::try_from(&json)?,
-            _ => return Err(Error::InvalidFrame),
-        };
+        let response = with_timeout(
+            self.options.auth_timeout,
+            ConnectPhase::Authentication,
+            channel.authenticate(token),
+        )
+        .await?;
 
         if response.success {
             let session_id = response.session_id.ok_or(Error::NoSessionIdInAuthResp)?;
@@ -62,3 +260,391 @@ impl<A: Authenticator> Vaas<A> {
         }
     }
 }
+
+#[async_trait]
+impl<A: Authenticator + Send + Sync + 'static> Connector for Vaas<A> {
+    async fn connect(&self) -> VResult<Connection> {
+        self.open_connection().await
+    }
+}
+
+/// Sends an authentication request to the gateway and receives the response.
+#[async_trait]
+trait AuthChannel: Send {
+    async fn authenticate(&mut self, token: String) -> VResult<AuthResponse>;
+}
+
+struct WebSocketAuthChannel<'a, R, W> {
+    reader: &'a mut R,
+    writer: &'a mut W,
+}
+
+#[async_trait]
+impl<R: FrameSource, W: FrameSink> AuthChannel for WebSocketAuthChannel<'_, R, W> {
+    async fn authenticate(&mut self, token: String) -> VResult<AuthResponse> {
+        let auth_request = AuthRequest::new(token, None).to_json()?;
+        self.writer.send_text(auth_request).await?;
+
+        let frame = self.reader.receive().await?;
+        match frame {
+            Frame::Text { payload: json, .. } => AuthResponse::try_from(&json),
+            _ => Err(Error::InvalidFrame),
+        }
+    }
+}
+
+impl Vaas<Box<dyn Authenticator + Send + Sync>> {
+    /// Create a `Vaas` instance from environment variables.
+    ///
+    /// | Variable | Description |
+    /// |----------|-------------|
+    /// | `CLIENT_ID` | Client id, required. |
+    /// | `CLIENT_SECRET` | Client secret for the client credentials flow. |
+    /// | `VAAS_USER_NAME`, `VAAS_PASSWORD` | User name and password for the password flow, used instead of `CLIENT_SECRET`. |
+    /// | `VAAS_URL` | URL of the VaaS gateway, optional. |
+    /// | `TOKEN_URL` | URL of the token endpoint, optional. |
+    /// | `VAAS_USE_CACHE` | `true` or `false`, optional. |
+    /// | `VAAS_USE_HASH_LOOKUP` | `true` or `false`, optional. |
+    ///
+    /// Returns [`Error::InvalidConfig`] listing all missing or malformed variables.
+    /// ```rust,no_run
+    /// # async fn run() -> vaas::error::VResult<()> {
+    /// use vaas::Vaas;
+    ///
+    /// let vaas = Vaas::from_env()?.connect().await?;
+    /// # Ok(()) }
+    /// ```
+    pub fn from_env() -> VResult<Self> {
+        let client_id = env_var("CLIENT_ID");
+        let client_secret = env_var("CLIENT_SECRET");
+        let user_name = env_var("VAAS_USER_NAME");
+        let password = env_var("VAAS_PASSWORD");
+        let url = env_var("VAAS_URL").map(|url| parse_env_url("VAAS_URL", &url));
+        let token_url = env_var("TOKEN_URL").map(|url| parse_env_url("TOKEN_URL", &url));
+        let use_cache = env_var("VAAS_USE_CACHE").map(|v| parse_env_bool("VAAS_USE_CACHE", &v));
+        let use_hash_lookup =
+            env_var("VAAS_USE_HASH_LOOKUP").map(|v| parse_env_bool("VAAS_USE_HASH_LOOKUP", &v));
+
+        let mut problems = Vec::new();
+        if client_id.is_none() {
+            problems.push("CLIENT_ID is not set".to_string());
+        }
+        let use_password = user_name.is_some() || password.is_some();
+        if use_password {
+            if user_name.is_none() {
+                problems.push("VAAS_USER_NAME is not set".to_string());
+            }
+            if password.is_none() {
+                problems.push("VAAS_PASSWORD is not set".to_string());
+            }
+        } else if client_secret.is_none() {
+            problems
+                .push("CLIENT_SECRET or VAAS_USER_NAME and VAAS_PASSWORD are not set".to_string());
+        }
+        for result in [&url, &token_url].into_iter().flatten() {
+            if let Err(problem) = result {
+                problems.push(problem.clone());
+            }
+        }
+        for result in [&use_cache, &use_hash_lookup].into_iter().flatten() {
+            if let Err(problem) = result {
+                problems.push(problem.clone());
+            }
+        }
+        if !problems.is_empty() {
+            return Err(Error::InvalidConfig(problems.join(", ")));
+        }
+
+        // All values are validated above.
+        let client_id = client_id.unwrap_or_default();
+        let token_url = token_url.and_then(Result::ok);
+        let authenticator: Box<dyn Authenticator + Send + Sync> = if use_password {
+            let mut authenticator = Password::new(
+                client_id,
+                user_name.unwrap_or_default(),
+                password.unwrap_or_default(),
+            );
+            if let Some(token_url) = token_url {
+                authenticator = authenticator.with_token_url(token_url);
+            }
+            Box::new(authenticator)
+        } else {
+            let mut authenticator =
+                ClientCredentials::new(client_id, client_secret.unwrap_or_default());
+            if let Some(token_url) = token_url {
+                authenticator = authenticator.with_token_url(token_url);
+            }
+            Box::new(authenticator)
+        };
+
+        let mut builder = Vaas::builder(authenticator);
+        if let Some(Ok(url)) = url {
+            builder = builder.url(url);
+        }
+        if let Some(Ok(use_cache)) = use_cache {
+            builder = builder.use_cache(use_cache);
+        }
+        if let Some(Ok(use_hash_lookup)) = use_hash_lookup {
+            builder = builder.use_hash_lookup(use_hash_lookup);
+        }
+        builder.build()
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn parse_env_url(name: &str, value: &str) -> Result<Url, String> {
+    Url::parse(value).map_err(|e| format!("{name} is not a valid URL: {e}"))
+}
+
+fn parse_env_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("{name} must be true or false, got `{value}`")),
+    }
+}
+
+pub(crate) async fn with_timeout<T>(
+    duration: Duration,
+    phase: ConnectPhase,
+    future: impl Future<Output = VResult<T>>,
+) -> VResult<T> {
+    timeout(duration, future)
+        .await
+        .map_err(|_| Error::ConnectTimeout(phase))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::authenticators::ClientCredentials;
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    // Environment variables are process wide, so tests which modify them must not run concurrently.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    const ENV_VARS: [&str; 8] = [
+        "CLIENT_ID",
+        "CLIENT_SECRET",
+        "VAAS_USER_NAME",
+        "VAAS_PASSWORD",
+        "VAAS_URL",
+        "TOKEN_URL",
+        "VAAS_USE_CACHE",
+        "VAAS_USE_HASH_LOOKUP",
+    ];
+
+    fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        ENV_VARS.iter().for_each(|name| std::env::remove_var(name));
+        vars.iter()
+            .for_each(|(name, value)| std::env::set_var(name, value));
+        let result = f();
+        ENV_VARS.iter().for_each(|name| std::env::remove_var(name));
+        result
+    }
+
+    fn invalid_config_message(
+        result: VResult<Vaas<Box<dyn Authenticator + Send + Sync>>>,
+    ) -> String {
+        match result {
+            Err(Error::InvalidConfig(message)) => message,
+            Err(e) => panic!("expected invalid config, got {e}"),
+            Ok(_) => panic!("expected invalid config, got a Vaas instance"),
+        }
+    }
+
+    #[test]
+    fn from_env_with_client_credentials() {
+        let vaas = with_env(
+            &[
+                ("CLIENT_ID", "client_id"),
+                ("CLIENT_SECRET", "client_secret"),
+                ("VAAS_URL", "wss://gateway.staging.vaas.test"),
+                ("VAAS_USE_CACHE", "false"),
+                ("VAAS_USE_HASH_LOOKUP", "TRUE"),
+            ],
+            Vaas::from_env,
+        )
+        .unwrap();
+
+        assert_eq!("wss://gateway.staging.vaas.test/", vaas.url.as_str());
+        assert!(!vaas.options.use_cache);
+        assert!(vaas.options.use_hash_lookup);
+    }
+
+    #[test]
+    fn from_env_with_password() {
+        let result = with_env(
+            &[
+                ("CLIENT_ID", "client_id"),
+                ("VAAS_USER_NAME", "user_name"),
+                ("VAAS_PASSWORD", "password"),
+            ],
+            Vaas::from_env,
+        );
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn from_env_with_token_url_requests_token_from_token_url() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/token")
+            .with_body(r#"{"access_token":"token"}"#)
+            .create_async()
+            .await;
+        let token_url = format!("{}/token", server.url());
+        let vaas = with_env(
+            &[
+                ("CLIENT_ID", "client_id"),
+                ("CLIENT_SECRET", "client_secret"),
+                ("TOKEN_URL", &token_url),
+            ],
+            Vaas::from_env,
+        )
+        .unwrap();
+
+        assert_eq!("token", vaas.authenticator.get_token().await.unwrap());
+        mock.assert_async().await;
+    }
+
+    #[test]
+    fn from_env_without_variables_lists_missing_variables() {
+        let message = invalid_config_message(with_env(&[], Vaas::from_env));
+
+        assert!(message.contains("CLIENT_ID"));
+        assert!(message.contains("CLIENT_SECRET"));
+    }
+
+    #[test]
+    fn from_env_with_incomplete_password_lists_missing_password() {
+        let message = invalid_config_message(with_env(
+            &[("CLIENT_ID", "client_id"), ("VAAS_USER_NAME", "user_name")],
+            Vaas::from_env,
+        ));
+
+        assert!(message.contains("VAAS_PASSWORD"));
+    }
+
+    #[test]
+    fn from_env_with_malformed_values_lists_malformed_variables() {
+        let message = invalid_config_message(with_env(
+            &[
+                ("CLIENT_ID", "client_id"),
+                ("CLIENT_SECRET", "client_secret"),
+                ("TOKEN_URL", "not a url"),
+                ("VAAS_USE_CACHE", "maybe"),
+            ],
+            Vaas::from_env,
+        ));
+
+        assert!(message.contains("TOKEN_URL"));
+        assert!(message.contains("VAAS_USE_CACHE"));
+    }
+
+    /// Returns a stale token first and a fresh one when refreshed.
+    struct RotatedToken;
+
+    #[async_trait]
+    impl Authenticator for RotatedToken {
+        async fn get_token(&self) -> VResult<String> {
+            Ok("stale".to_string())
+        }
+
+        async fn refresh_token(&self) -> VResult<String> {
+            Ok("fresh".to_string())
+        }
+    }
+
+    /// Accepts only the given token and records the received tokens.
+    struct MockGateway {
+        accepted_token: &'static str,
+        received_tokens: Vec<String>,
+    }
+
+    impl MockGateway {
+        fn accepting(accepted_token: &'static str) -> Self {
+            Self {
+                accepted_token,
+                received_tokens: Vec::new(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl AuthChannel for MockGateway {
+        async fn authenticate(&mut self, token: String) -> VResult<AuthResponse> {
+            let success = token == self.accepted_token;
+            self.received_tokens.push(token);
+            let json = if success {
+                r#"{"kind":"AuthResponse","success":true,"session_id":"session","text":""}"#
+            } else {
+                r#"{"kind":"AuthResponse","success":false,"session_id":null,"text":"token expired"}"#
+            };
+            AuthResponse::try_from(&json.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_accepted_token_does_not_refresh() {
+        let vaas = Vaas::builder(RotatedToken).build().unwrap();
+        let mut gateway = MockGateway::accepting("stale");
+
+        let session_id = vaas.authenticate("stale".to_string(), &mut gateway).await;
+
+        assert_eq!("session", session_id.unwrap());
+        assert_eq!(vec!["stale"], gateway.received_tokens);
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_rejected_token_retries_with_refreshed_token() {
+        let vaas = Vaas::builder(RotatedToken).build().unwrap();
+        let mut gateway = MockGateway::accepting("fresh");
+
+        let session_id = vaas.authenticate("stale".to_string(), &mut gateway).await;
+
+        assert_eq!("session", session_id.unwrap());
+        assert_eq!(vec!["stale", "fresh"], gateway.received_tokens);
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_rejected_refreshed_token_returns_reason() {
+        let vaas = Vaas::builder(RotatedToken).build().unwrap();
+        let mut gateway = MockGateway::accepting("none");
+
+        let result = vaas.authenticate("stale".to_string(), &mut gateway).await;
+
+        assert!(matches!(result, Err(Error::Unauthorized(reason)) if reason == "token expired"));
+        assert_eq!(2, gateway.received_tokens.len());
+    }
+
+    #[tokio::test]
+    async fn connect_with_unresponsive_token_endpoint_times_out() {
+        // The listener accepts connections in the backlog but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let token_url = Url::parse(&format!("http://{}/token", listener.local_addr().unwrap()));
+        let authenticator =
+            ClientCredentials::new("client_id".to_string(), "client_secret".to_string())
+                .with_token_url(token_url.unwrap());
+        let vaas = Vaas::builder(authenticator)
+            .auth_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let start = Instant::now();
+        let result = vaas.connect().await;
+
+        assert!(matches!(
+            result,
+            Err(Error::ConnectTimeout(ConnectPhase::TokenRequest))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -2,14 +2,20 @@
 //!
 //! The `VaaSVerdict` is the result of a request for a verdict. It contains the verdict itself and the SHA256 hash of the requested file.
 
-use crate::error::Error;
+use crate::error::{Error, VResult};
 use crate::message::{Verdict, VerdictResponse};
 use crate::sha256::Sha256;
 use std::convert::TryFrom;
-
-#[derive(Debug, Clone)]
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
 
 /// Response object from the api.
+///
+/// New fields may be added in minor releases, so verdicts are created with [`VaasVerdict::new`] outside the SDK,
+/// e.g. as fakes in tests.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct VaasVerdict {
     /// Sha256 of the requested file
     pub sha256: Sha256,
@@ -19,6 +25,42 @@ pub struct VaasVerdict {
     pub file_type: Option<String>,
     /// mime type as classified by https://www.darwinsys.com/file/
     pub mime_type: Option<String>,
+    /// Whether VaaS or a local decision of the SDK gave the verdict
+    pub source: VerdictSource,
+    /// When VaaS was asked and how long it took, if VaaS gave the verdict for this request
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timing: Option<ScanTiming>,
+}
+
+impl VaasVerdict {
+    /// A verdict of VaaS for the SHA256, without file type, MIME type and timing. The public fields can be set
+    /// afterwards.
+    pub fn new(sha256: Sha256, verdict: Verdict) -> Self {
+        Self {
+            sha256,
+            verdict,
+            file_type: None,
+            mime_type: None,
+            source: VerdictSource::Vaas,
+            timing: None,
+        }
+    }
+}
+
+/// The origin of a [`VaasVerdict`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VerdictSource {
+    /// VaaS answered the request.
+    #[default]
+    Vaas,
+    /// An earlier verdict of VaaS from the local cache, see [`Builder::local_cache`](crate::Builder::local_cache).
+    LocalCache,
+    /// The allowlist or blocklist, see [`Builder::allowlist`](crate::Builder::allowlist). Nothing was sent to VaaS.
+    LocalList,
 }
 
 impl TryFrom<VerdictResponse> for VaasVerdict {
@@ -29,6 +71,144 @@ impl TryFrom<VerdictResponse> for VaasVerdict {
             verdict: Verdict::try_from(&verdict_response)?,
             file_type: verdict_response.file_type,
             mime_type: verdict_response.mime_type,
+            source: VerdictSource::Vaas,
+            timing: None,
+        })
+    }
+}
+
+/// How long the steps of a request took, see [`VaasVerdict::timing`].
+///
+/// Verdicts from the local cache or the allowlist and blocklist have no timing, as VaaS was not asked. Neither has
+/// the verdict of [`Connection::upload_and_await`](crate::Connection::upload_and_await), unless the upload URL had
+/// to be requested again, as the verdict was requested before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanTiming {
+    /// When the verdict request was sent.
+    pub requested_at: SystemTime,
+    /// When VaaS answered the verdict request. The time since `requested_at` is the time of the hash lookup.
+    pub responded_at: SystemTime,
+    /// How long the upload took, if VaaS asked for one. Includes retries, but not the wait for the verdict.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub upload_duration: Option<Duration>,
+    /// The time from the start of the request until the verdict, including hashing, the rate limit and the upload.
+    pub total: Duration,
+    /// The size of the uploaded content, if VaaS asked for an upload.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub uploaded_bytes: Option<u64>,
+}
+
+impl ScanTiming {
+    /// The time from sending the verdict request until VaaS answered it.
+    pub fn lookup_duration(&self) -> Duration {
+        self.responded_at
+            .duration_since(self.requested_at)
+            .unwrap_or_default()
+    }
+}
+
+/// Collects the [`ScanTiming`] of a request while it is processed.
+#[derive(Debug)]
+pub(crate) struct Stopwatch {
+    started: Instant,
+    requested: Option<(SystemTime, Instant)>,
+    responded_at: Option<SystemTime>,
+    upload_duration: Option<Duration>,
+    uploaded_bytes: Option<u64>,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            requested: None,
+            responded_at: None,
+            upload_duration: None,
+            uploaded_bytes: None,
+        }
+    }
+
+    /// The verdict request was sent. Only the first request is timed if the upload URL is requested again.
+    pub fn requested(&mut self) {
+        self.requested
+            .get_or_insert_with(|| (SystemTime::now(), Instant::now()));
+    }
+
+    /// VaaS answered the first verdict request.
+    pub fn responded(&mut self) {
+        if let (Some((requested_at, requested)), None) = (self.requested, self.responded_at) {
+            // The wall clock may jump, so the time since the request is measured monotonically.
+            self.responded_at = Some(requested_at + requested.elapsed());
+        }
+    }
+
+    /// The content was uploaded. The durations of repeated uploads add up.
+    pub fn uploaded(&mut self, duration: Duration, bytes: u64) {
+        *self.upload_duration.get_or_insert(Duration::ZERO) += duration;
+        self.uploaded_bytes = Some(bytes);
+    }
+
+    /// Attach the timing to a verdict of VaaS, if a verdict request was answered.
+    pub fn stamp(&self, verdict: VResult<VaasVerdict>) -> VResult<VaasVerdict> {
+        verdict.map(|mut verdict| {
+            if let (VerdictSource::Vaas, Some((requested_at, _)), Some(responded_at)) =
+                (verdict.source, self.requested, self.responded_at)
+            {
+                verdict.timing = Some(ScanTiming {
+                    requested_at,
+                    responded_at,
+                    upload_duration: self.upload_duration,
+                    total: self.started.elapsed(),
+                    uploaded_bytes: self.uploaded_bytes,
+                });
+            }
+            verdict
         })
     }
 }
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    fn verdict(timing: Option<ScanTiming>) -> VaasVerdict {
+        VaasVerdict {
+            timing,
+            ..VaasVerdict::new(Sha256::from(&b"content"[..]), Verdict::Clean)
+        }
+    }
+
+    #[test]
+    fn absent_timing_is_not_serialized() {
+        let json = serde_json::to_value(verdict(None)).unwrap();
+
+        assert!(json.get("timing").is_none());
+        let parsed: VaasVerdict = serde_json::from_value(json).unwrap();
+        assert_eq!(None, parsed.timing);
+    }
+
+    #[test]
+    fn timing_round_trips_through_json() {
+        let requested_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let timing = ScanTiming {
+            requested_at,
+            responded_at: requested_at + Duration::from_millis(40),
+            upload_duration: Some(Duration::from_millis(250)),
+            total: Duration::from_millis(400),
+            uploaded_bytes: Some(1024),
+        };
+
+        let json = serde_json::to_string(&verdict(Some(timing))).unwrap();
+        let parsed: VaasVerdict = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(Some(timing), parsed.timing);
+        assert_eq!(Duration::from_millis(40), timing.lookup_duration());
+    }
+}
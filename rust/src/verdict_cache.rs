@@ -0,0 +1,211 @@
+//! A cache of verdicts in the process, see [`Builder::local_cache`](crate::Builder::local_cache).
+
+use crate::message::Verdict;
+use crate::sha256::Sha256;
+use crate::vaas_verdict::VaasVerdict;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The verdicts of the `capacity` least recently used files, each kept for at most `ttl`.
+///
+/// Only final verdicts are cached. An `Unknown` verdict asks for an upload, which has to happen on every request.
+#[derive(Debug)]
+pub(crate) struct VerdictCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    verdicts: HashMap<Sha256, Entry>,
+    /// The SHA256 of each entry by its last use, the least recently used first.
+    by_use: BTreeMap<u64, Sha256>,
+    uses: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    verdict: VaasVerdict,
+    expires: Instant,
+    last_use: u64,
+}
+
+impl VerdictCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The cached verdict of the file, unless it expired. A hit makes the entry the most recently used one.
+    pub fn get(&self, sha256: &Sha256) -> Option<VaasVerdict> {
+        let mut entries = self.lock();
+        let entry = entries.remove(sha256)?;
+        if entry.expires <= Instant::now() {
+            return None;
+        }
+        let verdict = entry.verdict.clone();
+        entries.insert(sha256.clone(), entry.verdict, entry.expires);
+        Some(verdict)
+    }
+
+    /// Cache a final verdict, evicting the least recently used entries beyond the capacity.
+    pub fn insert(&self, verdict: &VaasVerdict) {
+        if matches!(verdict.verdict, Verdict::Unknown { .. }) {
+            return;
+        }
+        let mut entries = self.lock();
+        entries.remove(&verdict.sha256);
+        while entries.verdicts.len() >= self.capacity {
+            let Some((_, oldest)) = entries.by_use.pop_first() else {
+                break;
+            };
+            entries.verdicts.remove(&oldest);
+        }
+        let expires = Instant::now() + self.ttl;
+        entries.insert(verdict.sha256.clone(), verdict.clone(), expires);
+    }
+
+    pub fn remove(&self, sha256: &Sha256) {
+        self.lock().remove(sha256);
+    }
+
+    pub fn clear(&self) {
+        *self.lock() = Entries::default();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Entries {
+    fn insert(&mut self, sha256: Sha256, verdict: VaasVerdict, expires: Instant) {
+        self.uses += 1;
+        self.by_use.insert(self.uses, sha256.clone());
+        let entry = Entry {
+            verdict,
+            expires,
+            last_use: self.uses,
+        };
+        self.verdicts.insert(sha256, entry);
+    }
+
+    fn remove(&mut self, sha256: &Sha256) -> Option<Entry> {
+        let entry = self.verdicts.remove(sha256)?;
+        self.by_use.remove(&entry.last_use);
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::UploadUrl;
+    use crate::vaas_verdict::VerdictSource;
+
+    fn verdict(content: &str, verdict: Verdict) -> VaasVerdict {
+        VaasVerdict {
+            sha256: Sha256::from(content.as_bytes()),
+            verdict,
+            file_type: None,
+            mime_type: None,
+            source: VerdictSource::Vaas,
+            timing: None,
+        }
+    }
+
+    fn clean(content: &str) -> VaasVerdict {
+        verdict(content, Verdict::Clean)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cached_verdict_expires_after_ttl() {
+        let cache = VerdictCache::new(10, Duration::from_secs(60));
+        let first = clean("first");
+        cache.insert(&first);
+
+        tokio::time::advance(Duration::from_secs(59)).await;
+        assert_eq!(
+            Some(Verdict::Clean),
+            cache.get(&first.sha256).map(|v| v.verdict)
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(cache.get(&first.sha256).is_none());
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_verdict_is_evicted_beyond_capacity() {
+        let cache = VerdictCache::new(2, Duration::from_secs(60));
+        let (first, second, third) = (clean("first"), clean("second"), clean("third"));
+        cache.insert(&first);
+        cache.insert(&second);
+
+        cache.get(&first.sha256);
+        cache.insert(&third);
+
+        assert!(cache.get(&first.sha256).is_some());
+        assert!(cache.get(&second.sha256).is_none());
+        assert!(cache.get(&third.sha256).is_some());
+    }
+
+    #[tokio::test]
+    async fn replaced_verdict_does_not_count_twice() {
+        let cache = VerdictCache::new(2, Duration::from_secs(60));
+        let malicious = Verdict::Malicious {
+            detection: "EICAR".to_string(),
+        };
+        cache.insert(&clean("first"));
+        cache.insert(&clean("second"));
+
+        cache.insert(&verdict("second", malicious.clone()));
+
+        assert!(cache.get(&Sha256::from("first".as_bytes())).is_some());
+        let second = cache.get(&Sha256::from("second".as_bytes())).unwrap();
+        assert_eq!(malicious, second.verdict);
+    }
+
+    #[tokio::test]
+    async fn unknown_verdict_is_not_cached() {
+        let cache = VerdictCache::new(10, Duration::from_secs(60));
+        let unknown = verdict(
+            "unknown",
+            Verdict::Unknown {
+                upload_url: UploadUrl("https://upload.test".to_string()),
+            },
+        );
+
+        cache.insert(&unknown);
+
+        assert!(cache.get(&unknown.sha256).is_none());
+    }
+
+    #[tokio::test]
+    async fn removed_and_cleared_verdicts_are_gone() {
+        let cache = VerdictCache::new(10, Duration::from_secs(60));
+        let (first, second) = (clean("first"), clean("second"));
+        cache.insert(&first);
+        cache.insert(&second);
+
+        cache.remove(&first.sha256);
+        assert!(cache.get(&first.sha256).is_none());
+        assert!(cache.get(&second.sha256).is_some());
+
+        cache.clear();
+        assert!(cache.get(&second.sha256).is_none());
+    }
+}
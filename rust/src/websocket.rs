@@ -0,0 +1,89 @@
+//! The websocket connection to VaaS, and the frames the connection reads from it.
+
+use crate::error::{Error, VResult};
+use crate::options::Options;
+use crate::tls::tls_connector;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::StreamExt;
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::USER_AGENT;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+pub(crate) use tokio_tungstenite::tungstenite::Error as WebSocketError;
+
+type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// The read half of the websocket.
+pub(crate) type WebSocketReadHalf = SplitStream<Stream>;
+
+/// The write half of the websocket.
+pub(crate) type WebSocketWriteHalf = SplitSink<Stream, Message>;
+
+/// A frame received by the connection. The connection only reads the payload of text frames.
+///
+/// Messages read from the websocket are always complete, as tungstenite reassembles continuation frames. Other
+/// sources, e.g. recorded sessions, may pass on the fragments of a message.
+#[derive(Debug, Clone)]
+pub(crate) enum Frame {
+    Text {
+        payload: String,
+        continuation: bool,
+        fin: bool,
+    },
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Frame {
+    /// A complete text message.
+    pub fn text(payload: String) -> Self {
+        Self::Text {
+            payload,
+            continuation: false,
+            fin: true,
+        }
+    }
+}
+
+impl From<Message> for Frame {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Text(text) => Self::text(text.as_str().to_string()),
+            // Raw frames are only written, tungstenite never reads them.
+            Message::Binary(_) | Message::Frame(_) => Self::Binary,
+            Message::Ping(_) => Self::Ping,
+            Message::Pong(_) => Self::Pong,
+            Message::Close(_) => Self::Close,
+        }
+    }
+}
+
+/// Open the websocket to the gateway, with the TLS configuration and user agent of the options.
+///
+/// Messages larger than [`Builder::max_message_size`](crate::Builder::max_message_size) are rejected while they are
+/// read, so they are never buffered completely.
+pub(crate) async fn connect(
+    url: &Url,
+    options: &Options,
+) -> VResult<(WebSocketReadHalf, WebSocketWriteHalf)> {
+    let mut request = url.as_str().into_client_request()?;
+    let user_agent = HeaderValue::from_str(&options.user_agent())
+        .map_err(|e| Error::InvalidConfig(format!("invalid user agent: {e}")))?;
+    request.headers_mut().insert(USER_AGENT, user_agent);
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(options.max_message_size))
+        .max_frame_size(Some(options.max_message_size));
+    let connector = tls_connector(options)?;
+    let (stream, _) =
+        tokio_tungstenite::connect_async_tls_with_config(request, Some(config), false, connector)
+            .await?;
+    let (writer, reader) = stream.split();
+    Ok((reader, writer))
+}
@@ -0,0 +1,213 @@
+use crate::error::{Error, VResult};
+use crate::websocket::{WebSocketError, WebSocketWriteHalf};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::SinkExt;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{warn, Instrument};
+
+/// The number of queued frames from which on the writer task is considered to lag behind.
+const LAGGING_FRAMES: usize = 1000;
+
+/// A frame queued for the writer task.
+#[derive(Debug)]
+pub(crate) enum OutgoingFrame {
+    Text(String),
+    Ping,
+}
+
+/// The write half of the websocket, abstracted so the writer task can be tested without a server.
+#[async_trait]
+pub(crate) trait FrameSink: Send + 'static {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError>;
+    async fn send_ping(&mut self) -> Result<(), WebSocketError>;
+}
+
+#[async_trait]
+impl FrameSink for WebSocketWriteHalf {
+    async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+        self.send(Message::text(text)).await
+    }
+
+    async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+        self.send(Message::Ping(Bytes::new())).await
+    }
+}
+
+/// Queues frames for the writer task, which owns the write half of the websocket.
+///
+/// Senders never wait for a write, and frames are written in the order in which they were queued.
+/// Write errors are passed to the error handler of the writer task instead of the sender.
+#[derive(Debug, Clone)]
+pub(crate) struct WsWriter {
+    frames: UnboundedSender<OutgoingFrame>,
+}
+
+impl WsWriter {
+    /// Spawn the writer task in the current span. It ends when all `WsWriter` clones are dropped.
+    ///
+    /// A warning is logged when the frames are queued faster than they are written, and when writing a ping fails.
+    pub fn spawn<S, F>(mut sink: S, on_error: F) -> (Self, JoinHandle<VResult<()>>)
+    where
+        S: FrameSink,
+        F: Fn(Error) + Send + 'static,
+    {
+        let (frames, mut receiver): (_, UnboundedReceiver<OutgoingFrame>) = unbounded_channel();
+        let writer = async move {
+            let mut lagging = false;
+            while let Some(frame) = receiver.recv().await {
+                let queued = receiver.len();
+                if queued >= LAGGING_FRAMES && !lagging {
+                    warn!(queued, "The websocket writer lags behind, frames are queued faster than they are written");
+                }
+                lagging = queued >= LAGGING_FRAMES;
+                let result = match frame {
+                    OutgoingFrame::Text(text) => sink.send_text(text).await,
+                    OutgoingFrame::Ping => sink
+                        .send_ping()
+                        .await
+                        .inspect_err(|e| warn!(error = %e, "Sending the keep-alive ping failed")),
+                };
+                if let Err(e) = result {
+                    on_error(e.into());
+                }
+            }
+            Ok(())
+        };
+        let task = tokio::spawn(writer.in_current_span());
+        (Self { frames }, task)
+    }
+
+    /// Queue the frame. Fails with [`Error::ConnectionClosed`] if the writer task has ended.
+    pub fn send(&self, frame: OutgoingFrame) -> VResult<()> {
+        self.frames.send(frame).map_err(|_| Error::ConnectionClosed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_test::traced_test;
+
+    /// Records the written frames and fails the writes of texts starting with `fail`.
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        frames: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl FrameSink for RecordingSink {
+        async fn send_text(&mut self, text: String) -> Result<(), WebSocketError> {
+            // A slow write must not block the senders.
+            tokio::time::sleep(Duration::from_micros(10)).await;
+            if text.starts_with("fail") {
+                return Err(WebSocketError::ConnectionClosed);
+            }
+            self.frames.lock().unwrap().push(text);
+            Ok(())
+        }
+
+        async fn send_ping(&mut self) -> Result<(), WebSocketError> {
+            self.frames.lock().unwrap().push("ping".to_string());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_senders_keep_their_order() {
+        let sink = RecordingSink::default();
+        let (writer, task) = WsWriter::spawn(sink.clone(), |_| {});
+
+        let senders = (0..10).map(|sender| {
+            let writer = writer.clone();
+            tokio::spawn(async move {
+                for i in 0..100 {
+                    writer
+                        .send(OutgoingFrame::Text(format!("{sender}-{i}")))
+                        .unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        });
+        futures::future::join_all(senders).await;
+        drop(writer);
+        task.await.unwrap().unwrap();
+
+        let frames = sink.frames.lock().unwrap();
+        assert_eq!(1000, frames.len());
+        for sender in 0..10 {
+            let prefix = format!("{sender}-");
+            let indices: Vec<usize> = frames
+                .iter()
+                .filter_map(|frame| frame.strip_prefix(&prefix)?.parse().ok())
+                .collect();
+            assert_eq!((0..100).collect::<Vec<_>>(), indices);
+        }
+    }
+
+    #[tokio::test]
+    async fn write_errors_are_passed_to_error_handler() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let recorded = errors.clone();
+        let sink = RecordingSink::default();
+        let (writer, task) = WsWriter::spawn(sink.clone(), move |e| {
+            recorded.lock().unwrap().push(e);
+        });
+
+        writer
+            .send(OutgoingFrame::Text("fail".to_string()))
+            .unwrap();
+        writer.send(OutgoingFrame::Ping).unwrap();
+        drop(writer);
+        task.await.unwrap().unwrap();
+
+        assert!(matches!(
+            errors.lock().unwrap().as_slice(),
+            [Error::WebSocket(_)]
+        ));
+        assert_eq!(vec!["ping"], *sink.frames.lock().unwrap());
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn lagging_writer_is_logged_once() {
+        let sink = RecordingSink::default();
+        let (writer, task) = WsWriter::spawn(sink.clone(), |_| {});
+
+        // The writer task only starts when the test yields, so all frames are queued at once.
+        for i in 0..LAGGING_FRAMES + 10 {
+            writer.send(OutgoingFrame::Text(i.to_string())).unwrap();
+        }
+        drop(writer);
+        task.await.unwrap().unwrap();
+
+        logs_assert(|lines| {
+            match lines
+                .iter()
+                .filter(|line| line.contains("lags behind"))
+                .count()
+            {
+                1 => Ok(()),
+                n => Err(format!("expected one warning, got {n}")),
+            }
+        });
+        assert!(logs_contain(&format!("queued={}", LAGGING_FRAMES + 9)));
+    }
+
+    #[tokio::test]
+    async fn send_after_writer_task_ended_fails() {
+        let (writer, task) = WsWriter::spawn(RecordingSink::default(), |_| {});
+
+        task.abort();
+        let _ = task.await;
+
+        assert!(matches!(
+            writer.send(OutgoingFrame::Ping),
+            Err(Error::ConnectionClosed)
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(all(feature = "ffi", unix))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The directory of the shared library, which cargo builds next to the test binaries.
+fn library_dir() -> PathBuf {
+    let test_binary = std::env::current_exe().unwrap();
+    test_binary.parent().unwrap().to_path_buf()
+}
+
+/// The target the tests are built for, which is the host they run on.
+fn host_target() -> String {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = Command::new(rustc).arg("-vV").output().unwrap();
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn c_program_calls_the_c_api() {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let out_dir = tempfile::tempdir().unwrap();
+    let executable = out_dir.path().join("ffi_test");
+    let target = host_target();
+    let compiler = cc::Build::new()
+        .cargo_metadata(false)
+        .opt_level(0)
+        .host(&target)
+        .target(&target)
+        .out_dir(out_dir.path())
+        .get_compiler();
+
+    let status = compiler
+        .to_command()
+        .arg(manifest_dir.join("tests/ffi/ffi_test.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-o")
+        .arg(&executable)
+        .arg("-L")
+        .arg(&library_dir)
+        .arg("-lvaas")
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .status()
+        .unwrap();
+    assert!(status.success(), "compiling the C test failed");
+
+    let output = Command::new(&executable).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
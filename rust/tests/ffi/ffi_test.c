@@ -0,0 +1,35 @@
+/* Calls the C API without credentials. Prints the failed check and returns 1 on failure. */
+#include <stddef.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "vaas.h"
+
+#define CHECK(condition)                                      \
+    do {                                                      \
+        if (!(condition)) {                                   \
+            fprintf(stderr, "check failed: %s\n", #condition); \
+            return 1;                                         \
+        }                                                     \
+    } while (0)
+
+int main(void) {
+    VaasHandle *handle = (VaasHandle *)1;
+    char *verdict = (char *)1;
+
+    CHECK(vaas_connect(NULL, "secret", &handle) == VAAS_ERROR_INVALID_ARGUMENT);
+    CHECK(handle == NULL);
+    CHECK(vaas_last_error() != NULL);
+    CHECK(strstr(vaas_last_error(), "client_id") != NULL);
+
+    CHECK(vaas_for_sha256(NULL, "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f", &verdict) ==
+          VAAS_ERROR_INVALID_ARGUMENT);
+    CHECK(verdict == NULL);
+    CHECK(strstr(vaas_last_error(), "handle") != NULL);
+
+    CHECK(vaas_for_file(NULL, "/tmp/file", NULL) == VAAS_ERROR_INVALID_ARGUMENT);
+
+    vaas_free_string(NULL);
+    vaas_close(NULL);
+    return 0;
+}
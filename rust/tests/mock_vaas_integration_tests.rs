@@ -0,0 +1,351 @@
+#![cfg(feature = "test-utils")]
+
+use futures::future::try_join_all;
+use reqwest::Url;
+use std::convert::TryFrom;
+use std::ops::Deref;
+use std::time::Duration;
+use vaas::auth::authenticators::ClientCredentials;
+use vaas::error::Error;
+use vaas::message::{UploadUrl, Verdict};
+use vaas::retry::RetryPolicy;
+use vaas::test_utils::{FaultPlan, MockAuthenticator, MockVaas, MockVaasServer};
+use vaas::{CancellationToken, Sha256};
+
+const MALICIOUS: &str = "ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2";
+const CLEAN: &str = "cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e";
+const UNKNOWN: &str = "1f72c1111111111111f912e40b7323a0192a300b376186c10f6803dc5efe28df";
+
+fn generic_malware() -> Verdict {
+    Verdict::Malicious {
+        detection: String::from("Generic.Malware"),
+    }
+}
+
+/// VaaS knowing the malicious and clean hash, and answering `Unknown` otherwise.
+async fn start_vaas() -> MockVaasServer {
+    MockVaas::new()
+        .verdict_for(Sha256::try_from(MALICIOUS).unwrap(), generic_malware())
+        .verdict_for(Sha256::try_from(CLEAN).unwrap(), Verdict::Clean)
+        .default_verdict(Verdict::Unknown {
+            upload_url: UploadUrl("http://localhost/upload".to_string()),
+        })
+        .start()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn from_sha256_list_multiple_hashes() {
+    let server = start_vaas().await;
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+    let sha256_list = vec![
+        Sha256::try_from(MALICIOUS).unwrap(),
+        Sha256::try_from(CLEAN).unwrap(),
+        Sha256::try_from(UNKNOWN).unwrap(),
+    ];
+
+    let results = vaas.for_sha256_list(&sha256_list, &ct).await;
+
+    assert_eq!(generic_malware(), results[0].as_ref().unwrap().verdict);
+    assert_eq!(MALICIOUS, results[0].as_ref().unwrap().sha256.deref());
+    assert_eq!(Verdict::Clean, results[1].as_ref().unwrap().verdict);
+    assert_eq!(CLEAN, results[1].as_ref().unwrap().sha256.deref());
+    assert!(matches!(
+        results[2].as_ref().unwrap().verdict,
+        Verdict::Unknown { .. }
+    ));
+    assert_eq!(UNKNOWN, results[2].as_ref().unwrap().sha256.deref());
+    assert_eq!(3, server.requests().len());
+}
+
+#[tokio::test]
+async fn from_files_unknown_files() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let tmp_file1 = tmp_dir.path().join("unknown1.txt");
+    std::fs::write(&tmp_file1, b"first unknown file").unwrap();
+    let tmp_file2 = tmp_dir.path().join("unknown2.txt");
+    std::fs::write(&tmp_file2, b"second unknown file").unwrap();
+    let sha256_1 = Sha256::try_from(&tmp_file1).unwrap();
+    let sha256_2 = Sha256::try_from(&tmp_file2).unwrap();
+    let server = MockVaas::new()
+        .verdict_for(sha256_2.clone(), generic_malware())
+        .expect_upload_for(sha256_1.clone())
+        .expect_upload_for(sha256_2.clone())
+        .start()
+        .await
+        .unwrap();
+    let files = vec![tmp_file1, tmp_file2];
+
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_minutes(10);
+    let verdicts = vaas.for_file_list(&files, &ct).await;
+
+    assert_eq!(Verdict::Clean, verdicts[0].as_ref().unwrap().verdict);
+    assert_eq!(sha256_1, verdicts[0].as_ref().unwrap().sha256);
+    assert_eq!(generic_malware(), verdicts[1].as_ref().unwrap().verdict);
+    assert_eq!(sha256_2, verdicts[1].as_ref().unwrap().sha256);
+    server.assert_expected_uploads();
+    let mut uploaded = server
+        .uploads()
+        .into_iter()
+        .map(|upload| upload.content)
+        .collect::<Vec<_>>();
+    uploaded.sort();
+    assert_eq!(
+        vec![&b"first unknown file"[..], &b"second unknown file"[..]],
+        uploaded
+    );
+}
+
+#[tokio::test]
+async fn from_sha256_multiple_clean_hash_on_separate_thread() {
+    let server = start_vaas().await;
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+    let sha256_1 = Sha256::try_from(MALICIOUS).unwrap();
+    let sha256_2 = Sha256::try_from(CLEAN).unwrap();
+
+    let (v1, v2) = tokio::spawn(async move {
+        let v1 = vaas.for_sha256(&sha256_1, &ct).await;
+        let v2 = vaas.for_sha256(&sha256_2, &ct).await;
+        (v1, v2)
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(generic_malware(), v1.unwrap().verdict);
+    assert_eq!(Verdict::Clean, v2.unwrap().verdict);
+}
+
+#[tokio::test]
+async fn from_sha256_multiple_clean_hash_await_concurrent_fixed_jobs() {
+    let server = start_vaas().await;
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+    let sha256_1 = Sha256::try_from(MALICIOUS).unwrap();
+    let sha256_2 = Sha256::try_from(CLEAN).unwrap();
+
+    let v1 = vaas.for_sha256(&sha256_1, &ct);
+    let v2 = vaas.for_sha256(&sha256_2, &ct);
+
+    let (v1, v2) = tokio::join!(v1, v2);
+    assert_eq!(generic_malware(), v1.unwrap().verdict);
+    assert_eq!(Verdict::Clean, v2.unwrap().verdict);
+}
+
+#[tokio::test]
+async fn from_sha256_multiple_clean_hash_await_concurrent_unknown_jobs() {
+    let server = start_vaas().await;
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+    let sha256_1 = Sha256::try_from(MALICIOUS).unwrap();
+    let sha256_2 = Sha256::try_from(CLEAN).unwrap();
+
+    let handles = vec![
+        vaas.for_sha256(&sha256_1, &ct),
+        vaas.for_sha256(&sha256_2, &ct),
+    ];
+
+    let result = try_join_all(handles).await;
+    let verdicts = result.unwrap();
+
+    assert_eq!(generic_malware(), verdicts[0].verdict);
+    assert_eq!(Verdict::Clean, verdicts[1].verdict);
+}
+
+#[tokio::test]
+async fn from_string_stream_returns_malicious_verdict() {
+    let content = "X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+    let server = MockVaas::new()
+        .verdict_for(Sha256::from(content.as_bytes()), generic_malware())
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+    let stream = futures::stream::once(async move { Ok::<_, std::io::Error>(content.as_bytes()) });
+
+    let verdict = vaas.for_stream(stream, content.len(), &ct).await.unwrap();
+
+    assert_eq!(generic_malware(), verdict.verdict);
+    assert_eq!(content.as_bytes(), server.uploads()[0].content);
+}
+
+#[tokio::test]
+async fn from_url_multiple_url() {
+    let malicious = Url::parse("https://secure.eicar.org/eicar.com").unwrap();
+    let clean = Url::parse("https://www.gdatasoftware.com/oem/verdict-as-a-service").unwrap();
+    let server = MockVaas::new()
+        .verdict_for_url(&malicious, generic_malware())
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+
+    let verdicts = vaas.for_url_list(&[malicious, clean], &ct).await;
+
+    assert_eq!(generic_malware(), verdicts[0].as_ref().unwrap().verdict);
+    assert_eq!(Verdict::Clean, verdicts[1].as_ref().unwrap().verdict);
+}
+
+#[tokio::test]
+async fn connect_with_client_credentials() {
+    let server = MockVaas::new().start().await.unwrap();
+    let authenticator = ClientCredentials::new("client".to_string(), "secret".to_string())
+        .with_token_url(server.token_url());
+
+    let connection = server
+        .builder(authenticator)
+        .build()
+        .unwrap()
+        .connect()
+        .await;
+
+    assert!(connection.is_ok())
+}
+
+#[tokio::test]
+async fn lazy_connection_reconnects_after_the_connection_is_dropped() {
+    let server = MockVaas::new()
+        .with_faults(FaultPlan::new().drop_connection_after(2))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.builder(MockAuthenticator).build().unwrap().lazy();
+    let sha256 = Sha256::try_from(CLEAN).unwrap();
+
+    let dropped = vaas.connection().await.unwrap();
+    vaas.for_sha256(&sha256, None).await.unwrap();
+    while !dropped.is_closed() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let on_dropped = dropped.for_sha256(&sha256, None).await;
+    let verdict = vaas.for_sha256(&sha256, None).await.unwrap();
+
+    assert!(on_dropped.is_err());
+    assert_eq!(Verdict::Clean, verdict.verdict);
+    assert_eq!(2, server.sessions());
+}
+
+#[tokio::test]
+async fn rejected_upload_is_retried() {
+    let content = b"unknown content".to_vec();
+    let sha256 = Sha256::from(content.as_slice());
+    let server = MockVaas::new()
+        .verdict_for(sha256.clone(), generic_malware())
+        .expect_upload_for(sha256)
+        .with_faults(FaultPlan::new().reject_uploads(1))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server
+        .builder(MockAuthenticator)
+        .retry_policy(RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(10),
+            ..RetryPolicy::default()
+        })
+        .build()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    let verdict = vaas.for_buf(content, None).await.unwrap();
+
+    assert_eq!(generic_malware(), verdict.verdict);
+    assert_eq!(1, server.rejected_uploads());
+    assert_eq!(1, server.uploads().len());
+}
+
+#[tokio::test]
+async fn rejected_upload_fails_without_retries() {
+    let content = b"unknown content".to_vec();
+    let server = MockVaas::new()
+        .expect_upload_for(Sha256::from(content.as_slice()))
+        .with_faults(FaultPlan::new().reject_uploads(1))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.connect().await.unwrap();
+
+    let verdict = vaas.for_buf(content, None).await;
+
+    assert!(
+        matches!(verdict, Err(Error::FailedUploadFile(status, _)) if status == 503),
+        "{verdict:?}"
+    );
+    assert!(server.uploads().is_empty());
+}
+
+#[tokio::test]
+async fn delayed_response_is_cancelled_after_the_timeout() {
+    let server = MockVaas::new()
+        .with_faults(FaultPlan::new().delay_responses(Duration::from_millis(300)))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.connect().await.unwrap();
+    let sha256 = Sha256::try_from(CLEAN).unwrap();
+
+    let cancelled = vaas
+        .for_sha256(
+            &sha256,
+            &CancellationToken::from(Duration::from_millis(100)),
+        )
+        .await;
+    let answered = vaas
+        .for_sha256(&sha256, &CancellationToken::from_seconds(10))
+        .await;
+
+    assert!(matches!(cancelled, Err(Error::Cancelled)), "{cancelled:?}");
+    assert_eq!(Verdict::Clean, answered.unwrap().verdict);
+}
+
+#[tokio::test]
+async fn garbage_response_fails_the_request() {
+    let malicious = Sha256::try_from(MALICIOUS).unwrap();
+    let server = MockVaas::new()
+        .with_faults(FaultPlan::new().garbage_for(malicious.clone()))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server.connect().await.unwrap();
+    let ct = CancellationToken::from_seconds(10);
+
+    let verdict = vaas.for_sha256(&malicious, &ct).await;
+
+    assert!(
+        matches!(verdict, Err(Error::InvalidMessage(_))),
+        "{verdict:?}"
+    );
+}
+
+#[tokio::test]
+async fn stalled_pong_does_not_break_the_connection() {
+    let server = MockVaas::new()
+        .with_faults(FaultPlan::new().stall_pongs(Duration::from_millis(1500)))
+        .start()
+        .await
+        .unwrap();
+    let vaas = server
+        .builder(MockAuthenticator)
+        .keep_alive_delay(Duration::from_secs(1))
+        .build()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let sha256 = Sha256::try_from(CLEAN).unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    let verdict = vaas
+        .for_sha256(&sha256, &CancellationToken::from_seconds(10))
+        .await;
+
+    assert_eq!(Verdict::Clean, verdict.unwrap().verdict);
+    assert!(!vaas.is_closed());
+}
@@ -0,0 +1,46 @@
+use std::convert::TryFrom;
+use vaas::message::{MessageType, VerdictRequest, VerdictRequestFile, VerdictResponse};
+use vaas::Sha256;
+
+const EICAR: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+#[test]
+fn synthesized_request_is_answered_with_a_parsable_response() {
+    let sha256 = Sha256::try_from(EICAR).unwrap();
+    let request = VerdictRequestFile::new(&sha256, "session".into(), true, true);
+    let json: serde_json::Value = serde_json::from_str(&request.to_json().unwrap()).unwrap();
+    assert_eq!("VerdictRequest", json["kind"]);
+    assert_eq!(request.guid().to_string(), json["guid"]);
+
+    let response = serde_json::json!({
+        "kind": "VerdictResponse",
+        "sha256": json["sha256"],
+        "guid": request.guid(),
+        "verdict": "Unknown",
+        "url": "https://upload.test",
+        "upload_token": "secret-upload-token",
+    })
+    .to_string();
+
+    let Ok(MessageType::VerdictResponse(response)) = MessageType::try_from(&response) else {
+        panic!("not a verdict response");
+    };
+    assert_eq!(request.guid().to_string(), response.guid);
+    assert_eq!(
+        Some("secret-upload-token"),
+        response.upload_token.as_deref()
+    );
+    assert!(!format!("{response:?}").contains("secret-upload-token"));
+}
+
+#[test]
+fn verdict_response_round_trips_through_json() {
+    let json = format!(
+        r#"{{"sha256":"{EICAR}","guid":"ed7207a5-d65a-4400-b91c-673ff39cfd8b","verdict":"Malicious","url":null,"upload_token":null,"detection":"EICAR-Test-File","file_type":null,"mime_type":null}}"#
+    );
+
+    let response = VerdictResponse::try_from(&json).unwrap();
+
+    assert_eq!("Malicious", response.verdict);
+    assert_eq!(json, serde_json::to_string(&response).unwrap());
+}
@@ -1,4 +1,3 @@
-use futures::future::try_join_all;
 use rand::{distributions::Alphanumeric, Rng};
 use reqwest::Url;
 use std::convert::TryFrom;
@@ -58,52 +57,6 @@ async fn connect_with_username_and_password() {
     assert!(connection.is_ok())
 }
 
-#[tokio::test]
-async fn from_sha256_list_multiple_hashes() {
-    let vaas = get_vaas().await;
-    let ct = CancellationToken::from_seconds(10);
-    let sha256_malicious =
-        Sha256::try_from("ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2")
-            .unwrap();
-    let sha256_clean =
-        Sha256::try_from("cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e")
-            .unwrap();
-    let sha256_unknown =
-        Sha256::try_from("1f72c1111111111111f912e40b7323a0192a300b376186c10f6803dc5efe28df")
-            .unwrap();
-    let sha256_list = vec![
-        sha256_malicious.clone(),
-        sha256_clean.clone(),
-        sha256_unknown.clone(),
-    ];
-
-    let results = vaas.for_sha256_list(&sha256_list, &ct).await;
-
-    assert_eq!(
-        Verdict::Malicious {
-            detection: String::from("Generic.Malware")
-        },
-        results[0].as_ref().unwrap().verdict
-    );
-    assert_eq!(
-        "ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2",
-        results[0].as_ref().unwrap().sha256.deref()
-    );
-    assert_eq!(Verdict::Clean, results[1].as_ref().unwrap().verdict);
-    assert_eq!(
-        "cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e".to_lowercase(),
-        results[1].as_ref().unwrap().sha256.deref()
-    );
-    assert!(matches!(
-        results[2].as_ref().unwrap().verdict,
-        Verdict::Unknown { .. }
-    ));
-    assert_eq!(
-        "1f72c1111111111111f912e40b7323a0192a300b376186c10f6803dc5efe28df",
-        results[2].as_ref().unwrap().sha256.deref()
-    );
-}
-
 #[tokio::test]
 async fn from_sha256_single_malicious_hash() {
     let vaas = get_vaas().await;
@@ -447,122 +400,6 @@ async fn from_file_single_unknown_file() {
     std::fs::remove_file(&tmp_file).unwrap();
 }
 
-#[tokio::test]
-async fn from_files_unknown_files() {
-    let unknown1: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(50)
-        .map(char::from)
-        .collect();
-    let unknown2: String = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(50)
-        .map(char::from)
-        .collect();
-    let tmp_file1 = std::env::temp_dir().join("unknown1.txt");
-    std::fs::write(&tmp_file1, unknown1.as_bytes()).unwrap();
-    let tmp_file2 = std::env::temp_dir().join("unknown2.txt");
-    std::fs::write(&tmp_file2, unknown2.as_bytes()).unwrap();
-    let files = vec![tmp_file1.clone(), tmp_file2.clone()];
-
-    let vaas = get_vaas().await;
-    let ct = CancellationToken::from_minutes(10);
-    let verdicts = vaas.for_file_list(&files, &ct).await;
-
-    assert_eq!(Verdict::Clean, verdicts[0].as_ref().unwrap().verdict);
-    assert_eq!(
-        Sha256::try_from(&tmp_file1).unwrap(),
-        verdicts[0].as_ref().unwrap().sha256
-    );
-    assert_eq!(Verdict::Clean, verdicts[1].as_ref().unwrap().verdict);
-    assert_eq!(
-        Sha256::try_from(&tmp_file2).unwrap(),
-        verdicts[1].as_ref().unwrap().sha256
-    );
-    std::fs::remove_file(tmp_file1).unwrap();
-    std::fs::remove_file(tmp_file2).unwrap();
-}
-
-#[tokio::test]
-async fn from_sha256_multiple_clean_hash_on_separate_thread() {
-    let vaas = get_vaas().await;
-    let ct = CancellationToken::from_seconds(10);
-    let sha256_1 =
-        Sha256::try_from("ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2")
-            .unwrap();
-    let sha256_2 =
-        Sha256::try_from("cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e")
-            .unwrap();
-
-    let (v1, v2) = tokio::spawn(async move {
-        let v1 = vaas.for_sha256(&sha256_1, &ct).await;
-        let v2 = vaas.for_sha256(&sha256_2, &ct).await;
-        (v1, v2)
-    })
-    .await
-    .unwrap();
-
-    assert_eq!(
-        Verdict::Malicious {
-            detection: String::from("Generic.Malware")
-        },
-        v1.unwrap().verdict
-    );
-    assert_eq!(Verdict::Clean, v2.unwrap().verdict);
-}
-
-#[tokio::test]
-async fn from_sha256_multiple_clean_hash_await_concurrent_fixed_jobs() {
-    let vaas = get_vaas().await;
-    let ct = CancellationToken::from_seconds(10);
-    let sha256_1 =
-        Sha256::try_from("ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2")
-            .unwrap();
-    let sha256_2 =
-        Sha256::try_from("cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e")
-            .unwrap();
-
-    let v1 = vaas.for_sha256(&sha256_1, &ct);
-    let v2 = vaas.for_sha256(&sha256_2, &ct);
-
-    let (v1, v2) = tokio::join!(v1, v2);
-    assert_eq!(
-        Verdict::Malicious {
-            detection: String::from("Generic.Malware")
-        },
-        v1.unwrap().verdict
-    );
-    assert_eq!(Verdict::Clean, v2.unwrap().verdict);
-}
-
-#[tokio::test]
-async fn from_sha256_multiple_clean_hash_await_concurrent_unknown_jobs() {
-    let vaas = get_vaas().await;
-    let ct = CancellationToken::from_seconds(10);
-    let sha256_1 =
-        Sha256::try_from("ab5788279033b0a96f2d342e5f35159f103f69e0191dd391e036a1cd711791a2")
-            .unwrap();
-    let sha256_2 =
-        Sha256::try_from("cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e")
-            .unwrap();
-
-    let handles = vec![
-        vaas.for_sha256(&sha256_1, &ct),
-        vaas.for_sha256(&sha256_2, &ct),
-    ];
-
-    let result = try_join_all(handles).await;
-    let verdicts = result.unwrap();
-
-    assert_eq!(
-        Verdict::Malicious {
-            detection: String::from("Generic.Malware")
-        },
-        verdicts[0].verdict
-    );
-    assert_eq!(Verdict::Clean, verdicts[1].verdict);
-}
-
 #[tokio::test]
 async fn from_file_single_clean_file_with_credentials() {
     let clean: [u8; 8] = [0x65, 0x0a, 0x67, 0x0a, 0x65, 0x0a, 0x62, 0x0a];
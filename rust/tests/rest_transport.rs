@@ -0,0 +1,183 @@
+#![cfg(feature = "rest")]
+
+use async_trait::async_trait;
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use vaas::auth::Authenticator;
+use vaas::error::{Error, VResult};
+use vaas::message::Verdict;
+use vaas::{Connection, Sha256, Transport, Vaas};
+use wiremock::matchers::{body_partial_json, header, method, path, path_regex};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+const EICAR: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+const CLEAN: &str = "cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e";
+
+/// Returns the `stale` token until VaaS rejected it, then the `fresh` one.
+#[derive(Clone, Default)]
+struct Tokens {
+    refreshed: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Authenticator for Tokens {
+    async fn get_token(&self) -> VResult<String> {
+        match self.refreshed.load(Ordering::SeqCst) {
+            0 => Ok("stale".to_string()),
+            _ => Ok("fresh".to_string()),
+        }
+    }
+
+    async fn refresh_token(&self) -> VResult<String> {
+        self.refreshed.fetch_add(1, Ordering::SeqCst);
+        self.get_token().await
+    }
+}
+
+/// Answers verdict requests and polls with the verdict for the guid of the request.
+struct Answer(&'static str);
+
+impl Respond for Answer {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let (guid, sha256) = match serde_json::from_slice::<Value>(&request.body) {
+            Ok(body) => (body["guid"].clone(), body["sha256"].clone()),
+            Err(_) => {
+                let guid = request.url.path_segments().unwrap().next_back().unwrap();
+                (json!(guid), json!(EICAR))
+            }
+        };
+        ResponseTemplate::new(200).set_body_json(json!({
+            "kind": "VerdictResponse",
+            "guid": guid,
+            "sha256": sha256,
+            "verdict": self.0,
+            "detection": "EICAR-Test-File",
+        }))
+    }
+}
+
+async fn connect(server: &MockServer, tokens: Tokens) -> Connection {
+    let url = Url::parse(&server.uri().replacen("http", "ws", 1)).unwrap();
+    Vaas::builder(tokens)
+        .url(url)
+        .transport(Transport::Rest)
+        .build()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+fn sha256(sha256: &str) -> Sha256 {
+    Sha256::try_from(sha256).unwrap()
+}
+
+#[tokio::test]
+async fn verdict_request_is_posted_with_the_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/verdicts"))
+        .and(header("Authorization", "Bearer stale"))
+        .and(body_partial_json(
+            json!({ "kind": "VerdictRequest", "sha256": EICAR }),
+        ))
+        .respond_with(Answer("Malicious"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let connection = connect(&server, Tokens::default()).await;
+
+    let verdict = connection.for_sha256(&sha256(EICAR), None).await.unwrap();
+
+    assert_eq!(
+        Verdict::Malicious {
+            detection: "EICAR-Test-File".to_string()
+        },
+        verdict.verdict
+    );
+}
+
+#[tokio::test]
+async fn pending_verdict_is_polled_until_it_is_known() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/verdicts"))
+        .respond_with(ResponseTemplate::new(202))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/verdicts/[0-9a-f-]+$"))
+        .respond_with(ResponseTemplate::new(202))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .expect(2)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex("^/verdicts/[0-9a-f-]+$"))
+        .respond_with(Answer("Clean"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let connection = connect(&server, Tokens::default()).await;
+
+    let verdict = connection.for_sha256(&sha256(EICAR), None).await.unwrap();
+
+    assert_eq!(Verdict::Clean, verdict.verdict);
+}
+
+#[tokio::test]
+async fn failed_request_fails_only_itself() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "sha256": EICAR })))
+        .respond_with(ResponseTemplate::new(500).set_body_string("scanner unavailable"))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "sha256": CLEAN })))
+        .respond_with(Answer("Clean"))
+        .mount(&server)
+        .await;
+    let connection = connect(&server, Tokens::default()).await;
+
+    let verdicts = connection
+        .for_sha256_list(&[sha256(EICAR), sha256(CLEAN)], None)
+        .await;
+
+    match verdicts.as_slice() {
+        [Err(Error::ErrorResponse(error)), Ok(clean)] => {
+            assert_eq!("HttpError", error.error_type);
+            assert!(error.text.contains("scanner unavailable"), "{error:?}");
+            assert_eq!(Verdict::Clean, clean.verdict);
+        }
+        verdicts => panic!("unexpected verdicts: {verdicts:?}"),
+    }
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(header("Authorization", "Bearer stale"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(header("Authorization", "Bearer fresh"))
+        .respond_with(Answer("Clean"))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let tokens = Tokens::default();
+    let connection = connect(&server, tokens.clone()).await;
+
+    connection.for_sha256(&sha256(EICAR), None).await.unwrap();
+    connection.for_sha256(&sha256(EICAR), None).await.unwrap();
+
+    assert_eq!(1, tokens.refreshed.load(Ordering::SeqCst));
+}
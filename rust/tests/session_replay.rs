@@ -0,0 +1,132 @@
+#![cfg(feature = "recording")]
+
+use reqwest::Url;
+use std::convert::TryFrom;
+use vaas::auth::authenticators::ClientCredentials;
+use vaas::error::{Error, VResult};
+use vaas::message::Verdict;
+use vaas::recording::ReplayTransport;
+use vaas::{CancellationToken, Connection, Sha256, Vaas};
+use wiremock::matchers::{body_bytes, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const EICAR: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+const CLEAN: &str = "cd617c5c1b1ff1c94a52ab8cf07192654f271a3f8bad49490288131ccb9efc1e";
+const EICAR_CONTENT: &[u8] =
+    b"X5O!P%@AP[4\\PZX54(P^)7CC)7}$EICAR-STANDARD-ANTIVIRUS-TEST-FILE!$H+H*";
+
+fn fixture(name: &str) -> ReplayTransport {
+    let path = format!(
+        "{}/tests/fixtures/sessions/{name}.ndjson",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    ReplayTransport::from_file(path).unwrap()
+}
+
+/// The credentials are never used, replayed sessions do not request a token.
+async fn replay(replay: ReplayTransport) -> VResult<Connection> {
+    let authenticator = ClientCredentials::new("client".to_string(), "secret".to_string());
+    Vaas::builder(authenticator)
+        .build()?
+        .connect_replay(replay)
+        .await
+}
+
+#[tokio::test]
+async fn clean_hash_lookup_is_replayed() {
+    let connection = replay(fixture("clean_hash_lookup")).await.unwrap();
+    let sha256 = Sha256::try_from(CLEAN).unwrap();
+
+    let verdict = connection.for_sha256(&sha256, None).await.unwrap();
+
+    assert_eq!(Verdict::Clean, verdict.verdict);
+    assert_eq!(sha256, verdict.sha256);
+}
+
+#[tokio::test]
+async fn unknown_file_is_uploaded_and_replayed_as_malicious() {
+    let upload_server = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path("/upload"))
+        .and(body_bytes(EICAR_CONTENT))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&upload_server)
+        .await;
+    let upload_url = Url::parse(&format!("{}/upload", upload_server.uri())).unwrap();
+    let connection = replay(fixture("unknown_upload_malicious").upload_to(upload_url))
+        .await
+        .unwrap();
+
+    let verdict = connection
+        .for_buf(EICAR_CONTENT.to_vec(), None)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        Verdict::Malicious {
+            detection: "EICAR-Test-File".to_string()
+        },
+        verdict.verdict
+    );
+    assert_eq!(Sha256::try_from(EICAR).unwrap(), verdict.sha256);
+    assert_eq!(Some("text/plain"), verdict.mime_type.as_deref());
+}
+
+#[tokio::test]
+async fn request_missing_from_the_recording_is_not_answered() {
+    let connection = replay(fixture("clean_hash_lookup")).await.unwrap();
+    let ct = CancellationToken::from_seconds(1);
+
+    let verdict = connection
+        .for_sha256(&Sha256::try_from(EICAR).unwrap(), &ct)
+        .await;
+
+    assert!(matches!(verdict, Err(Error::Cancelled)), "{verdict:?}");
+}
+
+#[cfg(feature = "test-utils")]
+#[tokio::test]
+async fn recorded_session_is_redacted_and_replays_the_same_verdicts() {
+    use vaas::test_utils::{MockAuthenticator, MockVaas, MOCK_TOKEN};
+
+    let eicar = Sha256::try_from(EICAR).unwrap();
+    let malicious = Verdict::Malicious {
+        detection: "EICAR-Test-File".to_string(),
+    };
+    let server = MockVaas::new()
+        .verdict_for(eicar.clone(), malicious.clone())
+        .start()
+        .await
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let recording = dir.path().join("session.ndjson");
+    let connection = server
+        .builder(MockAuthenticator)
+        .record_session(&recording)
+        .build()
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    let clean = Sha256::try_from(CLEAN).unwrap();
+    let recorded = [
+        connection.for_sha256(&eicar, None).await.unwrap(),
+        connection.for_sha256(&clean, None).await.unwrap(),
+    ];
+    drop(connection);
+
+    let content = std::fs::read_to_string(&recording).unwrap();
+    assert!(!content.contains(MOCK_TOKEN));
+    assert_eq!(6, content.lines().count(), "{content}");
+    let connection = replay(ReplayTransport::from_ndjson(&content).unwrap())
+        .await
+        .unwrap();
+    let (clean_replayed, eicar_replayed) = tokio::join!(
+        connection.for_sha256(&clean, None),
+        connection.for_sha256(&eicar, None)
+    );
+
+    assert_eq!(recorded[0].verdict, eicar_replayed.unwrap().verdict);
+    assert_eq!(recorded[1].verdict, clean_replayed.unwrap().verdict);
+}
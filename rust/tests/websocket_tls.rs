@@ -0,0 +1,169 @@
+use reqwest::Url;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::net::TcpListener;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::{self, Message};
+use vaas::auth::authenticators::StaticToken;
+use vaas::error::Error;
+use vaas::message::Verdict;
+use vaas::tls::{Certificate, Identity};
+use vaas::{Builder, CancellationToken, Sha256, Vaas};
+
+const SELF_SIGNED_PEM: &[u8] = include_bytes!("fixtures/wss_self_signed.pem");
+const SELF_SIGNED_KEY_PEM: &[u8] = include_bytes!("fixtures/wss_self_signed_key.pem");
+const CLIENT_CA_PEM: &[u8] = include_bytes!("fixtures/wss_client_ca.pem");
+const CLIENT_PEM: &[u8] = include_bytes!("fixtures/wss_client.pem");
+const CLIENT_KEY_PEM: &[u8] = include_bytes!("fixtures/wss_client_key.pem");
+const EICAR: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+/// The TLS configuration of a gateway with a self-signed certificate for `localhost`. If `client_ca` is given, the
+/// gateway only accepts clients with a certificate issued by it.
+fn server_config(client_ca: Option<&[u8]>) -> Arc<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            roots
+                .add(CertificateDer::from_pem_slice(client_ca).unwrap())
+                .unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let certificates = CertificateDer::pem_slice_iter(SELF_SIGNED_PEM)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let key = PrivateKeyDer::from_pem_slice(SELF_SIGNED_KEY_PEM).unwrap();
+    Arc::new(builder.with_single_cert(certificates, key).unwrap())
+}
+
+/// A VaaS gateway on `wss://localhost`, which authenticates one session and answers its verdict requests with
+/// `Clean`. Returns the port.
+fn serve_wss_session(config: Arc<ServerConfig>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let connection = ServerConnection::new(config).unwrap();
+        // The TLS handshake, which is completed by the websocket handshake, fails if the client does not trust the
+        // certificate or lacks a required client certificate.
+        let Ok(mut websocket) = tungstenite::accept(StreamOwned::new(connection, stream)) else {
+            return;
+        };
+        while let Ok(message) = websocket.read() {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let request: Value = serde_json::from_str(text.as_str()).unwrap();
+            let answer = match request["kind"].as_str() {
+                Some("AuthRequest") => json!({
+                    "kind": "AuthResponse",
+                    "success": true,
+                    "session_id": "session",
+                    "text": "",
+                }),
+                Some("VerdictRequest") => json!({
+                    "kind": "VerdictResponse",
+                    "sha256": request["sha256"],
+                    "guid": request["guid"],
+                    "verdict": "Clean",
+                    "url": null,
+                    "upload_token": null,
+                }),
+                _ => continue,
+            };
+            if websocket.send(Message::text(answer.to_string())).is_err() {
+                return;
+            }
+        }
+    });
+    port
+}
+
+fn builder(port: u16) -> Builder<StaticToken> {
+    Vaas::builder(StaticToken::new("token".to_string()))
+        .url(Url::parse(&format!("wss://localhost:{port}")).unwrap())
+}
+
+fn trusting_builder(port: u16) -> Builder<StaticToken> {
+    builder(port).add_root_certificate(Certificate::from_pem(SELF_SIGNED_PEM).unwrap())
+}
+
+#[tokio::test]
+async fn connects_to_gateway_whose_certificate_was_added_as_root() {
+    let port = serve_wss_session(server_config(None));
+    let vaas = trusting_builder(port).build().unwrap();
+
+    let connection = vaas.connect().await.unwrap();
+    let verdict = connection
+        .for_sha256(
+            &Sha256::try_from(EICAR).unwrap(),
+            &CancellationToken::from_seconds(10),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(Verdict::Clean, verdict.verdict);
+}
+
+#[tokio::test]
+async fn connects_to_gateway_with_untrusted_certificate_if_invalid_certificates_are_accepted() {
+    let port = serve_wss_session(server_config(None));
+    let vaas = builder(port)
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+
+    assert!(vaas.connect().await.is_ok());
+}
+
+#[tokio::test]
+async fn connecting_to_gateway_with_untrusted_certificate_fails() {
+    let port = serve_wss_session(server_config(None));
+    let vaas = builder(port).build().unwrap();
+
+    let result = vaas.connect().await;
+
+    assert!(matches!(result, Err(Error::WebSocket(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn connects_to_gateway_requiring_client_certificate_with_identity() {
+    let port = serve_wss_session(server_config(Some(CLIENT_CA_PEM)));
+    let vaas = trusting_builder(port)
+        .identity(Identity::from_pem(CLIENT_PEM, CLIENT_KEY_PEM).unwrap())
+        .build()
+        .unwrap();
+
+    let connection = vaas.connect().await.unwrap();
+    let verdict = connection
+        .for_sha256(
+            &Sha256::try_from(EICAR).unwrap(),
+            &CancellationToken::from_seconds(10),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(Verdict::Clean, verdict.verdict);
+}
+
+#[tokio::test]
+async fn connecting_to_gateway_requiring_client_certificate_without_identity_fails() {
+    let port = serve_wss_session(server_config(Some(CLIENT_CA_PEM)));
+    let vaas = trusting_builder(port).build().unwrap();
+
+    let result = vaas.connect().await;
+
+    assert!(matches!(result, Err(Error::WebSocket(_))), "{result:?}");
+}